@@ -0,0 +1,48 @@
+// string chunks as found in busy tiles, parsed through the public loaders
+#[macro_use]
+extern crate criterion;
+extern crate wow;
+
+use criterion::Criterion;
+use wow::adt::{MapTile, TileOptions, ChunkRect};
+
+fn chunk(token: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut result = token.iter().rev().cloned().collect::<Vec<u8>>();
+    result.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    result.extend_from_slice(data);
+    result
+}
+
+// a root file with `count` model and object paths and their ids
+fn tile(count: usize) -> Vec<u8> {
+    let mut names = Vec::new();
+    let mut ids = Vec::new();
+    for index in 0..count {
+        ids.extend_from_slice(&(names.len() as u32).to_le_bytes());
+        names.extend_from_slice(format!("World\\Azeroth\\Elwynn\\PassiveDoodads\\Trees\\ElwynnTree{:04}.m2\0", index).as_bytes());
+    }
+
+    let mut textures = Vec::new();
+    for index in 0..count / 8 {
+        textures.extend_from_slice(format!("Tileset\\Elwynn\\ElwynnGrass{:03}.blp\0", index).as_bytes());
+    }
+
+    let mut data = chunk(b"MVER", &18u32.to_le_bytes());
+    data.extend(chunk(b"MTEX", &textures));
+    data.extend(chunk(b"MMDX", &names));
+    data.extend(chunk(b"MMID", &ids));
+    data.extend(chunk(b"MWMO", &names));
+    data.extend(chunk(b"MWID", &ids));
+    data
+}
+
+fn bench_tile_strings(c: &mut Criterion) {
+    let data = tile(2000);
+    let options = TileOptions { region: ChunkRect::none(), ..TileOptions::new(None) };
+    c.bench_function("adt string chunks", |b| {
+        b.iter(|| MapTile::from_slices(&[(&data[..], true)], &options).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_tile_strings);
+criterion_main!(benches);
@@ -3,8 +3,16 @@ extern crate log;
 extern crate simplelog;
 extern crate wow;
 
+use std::fmt::Debug;
 use std::path::Path;
 use std::sync::Arc;
+use wow::asset::{self, Asset, LoadOptions};
+use wow::adt::MapTile;
+use wow::blp::Image;
+use wow::m2::Model;
+use wow::reader::ResourceReader;
+use wow::wdt::Wdt;
+use wow::wmo::MapObject;
 
 fn main() {
     simplelog::TermLogger::init(simplelog::LevelFilter::Trace, simplelog::Config::default())
@@ -27,25 +35,10 @@ fn main() {
 
         let reader = Arc::new(wow::reader::fs::FsResourceReader::new(dir));
 
-        match ext.as_str() {
-            ".blp" => match wow::blp::load(reader.clone(), file) {
-                Ok(image) => {
-                    info!("{:?} loaded successfully", path);
-                    println!("{:#?}", &image);
-                }
-                Err(e) => {
-                    error!("Failed to load {:?}; Cause: {}", path, &e);
-                }
-            }
-            ".adt" => match wow::adt::MapTile::load(reader.clone(), file, None) {
-                Ok(_) => {
-                    info!("{:?} loaded successfully", path);
-                }
-                Err(e) => {
-                    error!("Failed to load {:?}; Cause: {}", path, &e);
-                }
-            }
-            ".wmo" => match wow::wmo::load(reader.clone(), file) {
+        let options = LoadOptions::default();
+
+        if asset::matches::<MapObject>(file) {
+            match MapObject::load(&*reader, file, &options) {
                 Ok(wmo) => {
                     info!("{:?} loaded successfully", path);
                     let mut loaded_groups = Vec::new();
@@ -69,7 +62,30 @@ fn main() {
                     error!("Failed to load {:?}; Cause: {}", path, &e);
                 }
             }
-            _ => error!("Unknown file extension: {}", &ext)
+        } else if !(dump::<Image>(&*reader, file, path, &options)
+            || dump::<MapTile>(&*reader, file, path, &options)
+            || dump::<Model>(&*reader, file, path, &options)
+            || dump::<Wdt>(&*reader, file, path, &options))
+        {
+            error!("Unknown file extension: {}", &ext)
+        }
+    }
+}
+
+// false if `file` isn't an `A`
+fn dump<A: Asset + Debug>(reader: &dyn ResourceReader, file: &str, path: &Path, options: &LoadOptions) -> bool {
+    if !asset::matches::<A>(file) {
+        return false;
+    }
+
+    match A::load(reader, file, options) {
+        Ok(asset) => {
+            info!("{:?} loaded successfully", path);
+            println!("{:#?}", &asset);
+        }
+        Err(e) => {
+            error!("Failed to load {:?}; Cause: {}", path, &e);
         }
     }
+    true
 }
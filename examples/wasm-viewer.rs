@@ -0,0 +1,87 @@
+// loads BLP and WMO files handed over from JavaScript as ArrayBuffers. build with
+//   cargo build --example wasm-viewer --target wasm32-unknown-unknown \
+//     --no-default-features --features blp,wmo
+// and run wasm-bindgen on the output
+#![cfg(target_arch = "wasm32")]
+
+extern crate js_sys;
+extern crate wasm_bindgen;
+extern crate wow;
+
+use std::sync::Arc;
+use js_sys::{Array, ArrayBuffer, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wow::asset::{Asset, LoadOptions};
+use wow::blp::Image;
+use wow::reader::memory::MemoryReader;
+use wow::wmo::MapObject;
+
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Viewer {
+    reader: MemoryReader,
+    options: LoadOptions,
+}
+
+#[wasm_bindgen]
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+    rgba: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Viewer {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Viewer {
+        Viewer::default()
+    }
+
+    // `name` as other files refer to it, e.g. "World\wmo\Dungeon\test.wmo"
+    #[wasm_bindgen(js_name = addFile)]
+    pub fn add_file(&mut self, name: &str, buffer: &ArrayBuffer) {
+        self.reader.insert(name, Uint8Array::new(buffer).to_vec());
+    }
+
+    // the top mipmap, ready for an ImageData
+    #[wasm_bindgen(js_name = loadTexture)]
+    pub fn load_texture(&self, name: &str) -> Result<Texture, JsValue> {
+        let image = Image::load(&self.reader, name, &self.options).map_err(to_js)?;
+        let pixels = image.decode_mipmap(0).map_err(to_js)?;
+
+        let mut rgba = Vec::with_capacity(pixels.len() * 4);
+        for pixel in pixels.iter() {
+            rgba.extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+        }
+        Ok(Texture { width: image.width, height: image.height, rgba })
+    }
+
+    // the textures the object uses, so they can be fetched and added next. its
+    // groups have to be added before, groups missing then are skipped
+    #[wasm_bindgen(js_name = loadObject)]
+    pub fn load_object(&self, name: &str) -> Result<Array, JsValue> {
+        let wmo = MapObject::load(&self.reader, name, &self.options).map_err(to_js)?;
+        let groups = wmo.load_all_groups(Arc::new(self.reader.clone()));
+        let vertices: usize = groups.iter().map(|group| group.vertices.len()).sum();
+        web_log(&format!("{}: {} of {} groups, {} vertices", name, groups.len(), wmo.groups.len(), vertices));
+
+        Ok(wmo.textures.iter().map(|texture| JsValue::from_str(texture)).collect())
+    }
+}
+
+#[wasm_bindgen]
+impl Texture {
+    pub fn rgba(&self) -> Uint8Array {
+        Uint8Array::from(&self.rgba[..])
+    }
+}
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console, js_name = log)]
+    fn web_log(message: &str);
+}
+
+fn to_js<E: ToString>(error: E) -> JsValue {
+    JsValue::from_str(&error.to_string())
+}
@@ -1,31 +1,74 @@
-use std::io::{self, Seek, SeekFrom};
+use std::io::{self, Read};
 use std::sync::Arc;
 use std::collections::BTreeMap;
 use std::iter::Iterator;
 use byteorder::{ReadBytesExt, LE};
-use nalgebra::{Vector3, Point3};
-use chunked::{Chunked, Chunk};
-use reader::{ResourceReader, split_resource_name};
+use nalgebra::{Vector3, Point3, Matrix4, Rotation3};
+use chunked::{Chunked, ChunkRef, ChunkRefs, Token};
+use reader::{ResourceReader, ResourceHandle, split_resource_name};
+#[cfg(feature = "wdt")]
+use wdt::TileFileDataIds;
+use version::GameVersion;
+use asset::{Asset, LoadOptions};
+use bytes::Bytes;
 use alpha_map::AlphaMap;
-use read_ext::ReadExt;
+use liquid::{self, ChunkLiquid, LIQUID_WATER, LIQUID_OCEAN, LIQUID_MAGMA, LIQUID_SLIME};
+use read_ext::{self, ReadExt, FromRead};
+use records::{read_records, M2PlacementRecord};
+use error::Result;
 
-pub const TILE_SIZE: f32 = 533.0 + 1.0 / 3.0;
-pub const CHUNK_SIZE: f32 = TILE_SIZE / 16.0;
-pub const UNIT_SIZE: f32 = CHUNK_SIZE / 8.0;
-pub const MAP_CENTER: f32 = TILE_SIZE * 32.0; // every map is 64x64 tiles
+pub use geom::{TILE_SIZE, CHUNK_SIZE, UNIT_SIZE, MAP_CENTER};
 
-const MAP_CHUNK_COUNT: usize = 16 * 16;
+pub const MAP_CHUNKS_PER_SIDE: u32 = 16;
 const MAP_CHUNK_VERTICES: usize = 9 * 9 + 8 * 8;
 
+// MCNR declares 145 * 3 bytes but is followed by 13 bytes of padding
+// which aren't included in the subchunk size
+const MCNR_DATA_SIZE: usize = MAP_CHUNK_VERTICES * 3;
+const MCNR_PADDING: usize = 13;
+
+// normals are stored as i8 triplets scaled to 127; a stored vector whose length is
+// off by more than this fraction is considered denormalized
+pub const NORMAL_TOLERANCE: f32 = 0.05;
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MapTile {
     pub textures: Vec<String>,
     pub m2: Vec<String>,
     pub wmo: Vec<String>,
+    pub m2_placements: Vec<M2Placement>,
+    pub wmo_placements: Vec<WmoPlacement>,
     pub chunks: Vec<MapChunk>,
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct M2Placement {
+    pub name_id: u32,
+    pub unique_id: u32,
+    pub position: Point3<f32>,
+    pub rotation: Vector3<f32>,
+    pub scale: u16,
+    pub flags: u16,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WmoPlacement {
+    pub name_id: u32,
+    pub unique_id: u32,
+    pub position: Point3<f32>,
+    pub rotation: Vector3<f32>,
+    pub bounding_box_min: Point3<f32>,
+    pub bounding_box_max: Point3<f32>,
+    pub flags: u16,
+    pub doodad_set: u16,
+    pub name_set: u16,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MapChunk {
     pub index_x: u32,
     pub index_y: u32,
@@ -34,15 +77,19 @@ pub struct MapChunk {
     pub normals: Vec<Vector3<f32>>,
     pub holes: Holes,
     pub texture_layers: Vec<TextureLayer>,
+    // from MCLQ or MH2O, whichever the tile has
+    pub liquids: Vec<ChunkLiquid>,
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Holes {
     LowRes(u16),
     HighRes(u64),
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TextureLayer {
     pub texture_id: u32,
     pub flags: TextureLayerFlags,
@@ -50,6 +97,27 @@ pub struct TextureLayer {
     pub alpha_map: Option<AlphaMap>,
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct TileOptions {
+    // alpha map format of uncompressed layers (see `Wdt::big_alpha`)
+    pub big_alpha: Option<bool>,
+    // fills in for `big_alpha` where the release settles it, and skips looking
+    // for split files before cata
+    pub version: Option<GameVersion>,
+    pub region: ChunkRect,
+    // fail on denormalized MCNR normals instead of renormalizing them
+    pub strict_normals: bool,
+}
+
+// rectangle of MCNK indices within a tile
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
 pub struct MapChunkVertices<'a> {
     map_chunk: &'a MapChunk,
     index: usize,
@@ -64,6 +132,7 @@ pub struct MapChunkVertex {
 }
 
 bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct MapChunkFlags: u32 {
         const HAS_MCSH = 1;
         const IMPASSABLE = 1 << 1;
@@ -79,6 +148,7 @@ bitflags! {
     }
 }
 bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct TextureLayerFlags: u32 {
         const ANIM_ROTATION1 = 1;
         const ANIM_ROTATION2 = 1 << 1;
@@ -97,43 +167,234 @@ bitflags! {
 }
 impl_bitflags_from_read!(read_u32, MapChunkFlags);
 impl_bitflags_from_read!(read_u32, TextureLayerFlags);
+impl_bitflags_to_write!(write_u32, MapChunkFlags);
+impl_bitflags_to_write!(write_u32, TextureLayerFlags);
 
 impl MapTile {
-    pub fn load(reader: Arc<ResourceReader>, name: &str, big_alpha: Option<bool>) -> io::Result<MapTile> {
-        // vector of (split_name, is_rootfile)
-        let mut targets = vec![(name.to_owned(), true)];
-
-        // add split files (cata+)
-        let (name_dir, name_file, _) = split_resource_name(name);
-        for split_id in ["tex", "obj"].iter() {
-            let split_name = format!("{}{}_{}0.adt", name_dir, name_file, split_id);
-            if reader.exists(&split_name)? { targets.push((split_name, false)) }
+    // names of the root file and all existing split files (cata+)
+    pub fn source_files(reader: &dyn ResourceReader, name: &str) -> io::Result<Vec<String>> {
+        Ok(source_targets(reader, name)?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    pub fn load(reader: Arc<dyn ResourceReader>, name: &str, big_alpha: Option<bool>) -> Result<MapTile> {
+        MapTile::load_with(reader, name, &TileOptions::new(big_alpha))
+    }
+
+    // decodes only the map chunks within `rect`, in row-major order; chunks outside of it
+    // are skipped without parsing. placements and name tables are always read
+    pub fn load_region(reader: Arc<dyn ResourceReader>, name: &str, big_alpha: Option<bool>, rect: ChunkRect) -> Result<MapTile> {
+        MapTile::load_with(reader, name, &TileOptions { region: rect, ..TileOptions::new(big_alpha) })
+    }
+
+    pub fn load_with(reader: Arc<dyn ResourceReader>, name: &str, options: &TileOptions) -> Result<MapTile> {
+        MapTile::read_files(open_files(&*reader, name, options)?, options)
+    }
+
+    // opens the root and split files by FileDataID, as listed in the WDT (bfa+)
+    #[cfg(feature = "wdt")]
+    pub fn load_by_ids(reader: Arc<dyn ResourceReader>, ids: &TileFileDataIds, options: &TileOptions) -> Result<MapTile> {
+        let mut inputs = vec![(reader.open_by_id(ids.root)?, true)];
+        for &id in [ids.tex0, ids.obj0].iter().filter(|&&id| id != 0) {
+            inputs.push((reader.open_by_id(id)?, false));
         }
 
-        let map_chunks = vec![MapChunk {
-            index_x: 0,
-            index_y: 0,
+        MapTile::read_files(inputs, options)
+    }
+
+    // reads (input, is_rootfile) pairs, e.g. a root file and its split files
+    pub fn read_files<R: Read>(inputs: Vec<(R, bool)>, options: &TileOptions) -> Result<MapTile> {
+        let mut files = Vec::with_capacity(inputs.len());
+        for (mut input, is_rootfile) in inputs {
+            let mut data = Vec::new();
+            input.read_to_end(&mut data)?;
+            files.push((data, is_rootfile));
+        }
+
+        MapTile::from_slices(&files.iter().map(|&(ref data, is_rootfile)| (&data[..], is_rootfile)).collect::<Vec<_>>(), options)
+    }
+
+    // parses files already in memory (or mapped, see `FsResourceReader::map`), given as
+    // (data, is_rootfile) pairs; chunk data is borrowed, not copied
+    pub fn from_slices(files: &[(&[u8], bool)], options: &TileOptions) -> Result<MapTile> {
+        let rect = options.region.clipped();
+        let map_chunks = rect.indices().map(|(index_x, index_y)| MapChunk {
+            index_x,
+            index_y,
             position: Point3::new(0.0, 0.0, 0.0),
             heights: Vec::with_capacity(MAP_CHUNK_VERTICES),
             normals: Vec::with_capacity(MAP_CHUNK_VERTICES),
             holes: Holes::LowRes(0),
             texture_layers: Vec::with_capacity(4),
-        }; MAP_CHUNK_COUNT];
+            liquids: Vec::new(),
+        }).collect();
 
         let mut map_tile = MapTile {
             textures: Vec::new(),
             m2: Vec::new(),
             wmo: Vec::new(),
+            m2_placements: Vec::new(),
+            wmo_placements: Vec::new(),
             chunks: map_chunks,
         };
 
-        for &(ref split_name, is_rootfile) in targets.iter() {
-            let mut input = reader.open(&split_name)?;
-            read_adt_file(&mut map_tile, &mut input, is_rootfile, big_alpha)?;
+        for &(data, is_rootfile) in files {
+            read_adt_file(&mut map_tile, data, is_rootfile, &TileOptions { region: rect, ..*options })?;
         }
 
         Ok(map_tile)
     }
+
+    pub fn chunk(&self, index_x: u32, index_y: u32) -> Option<&MapChunk> {
+        self.chunks.iter().find(|chunk| chunk.index_x == index_x && chunk.index_y == index_y)
+    }
+}
+
+impl Asset for MapTile {
+    const EXTENSIONS: &'static [&'static str] = &[".adt"];
+
+    fn load(reader: &dyn ResourceReader, name: &str, options: &LoadOptions) -> Result<MapTile> {
+        let tile_options = TileOptions { version: options.version, ..TileOptions::new(options.big_alpha) };
+        options.apply(name, || MapTile::read_files(open_files(reader, name, &tile_options)?, &tile_options))
+    }
+}
+
+impl TileOptions {
+    pub fn new(big_alpha: Option<bool>) -> Self {
+        TileOptions { big_alpha, version: None, region: ChunkRect::all(), strict_normals: false }
+    }
+}
+
+impl ChunkRect {
+    pub fn all() -> Self {
+        ChunkRect { x: 0, y: 0, width: MAP_CHUNKS_PER_SIDE, height: MAP_CHUNKS_PER_SIDE }
+    }
+
+    // no terrain at all, for when only placements are needed
+    pub fn none() -> Self {
+        ChunkRect { x: 0, y: 0, width: 0, height: 0 }
+    }
+
+    pub fn single(x: u32, y: u32) -> Self {
+        ChunkRect { x, y, width: 1, height: 1 }
+    }
+
+    // chunk containing a world position, within the tile `world_to_tile` gives for it
+    pub fn at_world(x: f32, y: f32) -> Self {
+        let index = |value: f32| {
+            let tiles = (32.0 - value / TILE_SIZE).clamp(0.0, 64.0 - 1e-4);
+            ((tiles.fract() * MAP_CHUNKS_PER_SIDE as f32) as u32).min(MAP_CHUNKS_PER_SIDE - 1)
+        };
+        ChunkRect::single(index(y), index(x))
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+
+    pub fn indices(&self) -> impl Iterator<Item = (u32, u32)> {
+        let rect = *self;
+        (rect.y..(rect.y + rect.height)).flat_map(move |y| (rect.x..(rect.x + rect.width)).map(move |x| (x, y)))
+    }
+
+    fn clipped(&self) -> Self {
+        let x = self.x.min(MAP_CHUNKS_PER_SIDE);
+        let y = self.y.min(MAP_CHUNKS_PER_SIDE);
+        ChunkRect {
+            x,
+            y,
+            width: self.width.min(MAP_CHUNKS_PER_SIDE - x),
+            height: self.height.min(MAP_CHUNKS_PER_SIDE - y),
+        }
+    }
+
+    // position of a chunk in `MapTile::chunks` of a tile loaded with this rect
+    fn slot(&self, x: u32, y: u32) -> Option<usize> {
+        if self.contains(x, y) {
+            Some(((y - self.y) * self.width + (x - self.x)) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl M2Placement {
+    pub fn scale(&self) -> f32 {
+        self.scale as f32 / 1024.0
+    }
+
+    pub fn world_position(&self) -> Point3<f32> {
+        placement_to_world(&self.position)
+    }
+
+    // model space to Z-up world space
+    pub fn world_transform(&self) -> Matrix4<f32> {
+        placement_transform(&self.position, &self.rotation, self.scale())
+    }
+}
+
+impl WmoPlacement {
+    pub fn world_position(&self) -> Point3<f32> {
+        placement_to_world(&self.position)
+    }
+
+    // model space to Z-up world space
+    pub fn world_transform(&self) -> Matrix4<f32> {
+        placement_transform(&self.position, &self.rotation, 1.0)
+    }
+}
+
+// placement chunks use Y-up coordinates relative to the map corner;
+// this converts them to the Z-up world coordinates used by the server
+pub fn placement_to_world(position: &Point3<f32>) -> Point3<f32> {
+    Point3::new(MAP_CENTER - position.z, MAP_CENTER - position.x, position.y)
+}
+
+// builds the model matrix in placement space (see wowdev wiki on MDDF),
+// then converts the result into world space
+pub fn placement_transform(position: &Point3<f32>, rotation: &Vector3<f32>, scale: f32) -> Matrix4<f32> {
+    let rotate = |axis: Vector3<f32>, degrees: f32| {
+        Rotation3::new(axis * degrees.to_radians()).to_homogeneous()
+    };
+
+    let placement = Matrix4::new_translation(&position.coords)
+        * rotate(Vector3::y(), rotation.y - 270.0)
+        * rotate(Vector3::z(), -rotation.x)
+        * rotate(Vector3::x(), rotation.z - 90.0)
+        * Matrix4::new_scaling(scale);
+
+    let to_world = Matrix4::new(
+        0.0, 0.0, -1.0, MAP_CENTER,
+        -1.0, 0.0, 0.0, MAP_CENTER,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    );
+
+    to_world * placement
+}
+
+// MCNR stores (x, y, z) with x and y negated relative to world axes. the result is
+// always unit length; non-strict mode renormalizes denormalized input and maps
+// zero vectors to straight up
+pub fn decode_normal(raw: [i8; 3], strict: bool) -> io::Result<Vector3<f32>> {
+    let normal = Vector3::new(raw[0] as f32 / -127.0, raw[1] as f32 / -127.0, raw[2] as f32 / 127.0);
+    let length = normal.norm();
+
+    if (length - 1.0).abs() > NORMAL_TOLERANCE {
+        if strict {
+            return Err(io_error!(InvalidData, "denormalized normal {:?} (length {})", raw, length));
+        }
+        if length == 0.0 {
+            return Ok(Vector3::z());
+        }
+    }
+
+    Ok(normal / length)
+}
+
+// file name indices (map_X_Y.adt) of the tile containing a world position
+pub fn world_to_tile(x: f32, y: f32) -> (u32, u32) {
+    let clamp = |value: f32| value.clamp(0.0, 63.0) as u32;
+    (clamp((32.0 - y / TILE_SIZE).floor()), clamp((32.0 - x / TILE_SIZE).floor()))
 }
 
 impl MapChunk {
@@ -160,6 +421,40 @@ impl MapChunk {
             }
         }
     }
+
+    // same as `each_triangle`, leaving out the squares cut by holes
+    pub fn each_solid_triangle<F>(&self, high_detail: bool, mut f: F) where F: FnMut((u16, u16, u16)) {
+        let per_square = if high_detail { 4 } else { 2 };
+        let mut index = 0;
+        MapChunk::each_triangle(high_detail, |triangle| {
+            let square = index / per_square;
+            index += 1;
+            if !self.is_hole(square / 8, square % 8) {
+                f(triangle)
+            }
+        });
+    }
+
+    // whether a square of the 8x8 grid is cut out; low-res holes cover 2x2 squares
+    pub fn is_hole(&self, row: u32, column: u32) -> bool {
+        match self.holes {
+            Holes::LowRes(bits) => bits & (1 << ((row / 2) * 4 + column / 2)) != 0,
+            Holes::HighRes(bits) => bits & (1 << (row * 8 + column)) != 0,
+        }
+    }
+
+    // world position of `heights[index]`; rows alternate between 9 outer and 8 inner
+    // vertices, inner ones sitting in the middle of a square
+    pub fn vertex_position(&self, index: usize) -> Point3<f32> {
+        let (row, column) = (index / 17, index % 17);
+        let (x, y) = if column < 9 {
+            (row as f32, column as f32)
+        } else {
+            (row as f32 + 0.5, (column - 9) as f32 + 0.5)
+        };
+        let height = self.heights.get(index).cloned().unwrap_or(0.0);
+        Point3::new(self.position.x - x * UNIT_SIZE, self.position.y - y * UNIT_SIZE, self.position.z + height)
+    }
 }
 
 impl<'a> MapChunkVertices<'a> {
@@ -201,52 +496,86 @@ impl<'a> Iterator for MapChunkVertices<'a> {
     }
 }
 
-fn read_adt_file<R: ReadExt>(map_tile: &mut MapTile, input: &mut R, is_rootfile: bool, big_alpha: Option<bool>) -> io::Result<()> {
+// root and split files of a tile, as (input, is_rootfile)
+fn open_files(reader: &dyn ResourceReader, name: &str, options: &TileOptions) -> io::Result<Vec<(Box<dyn ResourceHandle>, bool)>> {
+    let mut inputs = vec![(reader.open(name)?, true)];
+    if options.version.map(|version| version.has_split_tiles()).unwrap_or(true) {
+        for split_name in split_names(name) {
+            if let Some(input) = reader.open_if_exists(&split_name)? {
+                inputs.push((input, false));
+            }
+        }
+    }
+    Ok(inputs)
+}
+
+// vector of (split_name, is_rootfile)
+fn source_targets(reader: &dyn ResourceReader, name: &str) -> io::Result<Vec<(String, bool)>> {
+    let mut targets = vec![(name.to_owned(), true)];
+
+    for split_name in split_names(name) {
+        if reader.exists(&split_name)? { targets.push((split_name, false)) }
+    }
+
+    Ok(targets)
+}
+
+// names split files (cata+) of a tile would have, whether they exist or not
+fn split_names(name: &str) -> Vec<String> {
+    let (name_dir, name_file, _) = split_resource_name(name);
+    ["tex", "obj"].iter()
+        .map(|split_id| format!("{}{}_{}0.adt", name_dir, name_file, split_id))
+        .collect()
+}
+
+fn read_adt_file(map_tile: &mut MapTile, data: &[u8], is_rootfile: bool, options: &TileOptions) -> io::Result<()> {
     let mut m2_tmp = BTreeMap::new();
     let mut wmo_tmp = BTreeMap::new();
 
     let mut map_chunk_index = 0;
 
-    for chunk in Chunked::new(input) {
+    for chunk in Chunked::over(data) {
         let chunk = chunk?;
         let mut cursor = chunk.cursor();
 
-        match chunk.token.as_str() {
+        match chunk.token {
             // texture paths
-            "MTEX" => {
-                cursor.read_cstring_array_into(&mut map_tile.textures)?;
+            Token::MTEX => {
+                read_ext::cstring_array_into(chunk.data, &mut map_tile.textures)?;
             }
             // m2 paths
-            "MMDX" => {
-                cursor.read_cstring_table_into(&mut m2_tmp)?;
+            Token::MMDX => {
+                read_ext::cstring_table_into(chunk.data, &mut m2_tmp)?;
             }
             // m2 local ids
-            "MMID" => {
+            Token::MMID => {
                 read_ids_chunk(&chunk, &mut m2_tmp, &mut map_tile.m2)?;
             }
             // wmo paths
-            "MWMO" => {
-                cursor.read_cstring_table_into(&mut wmo_tmp)?;
+            Token::MWMO => {
+                read_ext::cstring_table_into(chunk.data, &mut wmo_tmp)?;
             }
             // wmo local ids
-            "MWID" => {
+            Token::MWID => {
                 read_ids_chunk(&chunk, &mut wmo_tmp, &mut map_tile.wmo)?;
             }
             // m2 placement definitions
-            "MDDF" => {
-                for _ in 0..(chunk.data.len() / 36) {
-                    let_read! { LE | cursor =>
-                        name_id: u32;
-                        unique_id: u32;
-                        position: (f32, f32, f32);
-                        rotation: (f32, f32, f32);
-                        scale: u16;
-                        flags: u16;
-                    }
+            Token::MDDF => {
+                for record in read_records::<M2PlacementRecord>(chunk.data)? {
+                    let (position, rotation) = (record.position, record.rotation);
+
+                    map_tile.m2_placements.push(M2Placement {
+                        name_id: record.name_id,
+                        unique_id: record.unique_id,
+                        position: Point3::new(position[0], position[1], position[2]),
+                        rotation: Vector3::new(rotation[0], rotation[1], rotation[2]),
+                        scale: record.scale,
+                        flags: record.flags,
+                    });
                 }
             }
             // wmo placement definitions
-            "MODF" => {
+            Token::MODF => {
                 for _ in 0..(chunk.data.len() / 64) {
                     let_read! { LE | cursor =>
                         name_id: u32;
@@ -260,14 +589,42 @@ fn read_adt_file<R: ReadExt>(map_tile: &mut MapTile, input: &mut R, is_rootfile:
                         name_set: u16;
                         unknown1: u16;
                     }
+
+                    map_tile.wmo_placements.push(WmoPlacement {
+                        name_id,
+                        unique_id,
+                        position: Point3::new(position.0, position.1, position.2),
+                        rotation: Vector3::new(rotation.0, rotation.1, rotation.2),
+                        bounding_box_min: Point3::new(aabox_min.0, aabox_min.1, aabox_min.2),
+                        bounding_box_max: Point3::new(aabox_max.0, aabox_max.1, aabox_max.2),
+                        flags,
+                        doodad_set,
+                        name_set,
+                    });
+                }
+            }
+            // liquids of all map chunks (wotlk+)
+            Token::MH2O => {
+                for (index, layers) in liquid::read_mh2o(chunk.data)?.into_iter().enumerate() {
+                    let index = index as u32;
+                    if let Some(slot) = options.region.slot(index % MAP_CHUNKS_PER_SIDE, index / MAP_CHUNKS_PER_SIDE) {
+                        map_tile.chunks[slot].liquids = layers;
+                    }
                 }
             }
-            // map chunk
-            "MCNK" => {
-                let map_chunk = &mut map_tile.chunks[map_chunk_index];
-                if is_rootfile { read_mcnk_header(map_chunk, &mut cursor)? }
-                read_mcnk_subchunks(map_chunk, &mut cursor, big_alpha)?;
+            // map chunk; chunks are stored in row-major order in every split file
+            Token::MCNK => {
+                let (index_x, index_y) = (map_chunk_index % MAP_CHUNKS_PER_SIDE, map_chunk_index / MAP_CHUNKS_PER_SIDE);
                 map_chunk_index += 1;
+
+                if let Some(slot) = options.region.slot(index_x, index_y) {
+                    let map_chunk = &mut map_tile.chunks[slot];
+                    if is_rootfile {
+                        let header = read_mcnk_header(map_chunk, &mut cursor)?;
+                        read_mclq(map_chunk, &header, chunk.data)?;
+                    }
+                    read_mcnk_subchunks(map_chunk, chunk.subchunks(cursor.position() as usize)?, options)?;
+                }
             }
             _ => {}
         }
@@ -276,81 +633,110 @@ fn read_adt_file<R: ReadExt>(map_tile: &mut MapTile, input: &mut R, is_rootfile:
     Ok(())
 }
 
-fn read_mcnk_header<R: ReadExt>(map_chunk: &mut MapChunk, mut cursor: &mut R) -> io::Result<()> {
-    // TODO: add 3.3.5 compatibility ifs
-    let_read! { LE | cursor =>
-        flags: MapChunkFlags;
-        index_x: u32;
-        index_y: u32;
-        num_layers: u32;
-        num_doodad_refs: u32;
-        holes_high_res: u64;
-        offset_layer: u32;
-        offset_refs: u32;
-        offset_alpha: u32;
-        size_alpha: u32;
-        offset_shadow: u32;
-        size_shadow: u32;
-        area_id: u32;
-        num_mapobj_refs: u32;
-        holes_low_res: u16;
-        unknown1: u16;
-        texmap1: u64;
-        texmap2: u64;
-        no_effect_doodad: u64;
-        offset_sound_emitters: u32;
-        num_sound_emitters: u32;
-        offset_liquid: u32;
-        size_liquid: u32;
-        position: (f32, f32, f32);
-        offset_mccv: u32;
-        offset_mclv: u32;
-        unknown2: u32;
-    }
-
-    map_chunk.index_x = index_x;
-    map_chunk.index_y = index_y;
+// TODO: add 3.3.5 compatibility ifs
+#[derive(Debug, FromRead)]
+#[allow(dead_code)]
+struct MapChunkHeader {
+    flags: MapChunkFlags,
+    index_x: u32,
+    index_y: u32,
+    num_layers: u32,
+    num_doodad_refs: u32,
+    holes_high_res: u64,
+    offset_layer: u32,
+    offset_refs: u32,
+    offset_alpha: u32,
+    size_alpha: u32,
+    offset_shadow: u32,
+    size_shadow: u32,
+    area_id: u32,
+    num_mapobj_refs: u32,
+    holes_low_res: u16,
+    unknown1: u16,
+    texmap1: u64,
+    texmap2: u64,
+    no_effect_doodad: u64,
+    offset_sound_emitters: u32,
+    num_sound_emitters: u32,
+    offset_liquid: u32,
+    size_liquid: u32,
+    position: (f32, f32, f32),
+    offset_mccv: u32,
+    offset_mclv: u32,
+    unknown2: u32,
+}
+
+fn read_mcnk_header<R: ReadExt>(map_chunk: &mut MapChunk, cursor: &mut R) -> io::Result<MapChunkHeader> {
+    let header = MapChunkHeader::from_read::<LE>(cursor)?;
+    trace!("{:?}", header);
+
+    map_chunk.index_x = header.index_x;
+    map_chunk.index_y = header.index_y;
 
     // FIXME: this is Y-up coordinate; must be converted to Z-up
-    map_chunk.position = Point3::new(position.0, position.1, position.2);
+    map_chunk.position = Point3::new(header.position.0, header.position.1, header.position.2);
 
     // FIXME: high-res holes might use different algorithm (see wowdev wiki)
-    map_chunk.holes = if flags.contains(MapChunkFlags::USE_HIGH_RES_HOLES) {
-        Holes::HighRes(holes_high_res)
+    map_chunk.holes = if header.flags.contains(MapChunkFlags::USE_HIGH_RES_HOLES) {
+        Holes::HighRes(header.holes_high_res)
     } else {
-        Holes::LowRes(holes_low_res)
+        Holes::LowRes(header.holes_low_res)
     };
 
+    Ok(header)
+}
+
+// pre-wotlk liquids, one MCLQ body per liquid flag. they're found through the
+// header as the MCLQ chunk header doesn't declare their size
+fn read_mclq(map_chunk: &mut MapChunk, header: &MapChunkHeader, data: &[u8]) -> io::Result<()> {
+    if header.size_liquid <= 8 {
+        return Ok(());
+    }
+
+    let mut bytes = Bytes::new(data);
+    // the offset counts the MCNK header, which `data` leaves out, so it lands past the MCLQ one
+    bytes.seek(header.offset_liquid as usize)?;
+
+    for &(flag, liquid_type) in &[
+        (MapChunkFlags::LIQUID_RIVER, LIQUID_WATER),
+        (MapChunkFlags::LIQUID_OCEAN, LIQUID_OCEAN),
+        (MapChunkFlags::LIQUID_MAGMA, LIQUID_MAGMA),
+        (MapChunkFlags::LIQUID_SLIME, LIQUID_SLIME),
+    ] {
+        if header.flags.contains(flag) {
+            map_chunk.liquids.push(liquid::read_mclq(&mut bytes, liquid_type)?);
+        }
+    }
+
     Ok(())
 }
 
-fn read_mcnk_subchunks<R: ReadExt>(map_chunk: &mut MapChunk, cursor: &mut R, big_alpha: Option<bool>) -> io::Result<()> {
+fn read_mcnk_subchunks(map_chunk: &mut MapChunk, mut subchunks: ChunkRefs, options: &TileOptions) -> io::Result<()> {
     let mut mcal_offsets = Vec::new();
 
-    for subchunk in Chunked::new(cursor) {
+    while let Some(subchunk) = subchunks.next() {
         let subchunk = subchunk?;
-        let mut subcursor = subchunk.cursor();
+        let mut subcursor = subchunk.bytes();
 
-        match subchunk.token.as_str() {
+        match subchunk.token {
             // heights
-            "MCVT" => {
+            Token::MCVT => {
                 for _ in 0..MAP_CHUNK_VERTICES {
-                    let z = subcursor.read_f32::<LE>()?;
+                    let z = subcursor.f32::<LE>()?;
                     map_chunk.heights.push(z);
                 }
             }
             // normals
-            "MCNR" => {
+            Token::MCNR => {
                 for _ in 0..MAP_CHUNK_VERTICES {
-                    let x = subcursor.read_i8()? as f32 / -127f32;
-                    let y = subcursor.read_i8()? as f32 / -127f32;
-                    let z = subcursor.read_i8()? as f32 / 127f32;
-
-                    map_chunk.normals.push(Vector3::new(x, y, z).normalize());
+                    let raw = [subcursor.i8()?, subcursor.i8()?, subcursor.i8()?];
+                    map_chunk.normals.push(decode_normal(raw, options.strict_normals)?);
                 }
+
+                skip_mcnr_padding(&subchunk, &mut subchunks);
             }
             // textures
-            "MCLY" => {
+            Token::MCLY => {
                 for _ in 0..(subchunk.data.len() / 16) {
                     let_read! { LE | subcursor =>
                         texture_id: u32;
@@ -368,8 +754,11 @@ fn read_mcnk_subchunks<R: ReadExt>(map_chunk: &mut MapChunk, cursor: &mut R, big
                     mcal_offsets.push(mcal_offset as usize);
                 }
             }
+            // an empty MCLQ is followed by its body, see `read_mclq`; nothing
+            // used comes after it
+            Token::MCLQ if subchunk.data.is_empty() => break,
             // alpha maps
-            "MCAL" => {
+            Token::MCAL => {
                 for (index, start) in mcal_offsets.iter().enumerate() {
                     let layer = &mut map_chunk.texture_layers[index];
 
@@ -377,14 +766,14 @@ fn read_mcnk_subchunks<R: ReadExt>(map_chunk: &mut MapChunk, cursor: &mut R, big
                         continue;
                     }
 
-                    subcursor.seek(SeekFrom::Start(*start as u64))?;
+                    subcursor.seek(*start)?;
 
                     layer.alpha_map = if layer.flags.contains(TextureLayerFlags::ALPHA_MAP_COMPRESSED) {
                         Some(AlphaMap::read_compressed(&mut subcursor)?)
-                    } else if let Some(is_u8) = big_alpha {
+                    } else if let Some(is_u8) = options.big_alpha.or_else(|| options.version.and_then(|version| version.big_alpha())) {
                         Some(AlphaMap::read_raw(&mut subcursor, !is_u8)?)
                     } else {
-                        warn!("Skipping non-compressed alpha map {} (missing big_alpha option)", index);
+                        report_inconsistency!("Skipping non-compressed alpha map {} (missing big_alpha option)", index);
                         None
                     }
                 }
@@ -396,7 +785,14 @@ fn read_mcnk_subchunks<R: ReadExt>(map_chunk: &mut MapChunk, cursor: &mut R, big
     Ok(())
 }
 
-fn read_ids_chunk(chunk: &Chunk, data: &mut BTreeMap<u32, String>, into: &mut Vec<String>) -> io::Result<()> {
+// some writers include the padding after MCNR in its size, the client's files don't
+pub(crate) fn skip_mcnr_padding(mcnr: &ChunkRef, subchunks: &mut ChunkRefs) {
+    if mcnr.data.len() == MCNR_DATA_SIZE {
+        subchunks.skip_padding(MCNR_PADDING);
+    }
+}
+
+fn read_ids_chunk(chunk: &ChunkRef, data: &mut BTreeMap<u32, String>, into: &mut Vec<String>) -> io::Result<()> {
     into.reserve_exact(data.len());
 
     let mut cursor = chunk.cursor();
@@ -406,14 +802,125 @@ fn read_ids_chunk(chunk: &Chunk, data: &mut BTreeMap<u32, String>, into: &mut Ve
         if let Some(name) = data.remove(&offset) {
             into.push(name)
         } else {
-            warn!("found invalid offset {}", offset);
+            report_inconsistency!("found invalid offset {}", offset);
         }
     }
 
     for (_, name) in data.iter() {
-        warn!("missing reference for {}", &name);
+        report_inconsistency!("missing reference for {}", &name);
     }
 
     data.clear();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::chunk;
+
+    #[test]
+    fn test_world_to_tile() {
+        assert_eq!(world_to_tile(0.0, 0.0), (32, 32));
+        assert_eq!(world_to_tile(-1.0, 1.0), (31, 32));
+        assert_eq!(world_to_tile(MAP_CENTER, -MAP_CENTER), (63, 0));
+    }
+
+    fn assert_normal(raw: [i8; 3], expected: (f32, f32, f32)) {
+        let normal = decode_normal(raw, true).unwrap();
+        assert!((normal - Vector3::new(expected.0, expected.1, expected.2)).norm() < 1e-6, "{:?} -> {:?}", raw, normal);
+    }
+
+    #[test]
+    fn test_decode_normal() {
+        let diagonal = 0.5f32.sqrt();
+
+        assert_normal([0, 0, 127], (0.0, 0.0, 1.0));
+        assert_normal([127, 0, 0], (-1.0, 0.0, 0.0));
+        assert_normal([0, -127, 0], (0.0, 1.0, 0.0));
+        assert_normal([0, 0, -127], (0.0, 0.0, -1.0));
+        assert_normal([90, 0, 90], (-diagonal, 0.0, diagonal));
+        assert_normal([-90, 90, 0], (diagonal, -diagonal, 0.0));
+
+        assert!(decode_normal([10, 0, 10], true).is_err());
+        assert!(decode_normal([0, 0, 0], true).is_err());
+        assert!((decode_normal([10, 0, 10], false).unwrap() - Vector3::new(-diagonal, 0.0, diagonal)).norm() < 1e-6);
+        assert_eq!(decode_normal([0, 0, 0], false).unwrap(), Vector3::z());
+    }
+
+    #[test]
+    fn test_mcnr_padding() {
+        let mut normals = Vec::new();
+        for _ in 0..MAP_CHUNK_VERTICES { normals.extend_from_slice(&[0, 0, 127]) }
+
+        let mut heights = Vec::new();
+        for index in 0..MAP_CHUNK_VERTICES { heights.extend_from_slice(&(index as f32).to_le_bytes()) }
+
+        let mut data = chunk(b"MCNR", &normals);
+        data.extend_from_slice(&[0xFF; MCNR_PADDING]);
+        data.extend(chunk(b"MCVT", &heights));
+
+        let mut map_chunk = MapChunk {
+            index_x: 0,
+            index_y: 0,
+            position: Point3::origin(),
+            heights: Vec::new(),
+            normals: Vec::new(),
+            holes: Holes::LowRes(0),
+            texture_layers: Vec::new(),
+            liquids: Vec::new(),
+        };
+        read_mcnk_subchunks(&mut map_chunk, Chunked::over(&data), &TileOptions::new(None)).unwrap();
+
+        assert_eq!(map_chunk.normals.len(), MAP_CHUNK_VERTICES);
+        assert_eq!(map_chunk.heights.len(), MAP_CHUNK_VERTICES);
+        assert_eq!(map_chunk.heights[144], 144.0);
+    }
+
+    #[test]
+    fn test_mclq() {
+        let mut header = vec![0u8; 128];
+        header[0..4].copy_from_slice(&MapChunkFlags::LIQUID_MAGMA.bits().to_le_bytes());
+        header[96..100].copy_from_slice(&(8u32 + 128).to_le_bytes());
+        header[100..104].copy_from_slice(&(8 + liquid::MCLQ_SIZE as u32).to_le_bytes());
+
+        // as in the client's files, MCLQ declares no size and its body follows
+        let mut body = vec![0u8; liquid::MCLQ_SIZE];
+        body[0..4].copy_from_slice(&1.0f32.to_le_bytes());
+        header.extend(chunk(b"MCLQ", &[]));
+        header.extend(body);
+
+        let tile = MapTile::from_slices(&[(&chunk(b"MCNK", &header), true)], &TileOptions::new(None)).unwrap();
+        let liquids = &tile.chunks[0].liquids;
+        assert_eq!(liquids.len(), 1);
+        assert_eq!((liquids[0].liquid_type, liquids[0].min_height, liquids[0].cells), (LIQUID_MAGMA, 1.0, !0));
+        assert!(tile.chunks[1].liquids.is_empty());
+    }
+
+    #[test]
+    fn test_chunk_rect() {
+        let rect = ChunkRect { x: 14, y: 3, width: 4, height: 2 }.clipped();
+        assert_eq!(rect.indices().collect::<Vec<_>>(), vec![(14, 3), (15, 3), (14, 4), (15, 4)]);
+        assert_eq!(rect.slot(15, 4), Some(3));
+        assert_eq!(rect.slot(13, 4), None);
+
+        assert_eq!(ChunkRect::at_world(-1.0, 1.0), ChunkRect::single(15, 0));
+        assert_eq!(ChunkRect::at_world(CHUNK_SIZE * 1.5, -CHUNK_SIZE * 2.5), ChunkRect::single(2, 14));
+    }
+
+    #[test]
+    fn test_placement_transform() {
+        let position = Point3::new(100.0, 20.0, 200.0);
+        let transform = placement_transform(&position, &Vector3::new(0.0, 90.0, 0.0), 2.0);
+
+        let origin = Point3::from_homogeneous(transform * Point3::origin().to_homogeneous()).unwrap();
+        let expected = placement_to_world(&position);
+        assert!((origin - expected).norm() < 1e-3);
+
+        // model forward (+x) turns by the yaw, model up (+z) stays up
+        let forward = (transform * Vector3::x().to_homogeneous()).remove_row(3);
+        let up = (transform * Vector3::z().to_homogeneous()).remove_row(3);
+        assert!((forward - Vector3::new(0.0, 2.0, 0.0)).norm() < 1e-5);
+        assert!((up - Vector3::new(0.0, 0.0, 2.0)).norm() < 1e-5);
+    }
+}
@@ -2,9 +2,10 @@ use std::io::{self, Read};
 use read_ext::ReadExt;
 
 const ALPHAMAP_SIDE: usize = 64;
-const ALPHAMAP_SIZE: usize = ALPHAMAP_SIDE ^ 2;
+const ALPHAMAP_SIZE: usize = ALPHAMAP_SIDE * ALPHAMAP_SIDE;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct AlphaMap {
     is_u4: bool,
     data: Vec<u8>,
@@ -54,16 +55,15 @@ impl AlphaMap {
     }
 
     pub fn get(&self, index: usize) -> u8 {
-        let value = self.data[index / 2]; // FIXME: wrong index for u8?
-
         if self.is_u4 {
+            let value = self.data[index / 2];
             if index % 2 == 0 {
                 value & 0b00001111
             } else {
                 (value & 0b11110000) >> 4
             }
         } else {
-            value
+            self.data[index]
         }
     }
 
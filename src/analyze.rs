@@ -0,0 +1,235 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::sync::Arc;
+use byteorder::{ReadBytesExt, LE};
+use chunked::{Chunked, Token};
+use reader::{ResourceReader, split_resource_name};
+use read_ext::ReadExt;
+
+// tokens the format readers of this crate actually interpret;
+// subchunks are listed as "PARENT/TOKEN"
+const KNOWN_TOKENS: &[&str] = &[
+    "MVER",
+    // adt
+    "MTEX", "MMDX", "MMID", "MWMO", "MWID", "MDDF", "MODF", "MCNK",
+    "MCNK/MCVT", "MCNK/MCNR", "MCNK/MCLY", "MCNK/MCAL", "MCNK/MCLQ", "MH2O",
+    // wdt
+    "MPHD", "MAIN", "MAID",
+    // wmo root
+    "MOHD", "MOTX", "MOMT", "MOGN", "MOGI", "MOLT", "MODS", "MODN", "MODD",
+    "MOSB", "MOPV", "MOPT", "MOPR", "MOUV", "MCVP", "GFID",
+    // wmo group
+    "MOGP", "MOGP/MOVI", "MOGP/MOVT", "MOGP/MONR", "MOGP/MOTV", "MOGP/MOBA",
+    "MOGP/MOPY", "MOGP/MOCV", "MOGP/MLIQ", "MOGP/MOBN", "MOGP/MOBR", "MOGP/MOBS",
+    // legion model, skeleton and physics files
+    "MD21", "PFID", "SKID", "SFID", "BFID", "TXID", "AFID",
+    "SKL1", "SKS1", "SKB1", "SKPD", "BIDA", "BOMT",
+    "PHYS", "BODY", "SHAP", "BOXS", "CAPS", "SPHS", "JOIN",
+];
+
+// size of the fixed header preceding subchunks of nested chunks
+const MCNK_HEADER_SIZE: usize = 128;
+const MOGP_HEADER_SIZE: usize = 68;
+
+#[derive(Clone, Debug, Default)]
+pub struct TokenStats {
+    pub count: usize,
+    pub files: usize,
+    pub min_size: u32,
+    pub max_size: u32,
+    pub total_size: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TokenCensus {
+    pub files_scanned: usize,
+    pub failures: Vec<(String, String)>,
+    pub tokens: BTreeMap<String, TokenStats>,
+    pub versions: BTreeMap<String, BTreeMap<u32, usize>>,
+}
+
+pub fn token_census<I, S>(reader: Arc<dyn ResourceReader>, names: I) -> TokenCensus
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>
+{
+    let mut census = TokenCensus::default();
+
+    for name in names {
+        let name = name.as_ref();
+
+        let result = reader
+            .open(name)
+            .and_then(|mut input| census.add_file(name, &mut input));
+
+        if let Err(error) = result {
+            warn!("Unable to scan {}: {}", name, error);
+            census.failures.push((name.to_owned(), error.to_string()));
+        }
+    }
+
+    census
+}
+
+impl TokenCensus {
+    pub fn add_file<R: ReadExt>(&mut self, name: &str, input: &mut R) -> io::Result<()> {
+        let (_, file, ext) = split_resource_name(name);
+        let ext = ext.to_lowercase();
+        let file = file.to_lowercase();
+
+        // split adt files (cata+) store MCNK without the fixed header
+        let is_split_adt = ext == ".adt" && (file.ends_with("_tex0") || file.ends_with("_tex1")
+            || file.ends_with("_obj0") || file.ends_with("_obj1") || file.ends_with("_lod"));
+
+        // legion model files store their tokens in reading order
+        let chunks = match ext.as_str() {
+            ".m2" | ".skel" | ".phys" => Chunked::new_legion_m2(input),
+            _ => Chunked::new(input),
+        };
+
+        let mut seen = BTreeSet::new();
+        self.files_scanned += 1;
+
+        for chunk in chunks {
+            let chunk = chunk?;
+
+            if chunk.token == Token::MVER && chunk.data.len() >= 4 {
+                let version = chunk.cursor().read_u32::<LE>()?;
+                *self.versions.entry(ext.clone()).or_default().entry(version).or_insert(0) += 1;
+            }
+
+            let header_size = match chunk.token {
+                Token::MCNK if is_split_adt => Some(0),
+                Token::MCNK => Some(MCNK_HEADER_SIZE),
+                Token::MOGP => Some(MOGP_HEADER_SIZE),
+                _ => None,
+            };
+
+            if let Some(header_size) = header_size {
+                if let Ok(mut subchunks) = chunk.subchunks(header_size) {
+                    while let Some(subchunk) = subchunks.next() {
+                        // the rest of this chunk is lost, the next one may still be fine
+                        let subchunk = match subchunk {
+                            Ok(subchunk) => subchunk,
+                            Err(error) => {
+                                warn!("Unable to scan {} in {}: {}", &chunk.token, name, error);
+                                self.failures.push((format!("{} ({})", name, &chunk.token), error.to_string()));
+                                break;
+                            }
+                        };
+
+                        #[cfg(feature = "adt")]
+                        if subchunk.token == Token::MCNR {
+                            ::adt::skip_mcnr_padding(&subchunk, &mut subchunks);
+                        }
+
+                        let path = format!("{}/{}", &chunk.token, &subchunk.token);
+                        self.record(path, subchunk.data.len(), &mut seen);
+                    }
+                }
+            }
+
+            self.record(chunk.token.to_string(), chunk.data.len(), &mut seen);
+        }
+
+        Ok(())
+    }
+
+    pub fn is_known(token: &str) -> bool {
+        KNOWN_TOKENS.contains(&token)
+    }
+
+    pub fn known_tokens(&self) -> impl Iterator<Item = (&String, &TokenStats)> {
+        self.tokens.iter().filter(|&(token, _)| Self::is_known(token))
+    }
+
+    pub fn unknown_tokens(&self) -> impl Iterator<Item = (&String, &TokenStats)> {
+        self.tokens.iter().filter(|&(token, _)| !Self::is_known(token))
+    }
+
+    fn record(&mut self, token: String, size: usize, seen: &mut BTreeSet<String>) {
+        let size = size as u32;
+        let first_in_file = seen.insert(token.clone());
+        let stats = self.tokens.entry(token).or_default();
+
+        if stats.count == 0 || size < stats.min_size { stats.min_size = size }
+        if size > stats.max_size { stats.max_size = size }
+
+        stats.count += 1;
+        stats.total_size += size as u64;
+        if first_in_file { stats.files += 1 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{chunk, m2_chunk};
+
+    #[test]
+    fn test_token_census() {
+        let mut mogp = vec![0u8; MOGP_HEADER_SIZE];
+        mogp.extend(chunk(b"MOVI", &[0, 0, 1, 0, 2, 0]));
+        mogp.extend(chunk(b"MOXX", &[]));
+
+        let mut file = chunk(b"MVER", &[17, 0, 0, 0]);
+        file.extend(chunk(b"MOGP", &mogp));
+        file.extend(chunk(b"MOXX", &[1, 2]));
+        file.extend(chunk(b"MOXX", &[1, 2, 3, 4]));
+
+        let mut census = TokenCensus::default();
+        census.add_file("World/wmo/Test_000.wmo", &mut io::Cursor::new(&file)).unwrap();
+
+        assert_eq!(census.files_scanned, 1);
+        assert_eq!(census.versions[".wmo"][&17], 1);
+
+        let stats = &census.tokens["MOXX"];
+        assert_eq!((stats.count, stats.files, stats.min_size, stats.max_size, stats.total_size), (2, 1, 2, 4, 6));
+        assert_eq!(census.tokens["MOGP/MOVI"].total_size, 6);
+
+        let unknown: Vec<&String> = census.unknown_tokens().map(|(token, _)| token).collect();
+        assert_eq!(unknown, vec!["MOGP/MOXX", "MOXX"]);
+    }
+
+    #[test]
+    fn test_legion_model_census() {
+        let mut file = m2_chunk(b"MD21", b"MD20");
+        file.extend(m2_chunk(b"SFID", &[1, 0, 0, 0]));
+
+        let mut census = TokenCensus::default();
+        census.add_file("Creature/Test/Test.m2", &mut io::Cursor::new(&file)).unwrap();
+        census.add_file("World/Maps/Test/Test.wdt", &mut io::Cursor::new(chunk(b"MAID", &[]))).unwrap();
+
+        assert_eq!(census.tokens["MD21"].count, 1);
+        assert_eq!(census.tokens["SFID"].count, 1);
+        assert!(census.unknown_tokens().next().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "adt")]
+    fn test_broken_subchunks() {
+        use asset::ParseMode;
+
+        // MCNR followed by the padding its size leaves out
+        let mut mcnk = vec![0u8; MCNK_HEADER_SIZE];
+        mcnk.extend(chunk(b"MCNR", &[0; 145 * 3]));
+        mcnk.extend_from_slice(&[0xFF; 13]);
+        mcnk.extend(chunk(b"MCVT", &[0; 4]));
+
+        // a subchunk running past the end of its MCNK
+        let mut broken = vec![0u8; MCNK_HEADER_SIZE];
+        broken.extend(chunk(b"MCVT", &[0; 4]));
+        broken.truncate(broken.len() - 1);
+
+        let mut file = chunk(b"MCNK", &broken);
+        file.extend(chunk(b"MCNK", &mcnk));
+
+        let mut census = TokenCensus::default();
+        ParseMode::Strict.apply(|| census.add_file("World/Maps/Test/Test_1_2.adt", &mut io::Cursor::new(&file))).unwrap();
+
+        assert_eq!(census.failures.len(), 1);
+        assert_eq!(census.tokens["MCNK"].count, 2);
+        assert_eq!(census.tokens["MCNK/MCVT"].count, 1);
+        assert!(census.unknown_tokens().next().is_none());
+    }
+}
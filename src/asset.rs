@@ -0,0 +1,129 @@
+use std::cell::Cell;
+use reader::{ResourceReader, split_resource_name};
+use version::GameVersion;
+use diagnostics::{Diagnostic, Diagnostics};
+use limits::Limits;
+use error::Result;
+
+// settings understood by `Asset::load`; formats ignore what doesn't apply to them
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    pub version: Option<GameVersion>,
+    // see `TileOptions::big_alpha`
+    pub big_alpha: Option<bool>,
+    // receives the warnings raised while loading, see `load_with_diagnostics`
+    pub diagnostics: Option<Diagnostics>,
+    // caps on what a malformed file can make the loader allocate
+    pub limits: Limits,
+    pub parse_mode: ParseMode,
+}
+
+// how loaders deal with inconsistent data, see `report_inconsistency!`. chunk
+// iterators created while loading follow it as well, see `Strictness`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ParseMode {
+    // fail with an error, for validators
+    Strict,
+    // leave out or fill in what's broken and report a warning; the default, for viewers
+    #[default]
+    Lenient,
+}
+
+thread_local! {
+    static PARSE_MODE: Cell<ParseMode> = const { Cell::new(ParseMode::Lenient) };
+}
+
+// a file format which can be loaded by name, for code generic over formats
+pub trait Asset: Sized {
+    // lowercase and including the dot, as returned by `split_resource_name`
+    const EXTENSIONS: &'static [&'static str];
+
+    fn load(reader: &dyn ResourceReader, name: &str, options: &LoadOptions) -> Result<Self>;
+}
+
+impl LoadOptions {
+    // runs `load` under these limits and parse mode, collecting its warnings if
+    // there's a collector; for `Asset` impls
+    pub fn apply<T, F: FnOnce() -> T>(&self, name: &str, load: F) -> T {
+        self.limits.apply(|| self.parse_mode.apply(|| match self.diagnostics {
+            Some(ref diagnostics) => diagnostics.collect(name, load),
+            None => load(),
+        }))
+    }
+}
+
+impl ParseMode {
+    // the mode in effect on this thread
+    pub fn current() -> ParseMode {
+        PARSE_MODE.with(Cell::get)
+    }
+
+    // runs `load` with this mode in effect on this thread
+    pub fn apply<T, F: FnOnce() -> T>(&self, load: F) -> T {
+        struct Restore(ParseMode);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                PARSE_MODE.with(|mode| mode.set(self.0));
+            }
+        }
+
+        let _restore = Restore(PARSE_MODE.with(|mode| mode.replace(*self)));
+        load()
+    }
+}
+
+// loads an asset along with the warnings raised on the way; they're returned
+// on failure too, as they often explain it
+pub fn load_with_diagnostics<A: Asset>(reader: &dyn ResourceReader, name: &str, options: &LoadOptions) -> (Result<A>, Vec<Diagnostic>) {
+    let diagnostics = Diagnostics::new();
+    let options = LoadOptions { diagnostics: Some(diagnostics.clone()), ..options.clone() };
+    let result = A::load(reader, name, &options);
+    (result, diagnostics.take())
+}
+
+// whether `name` has one of the extensions of `A`
+pub fn matches<A: Asset>(name: &str) -> bool {
+    let extension = split_resource_name(name).2.to_lowercase();
+    A::EXTENSIONS.iter().any(|&known| known == extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use chunked::Chunked;
+
+    #[test]
+    #[cfg(all(feature = "blp", feature = "m2", feature = "wmo"))]
+    fn test_matches() {
+        use blp::Image;
+        use m2::Model;
+        use wmo::MapObject;
+
+        assert!(matches::<Image>("Textures\\A.BLP"));
+        assert!(matches::<Model>("Creature\\Bear\\Bear.m2"));
+        assert!(!matches::<MapObject>("Creature\\Bear\\Bear.m2"));
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        fn layers(count: u32) -> io::Result<u32> {
+            if count > 4 {
+                report_inconsistency!("{} texture layers, only 4 are used", count);
+            }
+            Ok(count.min(4))
+        }
+
+        let diagnostics = Diagnostics::new();
+        assert_eq!(diagnostics.collect("a.adt", || layers(6)).unwrap(), 4);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(ParseMode::Strict.apply(|| layers(6)).is_err());
+
+        // a truncated chunk is skipped, unless strict
+        let data = b"REVM\x08\0\0\0ab";
+        assert_eq!(Chunked::over(data).count(), 0);
+        assert!(ParseMode::Strict.apply(|| Chunked::over(data).next().unwrap().is_err()));
+        assert_eq!(ParseMode::current(), ParseMode::Lenient);
+    }
+}
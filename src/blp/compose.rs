@@ -0,0 +1,176 @@
+use std::io;
+use rgb::RGBA8;
+use misc::Blob;
+use blp::Image;
+use blp::mipmaps::MipFilter;
+
+// regions are specified in this layout and scaled to the actual canvas size
+const LAYOUT_SIZE: u32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// sections of the character skin texture armor textures are drawn into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharacterRegion {
+    Base,
+    ArmUpper,
+    ArmLower,
+    Hand,
+    FaceUpper,
+    FaceLower,
+    TorsoUpper,
+    TorsoLower,
+    LegUpper,
+    LegLower,
+    Foot,
+}
+
+impl CharacterRegion {
+    pub fn region(&self, canvas_width: u32, canvas_height: u32) -> Region {
+        let (x, y, width, height) = match *self {
+            CharacterRegion::Base => (0, 0, 256, 256),
+            CharacterRegion::ArmUpper => (0, 0, 128, 64),
+            CharacterRegion::ArmLower => (0, 64, 128, 64),
+            CharacterRegion::Hand => (0, 128, 128, 32),
+            CharacterRegion::FaceUpper => (0, 160, 128, 32),
+            CharacterRegion::FaceLower => (0, 192, 128, 64),
+            CharacterRegion::TorsoUpper => (128, 0, 128, 64),
+            CharacterRegion::TorsoLower => (128, 64, 128, 32),
+            CharacterRegion::LegUpper => (128, 96, 128, 64),
+            CharacterRegion::LegLower => (128, 160, 128, 64),
+            CharacterRegion::Foot => (128, 224, 128, 32),
+        };
+
+        let scale_x = |value: u32| value * canvas_width / LAYOUT_SIZE;
+        let scale_y = |value: u32| value * canvas_height / LAYOUT_SIZE;
+
+        Region { x: scale_x(x), y: scale_y(y), width: scale_x(width), height: scale_y(height) }
+    }
+}
+
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Blob<RGBA8>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        Canvas {
+            width,
+            height,
+            pixels: Blob(vec![RGBA8 { r: 0, g: 0, b: 0, a: 0 }; (width * height) as usize]),
+        }
+    }
+
+    pub fn full_region(&self) -> Region {
+        Region { x: 0, y: 0, width: self.width, height: self.height }
+    }
+
+    // blends the image over the region, resampling it to the region size; the
+    // mipmap closest to (but not smaller than) the region is used as a source
+    pub fn draw(&mut self, image: &Image, region: Region) -> io::Result<()> {
+        let level = (0..image.mipmap_count())
+            .take_while(|&level| {
+                let (width, height) = image.mipmap_size(level);
+                width >= region.width && height >= region.height
+            })
+            .last()
+            .unwrap_or(0);
+
+        let (width, height) = image.mipmap_size(level);
+        let pixels = image.decode_mipmap(level)?;
+        self.draw_pixels(&pixels, width, height, region);
+        Ok(())
+    }
+
+    pub fn draw_pixels(&mut self, pixels: &[RGBA8], width: u32, height: u32, region: Region) {
+        let right = (region.x + region.width).min(self.width);
+        let bottom = (region.y + region.height).min(self.height);
+
+        for y in region.y..bottom {
+            let source_y = ((y - region.y) * height / region.height) as usize;
+
+            for x in region.x..right {
+                let source_x = ((x - region.x) * width / region.width) as usize;
+                let source = pixels[source_y * width as usize + source_x];
+                let target = &mut self.pixels[(y * self.width + x) as usize];
+                *target = blend_over(*target, source);
+            }
+        }
+    }
+
+    pub fn into_image(self, filter: MipFilter) -> io::Result<Image> {
+        Image::from_rgba(self.width, self.height, self.pixels.0, filter)
+    }
+}
+
+// standard "source over destination" alpha compositing on straight alpha
+pub fn blend_over(target: RGBA8, source: RGBA8) -> RGBA8 {
+    if source.a == 255 || target.a == 0 {
+        return source;
+    }
+    if source.a == 0 {
+        return target;
+    }
+
+    let source_alpha = source.a as f32 / 255.0;
+    let target_alpha = target.a as f32 / 255.0 * (1.0 - source_alpha);
+    let alpha = source_alpha + target_alpha;
+
+    let channel = |s: u8, t: u8| ((s as f32 * source_alpha + t as f32 * target_alpha) / alpha).round() as u8;
+
+    RGBA8 {
+        r: channel(source.r, target.r),
+        g: channel(source.g, target.g),
+        b: channel(source.b, target.b),
+        a: (alpha * 255.0).round() as u8,
+    }
+}
+
+// composes layers in order into a canvas of the given size
+pub fn compose<'a, I>(width: u32, height: u32, layers: I) -> io::Result<Canvas>
+where
+    I: IntoIterator<Item = (&'a Image, Region)>
+{
+    let mut canvas = Canvas::new(width, height);
+    for (image, region) in layers {
+        canvas.draw(image, region)?;
+    }
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blp::ImageData;
+
+    fn solid(width: u32, height: u32, color: RGBA8) -> Image {
+        Image {
+            width,
+            height,
+            data: ImageData::TrueColor { mipmaps: vec![Blob(vec![color; (width * height) as usize])] },
+        }
+    }
+
+    #[test]
+    fn test_compose() {
+        let base = solid(4, 4, RGBA8 { r: 200, g: 0, b: 0, a: 255 });
+        let overlay = solid(2, 2, RGBA8 { r: 0, g: 0, b: 100, a: 128 });
+
+        let region = CharacterRegion::TorsoUpper.region(4, 4);
+        assert_eq!(region, Region { x: 2, y: 0, width: 2, height: 1 });
+
+        let canvas = compose(4, 4, vec![(&base, CharacterRegion::Base.region(4, 4)), (&overlay, region)]).unwrap();
+
+        assert_eq!(canvas.pixels[0], RGBA8 { r: 200, g: 0, b: 0, a: 255 });
+        assert_eq!(canvas.pixels[3], RGBA8 { r: 100, g: 0, b: 50, a: 255 });
+        assert_eq!(canvas.pixels[7], RGBA8 { r: 200, g: 0, b: 0, a: 255 });
+    }
+}
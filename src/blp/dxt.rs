@@ -0,0 +1,159 @@
+use std::io;
+use rgb::RGBA8;
+use blp::Compression;
+
+pub fn block_size(compression: &Compression) -> usize {
+    match *compression {
+        Compression::DXT1 => 8,
+        Compression::DXT3 | Compression::DXT5 => 16,
+    }
+}
+
+pub fn compressed_size(compression: &Compression, width: u32, height: u32) -> usize {
+    let blocks_x = width.div_ceil(4) as usize;
+    let blocks_y = height.div_ceil(4) as usize;
+    blocks_x * blocks_y * block_size(compression)
+}
+
+pub fn decompress(compression: &Compression, width: u32, height: u32, data: &[u8]) -> io::Result<Vec<RGBA8>> {
+    let expected = compressed_size(compression, width, height);
+    if data.len() < expected {
+        return Err(io_error!(UnexpectedEof, "dxt data has {} bytes, expected {}", data.len(), expected));
+    }
+
+    let (width, height) = (width as usize, height as usize);
+    let blocks_x = width.div_ceil(4);
+    let block_size = block_size(compression);
+
+    let mut pixels = vec![RGBA8 { r: 0, g: 0, b: 0, a: 0 }; width * height];
+
+    for (block_index, block) in data[..expected].chunks(block_size).enumerate() {
+        let block_x = (block_index % blocks_x) * 4;
+        let block_y = (block_index / blocks_x) * 4;
+
+        let texels = match *compression {
+            Compression::DXT1 => decode_color_block(block, true),
+            Compression::DXT3 => {
+                let mut texels = decode_color_block(&block[8..], false);
+                for (index, texel) in texels.iter_mut().enumerate() {
+                    let nibble = (block[index / 2] >> ((index % 2) * 4)) & 0x0F;
+                    texel.a = nibble * 17;
+                }
+                texels
+            }
+            Compression::DXT5 => {
+                let mut texels = decode_color_block(&block[8..], false);
+                let alphas = decode_alpha_block(&block[..8]);
+                for (texel, alpha) in texels.iter_mut().zip(alphas.iter()) {
+                    texel.a = *alpha;
+                }
+                texels
+            }
+        };
+
+        for (index, texel) in texels.iter().enumerate() {
+            let x = block_x + index % 4;
+            let y = block_y + index / 4;
+            if x < width && y < height {
+                pixels[y * width + x] = *texel;
+            }
+        }
+    }
+
+    Ok(pixels)
+}
+
+fn rgb565(value: u16) -> (u16, u16, u16) {
+    let r = (value >> 11) & 0x1F;
+    let g = (value >> 5) & 0x3F;
+    let b = value & 0x1F;
+    ((r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2))
+}
+
+fn decode_color_block(block: &[u8], allow_transparency: bool) -> [RGBA8; 16] {
+    let c0 = block[0] as u16 | (block[1] as u16) << 8;
+    let c1 = block[2] as u16 | (block[3] as u16) << 8;
+    let (r0, g0, b0) = rgb565(c0);
+    let (r1, g1, b1) = rgb565(c1);
+
+    let color = |r: u16, g: u16, b: u16| RGBA8 { r: r as u8, g: g as u8, b: b as u8, a: 0xFF };
+
+    let mut palette = [color(r0, g0, b0), color(r1, g1, b1), color(0, 0, 0), color(0, 0, 0)];
+
+    if c0 > c1 || !allow_transparency {
+        palette[2] = color((2 * r0 + r1) / 3, (2 * g0 + g1) / 3, (2 * b0 + b1) / 3);
+        palette[3] = color((r0 + 2 * r1) / 3, (g0 + 2 * g1) / 3, (b0 + 2 * b1) / 3);
+    } else {
+        palette[2] = color((r0 + r1) / 2, (g0 + g1) / 2, (b0 + b1) / 2);
+        palette[3] = RGBA8 { r: 0, g: 0, b: 0, a: 0 };
+    }
+
+    let indexes = block[4] as u32 | (block[5] as u32) << 8 | (block[6] as u32) << 16 | (block[7] as u32) << 24;
+
+    let mut texels = [palette[0]; 16];
+    for (index, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indexes >> (index * 2)) & 0b11) as usize];
+    }
+    texels
+}
+
+fn decode_alpha_block(block: &[u8]) -> [u8; 16] {
+    let a0 = block[0] as u16;
+    let a1 = block[1] as u16;
+
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 0];
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as u16) * a0 + i as u16 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as u16) * a0 + i as u16 * a1) / 5;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    let mut bits = 0u64;
+    for (index, byte) in block[2..8].iter().enumerate() {
+        bits |= (*byte as u64) << (index * 8);
+    }
+
+    let mut alphas = [0u8; 16];
+    for (index, alpha) in alphas.iter_mut().enumerate() {
+        *alpha = palette[((bits >> (index * 3)) & 0b111) as usize] as u8;
+    }
+    alphas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress_dxt1() {
+        // pure red and pure blue endpoints, first row uses all four palette entries
+        let block = [0x00, 0xF8, 0x1F, 0x00, 0b11_10_01_00, 0, 0, 0];
+        let pixels = decompress(&Compression::DXT1, 4, 4, &block).unwrap();
+
+        assert_eq!(pixels[0], RGBA8 { r: 255, g: 0, b: 0, a: 255 });
+        assert_eq!(pixels[1], RGBA8 { r: 0, g: 0, b: 255, a: 255 });
+        assert_eq!(pixels[2], RGBA8 { r: 170, g: 0, b: 85, a: 255 });
+        assert_eq!(pixels[3], RGBA8 { r: 85, g: 0, b: 170, a: 255 });
+        assert_eq!(pixels[4], pixels[0]);
+    }
+
+    #[test]
+    fn test_decompress_dxt5_alpha() {
+        let mut block = [0u8; 16];
+        block[0] = 255;
+        block[1] = 0;
+        block[2] = 0b001_000; // texel 0 -> a0, texel 1 -> a1
+        let pixels = decompress(&Compression::DXT5, 2, 2, &block).unwrap();
+
+        assert_eq!(pixels.len(), 4);
+        assert_eq!(pixels[0].a, 255);
+        assert_eq!(pixels[1].a, 0);
+        assert_eq!(pixels[2].a, 255);
+    }
+}
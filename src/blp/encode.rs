@@ -0,0 +1,188 @@
+use std::io::{self, Write};
+#[cfg(feature = "fs")]
+use std::io::BufWriter;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use byteorder::{WriteBytesExt, LE};
+use rgb::RGBA8;
+use misc::Blob;
+use write_ext::WriteExt;
+use blp::{Image, ImageData, Compression, PALETTE_SIZE};
+use blp::mipmaps::{self, MipFilter, MAX_MIPMAP_LEVELS};
+
+// magic, compression, 4 flag bytes, dimensions and the two mipmap tables
+const BLP2_HEADER_SIZE: u32 = 4 + 4 + 4 + 8 + 4 * MAX_MIPMAP_LEVELS as u32 * 2;
+// BLP2 always carries a palette, even if the encoding doesn't use it
+const BLP2_PALETTE_SIZE: u32 = PALETTE_SIZE as u32 * 4;
+
+impl Image {
+    // truecolor image with the full mip chain generated from `pixels`
+    pub fn from_rgba(width: u32, height: u32, pixels: Vec<RGBA8>, filter: MipFilter) -> io::Result<Image> {
+        Ok(Image {
+            width,
+            height,
+            data: ImageData::TrueColor {
+                mipmaps: mipmaps::generate_mipmaps(width, height, &pixels, filter)?,
+            },
+        })
+    }
+
+    // replaces the mip chain with one generated from the top level; compressed
+    // and indexed images are converted to truecolor in the process
+    pub fn regenerate_mipmaps(&mut self, filter: MipFilter) -> io::Result<()> {
+        let pixels = self.decode_mipmap(0)?;
+        self.data = ImageData::TrueColor {
+            mipmaps: mipmaps::generate_mipmaps(self.width, self.height, &pixels, filter)?,
+        };
+        Ok(())
+    }
+
+    pub fn write_blp<W: Write>(&self, mut output: &mut W) -> io::Result<()> {
+        let mipmap_count = self.mipmap_count();
+        if mipmap_count == 0 || mipmap_count > MAX_MIPMAP_LEVELS {
+            return Err(io_error!(InvalidInput, "BLP can't hold {} mipmaps", mipmap_count));
+        }
+
+        let (encoding, alpha_depth, preferred_format) = match self.data {
+            ImageData::Indexed { ref mipmaps, full_alpha, .. } => {
+                let has_alpha = mipmaps.iter().all(|mipmap| mipmap.alpha_values.is_some());
+                (1u8, if !has_alpha { 0 } else if full_alpha { 8 } else { 1 }, 0u8)
+            }
+            ImageData::Compressed { compression, .. } => match compression {
+                Compression::DXT1 => (2, 1, 0),
+                Compression::DXT3 => (2, 8, 1),
+                Compression::DXT5 => (2, 8, 7),
+            },
+            ImageData::TrueColor { .. } => (3, 8, 8),
+            ImageData::Jpeg { .. } => {
+                return Err(io_error!(InvalidInput, "writing jpeg content is not supported"));
+            }
+        };
+
+        let blocks = self.encode_mipmaps(alpha_depth);
+
+        let content = 1u32; // direct, not jpeg
+        let has_mipmaps = if mipmap_count > 1 { 1u8 } else { 0 };
+        let (width, height) = (self.width, self.height);
+
+        output.write_all(b"BLP2")?;
+        let_write! { LE | output =>
+            content: u32;
+            encoding: u8;
+            alpha_depth: u8;
+            preferred_format: u8;
+            has_mipmaps: u8;
+            width: u32;
+            height: u32;
+        }
+
+        let mut offset = BLP2_HEADER_SIZE + BLP2_PALETTE_SIZE;
+        for index in 0..MAX_MIPMAP_LEVELS {
+            match blocks.get(index) {
+                Some(block) => {
+                    output.write_u32::<LE>(offset)?;
+                    offset += block.len() as u32;
+                }
+                None => output.write_u32::<LE>(0)?,
+            }
+        }
+        for index in 0..MAX_MIPMAP_LEVELS {
+            output.write_u32::<LE>(blocks.get(index).map_or(0, |block| block.len() as u32))?;
+        }
+
+        match self.data {
+            ImageData::Indexed { ref palette, .. } => {
+                for color in palette.0.iter() {
+                    output.write_u8tuple4((color.b, color.g, color.r, 0))?;
+                }
+            }
+            _ => output.write_all(&[0; BLP2_PALETTE_SIZE as usize])?,
+        }
+
+        for block in blocks.iter() {
+            output.write_all(block)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn save_blp<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut output = BufWriter::new(File::create(path)?);
+        self.write_blp(&mut output)?;
+        output.flush()
+    }
+
+    fn encode_mipmaps(&self, alpha_depth: u8) -> Vec<Blob<u8>> {
+        match self.data {
+            ImageData::Compressed { ref mipmaps, .. } => mipmaps.clone(),
+            ImageData::TrueColor { ref mipmaps } => {
+                mipmaps.iter().map(|mipmap| {
+                    let mut block = Vec::with_capacity(mipmap.len() * 4);
+                    for pixel in mipmap.iter() {
+                        block.extend_from_slice(&[pixel.b, pixel.g, pixel.r, pixel.a]);
+                    }
+                    Blob(block)
+                }).collect()
+            }
+            ImageData::Indexed { ref mipmaps, .. } => {
+                mipmaps.iter().map(|mipmap| {
+                    let mut block = mipmap.indexes.0.clone();
+                    if let Some(ref alpha_values) = mipmap.alpha_values {
+                        pack_alpha(alpha_values, alpha_depth, &mut block);
+                    }
+                    Blob(block)
+                }).collect()
+            }
+            ImageData::Jpeg { .. } => unreachable!(),
+        }
+    }
+}
+
+fn pack_alpha(alpha_values: &[u8], alpha_depth: u8, target: &mut Vec<u8>) {
+    match alpha_depth {
+        8 => target.extend_from_slice(alpha_values),
+        1 => {
+            for bits in alpha_values.chunks(8) {
+                let byte = bits.iter().enumerate()
+                    .filter(|&(_, &alpha)| alpha >= 0x80)
+                    .fold(0u8, |byte, (bit, _)| byte | (1 << bit));
+                target.push(byte);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use rgb::RGBA8;
+    use blp::{self, Image, ImageData};
+    use blp::mipmaps::MipFilter;
+
+    #[test]
+    fn test_write_blp_roundtrip() {
+        let pixels: Vec<RGBA8> = (0..64u8).map(|i| RGBA8 { r: i * 4, g: 255 - i, b: i, a: 255 }).collect();
+        let image = Image::from_rgba(16, 4, pixels.clone(), MipFilter::Box).unwrap();
+        assert_eq!(image.mipmap_count(), 5);
+
+        let mut output = Vec::new();
+        image.write_blp(&mut output).unwrap();
+
+        let mut input = Cursor::new(&output);
+        let header = blp::read_header(&mut input).unwrap();
+        assert_eq!(header.mipmap_count(), 5);
+        assert_eq!(header.mipmap_blocks[4], (header.mipmap_blocks[3].0 + 2 * 4, 4));
+
+        let loaded = header.read_image(&mut input).unwrap();
+        assert_eq!((loaded.width, loaded.height), (16, 4));
+        match loaded.data {
+            ImageData::TrueColor { ref mipmaps } => assert_eq!(mipmaps[0].0, pixels),
+            _ => panic!("expected truecolor data"),
+        }
+        assert_eq!(loaded.decode_mipmap(4).unwrap().len(), 1);
+    }
+}
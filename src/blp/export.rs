@@ -0,0 +1,177 @@
+use std::io::{self, Write};
+#[cfg(feature = "fs")]
+use std::io::BufWriter;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use byteorder::{WriteBytesExt, LE};
+use blp::{Image, ImageData, Compression};
+use blp::dxt;
+
+const DDS_HEADER_SIZE: u32 = 124;
+const DDS_PIXEL_FORMAT_SIZE: u32 = 32;
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_LINEARSIZE: u32 = 0x80000;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x400000;
+
+impl Image {
+    // compressed images are written as is, everything else is decoded into A8R8G8B8
+    pub fn write_dds<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let mipmap_count = self.mipmap_count() as u32;
+
+        let (fourcc, pitch_or_linear_size) = match self.data {
+            ImageData::Compressed { ref compression, .. } => {
+                let fourcc = match *compression {
+                    Compression::DXT1 => b"DXT1",
+                    Compression::DXT3 => b"DXT3",
+                    Compression::DXT5 => b"DXT5",
+                };
+                (Some(fourcc), dxt::compressed_size(compression, self.width, self.height) as u32)
+            }
+            _ => (None, self.width * 4),
+        };
+
+        let mut flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | DDSD_MIPMAPCOUNT;
+        flags |= if fourcc.is_some() { DDSD_LINEARSIZE } else { DDSD_PITCH };
+
+        let mut caps = DDSCAPS_TEXTURE;
+        if mipmap_count > 1 { caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP }
+
+        output.write_all(b"DDS ")?;
+        output.write_u32::<LE>(DDS_HEADER_SIZE)?;
+        output.write_u32::<LE>(flags)?;
+        output.write_u32::<LE>(self.height)?;
+        output.write_u32::<LE>(self.width)?;
+        output.write_u32::<LE>(pitch_or_linear_size)?;
+        output.write_u32::<LE>(0)?; // depth
+        output.write_u32::<LE>(mipmap_count)?;
+        for _ in 0..11 { output.write_u32::<LE>(0)? }
+
+        output.write_u32::<LE>(DDS_PIXEL_FORMAT_SIZE)?;
+        if let Some(fourcc) = fourcc {
+            output.write_u32::<LE>(DDPF_FOURCC)?;
+            output.write_all(fourcc)?;
+            for _ in 0..5 { output.write_u32::<LE>(0)? }
+        } else {
+            output.write_u32::<LE>(DDPF_RGB | DDPF_ALPHAPIXELS)?;
+            output.write_u32::<LE>(0)?;
+            output.write_u32::<LE>(32)?;
+            output.write_u32::<LE>(0x00FF0000)?;
+            output.write_u32::<LE>(0x0000FF00)?;
+            output.write_u32::<LE>(0x000000FF)?;
+            output.write_u32::<LE>(0xFF000000)?;
+        }
+
+        output.write_u32::<LE>(caps)?;
+        for _ in 0..4 { output.write_u32::<LE>(0)? }
+
+        match self.data {
+            ImageData::Compressed { ref mipmaps, .. } => {
+                for mipmap in mipmaps {
+                    output.write_all(mipmap)?;
+                }
+            }
+            _ => {
+                for level in 0..self.mipmap_count() {
+                    for pixel in self.decode_mipmap(level)?.iter() {
+                        output.write_all(&[pixel.b, pixel.g, pixel.r, pixel.a])?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn save_dds<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut output = BufWriter::new(File::create(path)?);
+        self.write_dds(&mut output)?;
+        output.flush()
+    }
+}
+
+#[cfg(feature = "image")]
+mod dynamic_image {
+    use std::io;
+    #[cfg(feature = "fs")]
+    use std::path::Path;
+    use image::{DynamicImage, RgbaImage};
+    use rgb::RGBA8;
+    use blp::Image;
+    use blp::mipmaps::MipFilter;
+
+    impl Image {
+        // converts an external image into a truecolor BLP image with a full mip chain
+        pub fn from_dynamic_image(source: &DynamicImage, filter: MipFilter) -> io::Result<Image> {
+            let source = source.to_rgba8();
+            let (width, height) = source.dimensions();
+            let pixels = source.pixels()
+                .map(|pixel| RGBA8 { r: pixel[0], g: pixel[1], b: pixel[2], a: pixel[3] })
+                .collect();
+
+            Image::from_rgba(width, height, pixels, filter)
+        }
+
+        pub fn to_dynamic_image(&self) -> io::Result<DynamicImage> {
+            let pixels = self.decode_mipmap(0)?;
+
+            let mut raw = Vec::with_capacity(pixels.len() * 4);
+            for pixel in pixels.iter() {
+                raw.extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+            }
+
+            RgbaImage::from_raw(self.width, self.height, raw)
+                .map(DynamicImage::ImageRgba8)
+                .ok_or_else(|| io_error!(InvalidData, "pixel buffer doesn't match image dimensions"))
+        }
+
+        #[cfg(feature = "fs")]
+        pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+            self.to_dynamic_image()?
+                .save_with_format(path, ::image::ImageFormat::Png)
+                .map_err(|e| io_error!(Other, e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use misc::Blob;
+    use blp::{Image, ImageData, Compression};
+
+    #[test]
+    fn test_write_dds() {
+        let image = Image {
+            width: 4,
+            height: 4,
+            data: ImageData::Compressed {
+                compression: Compression::DXT1,
+                mipmaps: vec![Blob(vec![0xAB; 8]), Blob(vec![0xCD; 8]), Blob(vec![0xEF; 8])],
+            },
+        };
+
+        let mut output = Vec::new();
+        image.write_dds(&mut output).unwrap();
+
+        assert_eq!(output.len(), 4 + 124 + 3 * 8);
+        assert_eq!(&output[0..4], b"DDS ");
+        assert_eq!(&output[84..88], b"DXT1");
+        assert_eq!(output[28], 3); // mipmap count
+        assert_eq!(&output[128..136], &[0xAB; 8]);
+    }
+}
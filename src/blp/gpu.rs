@@ -0,0 +1,145 @@
+use std::borrow::Cow;
+use std::io;
+use blp::{Image, ImageData, Header, Content, Compression};
+use blp::dxt;
+
+// texture formats the image data can be uploaded as without further conversion
+// (uncompressed content is expanded to rgba8 first)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GpuFormat {
+    Bc1RgbaUnorm,
+    Bc2RgbaUnorm,
+    Bc3RgbaUnorm,
+    Rgba8Unorm,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GpuMipmap {
+    pub width: u32,
+    pub height: u32,
+    pub bytes_per_row: u32,
+    pub rows: u32,
+    pub size: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GpuLayout {
+    pub format: GpuFormat,
+    pub mipmaps: Vec<GpuMipmap>,
+}
+
+impl GpuFormat {
+    pub fn is_compressed(&self) -> bool {
+        *self != GpuFormat::Rgba8Unorm
+    }
+
+    // block dimensions in texels and block size in bytes
+    pub fn block_layout(&self) -> (u32, usize) {
+        match *self {
+            GpuFormat::Bc1RgbaUnorm => (4, 8),
+            GpuFormat::Bc2RgbaUnorm | GpuFormat::Bc3RgbaUnorm => (4, 16),
+            GpuFormat::Rgba8Unorm => (1, 4),
+        }
+    }
+
+    pub fn mipmap(&self, width: u32, height: u32) -> GpuMipmap {
+        let (block_side, block_size) = self.block_layout();
+        let blocks_x = width.div_ceil(block_side);
+        let blocks_y = height.div_ceil(block_side);
+
+        GpuMipmap {
+            width,
+            height,
+            bytes_per_row: blocks_x * block_size as u32,
+            rows: blocks_y,
+            size: (blocks_x * blocks_y) as usize * block_size,
+        }
+    }
+
+    fn from_compression(compression: Compression) -> Self {
+        match compression {
+            Compression::DXT1 => GpuFormat::Bc1RgbaUnorm,
+            Compression::DXT3 => GpuFormat::Bc2RgbaUnorm,
+            Compression::DXT5 => GpuFormat::Bc3RgbaUnorm,
+        }
+    }
+}
+
+impl Header {
+    pub fn gpu_format(&self) -> GpuFormat {
+        match self.content {
+            Content::Compressed { compression } => GpuFormat::from_compression(compression),
+            _ => GpuFormat::Rgba8Unorm,
+        }
+    }
+}
+
+impl Image {
+    pub fn gpu_format(&self) -> GpuFormat {
+        match self.data {
+            ImageData::Compressed { compression, .. } => GpuFormat::from_compression(compression),
+            _ => GpuFormat::Rgba8Unorm,
+        }
+    }
+
+    pub fn gpu_layout(&self) -> GpuLayout {
+        let format = self.gpu_format();
+        let mipmaps = (0..self.mipmap_count())
+            .map(|level| {
+                let (width, height) = self.mipmap_size(level);
+                format.mipmap(width, height)
+            })
+            .collect();
+
+        GpuLayout { format, mipmaps }
+    }
+
+    // bytes of a single mipmap level in `gpu_format()`; compressed data is borrowed as is
+    pub fn gpu_mipmap_data(&self, level: usize) -> io::Result<Cow<'_, [u8]>> {
+        if let ImageData::Compressed { compression, ref mipmaps } = self.data {
+            let mipmap = mipmaps.get(level)
+                .ok_or_else(|| io_error!(InvalidInput, "mipmap level {} is out of range", level))?;
+
+            let (width, height) = self.mipmap_size(level);
+            let expected = dxt::compressed_size(&compression, width, height);
+            if mipmap.len() < expected {
+                return Err(io_error!(InvalidData, "mipmap {} has {} bytes, expected {}", level, mipmap.len(), expected));
+            }
+
+            return Ok(Cow::Borrowed(&mipmap[..expected]));
+        }
+
+        let pixels = self.decode_mipmap(level)?;
+        let mut bytes = Vec::with_capacity(pixels.len() * 4);
+        for pixel in pixels.iter() {
+            bytes.extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+        }
+        Ok(Cow::Owned(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use misc::Blob;
+
+    #[test]
+    fn test_gpu_layout() {
+        let image = Image {
+            width: 8,
+            height: 2,
+            data: ImageData::Compressed {
+                compression: Compression::DXT5,
+                mipmaps: vec![Blob(vec![0; 32]), Blob(vec![0; 16]), Blob(vec![0; 16])],
+            },
+        };
+
+        let layout = image.gpu_layout();
+        assert_eq!(layout.format, GpuFormat::Bc3RgbaUnorm);
+        assert_eq!(layout.mipmaps[0], GpuMipmap { width: 8, height: 2, bytes_per_row: 32, rows: 1, size: 32 });
+        assert_eq!(layout.mipmaps[2], GpuMipmap { width: 2, height: 1, bytes_per_row: 16, rows: 1, size: 16 });
+        assert_eq!(image.gpu_mipmap_data(1).unwrap().len(), 16);
+
+        assert_eq!(GpuFormat::Rgba8Unorm.mipmap(3, 5).size, 60);
+    }
+}
@@ -0,0 +1,211 @@
+use std::f32::consts::PI;
+use std::io;
+use rgb::RGBA8;
+use misc::Blob;
+
+// the mipmap table in BLP headers has room for 16 levels
+pub const MAX_MIPMAP_LEVELS: usize = 16;
+
+// kaiser window parameters; support is given in destination pixels
+const KAISER_ALPHA: f32 = 4.0;
+const KAISER_SUPPORT: f32 = 3.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MipFilter {
+    // plain 2x2 average
+    Box,
+    // kaiser-windowed sinc; sharper, at the cost of some ringing
+    Kaiser,
+}
+
+// number of levels in a full chain down to 1x1, limited by the header table
+pub fn full_chain_length(width: u32, height: u32) -> usize {
+    let largest = width.max(height).max(1);
+    let levels = 32 - largest.leading_zeros() as usize;
+    levels.min(MAX_MIPMAP_LEVELS)
+}
+
+// builds the complete mip chain; the first level is the source image itself
+pub fn generate_mipmaps(width: u32, height: u32, pixels: &[RGBA8], filter: MipFilter) -> io::Result<Vec<Blob<RGBA8>>> {
+    if width == 0 || height == 0 || pixels.len() as u64 != width as u64 * height as u64 {
+        return Err(io_error!(InvalidInput, "pixel buffer doesn't match image dimensions {}x{}", width, height));
+    }
+
+    let levels = full_chain_length(width, height);
+    let mut mipmaps = Vec::with_capacity(levels);
+    mipmaps.push(Blob(pixels.to_vec()));
+
+    let (mut src_width, mut src_height) = (width, height);
+
+    for _ in 1..levels {
+        let dst_width = (src_width / 2).max(1);
+        let dst_height = (src_height / 2).max(1);
+
+        let next = downsample(&mipmaps[mipmaps.len() - 1], src_width, src_height, dst_width, dst_height, filter);
+        mipmaps.push(Blob(next));
+
+        src_width = dst_width;
+        src_height = dst_height;
+    }
+
+    Ok(mipmaps)
+}
+
+// taps of a single destination pixel along one axis: (source index, weight)
+fn filter_taps(dst: u32, src_size: u32, dst_size: u32, filter: MipFilter) -> Vec<(usize, f32)> {
+    if src_size == dst_size {
+        return vec![(dst as usize, 1.0)];
+    }
+
+    let scale = src_size as f32 / dst_size as f32;
+    let center = (dst as f32 + 0.5) * scale;
+
+    let mut taps = match filter {
+        MipFilter::Box => {
+            let first = (dst as f32 * scale) as usize;
+            let last = (((dst + 1) as f32 * scale).ceil() as usize).min(src_size as usize);
+            (first..last).map(|index| (index, 1.0)).collect::<Vec<_>>()
+        }
+        MipFilter::Kaiser => {
+            let radius = KAISER_SUPPORT * scale;
+            let first = (center - radius).floor() as i64;
+            let last = (center + radius).ceil() as i64;
+
+            (first..(last + 1)).filter_map(|index| {
+                let distance = (index as f32 + 0.5 - center) / scale;
+                let weight = sinc(distance) * kaiser(distance / KAISER_SUPPORT);
+                if weight == 0.0 {
+                    return None;
+                }
+                // samples outside of the image are clamped to the edge
+                let index = index.max(0).min(src_size as i64 - 1) as usize;
+                Some((index, weight))
+            }).collect()
+        }
+    };
+
+    let total: f32 = taps.iter().map(|&(_, weight)| weight).sum();
+    for tap in taps.iter_mut() {
+        tap.1 /= total;
+    }
+
+    taps
+}
+
+// separable resampling; color is weighted by alpha so transparent texels don't bleed in
+fn downsample(pixels: &[RGBA8], src_width: u32, src_height: u32, dst_width: u32, dst_height: u32, filter: MipFilter) -> Vec<RGBA8> {
+    let premultiplied: Vec<[f32; 4]> = pixels.iter().map(|pixel| {
+        let alpha = pixel.a as f32 / 255.0;
+        [pixel.r as f32 * alpha, pixel.g as f32 * alpha, pixel.b as f32 * alpha, pixel.a as f32]
+    }).collect();
+
+    let columns: Vec<_> = (0..dst_width).map(|x| filter_taps(x, src_width, dst_width, filter)).collect();
+    let rows: Vec<_> = (0..dst_height).map(|y| filter_taps(y, src_height, dst_height, filter)).collect();
+
+    let mut horizontal = vec![[0.0f32; 4]; (dst_width * src_height) as usize];
+    for y in 0..src_height as usize {
+        for (x, taps) in columns.iter().enumerate() {
+            let target = &mut horizontal[y * dst_width as usize + x];
+            for &(index, weight) in taps {
+                accumulate(target, &premultiplied[y * src_width as usize + index], weight);
+            }
+        }
+    }
+
+    let mut result = Vec::with_capacity((dst_width * dst_height) as usize);
+    for taps in rows.iter() {
+        for x in 0..dst_width as usize {
+            let mut value = [0.0f32; 4];
+            for &(index, weight) in taps {
+                accumulate(&mut value, &horizontal[index * dst_width as usize + x], weight);
+            }
+            result.push(unpremultiply(value));
+        }
+    }
+
+    result
+}
+
+fn accumulate(target: &mut [f32; 4], value: &[f32; 4], weight: f32) {
+    for channel in 0..4 {
+        target[channel] += value[channel] * weight;
+    }
+}
+
+fn unpremultiply(value: [f32; 4]) -> RGBA8 {
+    let alpha = value[3].clamp(0.0, 255.0);
+    let factor = if alpha > 0.0 { 255.0 / alpha } else { 0.0 };
+    let channel = |v: f32| (v * factor).round().clamp(0.0, 255.0) as u8;
+
+    RGBA8 {
+        r: channel(value[0]),
+        g: channel(value[1]),
+        b: channel(value[2]),
+        a: alpha.round() as u8,
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// window over -1..1
+fn kaiser(x: f32) -> f32 {
+    if x.abs() > 1.0 {
+        return 0.0;
+    }
+    bessel_i0(KAISER_ALPHA * (1.0 - x * x).sqrt()) / bessel_i0(KAISER_ALPHA)
+}
+
+// zeroth order modified bessel function of the first kind (power series)
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+
+    for k in 1..32 {
+        term *= (half / k as f32) * (half / k as f32);
+        sum += term;
+        if term < sum * 1e-8 {
+            break;
+        }
+    }
+
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_mipmaps() {
+        assert_eq!(full_chain_length(256, 64), 9);
+        assert_eq!(full_chain_length(1, 1), 1);
+        assert_eq!(full_chain_length(1 << 20, 1), MAX_MIPMAP_LEVELS);
+
+        let white = RGBA8 { r: 255, g: 255, b: 255, a: 255 };
+        let clear = RGBA8 { r: 0, g: 0, b: 0, a: 0 };
+        let pixels: Vec<RGBA8> = (0..32).map(|i| if i % 2 == 0 { white } else { clear }).collect();
+
+        for &filter in &[MipFilter::Box, MipFilter::Kaiser] {
+            let mipmaps = generate_mipmaps(8, 4, &pixels, filter).unwrap();
+            let sizes: Vec<usize> = mipmaps.iter().map(|level| level.len()).collect();
+            assert_eq!(sizes, vec![32, 8, 2, 1]);
+
+            // transparent texels must not darken the color
+            let last = mipmaps[3][0];
+            assert_eq!((last.r, last.g, last.b), (255, 255, 255));
+            assert!((last.a as i32 - 128).abs() <= 2);
+        }
+
+        assert!(generate_mipmaps(8, 8, &pixels, MipFilter::Box).is_err());
+        assert!(generate_mipmaps(0, 4, &[], MipFilter::Box).is_err());
+        // the pixel count doesn't fit in a u32
+        assert!(generate_mipmaps(0x10000, 0x10000, &[], MipFilter::Box).is_err());
+    }
+}
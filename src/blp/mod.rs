@@ -0,0 +1,641 @@
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use byteorder::{ReadBytesExt, LittleEndian as LE};
+use rgb::{RGB8, RGBA8};
+use misc::Blob;
+use read_ext::ReadExt;
+use reader::ResourceReader;
+use error::{Error, Result};
+use asset::{Asset, LoadOptions};
+use limits::Limits;
+
+pub mod compose;
+pub mod dxt;
+pub mod encode;
+pub mod export;
+pub mod gpu;
+pub mod mipmaps;
+pub mod validate;
+
+const PALETTE_SIZE: usize = 256;
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct Palette([RGB8; PALETTE_SIZE]);
+
+impl fmt::Debug for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&&self.0[..], f)
+    }
+}
+
+// serde derives stop at 32 element arrays, so the palette goes as a sequence
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Palette {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        ::serde::Serialize::serialize(&self.0[..], serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for Palette {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> ::std::result::Result<Palette, D::Error> {
+        let colors: Vec<RGB8> = ::serde::Deserialize::deserialize(deserializer)?;
+        if colors.len() != PALETTE_SIZE {
+            return Err(::serde::de::Error::invalid_length(colors.len(), &"256 palette colors"));
+        }
+        let mut palette = [RGB8::default(); PALETTE_SIZE];
+        palette.copy_from_slice(&colors);
+        Ok(Palette(palette))
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IndexedPixels {
+    pub indexes: Blob<u8>,
+    pub alpha_values: Option<Blob<u8>>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Compression {
+    DXT1,
+    DXT3,
+    DXT5,
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ImageData {
+    TrueColor {
+        mipmaps: Vec<Blob<RGBA8>>,
+    },
+    Indexed {
+        palette: Palette,
+        full_alpha: bool,
+        mipmaps: Vec<IndexedPixels>,
+    },
+    Compressed {
+        compression: Compression,
+        mipmaps: Vec<Blob<u8>>,
+    },
+    // every mipmap is a complete JPEG stream (shared header + mipmap block);
+    // only produced when the crate is built without the `jpeg` feature
+    Jpeg {
+        mipmaps: Vec<Blob<u8>>,
+    },
+}
+
+// how mipmap blocks are encoded, along with the data shared between them
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Content {
+    Jpeg {
+        header: Blob<u8>,
+    },
+    Indexed {
+        // boxed, as it's far bigger than the other variants
+        palette: Box<Palette>,
+    },
+    Compressed {
+        compression: Compression,
+    },
+    TrueColor,
+}
+
+// everything preceding the mipmap data; enough to read any single mipmap
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Header {
+    pub height: u32,
+    pub width: u32,
+    pub alpha_depth: u8,
+    pub content: Content,
+    pub mipmap_blocks: Vec<(u32, u32)>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Image {
+    pub height: u32,
+    pub width: u32,
+    pub data: ImageData,
+}
+
+
+impl Image {
+    pub fn mipmap_count(&self) -> usize {
+        match self.data {
+            ImageData::TrueColor { ref mipmaps } => mipmaps.len(),
+            ImageData::Indexed { ref mipmaps, .. } => mipmaps.len(),
+            ImageData::Compressed { ref mipmaps, .. } => mipmaps.len(),
+            ImageData::Jpeg { ref mipmaps } => mipmaps.len(),
+        }
+    }
+
+    pub fn mipmap_size(&self, level: usize) -> (u32, u32) {
+        mipmap_dimensions(self.width, self.height, level)
+    }
+
+    pub fn decode_mipmap(&self, level: usize) -> Result<Blob<RGBA8>> {
+        if level >= self.mipmap_count() {
+            return Err(Error::OutOfRange { what: "mipmap level", index: level, count: self.mipmap_count() });
+        }
+
+        let (width, height) = self.mipmap_size(level);
+
+        let pixels = match self.data {
+            ImageData::TrueColor { ref mipmaps } => {
+                mipmaps[level].0.clone()
+            }
+            ImageData::Indexed { ref palette, ref mipmaps, .. } => {
+                let mipmap = &mipmaps[level];
+
+                mipmap.indexes.iter().enumerate().map(|(index, color_index)| {
+                    let color = palette.0[*color_index as usize];
+                    let alpha = mipmap.alpha_values
+                        .as_ref()
+                        .and_then(|values| values.get(index).cloned())
+                        .unwrap_or(0xFF);
+
+                    RGBA8 { r: color.r, g: color.g, b: color.b, a: alpha }
+                }).collect()
+            }
+            ImageData::Compressed { ref compression, ref mipmaps } => {
+                dxt::decompress(compression, width, height, &mipmaps[level])?
+            }
+            ImageData::Jpeg { .. } => {
+                return Err(Error::FeatureDisabled("jpeg"));
+            }
+        };
+
+        Ok(Blob(pixels))
+    }
+}
+
+#[allow(dead_code)]
+pub fn load(reader: Arc<dyn ResourceReader>, name: &str) -> Result<Image> {
+    read(&mut reader.open(name)?)
+}
+
+impl Asset for Image {
+    const EXTENSIONS: &'static [&'static str] = &[".blp"];
+
+    fn load(reader: &dyn ResourceReader, name: &str, options: &LoadOptions) -> Result<Image> {
+        options.apply(name, || read(&mut reader.open(name)?))
+    }
+}
+
+// a whole image with all mipmaps, from any seekable source
+pub fn read<R: Read + Seek>(input: &mut R) -> Result<Image> {
+    let header = read_header(input)?;
+    header.read_image(input)
+}
+
+pub fn load_header(reader: Arc<dyn ResourceReader>, name: &str) -> Result<Header> {
+    let mut input = reader.open(name)?;
+    read_header(&mut input)
+}
+
+pub fn read_header<R: Read + Seek>(input: &mut R) -> Result<Header> {
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+
+    match &magic {
+        b"BLP1" => Ok(read_blp1_header(input)?),
+        b"BLP2" => Ok(read_blp2_header(input)?),
+        _ => Err(Error::BadMagic { format: "BLP", found: magic }),
+    }
+}
+
+impl Header {
+    pub fn mipmap_count(&self) -> usize {
+        self.mipmap_blocks.len()
+    }
+
+    pub fn mipmap_size(&self, level: usize) -> (u32, u32) {
+        mipmap_dimensions(self.width, self.height, level)
+    }
+
+    // reads every mipmap level
+    pub fn read_image<R: Read + Seek>(&self, input: &mut R) -> Result<Image> {
+        Ok(self.read_levels(input, 0..self.mipmap_count(), self.width, self.height)?)
+    }
+
+    // reads a single mipmap level; the result is an image of that level's size
+    // with exactly one mipmap
+    pub fn read_mipmap<R: Read + Seek>(&self, input: &mut R, level: usize) -> Result<Image> {
+        if level >= self.mipmap_count() {
+            return Err(Error::OutOfRange { what: "mipmap level", index: level, count: self.mipmap_count() });
+        }
+
+        let (width, height) = self.mipmap_size(level);
+        Ok(self.read_levels(input, level..(level + 1), width, height)?)
+    }
+
+    fn read_levels<R, L>(&self, input: &mut R, levels: L, width: u32, height: u32) -> io::Result<Image>
+    where
+        R: Read + Seek,
+        L: Iterator<Item = usize>
+    {
+        let data = match self.content {
+            Content::Jpeg { ref header } => {
+                let mut mipmaps = Vec::new();
+                for level in levels {
+                    self.check_mipmap(level)?;
+                    let (offset, size) = self.mipmap_blocks[level];
+                    mipmaps.push(read_jpeg_mipmap(input, header, offset, size)?);
+                }
+                jpeg_image_data(mipmaps, self.alpha_depth)?
+            }
+            Content::Indexed { ref palette } => {
+                let mut mipmaps = Vec::new();
+                for level in levels {
+                    self.check_mipmap(level)?;
+                    let (offset, size) = self.mipmap_blocks[level];
+                    let (mip_width, mip_height) = self.mipmap_size(level);
+                    mipmaps.push(read_indexed_mipmap(input, offset, size, mip_width, mip_height, self.alpha_depth)?);
+                }
+                ImageData::Indexed {
+                    palette: (**palette).clone(),
+                    full_alpha: self.alpha_depth > 1,
+                    mipmaps
+                }
+            }
+            Content::Compressed { compression } => {
+                let mut mipmaps = Vec::new();
+                for level in levels {
+                    self.check_mipmap(level)?;
+                    let (offset, size) = self.mipmap_blocks[level];
+                    trace!("reading {} bytes at {} offset", size, offset);
+                    input.seek(SeekFrom::Start(offset as u64))?;
+                    mipmaps.push(Blob(input.read_vec(size as usize)?));
+                }
+                ImageData::Compressed { compression, mipmaps }
+            }
+            Content::TrueColor => {
+                let mut mipmaps = Vec::new();
+                for level in levels {
+                    self.check_mipmap(level)?;
+                    let (offset, _) = self.mipmap_blocks[level];
+                    let (mip_width, mip_height) = self.mipmap_size(level);
+                    mipmaps.push(read_truecolor_mipmap(input, offset, mip_width, mip_height)?);
+                }
+                ImageData::TrueColor { mipmaps }
+            }
+        };
+
+        Ok(Image {
+            height,
+            width,
+            data
+        })
+    }
+
+    // before anything is allocated for `level`
+    fn check_mipmap(&self, level: usize) -> io::Result<()> {
+        let limits = Limits::current();
+        let (width, height) = self.mipmap_size(level);
+        limits.check_mipmap_size(self.mipmap_blocks[level].1 as u64)?;
+        limits.check_mipmap_size(width as u64 * height as u64 * 4)
+    }
+}
+
+fn read_blp1_header<R: Read + Seek>(mut input: &mut R) -> io::Result<Header> {
+    let_read! { LE | input =>
+        compression: u32;
+        alpha_depth: u32;
+        width: u32;
+        height: u32;
+        picture_type: u32;
+        has_mipmaps: u32;
+    }
+
+    if alpha_depth != 0 && alpha_depth != 1 && alpha_depth != 4 && alpha_depth != 8 {
+        report_inconsistency!("Trying to load BLP with unsupported alpha depth: {}", alpha_depth);
+    }
+
+    let mipmap_blocks = read_mipmap_blocks(input)?;
+
+    let content = match compression {
+        //
+        // jpeg
+        //
+        0 => Content::Jpeg { header: read_jpeg_header(input)? },
+        //
+        // uncompressed, indexed
+        //
+        1 => Content::Indexed { palette: Box::new(read_palette(input)?) },
+        _ => return Err(Error::Unsupported { format: "BLP1", field: "compression", found: compression }.into())
+    };
+
+    Ok(Header {
+        height,
+        width,
+        alpha_depth: alpha_depth as u8,
+        content,
+        mipmap_blocks
+    })
+}
+
+fn read_blp2_header<R: Read + Seek>(mut input: &mut R) -> io::Result<Header> {
+    let_read! { LE | input =>
+        compression: u32;
+        encoding: u8;
+        alpha_depth: u8;
+        preferred_format: u8;
+        has_mipmaps: u8;
+        width: u32;
+        height: u32;
+    }
+
+    if compression != 0 && compression != 1 {
+        return Err(Error::Unsupported { format: "BLP2", field: "compression", found: compression }.into());
+    }
+
+    if alpha_depth != 0 && alpha_depth != 1 && alpha_depth != 8 {
+        report_inconsistency!("Trying to load BLP with unsupported alpha depth: {}", alpha_depth);
+    }
+
+    let mipmap_blocks = read_mipmap_blocks(input)?;
+
+    let content = if compression == 0 {
+        Content::Jpeg { header: read_jpeg_header(input)? }
+    } else {
+        match encoding {
+            //
+            // uncompressed, indexed
+            //
+            1 => Content::Indexed { palette: Box::new(read_palette(input)?) },
+            //
+            // compressed, DXTC
+            //
+            2 => {
+                let compression = {
+                    if alpha_depth == 8 && preferred_format == 7 {
+                        Compression::DXT5
+                    } else if alpha_depth == 8 || alpha_depth == 4 {
+                        Compression::DXT3
+                    } else {
+                        Compression::DXT1
+                    }
+                };
+                Content::Compressed { compression }
+            }
+            //
+            // uncompressed, truecolor
+            //
+            3 => Content::TrueColor,
+            _ => return Err(Error::Unsupported { format: "BLP2", field: "encoding", found: encoding as u32 }.into())
+        }
+    };
+
+    Ok(Header {
+        height,
+        width,
+        alpha_depth,
+        content,
+        mipmap_blocks
+    })
+}
+
+fn mipmap_dimensions(width: u32, height: u32, level: usize) -> (u32, u32) {
+    let mip_width = (width >> level).max(1);
+    let mip_height = (height >> level).max(1);
+    (mip_width, mip_height)
+}
+
+fn read_mipmap_blocks<R: Read>(input: &mut R) -> io::Result<Vec<(u32, u32)>> {
+    let mut offsets = [0u32; 16];
+    let mut sizes = [0u32; 16];
+
+    for offset in offsets.iter_mut() {
+        *offset = input.read_u32::<LE>()?;
+    }
+
+    for size in sizes.iter_mut() {
+        *size = input.read_u32::<LE>()?;
+    }
+
+    let mipmap_blocks: Vec<(u32, u32)> = offsets.iter()
+        .take_while(|x| **x != 0)
+        .copied()
+        .zip(sizes.iter().take_while(|x| **x != 0).copied())
+        .collect();
+
+    trace!("mipmap_blocks = {:?}", &mipmap_blocks);
+    Ok(mipmap_blocks)
+}
+
+fn read_palette<R: Read>(input: &mut R) -> io::Result<Palette> {
+    let mut palette = [RGB8::default(); PALETTE_SIZE];
+
+    for entry in palette.iter_mut() {
+        let color = input.read_u8tuple4()?;
+        *entry = RGB8 {
+            b: color.0,
+            g: color.1,
+            r: color.2
+        };
+    }
+
+    Ok(Palette(palette))
+}
+
+// all mipmaps share a single JPEG header stored right after the mipmap table
+fn read_jpeg_header<R: Read>(input: &mut R) -> io::Result<Blob<u8>> {
+    let header_size = input.read_u32::<LE>()?;
+    Limits::current().check_mipmap_size(header_size as u64)?;
+    Ok(Blob(input.read_vec(header_size as usize)?))
+}
+
+fn read_indexed_mipmap<R: Read + Seek>(input: &mut R, offset: u32, size: u32, width: u32, height: u32, alpha_depth: u8) -> io::Result<IndexedPixels> {
+    let pixel_count = width as usize * height as usize;
+
+    input.seek(SeekFrom::Start(offset as u64))?;
+    let mut block = input.take(size as u64);
+
+    let indexes = block.read_vec(pixel_count)?;
+
+    let alpha_values = match alpha_depth {
+        8 => {
+            Some(block.read_vec(pixel_count)?)
+        },
+        4 => {
+            let mut alpha_values = Vec::with_capacity(pixel_count);
+
+            for _ in 0..pixel_count.div_ceil(2) {
+                let byte = block.read_u8()?;
+                alpha_values.push((byte & 0x0F) * 17);
+                alpha_values.push((byte >> 4) * 17);
+            }
+            alpha_values.truncate(pixel_count);
+            Some(alpha_values)
+        },
+        1 => {
+            let mut alpha_values = Vec::with_capacity(pixel_count);
+
+            for _ in 0..pixel_count.div_ceil(8) {
+                let byte = block.read_u8()?;
+                for bit in 0..8 {
+                    if byte & (1 << bit) != 0 {
+                        alpha_values.push(0xFF);
+                    } else {
+                        alpha_values.push(0x00);
+                    }
+                }
+            }
+            alpha_values.truncate(pixel_count);
+            Some(alpha_values)
+        },
+        _ => None
+    };
+
+    Ok(IndexedPixels {
+        indexes: indexes.into(),
+        alpha_values: alpha_values.map(|v| v.into())
+    })
+}
+
+fn read_truecolor_mipmap<R: Read + Seek>(input: &mut R, offset: u32, width: u32, height: u32) -> io::Result<Blob<RGBA8>> {
+    input.seek(SeekFrom::Start(offset as u64))?;
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+
+    for _ in 0..height {
+        for _ in 0..width {
+            let color = input.read_u8tuple4()?;
+            pixels.push(RGBA8 {
+                b: color.0,
+                g: color.1,
+                r: color.2,
+                a: color.3
+            });
+        }
+    }
+
+    Ok(Blob(pixels))
+}
+
+fn read_jpeg_mipmap<R: Read + Seek>(input: &mut R, header: &[u8], offset: u32, size: u32) -> io::Result<Blob<u8>> {
+    trace!("reading {} bytes of jpeg data at {} offset", size, offset);
+    input.seek(SeekFrom::Start(offset as u64))?;
+
+    let mut stream = Vec::with_capacity(header.len() + size as usize);
+    stream.extend_from_slice(header);
+    input.read_vec_into(size as usize, &mut stream)?;
+
+    Ok(Blob(stream))
+}
+
+#[cfg(not(feature = "jpeg"))]
+fn jpeg_image_data(mipmaps: Vec<Blob<u8>>, _alpha_depth: u8) -> io::Result<ImageData> {
+    Ok(ImageData::Jpeg { mipmaps })
+}
+
+#[cfg(feature = "jpeg")]
+fn jpeg_image_data(mipmaps: Vec<Blob<u8>>, alpha_depth: u8) -> io::Result<ImageData> {
+    use jpeg_decoder::{Decoder, PixelFormat};
+
+    let mut decoded = Vec::with_capacity(mipmaps.len());
+
+    for (level, stream) in mipmaps.iter().enumerate() {
+        let mut decoder = Decoder::new(stream.as_slice());
+        let raw = decoder.decode()
+            .map_err(|e| io_error!(InvalidData, "unable to decode jpeg mipmap {}: {}", level, e))?;
+        let info = decoder.info()
+            .ok_or_else(|| io_error!(InvalidData, "jpeg mipmap {} has no frame info", level))?;
+
+        let pixels: Vec<RGBA8> = match info.pixel_format {
+            // blp stores BGRA components without any color transform,
+            // which the decoder treats as (inverted) CMYK
+            PixelFormat::CMYK32 => raw.chunks(4).map(|px| RGBA8 {
+                b: 255 - px[0],
+                g: 255 - px[1],
+                r: 255 - px[2],
+                a: if alpha_depth == 0 { 255 } else { 255 - px[3] }
+            }).collect(),
+            PixelFormat::RGB24 => raw.chunks(3).map(|px| RGBA8 {
+                r: px[0],
+                g: px[1],
+                b: px[2],
+                a: 255
+            }).collect(),
+            PixelFormat::L8 => raw.iter().map(|&l| RGBA8 {
+                r: l,
+                g: l,
+                b: l,
+                a: 255
+            }).collect(),
+            other => return Err(io_error!(InvalidData, "unsupported jpeg pixel format: {:?}", other)),
+        };
+
+        decoded.push(Blob(pixels));
+    }
+
+    Ok(ImageData::TrueColor { mipmaps: decoded })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use byteorder::WriteBytesExt;
+
+    // 2x2 BLP2 truecolor image with two mipmap levels
+    fn truecolor_blp() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"BLP2");
+        data.write_u32::<LE>(1).unwrap();
+        data.extend_from_slice(&[3, 8, 0, 1]);
+        data.write_u32::<LE>(2).unwrap();
+        data.write_u32::<LE>(2).unwrap();
+
+        let header_size = 20 + 16 * 4 * 2;
+        let offsets = [header_size, header_size + 16];
+        let sizes = [16, 4];
+        for index in 0..16 { data.write_u32::<LE>(*offsets.get(index).unwrap_or(&0)).unwrap() }
+        for index in 0..16 { data.write_u32::<LE>(*sizes.get(index).unwrap_or(&0)).unwrap() }
+
+        for pixel in 0..4u8 { data.extend_from_slice(&[pixel, 0, 0, 255]) }
+        data.extend_from_slice(&[9, 8, 7, 6]);
+        data
+    }
+
+    #[test]
+    fn test_read_mipmap() {
+        let mut input = Cursor::new(truecolor_blp());
+        let header = read_header(&mut input).unwrap();
+        assert_eq!(header.mipmap_count(), 2);
+
+        let level = header.read_mipmap(&mut input, 1).unwrap();
+        assert_eq!((level.width, level.height), (1, 1));
+        assert_eq!(level.decode_mipmap(0).unwrap()[0], RGBA8 { r: 7, g: 8, b: 9, a: 6 });
+
+        let image = header.read_image(&mut input).unwrap();
+        assert_eq!(image.mipmap_count(), 2);
+        assert_eq!(image.decode_mipmap(0).unwrap()[3], RGBA8 { r: 0, g: 0, b: 3, a: 255 });
+
+        assert!(header.read_mipmap(&mut input, 2).is_err());
+        assert_eq!(read(&mut Cursor::new(truecolor_blp())).unwrap().mipmap_count(), 2);
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn test_serde_palette() {
+        let mut palette = Palette([RGB8::default(); PALETTE_SIZE]);
+        palette.0[255] = RGB8 { r: 1, g: 2, b: 3 };
+        let content = Content::Indexed { palette: Box::new(palette) };
+
+        let encoded = ::bincode::serialize(&content).unwrap();
+        match ::bincode::deserialize(&encoded).unwrap() {
+            Content::Indexed { palette } => assert_eq!(palette.0[255], RGB8 { r: 1, g: 2, b: 3 }),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(::bincode::deserialize::<Palette>(&::bincode::serialize(&vec![RGB8::default(); 3]).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use byteorder::{ReadBytesExt, LittleEndian as LE};
+use reader::ResourceReader;
+use blp::{self, Header, Image, Compression, PALETTE_SIZE};
+use blp::dxt;
+use blp::mipmaps::{full_chain_length, MAX_MIPMAP_LEVELS};
+use error::{Error, Result};
+
+const BLP1_HEADER_SIZE: u64 = 4 + 6 * 4 + 8 * MAX_MIPMAP_LEVELS as u64;
+const BLP2_HEADER_SIZE: u64 = 4 + 4 + 4 + 8 + 8 * MAX_MIPMAP_LEVELS as u64;
+
+// header fields as stored, before any interpretation
+struct RawHeader {
+    version: u8,
+    compression: u32,
+    encoding: u8,
+    alpha_depth: u32,
+    preferred_format: u8,
+    has_mipmaps: bool,
+    width: u32,
+    height: u32,
+    offsets: [u32; MAX_MIPMAP_LEVELS],
+    sizes: [u32; MAX_MIPMAP_LEVELS],
+}
+
+// cross-checks the header against itself and the stream length; returns every
+// inconsistency found instead of stopping at the first one
+pub fn validate<R: Read + Seek>(input: &mut R) -> Result<Vec<String>> {
+    let file_size = input.seek(SeekFrom::End(0))?;
+    input.seek(SeekFrom::Start(0))?;
+
+    let raw = read_raw_header(input)?;
+    let mut issues = Vec::new();
+
+    let is_jpeg = raw.compression == 0;
+
+    match raw.version {
+        1 if raw.compression > 1 => issues.push(format!("unknown BLP1 compression {}", raw.compression)),
+        2 if raw.compression > 1 => issues.push(format!("unknown BLP2 compression {}", raw.compression)),
+        2 if !is_jpeg && (raw.encoding < 1 || raw.encoding > 3) => issues.push(format!("unknown encoding {}", raw.encoding)),
+        _ => {}
+    }
+
+    if ![0, 1, 4, 8].contains(&raw.alpha_depth) {
+        issues.push(format!("unsupported alpha depth {}", raw.alpha_depth));
+    }
+
+    // only BLP2 DXT images have a meaningful preferred format
+    let compression = if raw.version == 2 && !is_jpeg && raw.encoding == 2 {
+        match (raw.alpha_depth, raw.preferred_format) {
+            (0, 0) | (1, 0) => Some(Compression::DXT1),
+            (4, 1) | (8, 1) => Some(Compression::DXT3),
+            (8, 7) => Some(Compression::DXT5),
+            (alpha_depth, preferred_format) => {
+                issues.push(format!("alpha depth {} doesn't match preferred format {}", alpha_depth, preferred_format));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    if raw.version == 2 && !is_jpeg && raw.encoding == 3 && raw.alpha_depth != 0 && raw.alpha_depth != 8 {
+        issues.push(format!("truecolor image with alpha depth {}", raw.alpha_depth));
+    }
+
+    if raw.width == 0 || raw.height == 0 {
+        issues.push(format!("empty image {}x{}", raw.width, raw.height));
+    } else if !raw.width.is_power_of_two() || !raw.height.is_power_of_two() {
+        issues.push(format!("dimensions {}x{} are not powers of two", raw.width, raw.height));
+    }
+
+    let mipmap_count = raw.offsets.iter().take_while(|offset| **offset != 0).count();
+    let expected_count = if raw.has_mipmaps { full_chain_length(raw.width, raw.height) } else { 1 };
+
+    if mipmap_count != expected_count {
+        issues.push(format!("{} mipmaps for a {}x{} image (mipmap flag {}), expected {}",
+            mipmap_count, raw.width, raw.height, raw.has_mipmaps, expected_count));
+    }
+
+    for level in mipmap_count..MAX_MIPMAP_LEVELS {
+        if raw.offsets[level] != 0 {
+            issues.push(format!("mipmap {} follows an empty table entry", level));
+        }
+    }
+
+    let data_start = match (raw.version, is_jpeg) {
+        (1, true) | (2, true) => {
+            input.seek(SeekFrom::Start(if raw.version == 1 { BLP1_HEADER_SIZE } else { BLP2_HEADER_SIZE }))?;
+            let jpeg_header_size = input.read_u32::<LE>()? as u64;
+            input.stream_position()? + jpeg_header_size
+        }
+        (1, false) => BLP1_HEADER_SIZE + PALETTE_SIZE as u64 * 4,
+        _ => BLP2_HEADER_SIZE + PALETTE_SIZE as u64 * 4,
+    };
+
+    let mut ranges: Vec<(usize, u64, u64)> = Vec::new();
+
+    for level in 0..mipmap_count {
+        let (offset, size) = (raw.offsets[level] as u64, raw.sizes[level] as u64);
+        let (width, height) = ((raw.width >> level).max(1), (raw.height >> level).max(1));
+
+        if size == 0 {
+            issues.push(format!("mipmap {} has zero size", level));
+            continue;
+        }
+        if offset < data_start {
+            issues.push(format!("mipmap {} at {} overlaps the header ending at {}", level, offset, data_start));
+        }
+        if offset + size > file_size {
+            issues.push(format!("mipmap {} ({} bytes at {}) extends past the end of file ({} bytes)", level, size, offset, file_size));
+        }
+
+        let pixel_count = width as u64 * height as u64;
+        let expected_size = if is_jpeg {
+            None
+        } else if let Some(ref compression) = compression {
+            Some(dxt::compressed_size(compression, width, height) as u64)
+        } else if raw.version == 2 && raw.encoding == 3 {
+            Some(pixel_count * 4)
+        } else if raw.version == 1 || raw.encoding == 1 {
+            Some(pixel_count + (pixel_count * raw.alpha_depth as u64).div_ceil(8))
+        } else {
+            None
+        };
+
+        if let Some(expected_size) = expected_size {
+            if size < expected_size {
+                issues.push(format!("mipmap {} has {} bytes, {}x{} needs {}", level, size, width, height, expected_size));
+            }
+        }
+
+        for &(other_level, other_offset, other_size) in ranges.iter() {
+            if offset < other_offset + other_size && other_offset < offset + size {
+                issues.push(format!("mipmap {} overlaps mipmap {}", level, other_level));
+            }
+        }
+        ranges.push((level, offset, size));
+    }
+
+    Ok(issues)
+}
+
+// reads the header only if it passes validation; all issues are reported in the error
+pub fn read_header_strict<R: Read + Seek>(input: &mut R) -> Result<Header> {
+    let issues = validate(input)?;
+
+    if !issues.is_empty() {
+        return Err(Error::Invalid { format: "BLP", issues });
+    }
+
+    input.seek(SeekFrom::Start(0))?;
+    blp::read_header(input)
+}
+
+pub fn load_strict(reader: Arc<dyn ResourceReader>, name: &str) -> Result<Image> {
+    let mut input = reader.open(name)?;
+    let header = read_header_strict(&mut input).map_err(|e| e.in_file(name))?;
+    header.read_image(&mut input)
+}
+
+fn read_raw_header<R: Read>(mut input: &mut R) -> io::Result<RawHeader> {
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+
+    let mut raw = match &magic {
+        b"BLP1" => {
+            let_read! { LE | input =>
+                compression: u32;
+                alpha_depth: u32;
+                width: u32;
+                height: u32;
+                picture_type: u32;
+                has_mipmaps: u32;
+            }
+            RawHeader {
+                version: 1,
+                compression,
+                encoding: 1,
+                alpha_depth,
+                preferred_format: 0,
+                has_mipmaps: has_mipmaps != 0,
+                width,
+                height,
+                offsets: [0; MAX_MIPMAP_LEVELS],
+                sizes: [0; MAX_MIPMAP_LEVELS],
+            }
+        }
+        b"BLP2" => {
+            let_read! { LE | input =>
+                compression: u32;
+                encoding: u8;
+                alpha_depth: u8;
+                preferred_format: u8;
+                has_mipmaps: u8;
+                width: u32;
+                height: u32;
+            }
+            RawHeader {
+                version: 2,
+                compression,
+                encoding,
+                alpha_depth: alpha_depth as u32,
+                preferred_format,
+                has_mipmaps: has_mipmaps != 0,
+                width,
+                height,
+                offsets: [0; MAX_MIPMAP_LEVELS],
+                sizes: [0; MAX_MIPMAP_LEVELS],
+            }
+        }
+        _ => return Err(Error::BadMagic { format: "BLP", found: magic }.into()),
+    };
+
+    for offset in raw.offsets.iter_mut() {
+        *offset = input.read_u32::<LE>()?;
+    }
+    for size in raw.sizes.iter_mut() {
+        *size = input.read_u32::<LE>()?;
+    }
+
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use rgb::RGBA8;
+    use blp::Image;
+    use blp::mipmaps::MipFilter;
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let pixels = vec![RGBA8 { r: 1, g: 2, b: 3, a: 4 }; 16];
+        let mut file = Vec::new();
+        Image::from_rgba(4, 4, pixels, MipFilter::Box).unwrap().write_blp(&mut file).unwrap();
+
+        assert!(validate(&mut Cursor::new(&file)).unwrap().is_empty());
+        assert!(read_header_strict(&mut Cursor::new(&file)).is_ok());
+
+        // preferred format 7 (DXT5) on an image without alpha, overlapping
+        // second mipmap, and a truncated last mipmap
+        file[8] = 2;
+        file[9] = 0;
+        file[10] = 7;
+        let first_offset = file[20..24].to_vec();
+        file[24..28].copy_from_slice(&first_offset);
+        file.truncate(file.len() - 1);
+
+        let issues = validate(&mut Cursor::new(&file)).unwrap();
+        assert_eq!(issues.len(), 3, "{:?}", issues);
+        match read_header_strict(&mut Cursor::new(&file)) {
+            Err(Error::Invalid { format: "BLP", issues: ref strict }) => assert_eq!(strict, &issues),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+
+        file[0..4].copy_from_slice(b"BLP3");
+        match blp::read_header(&mut Cursor::new(&file)) {
+            Err(Error::BadMagic { format: "BLP", found }) => assert_eq!(&found, b"BLP3"),
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_validate_huge_dimensions() {
+        let pixels = vec![RGBA8 { r: 1, g: 2, b: 3, a: 4 }; 16];
+        let mut file = Vec::new();
+        Image::from_rgba(4, 4, pixels, MipFilter::Box).unwrap().write_blp(&mut file).unwrap();
+
+        // 65536x65536 has more pixels than fit in a u32
+        file[12..16].copy_from_slice(&0x10000u32.to_le_bytes());
+        file[16..20].copy_from_slice(&0x10000u32.to_le_bytes());
+
+        let issues = validate(&mut Cursor::new(&file)).unwrap();
+        assert!(issues.iter().any(|issue| issue.contains("65536x65536 needs")), "{:?}", issues);
+    }
+}
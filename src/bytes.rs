@@ -0,0 +1,181 @@
+// reading of data already in memory at explicit offsets, without io. the slice
+// based parsers (chunk iteration over slices, records, string tables) sit on
+// this, and `Read + Seek` input reaches them a chunk at a time. apart from the
+// `io` glue at the end nothing here needs more than core
+use std::fmt;
+use std::io;
+use std::str;
+use byteorder::ByteOrder;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseError {
+    // `needed` bytes at `offset`, but the data ends before
+    UnexpectedEof { offset: usize, needed: usize },
+    InvalidData { offset: usize, reason: &'static str },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::UnexpectedEof { offset, needed } => write!(f, "{} bytes needed at offset {}", needed, offset),
+            ParseError::InvalidData { offset, reason } => write!(f, "{} at offset {}", reason, offset),
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, ParseError>;
+
+// a position within a borrowed slice; copies are cheap and independent, so
+// sub-slices can be handed to other threads as they are
+#[derive(Clone, Copy, Debug)]
+pub struct Bytes<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Bytes<'a> {
+    pub fn new(data: &'a [u8]) -> Bytes<'a> {
+        Bytes { data, position: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    // everything after the position, which stays where it is
+    pub fn rest(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+
+    pub fn seek(&mut self, position: usize) -> Result<()> {
+        if position > self.data.len() {
+            return Err(ParseError::UnexpectedEof { offset: self.data.len(), needed: position - self.data.len() });
+        }
+        self.position = position;
+        Ok(())
+    }
+
+    pub fn skip(&mut self, count: usize) -> Result<()> {
+        self.take(count).map(|_| ())
+    }
+
+    pub fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        if count > self.remaining() {
+            return Err(ParseError::UnexpectedEof { offset: self.position, needed: count });
+        }
+        let result = &self.data[self.position..self.position + count];
+        self.position += count;
+        Ok(result)
+    }
+
+    // the next `count` bytes as their own `Bytes`, counting from 0
+    pub fn sub(&mut self, count: usize) -> Result<Bytes<'a>> {
+        self.take(count).map(Bytes::new)
+    }
+
+    // up to the NUL, which is consumed as well
+    pub fn cstr(&mut self) -> Result<&'a str> {
+        let offset = self.position;
+        let length = self.rest().iter().position(|&byte| byte == 0)
+            .ok_or(ParseError::UnexpectedEof { offset, needed: self.remaining() + 1 })?;
+        let bytes = self.take(length + 1)?;
+        str::from_utf8(&bytes[..length]).map_err(|_| ParseError::InvalidData { offset, reason: "string isn't valid UTF-8" })
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn i8(&mut self) -> Result<i8> {
+        Ok(self.u8()? as i8)
+    }
+
+    pub fn u8x4(&mut self) -> Result<[u8; 4]> {
+        let bytes = self.take(4)?;
+        Ok([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    pub fn u16<O: ByteOrder>(&mut self) -> Result<u16> {
+        Ok(O::read_u16(self.take(2)?))
+    }
+
+    pub fn i16<O: ByteOrder>(&mut self) -> Result<i16> {
+        Ok(O::read_i16(self.take(2)?))
+    }
+
+    pub fn u32<O: ByteOrder>(&mut self) -> Result<u32> {
+        Ok(O::read_u32(self.take(4)?))
+    }
+
+    pub fn i32<O: ByteOrder>(&mut self) -> Result<i32> {
+        Ok(O::read_i32(self.take(4)?))
+    }
+
+    pub fn u64<O: ByteOrder>(&mut self) -> Result<u64> {
+        Ok(O::read_u64(self.take(8)?))
+    }
+
+    pub fn f32<O: ByteOrder>(&mut self) -> Result<f32> {
+        Ok(O::read_f32(self.take(4)?))
+    }
+
+    pub fn f32x2<O: ByteOrder>(&mut self) -> Result<[f32; 2]> {
+        Ok([self.f32::<O>()?, self.f32::<O>()?])
+    }
+
+    pub fn f32x3<O: ByteOrder>(&mut self) -> Result<[f32; 3]> {
+        Ok([self.f32::<O>()?, self.f32::<O>()?, self.f32::<O>()?])
+    }
+}
+
+impl From<ParseError> for io::Error {
+    fn from(error: ParseError) -> io::Error {
+        match error {
+            ParseError::UnexpectedEof { .. } => io_error!(UnexpectedEof, error.to_string()),
+            ParseError::InvalidData { .. } => io_error!(InvalidData, error.to_string()),
+        }
+    }
+}
+
+// so `FromRead` records can be read from the middle of a slice as well
+impl<'a> io::Read for Bytes<'a> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let count = buffer.len().min(self.remaining());
+        buffer[..count].copy_from_slice(self.take(count)?);
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{BE, LE};
+    use read_ext::FromRead;
+
+    #[test]
+    fn test_bytes() {
+        let data = b"\x01\x02\0\0\x03a.m2\0rest";
+        let mut bytes = Bytes::new(data);
+
+        assert_eq!(bytes.u8().unwrap(), 1);
+        assert_eq!(bytes.u16::<BE>().unwrap(), 0x200);
+        let mut copy = bytes;
+        assert_eq!(u16::from_read::<LE>(&mut copy).unwrap(), 0x300);
+        assert_eq!(bytes.u16::<LE>().unwrap(), 0x300);
+        assert_eq!(bytes.cstr().unwrap(), "a.m2");
+        assert_eq!(bytes.position(), 10);
+
+        assert_eq!(bytes.u32::<LE>().unwrap(), u32::from_le_bytes(*b"rest"));
+        assert_eq!(bytes.u8(), Err(ParseError::UnexpectedEof { offset: 14, needed: 1 }));
+        assert!(Bytes::new(b"abc").cstr().is_err());
+        assert_eq!(io::Error::from(Bytes::new(b"").u8().unwrap_err()).kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,173 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use bincode;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use adt::MapTile;
+use wmo::{self, MapObject};
+use reader::ResourceReader;
+use error::Result;
+
+// bump whenever a cached type changes its layout
+const CACHE_FORMAT_VERSION: u32 = 1;
+
+// on-disk cache of parsed assets keyed by a hash of their source files
+pub struct SnapshotCache {
+    dir: PathBuf,
+}
+
+impl SnapshotCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(SnapshotCache { dir: dir.as_ref().to_owned() })
+    }
+
+    pub fn load_map_tile(&self, reader: Arc<dyn ResourceReader>, name: &str, big_alpha: Option<bool>) -> Result<MapTile> {
+        let sources = MapTile::source_files(&*reader, name)?;
+        let salt = format!("adt:{:?}", big_alpha);
+        let key = source_hash(&*reader, &sources, salt.as_bytes())?;
+
+        self.fetch_or_store(key, "adt", || MapTile::load(reader.clone(), name, big_alpha))
+    }
+
+    pub fn load_map_object(&self, reader: Arc<dyn ResourceReader>, name: &str) -> Result<MapObject> {
+        // group resource keys are derived from the root name, so it's part of the key
+        let sources = MapObject::source_files(&*reader, name)?;
+        let salt = format!("wmo:{}", name.to_lowercase());
+        let key = source_hash(&*reader, &sources, salt.as_bytes())?;
+
+        self.fetch_or_store(key, "wmo", || wmo::load(reader.clone(), name, None))
+    }
+
+    pub fn clear(&self) -> io::Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().map(|ext| ext == "gz").unwrap_or(false) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, key: u64, kind: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.{}.gz", key, kind))
+    }
+
+    fn fetch_or_store<T, F>(&self, key: u64, kind: &str, load: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T>
+    {
+        let path = self.entry_path(key, kind);
+
+        if path.exists() {
+            match read_entry(&path) {
+                Ok(value) => {
+                    trace!("cache hit: {:?}", &path);
+                    return Ok(value);
+                }
+                Err(error) => warn!("Ignoring broken cache entry {:?}: {}", &path, error),
+            }
+        }
+
+        let value = load()?;
+
+        if let Err(error) = write_entry(&path, &value) {
+            warn!("Unable to write cache entry {:?}: {}", &path, error);
+        }
+
+        Ok(value)
+    }
+}
+
+fn read_entry<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
+    let input = GzDecoder::new(BufReader::new(File::open(path)?));
+    bincode::deserialize_from(input).map_err(|e| io_error!(InvalidData, e))
+}
+
+fn write_entry<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    // write to a temporary file first, so concurrent readers never see partial entries
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut output = GzEncoder::new(BufWriter::new(File::create(&temp_path)?), Compression::fast());
+        bincode::serialize_into(&mut output, value).map_err(|e| io_error!(Other, e))?;
+        output.finish()?.flush()?;
+    }
+    fs::rename(temp_path, path)
+}
+
+fn source_hash(reader: &dyn ResourceReader, names: &[String], salt: &[u8]) -> io::Result<u64> {
+    let mut hasher = Fnv64::new();
+    hasher.write(&CACHE_FORMAT_VERSION.to_le_bytes());
+    hasher.write(salt);
+
+    let mut buffer = vec![0u8; 64 * 1024];
+    for name in names {
+        let mut input = reader.open(name)?;
+        loop {
+            let bytes = input.read(&mut buffer)?;
+            if bytes == 0 { break }
+            hasher.write(&buffer[..bytes]);
+        }
+        // separate files, so moving bytes between them changes the hash
+        hasher.write(&[0xFF]);
+    }
+
+    Ok(hasher.finish())
+}
+
+// FNV-1a; unlike std's DefaultHasher it's stable across releases
+struct Fnv64(u64);
+
+impl Fnv64 {
+    fn new() -> Self {
+        Fnv64(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+    use test_util::{chunk, memory_reader};
+    use super::*;
+
+    #[test]
+    fn test_fnv64() {
+        let mut hasher = Fnv64::new();
+        assert_eq!(hasher.finish(), 0xcbf29ce484222325);
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn test_map_object_key_covers_groups() {
+        let dir = env::temp_dir().join(format!("wow-rs-cache-test-{}", process::id()));
+        let cache = SnapshotCache::new(&dir).unwrap();
+        let root = chunk(b"MVER", &17u32.to_le_bytes());
+
+        for group in &[b"MOGP", b"MOGX"] {
+            let reader = memory_reader(vec![("a.wmo", root.clone()), ("a_000.wmo", chunk(group, &[]))]);
+            cache.load_map_object(reader, "a.wmo").unwrap();
+        }
+
+        let entries = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(entries, 2);
+    }
+}
@@ -1,73 +1,411 @@
-use std::str::from_utf8;
+use std::fmt;
 use std::iter::Iterator;
-use std::io::{self, Cursor};
-use byteorder::LE;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Take};
+use byteorder::{ByteOrder, LE};
 use read_ext::ReadExt;
+use observer;
+use diagnostics;
+use error::Error;
+use limits::Limits;
+use bytes::{self, Bytes};
+use asset::ParseMode;
 
+// four character chunk identifier in reading order, e.g. `Token::MVER`.
+// compares without allocating, and a misspelled constant doesn't compile
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Token(pub [u8; 4]);
+
+impl Token {
+    // common
+    pub const MVER: Token = Token(*b"MVER");
+
+    // adt
+    pub const MTEX: Token = Token(*b"MTEX");
+    pub const MMDX: Token = Token(*b"MMDX");
+    pub const MMID: Token = Token(*b"MMID");
+    pub const MWMO: Token = Token(*b"MWMO");
+    pub const MWID: Token = Token(*b"MWID");
+    pub const MDDF: Token = Token(*b"MDDF");
+    pub const MODF: Token = Token(*b"MODF");
+    pub const MCNK: Token = Token(*b"MCNK");
+    pub const MCVT: Token = Token(*b"MCVT");
+    pub const MCNR: Token = Token(*b"MCNR");
+    pub const MCLY: Token = Token(*b"MCLY");
+    pub const MCAL: Token = Token(*b"MCAL");
+    pub const MCLQ: Token = Token(*b"MCLQ");
+    pub const MH2O: Token = Token(*b"MH2O");
+
+    // wdt
+    pub const MPHD: Token = Token(*b"MPHD");
+    pub const MAIN: Token = Token(*b"MAIN");
+    pub const MAID: Token = Token(*b"MAID");
+
+    // wmo root
+    pub const MOMO: Token = Token(*b"MOMO");
+    pub const MOHD: Token = Token(*b"MOHD");
+    pub const MOTX: Token = Token(*b"MOTX");
+    pub const MOMT: Token = Token(*b"MOMT");
+    pub const MOUV: Token = Token(*b"MOUV");
+    pub const MOGN: Token = Token(*b"MOGN");
+    pub const MOGI: Token = Token(*b"MOGI");
+    pub const MOSB: Token = Token(*b"MOSB");
+    pub const MOPV: Token = Token(*b"MOPV");
+    pub const MOPT: Token = Token(*b"MOPT");
+    pub const MOPR: Token = Token(*b"MOPR");
+    pub const MOLT: Token = Token(*b"MOLT");
+    pub const MODS: Token = Token(*b"MODS");
+    pub const MODN: Token = Token(*b"MODN");
+    pub const MODD: Token = Token(*b"MODD");
+    pub const MCVP: Token = Token(*b"MCVP");
+    pub const GFID: Token = Token(*b"GFID");
+
+    // wmo group
+    pub const MOGP: Token = Token(*b"MOGP");
+    pub const MOPY: Token = Token(*b"MOPY");
+    pub const MOVI: Token = Token(*b"MOVI");
+    pub const MOIN: Token = Token(*b"MOIN");
+    pub const MOVT: Token = Token(*b"MOVT");
+    pub const MONR: Token = Token(*b"MONR");
+    pub const MOTV: Token = Token(*b"MOTV");
+    pub const MOCV: Token = Token(*b"MOCV");
+    pub const MOBA: Token = Token(*b"MOBA");
+    pub const MOBS: Token = Token(*b"MOBS");
+    pub const MOBN: Token = Token(*b"MOBN");
+    pub const MOBR: Token = Token(*b"MOBR");
+    pub const MLIQ: Token = Token(*b"MLIQ");
+
+    // legion m2
+    pub const MD21: Token = Token(*b"MD21");
+    pub const PFID: Token = Token(*b"PFID");
+    pub const SKID: Token = Token(*b"SKID");
+    pub const SFID: Token = Token(*b"SFID");
+    pub const BFID: Token = Token(*b"BFID");
+    pub const TXID: Token = Token(*b"TXID");
+    pub const AFID: Token = Token(*b"AFID");
+
+    // skeletons and bones
+    pub const SKL1: Token = Token(*b"SKL1");
+    pub const SKS1: Token = Token(*b"SKS1");
+    pub const SKB1: Token = Token(*b"SKB1");
+    pub const SKPD: Token = Token(*b"SKPD");
+    pub const BIDA: Token = Token(*b"BIDA");
+    pub const BOMT: Token = Token(*b"BOMT");
+
+    // physics
+    pub const PHYS: Token = Token(*b"PHYS");
+    pub const BODY: Token = Token(*b"BODY");
+    pub const SHAP: Token = Token(*b"SHAP");
+    pub const BOXS: Token = Token(*b"BOXS");
+    pub const CAPS: Token = Token(*b"CAPS");
+    pub const SPHS: Token = Token(*b"SPHS");
+    pub const JOIN: Token = Token(*b"JOIN");
+
+    pub fn as_bytes(&self) -> &[u8; 4] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &byte in self.0.iter() {
+            let c = if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '?' };
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Token({})", self)
+    }
+}
+
+#[derive(Debug)]
 pub struct Chunk {
-    pub token: String,
+    pub token: Token,
     pub data: Vec<u8>,
+    // of the header, counted from where iteration started
+    pub offset: u64,
 }
 
 impl Chunk {
-    pub fn new(token: String, data: Vec<u8>) -> Self {
-        Chunk { token, data }
+    pub fn new(token: Token, data: Vec<u8>) -> Self {
+        Chunk { token, data, offset: 0 }
     }
 
     pub fn cursor(&self) -> Cursor<&[u8]> {
         Cursor::new(&self.data)
     }
+
+    pub fn bytes(&self) -> Bytes<'_> {
+        Bytes::new(&self.data)
+    }
+
+    // see `ChunkRef::subchunks`
+    pub fn subchunks(&self, header_size: usize) -> io::Result<ChunkRefs<'_>> {
+        subchunks(self.token, &self.data, self.offset, header_size)
+    }
+}
+
+// a chunk borrowed from an in-memory (or mapped) file, see `Chunked::over`
+#[derive(Debug)]
+pub struct ChunkRef<'a> {
+    pub token: Token,
+    pub data: &'a [u8],
+    // of the header, from the start of the slice given to `Chunked::over`;
+    // subchunks count from there as well
+    pub offset: u64,
+}
+
+impl<'a> ChunkRef<'a> {
+    pub fn cursor(&self) -> Cursor<&'a [u8]> {
+        Cursor::new(self.data)
+    }
+
+    pub fn bytes(&self) -> Bytes<'a> {
+        Bytes::new(self.data)
+    }
+
+    // chunks nested after a fixed-size header, as in MCNK and MOGP. they end with
+    // the parent's data; a trailing partial header is ignored like at the end of a file
+    pub fn subchunks(&self, header_size: usize) -> io::Result<ChunkRefs<'a>> {
+        subchunks(self.token, self.data, self.offset, header_size)
+    }
+}
+
+fn subchunks(token: Token, data: &[u8], offset: u64, header_size: usize) -> io::Result<ChunkRefs<'_>> {
+    let data = data.get(header_size..)
+        .ok_or_else(|| io_error!(UnexpectedEof, "{} chunk is shorter than its {} byte header", token, header_size))?;
+    let mut chunks = Chunked::over(data);
+    chunks.base = offset + 8 + header_size as u64;
+    Ok(chunks)
+}
+
+// how iterators deal with truncated or corrupt chunk streams
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strictness {
+    // fail with a `MalformedChunk` error
+    Strict,
+    // warn, skip the bad region and carry on where possible; the default,
+    // as lots of community-edited files are slightly malformed
+    Lenient,
+}
+
+impl Default for Strictness {
+    // follows the `ParseMode` in effect, so loads set to strict are strict throughout
+    fn default() -> Self {
+        match ParseMode::current() {
+            ParseMode::Strict => Strictness::Strict,
+            ParseMode::Lenient => Strictness::Lenient,
+        }
+    }
+}
+
+// tolerances for chunk streams written by third-party tools; discrepancies
+// they paper over are still reported as warnings
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChunkOptions {
+    pub strictness: Strictness,
+    // chunk payloads are padded to multiples of this many bytes (0 or 1 for none),
+    // counted from where iteration started
+    pub alignment: u32,
+    // a size running past the end takes what's left instead of being malformed
+    pub clamp_sizes: bool,
+    // zero bytes after the last chunk end iteration quietly
+    pub trailing_padding: bool,
+}
+
+impl ChunkOptions {
+    fn padding(&self, position: u64) -> u64 {
+        let alignment = self.alignment as u64;
+        if alignment > 1 { (alignment - position % alignment) % alignment } else { 0 }
+    }
+}
+
+fn check_padding(padding: &[u8], token: Token, offset: u64, strictness: Strictness) -> io::Result<()> {
+    if padding.iter().any(|&byte| byte != 0) {
+        if strictness == Strictness::Strict {
+            return Err(malformed(Some(token), offset, "non-zero alignment padding".to_owned()));
+        }
+        report_chunk_warn!(token, offset, "non-zero alignment padding after {} chunk at offset {}", token, offset);
+    }
+    Ok(())
+}
+
+fn report_clamped(token: Token, offset: u64, size: u32, available: u64) {
+    report_chunk_warn!(token, offset, "{} chunk at offset {} declares {} bytes but only {} are left, clamping", token, offset, size, available);
+}
+
+// strict mode errors carry an `error::Error::MalformedChunk`, use
+// `wow::Error::from` to get it back
+fn malformed(token: Option<Token>, offset: u64, reason: String) -> io::Error {
+    Error::MalformedChunk { token, offset, reason }.into()
+}
+
+// Ok(()) if the caller should skip past `error`
+fn recover(strictness: Strictness, error: io::Error) -> io::Result<()> {
+    match strictness {
+        Strictness::Strict => Err(error),
+        Strictness::Lenient => {
+            report_warn!("{}, skipping", error);
+            Ok(())
+        }
+    }
 }
 
 pub struct Chunked<R: ReadExt> {
     reader: R,
     legion_m2: bool,
+    // yield only these, see `filter_tokens`
+    wanted: Option<Vec<Token>>,
+    options: ChunkOptions,
+    // bytes consumed so far
+    position: u64,
 }
 
 impl<R: ReadExt> Chunked<R> {
     pub fn new(reader: R) -> Self {
-        Chunked { reader, legion_m2: false }
+        Chunked { reader, legion_m2: false, wanted: None, options: ChunkOptions::default(), position: 0 }
     }
 
     pub fn new_legion_m2(reader: R) -> Self {
-        Chunked { reader, legion_m2: true }
+        Chunked { legion_m2: true, ..Chunked::new(reader) }
+    }
+
+    // skips all chunks but `tokens` without buffering their data
+    pub fn filter_tokens(mut self, tokens: &[Token]) -> Self {
+        self.wanted = Some(tokens.to_vec());
+        self
+    }
+
+    pub fn strictness(mut self, strictness: Strictness) -> Self {
+        self.options.strictness = strictness;
+        self
+    }
+
+    pub fn options(mut self, options: ChunkOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    // discards bytes which follow a chunk but aren't counted in its size;
+    // stops quietly at the end of input
+    pub fn skip_padding(&mut self, size: usize) -> io::Result<()> {
+        self.position += io::copy(&mut (&mut self.reader).take(size as u64), &mut io::sink())?;
+        Ok(())
     }
 
     fn read_chunk(&mut self) -> io::Result<Option<Chunk>> {
-        let mut token_buffer = [0u8; 4];
+        loop {
+            let offset = self.position;
+            let mut header = [0u8; 8];
 
-        match self.reader.read(&mut token_buffer) {
-            Err(e) => {
-                Err(e)
-            },
-            Ok(bytes) if bytes == 0 => {
-                Ok(None)
-            },
-            Ok(bytes) if bytes < 4 => {
-                // FIXME: WTF
-                Ok(None)
-                //Err(io_error!(UnexpectedEof, "incomplete chunk token"))
-            },
-            Ok(_) => {
-                // seems like wow uses u32 constants to encode chunk tokens. legion's m2 files
-                // encode those using big-endian, while all other files use little-endian.
-                // so for all but legion m2, we reverse byte order to get left-to-right token names.
-                if !self.legion_m2 { token_buffer.reverse(); }
-
-                let token = from_utf8(&token_buffer)
-                    .map(|s| s.to_owned())
-                    .map_err(|e| io_error!(InvalidData, e))?;
-
-                let size = self.reader.read_u32::<LE>()?;
-
-                trace!("found chunk: {} ({} bytes)", &token, size);
-
-                let mut data = self.reader.read_vec(size as usize)?;
-
-                Ok(Some(Chunk::new(token, data)))
+            match read_full(&mut self.reader, &mut header)? {
+                0 => return Ok(None),
+                8 if header != [0; 8] || !self.options.trailing_padding => {}
+                bytes => {
+                    self.position += bytes as u64;
+                    if self.options.trailing_padding && header.iter().all(|&byte| byte == 0) {
+                        let mut rest = Vec::new();
+                        self.position += self.reader.read_to_end(&mut rest)? as u64;
+                        if rest.iter().all(|&byte| byte == 0) {
+                            return Ok(None);
+                        }
+                    }
+
+                    // a plain stream can't be resynchronized, so lenient mode stops here
+                    let reason = format!("incomplete chunk header ({} bytes)", bytes);
+                    recover(self.options.strictness, malformed(None, offset, reason))?;
+                    return Ok(None);
+                }
+            }
+
+            let (token, size) = parse_header(&mut Bytes::new(&header), self.legion_m2)?;
+            let wanted = is_wanted(&self.wanted, token);
+            if wanted {
+                Limits::current().check_chunk_size(size as u64)?;
+            }
+
+            let mut data = Vec::new();
+            let mut body = (&mut self.reader).take(size as u64);
+            let bytes = if wanted { body.read_to_end(&mut data)? as u64 } else { io::copy(&mut body, &mut io::sink())? };
+            self.position += 8 + bytes;
+
+            if bytes < size as u64 {
+                if !self.options.clamp_sizes {
+                    let reason = format!("needs {} bytes, {} left", size, bytes);
+                    recover(self.options.strictness, malformed(Some(token), offset, reason))?;
+                    return Ok(None);
+                }
+                report_clamped(token, offset, size, bytes);
+            } else {
+                let mut padding = vec![0u8; self.options.padding(self.position) as usize];
+                let skipped = read_full(&mut self.reader, &mut padding)?;
+                self.position += skipped as u64;
+                check_padding(&padding[..skipped], token, offset, self.options.strictness)?;
             }
+
+            if wanted {
+                trace!("found chunk: {} ({} bytes)", token, size);
+                observer::notify(|observer| observer.chunk_parsed(token, size));
+                diagnostics::enter_chunk(token, offset);
+                return Ok(Some(Chunk { token, data, offset }));
+            }
+        }
+    }
+}
+
+// like `read_exact`, but reports how much was there instead of failing
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(bytes) => filled += bytes,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
         }
     }
+    Ok(filled)
+}
+
+fn is_wanted(wanted: &Option<Vec<Token>>, token: Token) -> bool {
+    wanted.as_ref().is_none_or(|wanted| wanted.contains(&token))
+}
+
+impl<'a> Chunked<&'a [u8]> {
+    // iterates chunks of a file already in memory without copying their data
+    pub fn over(data: &'a [u8]) -> ChunkRefs<'a> {
+        ChunkRefs { data, base: 0, position: 0, legion_m2: false, options: ChunkOptions::default() }
+    }
+}
+
+impl<R: ReadExt + Seek> Chunked<R> {
+    // reads chunk headers only; bodies are skipped with a seek unless read through
+    // `ChunkStream::body`, so nothing but the wanted chunks is loaded
+    pub fn stream(mut reader: R) -> io::Result<ChunkStream<R>> {
+        let start = reader.stream_position()?;
+        Ok(ChunkStream { reader, start, next: start, legion_m2: false, wanted: None, options: ChunkOptions::default() })
+    }
+}
+
+// seems like wow uses u32 constants to encode chunk tokens. legion's m2 files
+// encode those using big-endian, while all other files use little-endian.
+// so for all but legion m2, we reverse byte order to get left-to-right token names.
+fn decode_token(mut token_buffer: [u8; 4], legion_m2: bool) -> Token {
+    if !legion_m2 { token_buffer.reverse(); }
+    Token(token_buffer)
+}
+
+// the header every chunk starts with, however it's read
+fn parse_header(header: &mut Bytes, legion_m2: bool) -> bytes::Result<(Token, u32)> {
+    Ok((decode_token(header.u8x4()?, legion_m2), header.u32::<LE>()?))
+}
+
+// every known token consists of uppercase letters and digits
+fn is_plausible_token(token: &[u8]) -> bool {
+    token.iter().all(|&byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
 }
 
 impl<R: ReadExt> Iterator for Chunked<R> {
@@ -81,3 +419,323 @@ impl<R: ReadExt> Iterator for Chunked<R> {
         }
     }
 }
+
+pub struct ChunkRefs<'a> {
+    data: &'a [u8],
+    // offset of `data` within the outermost slice, for nested chunks
+    base: u64,
+    position: usize,
+    legion_m2: bool,
+    options: ChunkOptions,
+}
+
+impl<'a> ChunkRefs<'a> {
+    pub fn strictness(mut self, strictness: Strictness) -> Self {
+        self.options.strictness = strictness;
+        self
+    }
+
+    pub fn options(mut self, options: ChunkOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    // same as `Chunked::skip_padding`
+    pub fn skip_padding(&mut self, size: usize) {
+        self.position = (self.position + size).min(self.data.len());
+    }
+
+    fn read_chunk(&mut self) -> io::Result<Option<ChunkRef<'a>>> {
+        loop {
+            let offset = self.position;
+            let rest = &self.data[offset..];
+            if rest.is_empty() || self.options.trailing_padding && rest.iter().all(|&byte| byte == 0) {
+                return Ok(None);
+            }
+            if rest.len() < 8 {
+                self.position = self.data.len();
+                let reason = format!("incomplete chunk header ({} bytes)", rest.len());
+                recover(self.options.strictness, malformed(None, self.base + offset as u64, reason))?;
+                return Ok(None);
+            }
+
+            let (token, size) = parse_header(&mut Bytes::new(rest), self.legion_m2)?;
+
+            let data = match rest[8..].get(..size as usize) {
+                None if self.options.clamp_sizes => {
+                    report_clamped(token, self.base + offset as u64, size, rest.len() as u64 - 8);
+                    Some(&rest[8..])
+                }
+                data => data,
+            };
+
+            match data {
+                Some(data) => {
+                    self.position += 8 + data.len();
+                    let padding = (self.options.padding(self.position as u64) as usize).min(self.data.len() - self.position);
+                    let start = self.position;
+                    self.position += padding;
+                    check_padding(&self.data[start..self.position], token, self.base + offset as u64, self.options.strictness)?;
+
+                    trace!("found chunk: {} ({} bytes)", token, size);
+                    observer::notify(|observer| observer.chunk_parsed(token, size));
+                    diagnostics::enter_chunk(token, self.base + offset as u64);
+                    return Ok(Some(ChunkRef { token, data, offset: self.base + offset as u64 }));
+                }
+                None => {
+                    let reason = format!("needs {} bytes, {} left", size, rest.len() - 8);
+                    recover(self.options.strictness, malformed(Some(token), self.base + offset as u64, reason))?;
+                    // most likely a broken size; carry on at the next header which fits
+                    self.position = self.resync(offset + 1);
+                }
+            }
+        }
+    }
+
+    fn resync(&self, from: usize) -> usize {
+        (from..self.data.len().saturating_sub(7))
+            .find(|&start| {
+                let header = &self.data[start..start + 8];
+                let size = LE::read_u32(&header[4..]) as usize;
+                is_plausible_token(&header[..4]) && size <= self.data.len() - start - 8
+            })
+            .unwrap_or(self.data.len())
+    }
+}
+
+impl<'a> Iterator for ChunkRefs<'a> {
+    type Item = io::Result<ChunkRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_chunk() {
+            Ok(None) => None,
+            Ok(Some(item)) => Some(Ok(item)),
+            Err(e) => {
+                // don't yield the same error forever
+                self.position = self.data.len();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+// where a chunk's data lives in the reader behind a `ChunkStream`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkEntry {
+    pub token: Token,
+    pub size: u32,
+    // of the data, right after the chunk header
+    pub offset: u64,
+}
+
+impl ChunkEntry {
+    // random access to the chunk in the reader it was indexed from
+    pub fn read<R: Read + Seek>(&self, reader: &mut R) -> io::Result<Vec<u8>> {
+        Limits::current().check_chunk_size(self.size as u64)?;
+        reader.seek(SeekFrom::Start(self.offset))?;
+        reader.take(self.size as u64).read_vec(self.size as usize)
+    }
+}
+
+// table of contents of a whole file, scanned once by seeking from header to header
+pub fn index<R: ReadExt + Seek>(reader: R) -> io::Result<Vec<ChunkEntry>> {
+    let mut stream = Chunked::stream(reader)?;
+    let mut entries = Vec::new();
+    while let Some(entry) = stream.next_chunk()? {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+pub struct ChunkStream<R: ReadExt + Seek> {
+    reader: R,
+    // where iteration started, for error offsets
+    start: u64,
+    // offset of the next chunk header
+    next: u64,
+    legion_m2: bool,
+    wanted: Option<Vec<Token>>,
+    options: ChunkOptions,
+}
+
+impl<R: ReadExt + Seek> ChunkStream<R> {
+    // `next_chunk` seeks past all chunks but `tokens`
+    pub fn filter_tokens(mut self, tokens: &[Token]) -> Self {
+        self.wanted = Some(tokens.to_vec());
+        self
+    }
+
+    pub fn strictness(mut self, strictness: Strictness) -> Self {
+        self.options.strictness = strictness;
+        self
+    }
+
+    // bodies aren't read, so `clamp_sizes` has no effect here
+    pub fn options(mut self, options: ChunkOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn next_chunk(&mut self) -> io::Result<Option<ChunkEntry>> {
+        while let Some(header) = self.next_header()? {
+            if is_wanted(&self.wanted, header.token) {
+                trace!("found chunk: {} ({} bytes)", header.token, header.size);
+                observer::notify(|observer| observer.chunk_parsed(header.token, header.size));
+                diagnostics::enter_chunk(header.token, header.offset - 8);
+                return Ok(Some(header));
+            }
+        }
+        Ok(None)
+    }
+
+    // bodies aren't read here, so only a broken header is noticed
+    fn next_header(&mut self) -> io::Result<Option<ChunkEntry>> {
+        self.reader.seek(SeekFrom::Start(self.next))?;
+
+        let mut header = [0u8; 8];
+        match read_full(&mut self.reader, &mut header)? {
+            0 => return Ok(None),
+            bytes if self.options.trailing_padding && header[..bytes].iter().all(|&byte| byte == 0) => return Ok(None),
+            8 => {}
+            bytes => {
+                let reason = format!("incomplete chunk header ({} bytes)", bytes);
+                recover(self.options.strictness, malformed(None, self.next - self.start, reason))?;
+                return Ok(None);
+            }
+        }
+
+        let (token, size) = parse_header(&mut Bytes::new(&header), self.legion_m2)?;
+        let offset = self.next + 8;
+        let end = offset + size as u64;
+        self.next = end + self.options.padding(end - self.start);
+
+        Ok(Some(ChunkEntry { token, size, offset }))
+    }
+
+    // bounded reader over the data of any chunk seen so far
+    pub fn body(&mut self, header: &ChunkEntry) -> io::Result<Take<&mut R>> {
+        self.reader.seek(SeekFrom::Start(header.offset))?;
+        Ok((&mut self.reader).take(header.size as u64))
+    }
+
+    // the whole chunk data, for the chunks which are actually needed
+    pub fn read_body(&mut self, header: &ChunkEntry) -> io::Result<Vec<u8>> {
+        Limits::current().check_chunk_size(header.size as u64)?;
+        self.body(header)?.read_vec(header.size as usize)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_refs() {
+        let data = b"REVM\x04\0\0\0\x12\0\0\0KNCM\x02\0\0\0ab\0\0";
+        let streamed: Vec<Chunk> = Chunked::new(&data[..]).collect::<io::Result<_>>().unwrap();
+
+        let mut chunks = Chunked::over(data);
+        let first = chunks.next().unwrap().unwrap();
+        assert_eq!((first.token, first.data), (Token::MVER, &streamed[0].data[..]));
+        let second = chunks.next().unwrap().unwrap();
+        assert_eq!((second.token, second.data), (Token::MCNK, &b"ab"[..]));
+        chunks.skip_padding(2);
+        assert!(chunks.next().is_none());
+
+        assert!(Chunked::over(b"REVM\x08\0\0\0ab").strictness(Strictness::Strict).next().unwrap().is_err());
+
+        let nested = Chunk::new(Token::MCNK, b"hdrTVCM\x01\0\0\0z\0".to_vec());
+        let inner: Vec<ChunkRef> = nested.subchunks(3).unwrap().collect::<io::Result<_>>().unwrap();
+        assert_eq!((inner.len(), inner[0].token, inner[0].data), (1, Token::MCVT, &b"z"[..]));
+        assert!(nested.subchunks(20).is_err());
+        assert_eq!(format!("{} {:?}", Token::MCNK, Token(*b"MV\0R")), "MCNK Token(MV?R)");
+    }
+
+    #[test]
+    fn test_chunk_stream() {
+        let data = b"REVM\x04\0\0\0\x12\0\0\0KNCM\x02\0\0\0ab";
+        let mut stream = Chunked::stream(Cursor::new(&data[..])).unwrap();
+
+        let version = stream.next_chunk().unwrap().unwrap();
+        let chunk = stream.next_chunk().unwrap().unwrap();
+        assert_eq!(chunk, ChunkEntry { token: Token::MCNK, size: 2, offset: 20 });
+        assert!(stream.next_chunk().unwrap().is_none());
+
+        // bodies can be read in any order once their headers were seen
+        assert_eq!(stream.read_body(&chunk).unwrap(), b"ab");
+        assert_eq!(stream.read_body(&version).unwrap(), [0x12, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_index() {
+        let mut input = Cursor::new(&b"REVM\x04\0\0\0\x12\0\0\0KNCM\x02\0\0\0ab"[..]);
+        let entries = index(&mut input).unwrap();
+
+        assert_eq!(entries.iter().map(|entry| (entry.token, entry.offset)).collect::<Vec<_>>(),
+                   vec![(Token::MVER, 8), (Token::MCNK, 20)]);
+        assert_eq!(entries[1].read(&mut input).unwrap(), b"ab");
+        assert_eq!(entries[0].read(&mut input).unwrap(), [0x12, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_filter_tokens() {
+        let data = b"REVM\x04\0\0\0\x12\0\0\0KNCM\x02\0\0\0abXETM\x01\0\0\0t";
+
+        let chunks: Vec<Chunk> = Chunked::new(&data[..]).filter_tokens(&[Token::MVER, Token::MTEX])
+            .collect::<io::Result<_>>().unwrap();
+        assert_eq!(chunks.iter().map(|chunk| chunk.token).collect::<Vec<_>>(), vec![Token::MVER, Token::MTEX]);
+        assert_eq!(chunks[1].data, b"t");
+
+        let mut stream = Chunked::stream(Cursor::new(&data[..])).unwrap().filter_tokens(&[Token::MCNK]);
+        assert_eq!(stream.next_chunk().unwrap().unwrap().offset, 20);
+        assert!(stream.next_chunk().unwrap().is_none());
+
+        // a skipped chunk running past the end is still an error
+        let mut chunks = Chunked::new(&b"KNCM\x09\0\0\0ab"[..]).filter_tokens(&[]).strictness(Strictness::Strict);
+        assert!(chunks.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_strictness() {
+        // the first chunk claims more data than there is, the second is intact
+        let data = b"REVM\xFF\0\0\0\x12\0\0\0KNCM\x02\0\0\0ab\0\0";
+
+        let error = Chunked::over(data).strictness(Strictness::Strict).next().unwrap().unwrap_err();
+        match Error::from(error) {
+            Error::MalformedChunk { token, offset, .. } => assert_eq!((token, offset), (Some(Token::MVER), 0)),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let chunks: Vec<ChunkRef> = Chunked::over(data).collect::<io::Result<_>>().unwrap();
+        assert_eq!(chunks.iter().map(|chunk| chunk.token).collect::<Vec<_>>(), vec![Token::MCNK]);
+
+        // plain streams can't skip ahead, but stop quietly at a truncated tail
+        assert_eq!(Chunked::new(&data[12..]).count(), 1);
+        let error = Chunked::new(&data[12..]).strictness(Strictness::Strict).nth(1).unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_chunk_options() {
+        let options = ChunkOptions { strictness: Strictness::Strict, alignment: 4, clamp_sizes: true, trailing_padding: true };
+        // padded to 4 bytes, zeros at the end and a last chunk overrunning the input
+        let data = b"KNCM\x02\0\0\0ab\0\0XETM\x01\0\0\0t\0\0\0OMOM\x09\0\0\0xyz";
+        let padded = b"KNCM\x02\0\0\0ab\0\0\0\0\0\0\0";
+
+        let chunks: Vec<ChunkRef> = Chunked::over(data).options(options).collect::<io::Result<_>>().unwrap();
+        assert_eq!(chunks.iter().map(|chunk| chunk.data).collect::<Vec<_>>(), vec![&b"ab"[..], b"t", b"xyz"]);
+        assert_eq!(Chunked::over(padded).options(options).count(), 1);
+
+        let chunks: Vec<Chunk> = Chunked::new(&data[..]).options(options).collect::<io::Result<_>>().unwrap();
+        assert_eq!(chunks.iter().map(|chunk| chunk.token).collect::<Vec<_>>(), vec![Token::MCNK, Token::MTEX, Token::MOMO]);
+        assert_eq!(Chunked::new(&padded[..]).options(options).count(), 1);
+
+        let mut stream = Chunked::stream(Cursor::new(&data[..])).unwrap().options(options);
+        stream.next_chunk().unwrap();
+        assert_eq!(stream.next_chunk().unwrap().unwrap().offset, 20);
+    }
+}
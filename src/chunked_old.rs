@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::str::from_utf8;
 use std::io::{self, Read, Cursor};
 use byteorder::{ReadBytesExt, LittleEndian as LE};
+use limits::Limits;
 
 type ChunkData = Vec<u8>;
 type ChunkMap = HashMap<String, Vec<ChunkData>>;
@@ -34,6 +35,7 @@ fn read_chunks_into_map<R>(input: &mut R, legion_m2: bool) -> io::Result<ChunkMa
                     .map_err(|e| io_error!(InvalidData, e))?;
 
                 let size = input.read_u32::<LE>()?;
+                Limits::current().check_chunk_size(size as u64)?;
                 let mut data = Vec::with_capacity(size as usize);
                 input.take(size as u64).read_to_end(&mut data)?;
 
@@ -116,7 +118,7 @@ macro_rules! let_chunk {
             let len = reader.get_ref().len();
             let pos = reader.position() as usize;
             if pos < len {
-                warn!("{} chunk data has {} unread bytes", &token, len - pos);
+                report_inconsistency!("{} chunk data has {} unread bytes", &token, len - pos);
             } else {
                 trace!("chunk reading done");
             }
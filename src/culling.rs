@@ -0,0 +1,101 @@
+use nalgebra::{Point3, Vector3};
+#[cfg(feature = "adt")]
+use adt::{M2Placement, WmoPlacement};
+
+// object-space bounds as stored in model headers (M2 header, WMO MOHD)
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LocalBounds {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+    pub radius: f32,
+}
+
+// conservative world-space bounding sphere of a placed object
+pub use geom::BoundingSphere as CullingBounds;
+
+impl LocalBounds {
+    pub fn from_box(min: Point3<f32>, max: Point3<f32>) -> Self {
+        let radius = (max - min).norm() / 2.0;
+        LocalBounds { min, max, radius }
+    }
+
+    // smallest box containing both, with the radius of a sphere around its center
+    pub fn union(&self, other: &LocalBounds) -> LocalBounds {
+        LocalBounds::from_box(
+            Point3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            Point3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        )
+    }
+
+    // radius of a sphere around the model origin which contains the model
+    // regardless of its rotation
+    pub fn radius_from_origin(&self) -> f32 {
+        let farthest = Vector3::new(
+            self.min.x.abs().max(self.max.x.abs()),
+            self.min.y.abs().max(self.max.y.abs()),
+            self.min.z.abs().max(self.max.z.abs()),
+        );
+        farthest.norm().max(self.radius)
+    }
+}
+
+#[cfg(feature = "adt")]
+impl M2Placement {
+    pub fn culling_bounds(&self, model: &LocalBounds) -> CullingBounds {
+        CullingBounds {
+            center: self.world_position(),
+            radius: model.radius_from_origin() * self.scale(),
+        }
+    }
+}
+
+#[cfg(feature = "adt")]
+impl WmoPlacement {
+    // MODF already carries the transformed bounding box, so no file access is needed
+    pub fn culling_bounds(&self) -> CullingBounds {
+        self.world_aabb().bounding_sphere()
+    }
+}
+
+#[cfg(all(test, feature = "adt"))]
+mod tests {
+    use super::*;
+    use adt::MAP_CENTER;
+
+    #[test]
+    fn test_wmo_culling_bounds() {
+        let placement = WmoPlacement {
+            name_id: 0,
+            unique_id: 0,
+            position: Point3::new(10.0, 0.0, 10.0),
+            rotation: Vector3::new(0.0, 0.0, 0.0),
+            bounding_box_min: Point3::new(0.0, -3.0, 0.0),
+            bounding_box_max: Point3::new(8.0, 3.0, 6.0),
+            flags: 0,
+            doodad_set: 0,
+            name_set: 0,
+        };
+
+        let bounds = placement.culling_bounds();
+        assert_eq!(bounds.center, Point3::new(MAP_CENTER - 3.0, MAP_CENTER - 4.0, 0.0));
+        assert_eq!(bounds.radius, (64.0f32 + 36.0 + 36.0).sqrt() / 2.0);
+    }
+
+    #[test]
+    fn test_m2_culling_bounds() {
+        let placement = M2Placement {
+            name_id: 0,
+            unique_id: 0,
+            position: Point3::new(0.0, 5.0, 0.0),
+            rotation: Vector3::new(0.0, 90.0, 0.0),
+            scale: 2048,
+            flags: 0,
+        };
+        let model = LocalBounds::from_box(Point3::new(-1.0, -2.0, 0.0), Point3::new(1.0, 2.0, 2.0));
+
+        let bounds = placement.culling_bounds(&model);
+        assert_eq!(bounds.center, Point3::new(MAP_CENTER, MAP_CENTER, 5.0));
+        assert_eq!(bounds.radius, 6.0);
+    }
+}
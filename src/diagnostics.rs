@@ -0,0 +1,148 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use chunked::Token;
+
+// a warning raised while loading, with where it was found
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub file: Option<String>,
+    // the chunk the warning is about, or else the one last read before it was raised
+    pub token: Option<Token>,
+    // of that chunk's header, from the start of its file
+    pub offset: Option<u64>,
+    pub message: String,
+}
+
+// collects the warnings of loads which have it in their `LoadOptions`;
+// clones share the same list, so one collector can span several loads and the
+// threads they run on, e.g. the workers of a `TileLoader`
+#[derive(Clone, Default)]
+pub struct Diagnostics(Arc<Mutex<Vec<Diagnostic>>>);
+
+struct Scope {
+    diagnostics: Diagnostics,
+    file: String,
+    chunk: Option<(Token, u64)>,
+}
+
+thread_local! {
+    static SCOPE: RefCell<Option<Scope>> = const { RefCell::new(None) };
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    pub fn push(&self, diagnostic: Diagnostic) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).push(diagnostic);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_vec(&self) -> Vec<Diagnostic> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    // the diagnostics so far, leaving the collector empty
+    pub fn take(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        ::std::mem::take(&mut *diagnostics)
+    }
+
+    // runs `load`, recording the warnings it raises on this thread against `file`
+    pub fn collect<T, F: FnOnce() -> T>(&self, file: &str, load: F) -> T {
+        struct Restore(Option<Scope>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let previous = self.0.take();
+                SCOPE.with(|scope| *scope.borrow_mut() = previous);
+            }
+        }
+
+        let scope = Scope { diagnostics: self.clone(), file: file.to_owned(), chunk: None };
+        let previous = SCOPE.with(|current| current.borrow_mut().replace(scope));
+        let _restore = Restore(previous);
+        load()
+    }
+}
+
+impl fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Diagnostics({})", self.len())
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            write!(f, "{}: ", file)?;
+        }
+        match (self.token, self.offset) {
+            (Some(token), Some(offset)) => write!(f, "{} at {}: ", token, offset)?,
+            (None, Some(offset)) => write!(f, "at {}: ", offset)?,
+            _ => {}
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+// called by the chunk iterators, so warnings can point at the chunk being parsed
+pub fn enter_chunk(token: Token, offset: u64) {
+    SCOPE.with(|scope| if let Some(ref mut scope) = *scope.borrow_mut() {
+        scope.chunk = Some((token, offset));
+    });
+}
+
+// records a warning with the collector of the running load, if any. without an
+// explicit chunk the one last read is used
+pub fn report(chunk: Option<(Token, u64)>, message: &str) {
+    SCOPE.with(|scope| if let Some(ref scope) = *scope.borrow() {
+        let chunk = chunk.or(scope.chunk);
+        scope.diagnostics.push(Diagnostic {
+            file: Some(scope.file.clone()),
+            token: chunk.map(|(token, _)| token),
+            offset: chunk.map(|(_, offset)| offset),
+            message: message.to_owned(),
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunked::Chunked;
+
+    #[test]
+    fn test_collect() {
+        let diagnostics = Diagnostics::new();
+        let data = b"REVM\x04\0\0\0\x12\0\0\0KNCM\x0c\0\0\0hdr\0TVCM\x00\0\0\0";
+
+        diagnostics.collect("test.adt", || {
+            for chunk in Chunked::over(data) {
+                let chunk = chunk.unwrap();
+                if chunk.token == Token::MCNK {
+                    for subchunk in chunk.subchunks(4).unwrap() {
+                        subchunk.unwrap();
+                    }
+                }
+            }
+            report_warn!("odd MCVT");
+        });
+        // nothing is collected outside of `collect`
+        report(None, "ignored");
+
+        let diagnostics = diagnostics.take();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].token, diagnostics[0].offset), (Some(Token::MCVT), Some(12 + 8 + 4)));
+        assert_eq!(diagnostics[0].to_string(), "test.adt: MCVT at 24: odd MCVT");
+    }
+}
@@ -0,0 +1,149 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+use chunked::Token;
+
+pub type Result<T> = result::Result<T, Error>;
+
+// what format loaders return. internal helpers still work with io::Result; a
+// `wow::Error` passing through them rides inside an io::Error and comes back
+// out unchanged when converted (see the `From` impls below)
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    UnsupportedVersion { format: &'static str, found: u32 },
+    // the file doesn't start with the magic of `format`
+    BadMagic { format: &'static str, found: [u8; 4] },
+    // a header field holds a value the loader doesn't know, e.g. a BLP encoding
+    Unsupported { format: &'static str, field: &'static str, found: u32 },
+    // e.g. a mipmap level past the last one
+    OutOfRange { what: &'static str, index: usize, count: usize },
+    // everything a validator found wrong, see `blp::validate`
+    Invalid { format: &'static str, issues: Vec<String> },
+    // the data needs a Cargo feature which isn't enabled
+    FeatureDisabled(&'static str),
+    // `offset` is where the chunk header starts, relative to where iteration started
+    MalformedChunk { token: Option<Token>, offset: u64, reason: String },
+    MissingChunk { format: &'static str, token: Token },
+    // any other inconsistency in the parsed data
+    InvalidData(String),
+    // a size read from the file is over the `Limits` in effect
+    LimitExceeded { what: &'static str, size: u64, limit: u64 },
+    // adds the file which failed to load
+    InFile { name: String, source: Box<Error> },
+}
+
+impl Error {
+    pub fn in_file<S: Into<String>>(self, name: S) -> Error {
+        match self {
+            // keep the innermost name, that's the file which is actually broken
+            Error::InFile { .. } => self,
+            error => Error::InFile { name: name.into(), source: Box::new(error) },
+        }
+    }
+
+    // the error without file context
+    pub fn root(&self) -> &Error {
+        match *self {
+            Error::InFile { ref source, .. } => source.root(),
+            ref error => error,
+        }
+    }
+
+    pub fn kind(&self) -> io::ErrorKind {
+        match *self {
+            Error::Io(ref error) => error.kind(),
+            Error::MalformedChunk { .. } => io::ErrorKind::UnexpectedEof,
+            Error::OutOfRange { .. } => io::ErrorKind::InvalidInput,
+            Error::FeatureDisabled(_) => io::ErrorKind::Other,
+            Error::InFile { ref source, .. } => source.kind(),
+            _ => io::ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref error) => write!(f, "{}", error),
+            Error::UnsupportedVersion { format, found } => write!(f, "unsupported {} version {}", format, found),
+            Error::BadMagic { format, found } => write!(f, "not a {} file (starts with {:?})", format, String::from_utf8_lossy(&found)),
+            Error::Unsupported { format, field, found } => write!(f, "unsupported {} {} {}", format, field, found),
+            Error::OutOfRange { what, index, count } => write!(f, "{} {} is out of range, there are {}", what, index, count),
+            Error::Invalid { format, ref issues } => write!(f, "invalid {}: {}", format, issues.join("; ")),
+            Error::FeatureDisabled(feature) => write!(f, "this needs the `{}` feature", feature),
+            Error::MalformedChunk { token: Some(token), offset, ref reason } =>
+                write!(f, "malformed {} chunk at offset {}: {}", token, offset, reason),
+            Error::MalformedChunk { token: None, offset, ref reason } =>
+                write!(f, "malformed chunk at offset {}: {}", offset, reason),
+            Error::MissingChunk { format, token } => write!(f, "{} chunk not found in {} file", token, format),
+            Error::InvalidData(ref message) => write!(f, "{}", message),
+            Error::LimitExceeded { what, size, limit } => write!(f, "{} of {} bytes exceeds the limit of {}", what, size, limit),
+            Error::InFile { ref name, ref source } => write!(f, "{}: {}", name, source),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref error) => Some(error),
+            Error::InFile { ref source, .. } => Some(&**source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        // `into_inner` would drop the details of os errors, so look first
+        if !error.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            return Error::Io(error);
+        }
+
+        let kind = error.kind();
+        match error.into_inner().map(|inner| inner.downcast::<Error>()) {
+            Some(Ok(inner)) => *inner,
+            Some(Err(inner)) => Error::Io(io::Error::new(kind, inner)),
+            None => Error::Io(kind.into()),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        match error {
+            Error::Io(error) => error,
+            error => io::Error::new(error.kind(), error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_round_trip() {
+        let error = Error::UnsupportedVersion { format: "WMO", found: 16 };
+        let wrapped: io::Error = error.into();
+        assert_eq!(wrapped.kind(), io::ErrorKind::InvalidData);
+
+        match Error::from(wrapped).in_file("a.wmo") {
+            Error::InFile { ref name, ref source } => {
+                assert_eq!(name, "a.wmo");
+                match **source {
+                    Error::UnsupportedVersion { found: 16, .. } => {}
+                    ref other => panic!("unexpected {:?}", other),
+                }
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        match Error::from(io_error!(NotFound, "gone")) {
+            Error::Io(ref error) => assert_eq!(error.kind(), io::ErrorKind::NotFound),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
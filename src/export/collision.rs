@@ -0,0 +1,208 @@
+// walkable geometry of an area as one triangle soup in world space, the input
+// recast-style navmesh generators take
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use nalgebra::{Matrix4, Point3};
+use adt::MapTile;
+use m2::{self, Model};
+use reader::ResourceReader;
+use version::GameVersion;
+use wmo::{self, MapObject, MeshGroup};
+
+#[derive(Clone, Debug, Default)]
+pub struct Collision {
+    pub vertices: Vec<Point3<f32>>,
+    // triangle list indexing into `vertices`
+    pub indices: Vec<u32>,
+}
+
+// loads the models placed on tiles, caching their collision by name
+pub struct CollisionBuilder {
+    reader: Arc<dyn ResourceReader>,
+    version: Option<GameVersion>,
+    models: HashMap<String, Arc<Collision>>,
+    // None for objects which failed to load, so they're reported only once
+    objects: HashMap<String, Option<Arc<(MapObject, Collision)>>>,
+}
+
+impl Collision {
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn append(&mut self, other: &Collision, transform: &Matrix4<f32>) {
+        let base = self.vertices.len() as u32;
+
+        self.vertices.extend(other.vertices.iter().map(|vertex| {
+            Point3::from_homogeneous(transform * vertex.to_homogeneous()).unwrap_or(*vertex)
+        }));
+        self.indices.extend(other.indices.iter().map(|&index| base + index));
+    }
+
+    // terrain of the loaded chunks, leaving out holes
+    pub fn add_terrain(&mut self, tile: &MapTile) {
+        for chunk in tile.chunks.iter().filter(|chunk| chunk.heights.len() == 145) {
+            let base = self.vertices.len() as u32;
+            self.vertices.extend((0..chunk.heights.len()).map(|index| chunk.vertex_position(index)));
+
+            let indices = &mut self.indices;
+            chunk.each_solid_triangle(true, |(a, b, c)| {
+                indices.extend_from_slice(&[base + a as u32, base + b as u32, base + c as u32]);
+            });
+        }
+    }
+
+    // the simplified collision mesh of a model (not its render geometry), in model space
+    pub fn from_model(model: &Model) -> Collision {
+        Collision {
+            vertices: model.collision_vertices.clone(),
+            indices: model.collision_indices.iter().map(|&index| index as u32).collect(),
+        }
+    }
+
+    // faces of a group which block movement according to MOPY. groups with a bsp tree
+    // only collide with the faces it references, so others are left out as well
+    pub fn from_group(group: &MeshGroup) -> Collision {
+        let referenced: Option<HashSet<u16>> = if group.bsp.nodes.is_empty() {
+            None
+        } else {
+            Some(group.bsp.face_refs.iter().cloned().collect())
+        };
+
+        let indices = group.indexes.chunks(3)
+            .zip(group.faces.iter())
+            .enumerate()
+            .filter(|&(face, (triangle, info))| {
+                triangle.len() == 3 && info.is_collidable()
+                    && referenced.as_ref().is_none_or(|referenced| referenced.contains(&(face as u16)))
+            })
+            .flat_map(|(_, (triangle, _))| triangle.iter().map(|&index| index as u32))
+            .collect();
+
+        Collision {
+            vertices: group.vertices.iter().map(|v| Point3::new(v.0, v.1, v.2)).collect(),
+            indices,
+        }
+    }
+}
+
+impl CollisionBuilder {
+    pub fn new(reader: Arc<dyn ResourceReader>, version: Option<GameVersion>) -> CollisionBuilder {
+        CollisionBuilder { reader, version, models: HashMap::new(), objects: HashMap::new() }
+    }
+
+    // terrain and placed models of a tile
+    pub fn tile(&mut self, tile: &MapTile) -> Collision {
+        let mut soup = Collision::default();
+        soup.add_terrain(tile);
+        self.add_placements(&mut soup, tile);
+        soup
+    }
+
+    // models and map objects placed on a tile (MDDF/MODF), the latter with their doodads
+    pub fn add_placements(&mut self, soup: &mut Collision, tile: &MapTile) {
+        for placement in tile.m2_placements.iter() {
+            if let Some(name) = tile.m2.get(placement.name_id as usize) {
+                let model = self.model(&m2::model_file_name(name));
+                soup.append(&model, &placement.world_transform());
+            }
+        }
+
+        for placement in tile.wmo_placements.iter() {
+            if let Some(name) = tile.wmo.get(placement.name_id as usize) {
+                self.add_map_object(soup, name, placement.doodad_set as usize, &placement.world_transform());
+            }
+        }
+    }
+
+    // collision of a model in model space; empty if it fails to load
+    pub fn model(&mut self, name: &str) -> Arc<Collision> {
+        let (reader, version) = (&self.reader, self.version);
+        self.models.entry(name.to_lowercase()).or_insert_with(|| {
+            match m2::load(reader.clone(), name, version) {
+                Ok(model) => Arc::new(Collision::from_model(&model)),
+                Err(error) => {
+                    report_warn!("No collision for {}: {}", name, error);
+                    Arc::new(Collision::default())
+                }
+            }
+        }).clone()
+    }
+
+    // a map object's groups and the doodads of `doodad_set`, transformed by `transform`
+    pub fn add_map_object(&mut self, soup: &mut Collision, name: &str, doodad_set: usize, transform: &Matrix4<f32>) {
+        let object = match self.map_object(name) {
+            Some(object) => object,
+            None => return,
+        };
+        let (ref root, ref groups) = *object;
+        soup.append(groups, transform);
+
+        for doodad in root.doodads_for_set(doodad_set) {
+            if let Some(name) = doodad.model.and_then(|index| root.m2.get(index)) {
+                let model = self.model(&m2::model_file_name(name));
+                soup.append(&model, &(transform * doodad.transform()));
+            }
+        }
+    }
+
+    fn map_object(&mut self, name: &str) -> Option<Arc<(MapObject, Collision)>> {
+        let (reader, version) = (&self.reader, self.version);
+        self.objects.entry(name.to_lowercase()).or_insert_with(|| {
+            let load = || -> ::error::Result<(MapObject, Collision)> {
+                let root = wmo::load(reader.clone(), name, version)?;
+                let mut collision = Collision::default();
+                for group in root.groups.iter() {
+                    collision.append(&Collision::from_group(&group.load(reader.clone())?), &Matrix4::identity());
+                }
+                Ok((root, collision))
+            };
+
+            match load() {
+                Ok(object) => Some(Arc::new(object)),
+                Err(error) => {
+                    report_warn!("No collision for {}: {}", name, error);
+                    None
+                }
+            }
+        }).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adt::{MapChunk, Holes};
+
+    #[test]
+    fn test_terrain_collision() {
+        let chunk = |holes| MapChunk {
+            index_x: 0,
+            index_y: 0,
+            position: Point3::new(0.0, 0.0, 0.0),
+            heights: vec![0.0; 145],
+            normals: Vec::new(),
+            holes,
+            texture_layers: Vec::new(),
+            liquids: Vec::new(),
+        };
+        let tile = MapTile {
+            textures: Vec::new(),
+            m2: Vec::new(),
+            wmo: Vec::new(),
+            m2_placements: Vec::new(),
+            wmo_placements: Vec::new(),
+            chunks: vec![chunk(Holes::LowRes(0)), chunk(Holes::LowRes(0b11))],
+        };
+
+        let mut soup = Collision::default();
+        soup.add_terrain(&tile);
+        assert_eq!(soup.vertices.len(), 2 * 145);
+        assert_eq!(soup.triangle_count(), 64 * 4 + (64 - 8) * 4);
+
+        let mut moved = Collision::default();
+        moved.append(&soup, &Matrix4::new_translation(&::nalgebra::Vector3::new(0.0, 0.0, 5.0)));
+        assert_eq!(moved.vertices[0], Point3::new(0.0, 0.0, 5.0));
+        assert_eq!(moved.indices, soup.indices);
+    }
+}
@@ -0,0 +1,578 @@
+// glTF 2.0 export as self-contained .glb files, with textures embedded as png.
+// geometry is written in its native Z-up coordinates below a root node rotating
+// the scene into glTF's Y-up space
+use std::collections::HashMap;
+use image::ImageOutputFormat;
+use serde_json::Value;
+use asset::{Asset, LoadOptions};
+use adt::MapTile;
+use blp::Image;
+use m2::{Model, BlendMode, MaterialFlags};
+use m2::sequence::SequenceFlags;
+use m2::skin::SkinProfile;
+use m2::track::{M2Track, Interpolation};
+use reader::ResourceReader;
+use wmo::{MapObject, MeshGroup};
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_BYTE: u32 = 5121;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// -90 degrees around X, as (x, y, z, w)
+const Z_UP_TO_Y_UP: [f32; 4] = [-0.70710677, 0.0, 0.0, 0.70710677];
+
+// a root file with its groups, in the order given. `textures` resolves the
+// material textures; without it materials are left untextured
+pub fn map_object(root: &MapObject, groups: &[MeshGroup], textures: Option<&dyn ResourceReader>) -> Vec<u8> {
+    let mut builder = Builder::new(textures);
+    let mut materials = HashMap::new();
+    let mut children = Vec::new();
+
+    for (index, group) in groups.iter().enumerate() {
+        let positions: Vec<f32> = group.vertices.iter().flat_map(|v| vec![v.0, v.1, v.2]).collect();
+        let normals: Vec<f32> = group.normals.iter().flat_map(|n| vec![n.0, n.1, n.2]).collect();
+
+        let mut attributes = json!({ "POSITION": builder.floats(&positions, "VEC3", Some(ARRAY_BUFFER)) });
+        if normals.len() == positions.len() {
+            attributes["NORMAL"] = json!(builder.floats(&normals, "VEC3", Some(ARRAY_BUFFER)));
+        }
+        if let Some(texcoords) = group.texcoords.first().filter(|texcoords| texcoords.len() * 3 == positions.len()) {
+            let texcoords: Vec<f32> = texcoords.iter().flat_map(|t| vec![t.0, t.1]).collect();
+            attributes["TEXCOORD_0"] = json!(builder.floats(&texcoords, "VEC2", Some(ARRAY_BUFFER)));
+        }
+
+        let primitives: Vec<Value> = group.batches.iter().filter_map(|batch| {
+            let start = batch.index_start as usize;
+            let indices = group.indexes.get(start..start + batch.index_count as usize)?;
+            let indices = builder.indices_u16(indices);
+
+            let material_id = batch.material_id as usize;
+            let material = *materials.entry(material_id).or_insert_with(|| {
+                let texture = root.materials.get(material_id)
+                    .and_then(|material| material.texture_id)
+                    .and_then(|id| root.textures.get(id))
+                    .and_then(|name| builder.texture(name));
+                builder.material(&format!("material{}", material_id), texture, "MASK", false)
+            });
+
+            Some(json!({ "attributes": attributes, "indices": indices, "material": material }))
+        }).collect();
+
+        if primitives.is_empty() {
+            continue;
+        }
+
+        let mesh = builder.push_mesh(json!({ "name": format!("group{}", index), "primitives": primitives }));
+        children.push(builder.push_node(json!({ "name": format!("group{}", index), "mesh": mesh })));
+    }
+
+    builder.finish(children)
+}
+
+// terrain geometry of the loaded chunks, in world coordinates. texture layers are
+// blended through alpha maps, which has no glTF equivalent, so it's left untextured
+pub fn terrain(tile: &MapTile) -> Vec<u8> {
+    let mut builder = Builder::new(None);
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut indices = Vec::new();
+
+    for chunk in tile.chunks.iter() {
+        let base = (positions.len() / 3) as u32;
+
+        for index in 0..chunk.heights.len() {
+            let position = chunk.vertex_position(index);
+            positions.extend_from_slice(&[position.x, position.y, position.z]);
+
+            let normal = chunk.normals.get(index).map(|n| [n.x, n.y, n.z]).unwrap_or([0.0, 0.0, 1.0]);
+            normals.extend_from_slice(&normal);
+        }
+
+        if chunk.heights.len() == 145 {
+            chunk.each_solid_triangle(true, |(a, b, c)| {
+                indices.extend_from_slice(&[base + a as u32, base + b as u32, base + c as u32]);
+            });
+        }
+    }
+
+    let mut children = Vec::new();
+    if !indices.is_empty() {
+        let attributes = json!({
+            "POSITION": builder.floats(&positions, "VEC3", Some(ARRAY_BUFFER)),
+            "NORMAL": builder.floats(&normals, "VEC3", Some(ARRAY_BUFFER)),
+        });
+        let indices = builder.indices_u32(&indices);
+        let mesh = builder.push_mesh(json!({ "name": "terrain", "primitives": [{ "attributes": attributes, "indices": indices }] }));
+        children.push(builder.push_node(json!({ "name": "terrain", "mesh": mesh })));
+    }
+
+    builder.finish(children)
+}
+
+// a model with one of its skin profiles. bones become a glTF skin and every sequence
+// with keyframes in the model becomes an animation; those kept in .anim files are skipped
+pub fn model(model: &Model, skin: &SkinProfile, textures: Option<&dyn ResourceReader>) -> Vec<u8> {
+    let mut builder = Builder::new(textures);
+    let mesh = model.build_mesh(skin);
+    let skinned = !model.bones.is_empty();
+
+    let mut positions = Vec::with_capacity(mesh.vertices.len() * 3);
+    let mut normals = Vec::with_capacity(mesh.vertices.len() * 3);
+    let mut texcoords = Vec::with_capacity(mesh.vertices.len() * 2);
+    let mut joints = Vec::with_capacity(mesh.vertices.len() * 4);
+    let mut weights = Vec::with_capacity(mesh.vertices.len() * 4);
+
+    for vertex in mesh.vertices.iter() {
+        positions.extend_from_slice(&vertex.position);
+        normals.extend_from_slice(&vertex.normal);
+        texcoords.extend_from_slice(&vertex.tex_coords[0]);
+
+        // weights must add up to one, which unweighted vertices don't
+        if vertex.bone_weights.iter().all(|&weight| weight == 0) {
+            joints.extend_from_slice(&[0; 4]);
+            weights.extend_from_slice(&[255, 0, 0, 0]);
+        } else {
+            joints.extend_from_slice(&vertex.bone_indices);
+            weights.extend_from_slice(&vertex.bone_weights);
+        }
+    }
+
+    let mut children = Vec::new();
+
+    if !positions.is_empty() {
+        let mut attributes = json!({
+            "POSITION": builder.floats(&positions, "VEC3", Some(ARRAY_BUFFER)),
+            "NORMAL": builder.floats(&normals, "VEC3", Some(ARRAY_BUFFER)),
+            "TEXCOORD_0": builder.floats(&texcoords, "VEC2", Some(ARRAY_BUFFER)),
+        });
+        if skinned {
+            attributes["JOINTS_0"] = json!(builder.bytes(&joints, "VEC4", false));
+            attributes["WEIGHTS_0"] = json!(builder.bytes(&weights, "VEC4", true));
+        }
+
+        let primitives: Vec<Value> = mesh.batches.iter().filter_map(|batch| {
+            let start = batch.index_start as usize;
+            let indices = mesh.indices.get(start..start + batch.index_count as usize)?;
+            let indices = builder.indices_u16(indices);
+
+            let texture = batch.texture_files.first()
+                .and_then(|name| name.as_ref())
+                .and_then(|name| builder.texture(name));
+            let (alpha_mode, double_sided) = match batch.material.map(|index| &model.materials[index]) {
+                Some(material) => (
+                    match material.blend_mode {
+                        BlendMode::Opaque => "OPAQUE",
+                        BlendMode::AlphaKey => "MASK",
+                        _ => "BLEND",
+                    },
+                    material.flags.contains(MaterialFlags::TWO_SIDED),
+                ),
+                None => ("OPAQUE", false),
+            };
+            let material = builder.material(&format!("submesh{}", batch.submesh_id), texture, alpha_mode, double_sided);
+
+            Some(json!({ "attributes": attributes, "indices": indices, "material": material }))
+        }).collect();
+
+        if !primitives.is_empty() {
+            let mesh = builder.push_mesh(json!({ "name": model.name, "primitives": primitives }));
+            let mut node = json!({ "name": model.name, "mesh": mesh });
+            if skinned {
+                node["skin"] = json!(0);
+            }
+            children.push(builder.push_node(node));
+        }
+    }
+
+    if skinned {
+        children.extend(skeleton(&mut builder, model));
+    }
+
+    builder.finish(children)
+}
+
+// adds a node per bone, the skin using them and the animations; returns the root bones.
+// a bone node sits at its pivot relative to its parent's, so with an inverse bind
+// matrix undoing the pivot, node transforms match the bone matrices of the client
+fn skeleton(builder: &mut Builder, model: &Model) -> Vec<usize> {
+    let first = builder.nodes.len();
+    let parent_of = |index: usize| model.bones[index].parent.map(|parent| parent as usize).filter(|&parent| parent < model.bones.len());
+    let rest = |index: usize| {
+        let pivot = model.bones[index].pivot.coords;
+        match parent_of(index) {
+            Some(parent) => pivot - model.bones[parent].pivot.coords,
+            None => pivot,
+        }
+    };
+
+    let mut roots = Vec::new();
+    for (index, bone) in model.bones.iter().enumerate() {
+        let rest = rest(index);
+        let children: Vec<usize> = (0..model.bones.len())
+            .filter(|&child| parent_of(child) == Some(index))
+            .map(|child| first + child)
+            .collect();
+
+        let mut node = json!({ "name": format!("bone{}", index), "translation": [rest.x, rest.y, rest.z] });
+        if !children.is_empty() {
+            node["children"] = json!(children);
+        }
+        builder.push_node(node);
+
+        if bone.parent.is_none() || parent_of(index).is_none() {
+            roots.push(first + index);
+        }
+    }
+
+    let mut inverse_binds = Vec::with_capacity(model.bones.len() * 16);
+    for bone in model.bones.iter() {
+        let pivot = bone.pivot.coords;
+        inverse_binds.extend_from_slice(&[
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            -pivot.x, -pivot.y, -pivot.z, 1.0,
+        ]);
+    }
+    let inverse_binds = builder.floats(&inverse_binds, "MAT4", None);
+    let joints: Vec<usize> = (first..first + model.bones.len()).collect();
+    builder.skins.push(json!({ "joints": joints, "inverseBindMatrices": inverse_binds }));
+
+    for (index, sequence) in model.sequences.iter().enumerate() {
+        // aliases share the keyframes of another sequence
+        let data_index = if sequence.flags.contains(SequenceFlags::IS_ALIAS) { sequence.alias_next as usize } else { index };
+
+        let mut channels = Channels::default();
+
+        for (bone_index, bone) in model.bones.iter().enumerate() {
+            let node = first + bone_index;
+            let rest = rest(bone_index);
+
+            let translation = |value: &::nalgebra::Vector3<f32>| vec![rest.x + value.x, rest.y + value.y, rest.z + value.z];
+            channels.add(builder, node, "translation", &bone.translation, data_index, translation);
+            channels.add(builder, node, "rotation", &bone.rotation, data_index, |value| {
+                let q = value.to_quaternion().normalize();
+                vec![q.i, q.j, q.k, q.w]
+            });
+            channels.add(builder, node, "scale", &bone.scale, data_index, |value| vec![value.x, value.y, value.z]);
+        }
+
+        if !channels.channels.is_empty() {
+            let name = format!("{}_{}", sequence.id, sequence.variation_index);
+            builder.animations.push(json!({ "name": name, "channels": channels.channels, "samplers": channels.samplers }));
+        }
+    }
+
+    roots
+}
+
+// the channels of one animation, with a sampler each
+#[derive(Default)]
+struct Channels {
+    channels: Vec<Value>,
+    samplers: Vec<Value>,
+}
+
+impl Channels {
+    // adds a channel for one bone track, if it has keyframes in `sequence`
+    fn add<T, F>(&mut self, builder: &mut Builder, node: usize, path: &str, track: &M2Track<T>, sequence: usize, convert: F)
+        where F: Fn(&T) -> Vec<f32>
+    {
+        let (timestamps, values) = track.keyframes(sequence);
+        if timestamps.is_empty() || timestamps.len() != values.len() {
+            return;
+        }
+
+        let times: Vec<f32> = timestamps.iter().map(|&ms| ms as f32 / 1000.0).collect();
+        let values: Vec<f32> = values.iter().flat_map(convert).collect();
+        let interpolation = match track.interpolation {
+            Interpolation::None => "STEP",
+            _ => "LINEAR",
+        };
+        // rotations are quaternions, translations and scales vectors
+        let kind = if path == "rotation" { "VEC4" } else { "VEC3" };
+
+        let input = builder.floats(&times, "SCALAR", None);
+        let output = builder.floats(&values, kind, None);
+        self.channels.push(json!({ "sampler": self.samplers.len(), "target": { "node": node, "path": path } }));
+        self.samplers.push(json!({ "input": input, "output": output, "interpolation": interpolation }));
+    }
+}
+
+struct Builder<'a> {
+    reader: Option<&'a dyn ResourceReader>,
+    binary: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    meshes: Vec<Value>,
+    nodes: Vec<Value>,
+    materials: Vec<Value>,
+    textures: Vec<Value>,
+    images: Vec<Value>,
+    skins: Vec<Value>,
+    animations: Vec<Value>,
+    // texture indices by lowercase file name; None if it failed to load
+    loaded_textures: HashMap<String, Option<usize>>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(reader: Option<&'a dyn ResourceReader>) -> Builder<'a> {
+        Builder {
+            reader,
+            binary: Vec::new(),
+            buffer_views: Vec::new(),
+            accessors: Vec::new(),
+            meshes: Vec::new(),
+            nodes: Vec::new(),
+            materials: Vec::new(),
+            textures: Vec::new(),
+            images: Vec::new(),
+            skins: Vec::new(),
+            animations: Vec::new(),
+            loaded_textures: HashMap::new(),
+        }
+    }
+
+    fn view(&mut self, data: &[u8], target: Option<u32>) -> usize {
+        // accessors must be aligned to their component size
+        while !self.binary.len().is_multiple_of(4) {
+            self.binary.push(0);
+        }
+
+        let mut view = json!({ "buffer": 0, "byteOffset": self.binary.len(), "byteLength": data.len() });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.binary.extend_from_slice(data);
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    fn accessor(&mut self, view: usize, component_type: u32, count: usize, kind: &str) -> usize {
+        self.accessors.push(json!({ "bufferView": view, "componentType": component_type, "count": count, "type": kind }));
+        self.accessors.len() - 1
+    }
+
+    // min and max are included, which positions and animation inputs require
+    fn floats(&mut self, values: &[f32], kind: &str, target: Option<u32>) -> usize {
+        let components = components(kind);
+        let data: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+
+        let view = self.view(&data, target);
+        let accessor = self.accessor(view, FLOAT, values.len() / components, kind);
+        if kind != "MAT4" && !values.is_empty() {
+            let mut min = values[..components].to_vec();
+            let mut max = min.clone();
+            for element in values.chunks(components) {
+                for (component, &value) in element.iter().enumerate() {
+                    min[component] = min[component].min(value);
+                    max[component] = max[component].max(value);
+                }
+            }
+            self.accessors[accessor]["min"] = json!(min);
+            self.accessors[accessor]["max"] = json!(max);
+        }
+        accessor
+    }
+
+    fn bytes(&mut self, values: &[u8], kind: &str, normalized: bool) -> usize {
+        let view = self.view(values, Some(ARRAY_BUFFER));
+        let accessor = self.accessor(view, UNSIGNED_BYTE, values.len() / components(kind), kind);
+        if normalized {
+            self.accessors[accessor]["normalized"] = json!(true);
+        }
+        accessor
+    }
+
+    fn indices_u16(&mut self, indices: &[u16]) -> usize {
+        let data: Vec<u8> = indices.iter().flat_map(|index| index.to_le_bytes()).collect();
+        let view = self.view(&data, Some(ELEMENT_ARRAY_BUFFER));
+        self.accessor(view, UNSIGNED_SHORT, indices.len(), "SCALAR")
+    }
+
+    fn indices_u32(&mut self, indices: &[u32]) -> usize {
+        let data: Vec<u8> = indices.iter().flat_map(|index| index.to_le_bytes()).collect();
+        let view = self.view(&data, Some(ELEMENT_ARRAY_BUFFER));
+        self.accessor(view, UNSIGNED_INT, indices.len(), "SCALAR")
+    }
+
+    // loads a blp through the reader and embeds it as png, once per file
+    fn texture(&mut self, name: &str) -> Option<usize> {
+        let reader = self.reader?;
+        let key = name.to_lowercase();
+        if let Some(&texture) = self.loaded_textures.get(&key) {
+            return texture;
+        }
+
+        let png = Image::load(reader, name, &LoadOptions::default())
+            .map_err(|e| e.to_string())
+            .and_then(|image| image.to_dynamic_image().map_err(|e| e.to_string()))
+            .and_then(|image| {
+                let mut png = Vec::new();
+                image.write_to(&mut png, ImageOutputFormat::Png).map(|_| png).map_err(|e| e.to_string())
+            });
+
+        let texture = match png {
+            Ok(png) => {
+                let view = self.view(&png, None);
+                self.images.push(json!({ "name": name, "bufferView": view, "mimeType": "image/png" }));
+                self.textures.push(json!({ "source": self.images.len() - 1 }));
+                Some(self.textures.len() - 1)
+            }
+            Err(e) => {
+                report_warn!("Failed to embed texture {}: {}", name, e);
+                None
+            }
+        };
+        self.loaded_textures.insert(key, texture);
+        texture
+    }
+
+    fn material(&mut self, name: &str, texture: Option<usize>, alpha_mode: &str, double_sided: bool) -> usize {
+        let mut pbr = json!({ "metallicFactor": 0.0, "roughnessFactor": 1.0 });
+        if let Some(texture) = texture {
+            pbr["baseColorTexture"] = json!({ "index": texture });
+        }
+        // untextured materials would come out blank when masked
+        let alpha_mode = if texture.is_none() && alpha_mode == "MASK" { "OPAQUE" } else { alpha_mode };
+
+        self.materials.push(json!({
+            "name": name,
+            "pbrMetallicRoughness": pbr,
+            "alphaMode": alpha_mode,
+            "doubleSided": double_sided,
+        }));
+        self.materials.len() - 1
+    }
+
+    fn push_mesh(&mut self, mesh: Value) -> usize {
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
+    }
+
+    fn push_node(&mut self, node: Value) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    // the glb file, with `children` put below the coordinate system root
+    fn finish(mut self, children: Vec<usize>) -> Vec<u8> {
+        let mut root = json!({ "name": "root", "rotation": Z_UP_TO_Y_UP });
+        if !children.is_empty() {
+            root["children"] = json!(children);
+        }
+        let root = self.push_node(root);
+
+        let mut document = json!({
+            "asset": { "version": "2.0", "generator": "wow-rs" },
+            "scene": 0,
+            "scenes": [{ "nodes": [root] }],
+            "nodes": self.nodes,
+        });
+        {
+            let mut set = |key: &str, values: Vec<Value>| if !values.is_empty() {
+                document[key] = Value::Array(values);
+            };
+            set("meshes", self.meshes);
+            set("materials", self.materials);
+            set("textures", self.textures);
+            set("images", self.images);
+            set("skins", self.skins);
+            set("animations", self.animations);
+            set("accessors", self.accessors);
+            set("bufferViews", self.buffer_views);
+        }
+
+        while !self.binary.len().is_multiple_of(4) {
+            self.binary.push(0);
+        }
+        if !self.binary.is_empty() {
+            document["buffers"] = json!([{ "byteLength": self.binary.len() }]);
+        }
+
+        let mut json = document.to_string().into_bytes();
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+
+        let binary_chunk = if self.binary.is_empty() { 0 } else { 8 + self.binary.len() };
+        let mut output = Vec::with_capacity(12 + 8 + json.len() + binary_chunk);
+        output.extend_from_slice(b"glTF");
+        output.extend_from_slice(&2u32.to_le_bytes());
+        output.extend_from_slice(&((12 + 8 + json.len() + binary_chunk) as u32).to_le_bytes());
+        output.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        output.extend_from_slice(b"JSON");
+        output.extend_from_slice(&json);
+        if !self.binary.is_empty() {
+            output.extend_from_slice(&(self.binary.len() as u32).to_le_bytes());
+            output.extend_from_slice(b"BIN\0");
+            output.extend_from_slice(&self.binary);
+        }
+        output
+    }
+}
+
+fn components(kind: &str) -> usize {
+    match kind {
+        "SCALAR" => 1,
+        "VEC2" => 2,
+        "VEC3" => 3,
+        "VEC4" => 4,
+        "MAT4" => 16,
+        _ => unreachable!("unknown accessor type {}", kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use byteorder::{ReadBytesExt, LE};
+    use nalgebra::Point3;
+    use adt::{MapChunk, Holes};
+
+    #[test]
+    fn test_terrain_glb() {
+        let chunk = MapChunk {
+            index_x: 0,
+            index_y: 0,
+            position: Point3::new(100.0, 200.0, 10.0),
+            heights: vec![1.0; 145],
+            normals: Vec::new(),
+            // cuts out the top left 2x2 squares
+            holes: Holes::LowRes(1),
+            texture_layers: Vec::new(),
+            liquids: Vec::new(),
+        };
+        let tile = MapTile {
+            textures: Vec::new(),
+            m2: Vec::new(),
+            wmo: Vec::new(),
+            m2_placements: Vec::new(),
+            wmo_placements: Vec::new(),
+            chunks: vec![chunk],
+        };
+
+        let glb = terrain(&tile);
+        let mut input = &glb[..];
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic).unwrap();
+        assert_eq!(&magic, b"glTF");
+        assert_eq!(input.read_u32::<LE>().unwrap(), 2);
+        assert_eq!(input.read_u32::<LE>().unwrap() as usize, glb.len());
+
+        let json_length = input.read_u32::<LE>().unwrap() as usize;
+        input.read_exact(&mut magic).unwrap();
+        assert_eq!(&magic, b"JSON");
+        let document: Value = ::serde_json::from_slice(&input[..json_length]).unwrap();
+
+        let indices = document["meshes"][0]["primitives"][0]["indices"].as_u64().unwrap() as usize;
+        assert_eq!(document["accessors"][indices]["count"], json!((64 - 4) * 4 * 3));
+        assert_eq!(document["accessors"][0]["max"], json!([100.0, 200.0, 11.0]));
+        assert_eq!(document["buffers"][0]["byteLength"].as_u64().unwrap() as usize, glb.len() - 12 - 8 - json_length - 8);
+    }
+}
@@ -0,0 +1,8 @@
+// conversion of loaded assets into formats other tools understand
+
+#[cfg(feature = "gltf")]
+pub mod gltf;
+#[cfg(feature = "blp")]
+pub mod obj;
+#[cfg(feature = "m2")]
+pub mod collision;
@@ -0,0 +1,249 @@
+// Wavefront OBJ export. textures are referenced as png files next to the mtl,
+// under the path `texture_path` gives; converting them is up to the caller
+// (e.g. with `Image::save_png`)
+use std::collections::HashMap;
+use std::fmt::Write;
+use rgb::RGBA8;
+use asset::{Asset, LoadOptions};
+use adt::{MapTile, MAP_CHUNKS_PER_SIDE};
+use blp::Image;
+use blp::compose::Canvas;
+use reader::{ResourceReader, split_resource_name};
+use wmo::{MapObject, MeshGroup};
+
+// terrain textures wrap this many times across a chunk
+pub const TEXTURE_REPEATS_PER_CHUNK: u32 = 8;
+
+#[derive(Clone, Debug, Default)]
+pub struct Obj {
+    pub obj: String,
+    // contents of the file `obj` refers to with `mtllib`
+    pub mtl: String,
+}
+
+// groups become objects with a group per material batch
+pub fn map_object(root: &MapObject, groups: &[MeshGroup], mtl_name: &str) -> Obj {
+    let mut output = Obj::new(mtl_name);
+    let mut materials = Vec::new();
+    let mut base = 1;
+
+    for (index, group) in groups.iter().enumerate() {
+        let _ = writeln!(output.obj, "o group{}", index);
+
+        for vertex in group.vertices.iter() {
+            let _ = writeln!(output.obj, "v {} {} {}", vertex.0, vertex.1, vertex.2);
+        }
+        let texcoords = group.texcoords.first().filter(|texcoords| texcoords.len() == group.vertices.len());
+        for texcoord in texcoords.iter().flat_map(|texcoords| texcoords.iter()) {
+            let _ = writeln!(output.obj, "vt {} {}", texcoord.0, 1.0 - texcoord.1);
+        }
+        let has_normals = group.normals.len() == group.vertices.len();
+        if has_normals {
+            for normal in group.normals.iter() {
+                let _ = writeln!(output.obj, "vn {} {} {}", normal.0, normal.1, normal.2);
+            }
+        }
+
+        for batch in group.batches.iter() {
+            let start = batch.index_start as usize;
+            let indices = match group.indexes.get(start..start + batch.index_count as usize) {
+                Some(indices) => indices,
+                None => continue,
+            };
+
+            if !materials.contains(&batch.material_id) {
+                materials.push(batch.material_id);
+            }
+            let _ = writeln!(output.obj, "g group{}_material{}", index, batch.material_id);
+            let _ = writeln!(output.obj, "usemtl material{}", batch.material_id);
+            for triangle in indices.chunks(3).filter(|triangle| triangle.len() == 3) {
+                output.face([triangle[0] as usize, triangle[1] as usize, triangle[2] as usize], base, texcoords.is_some(), has_normals);
+            }
+        }
+
+        base += group.vertices.len();
+    }
+
+    materials.sort();
+    for material_id in materials {
+        let texture = root.materials.get(material_id as usize)
+            .and_then(|material| material.texture_id)
+            .and_then(|id| root.textures.get(id));
+        output.material(&format!("material{}", material_id), texture.map(|name| texture_path(name)).as_deref());
+    }
+
+    output
+}
+
+// terrain of the loaded chunks in world coordinates, with texture coordinates spanning
+// the whole tile as laid out by `bake_terrain`. `texture` is the file name of the baked image
+pub fn terrain(tile: &MapTile, mtl_name: &str, texture: Option<&str>) -> Obj {
+    let mut output = Obj::new(mtl_name);
+    let grid_size = (MAP_CHUNKS_PER_SIDE * 8) as f32;
+    let mut base = 1;
+
+    let _ = writeln!(output.obj, "o terrain");
+    for chunk in tile.chunks.iter() {
+        if chunk.heights.len() != 145 {
+            continue;
+        }
+
+        for index in 0..chunk.heights.len() {
+            let position = chunk.vertex_position(index);
+            let _ = writeln!(output.obj, "v {} {} {}", position.x, position.y, position.z);
+        }
+        for index in 0..chunk.heights.len() {
+            let (row, column) = (index / 17, index % 17);
+            let (row, column) = if column < 9 { (row as f32, column as f32) } else { (row as f32 + 0.5, (column - 9) as f32 + 0.5) };
+            let u = (chunk.index_x as f32 * 8.0 + column) / grid_size;
+            let v = (chunk.index_y as f32 * 8.0 + row) / grid_size;
+            let _ = writeln!(output.obj, "vt {} {}", u, 1.0 - v);
+        }
+        let has_normals = chunk.normals.len() == chunk.heights.len();
+        if has_normals {
+            for normal in chunk.normals.iter() {
+                let _ = writeln!(output.obj, "vn {} {} {}", normal.x, normal.y, normal.z);
+            }
+        }
+
+        let _ = writeln!(output.obj, "usemtl terrain");
+        chunk.each_solid_triangle(true, |(a, b, c)| {
+            output.face([a as usize, b as usize, c as usize], base, true, has_normals);
+        });
+
+        base += chunk.heights.len();
+    }
+
+    output.material("terrain", texture);
+    output
+}
+
+// blends the texture layers of every loaded chunk into one image of the whole tile,
+// `chunk_size` pixels per chunk. textures which fail to load are left out
+pub fn bake_terrain(tile: &MapTile, reader: &dyn ResourceReader, chunk_size: u32) -> Canvas {
+    let mut canvas = Canvas::new(chunk_size * MAP_CHUNKS_PER_SIDE, chunk_size * MAP_CHUNKS_PER_SIDE);
+    let mut textures: HashMap<u32, Option<(Vec<RGBA8>, u32, u32)>> = HashMap::new();
+    // smallest mipmap still at least as big as one repeat
+    let repeat_size = (chunk_size / TEXTURE_REPEATS_PER_CHUNK).max(1);
+
+    for chunk in tile.chunks.iter() {
+        let (left, top) = (chunk.index_x * chunk_size, chunk.index_y * chunk_size);
+
+        for (layer_index, layer) in chunk.texture_layers.iter().enumerate() {
+            let texture = textures.entry(layer.texture_id).or_insert_with(|| {
+                let name = tile.textures.get(layer.texture_id as usize)?;
+                let decoded = Image::load(reader, name, &LoadOptions::default()).map_err(|e| e.to_string()).and_then(|image| {
+                    let level = (0..image.mipmap_count())
+                        .take_while(|&level| image.mipmap_size(level).0 >= repeat_size)
+                        .last()
+                        .unwrap_or(0);
+                    let (width, height) = image.mipmap_size(level);
+                    let pixels = image.decode_mipmap(level).map_err(|e| e.to_string())?;
+                    Ok((pixels.to_vec(), width, height))
+                });
+                decoded.map_err(|e| report_warn!("Failed to load terrain texture {}: {}", name, e)).ok()
+            });
+            let (pixels, width, height) = match *texture {
+                Some((ref pixels, width, height)) if width > 0 && height > 0 => (pixels, width, height),
+                _ => continue,
+            };
+
+            for y in 0..chunk_size {
+                for x in 0..chunk_size {
+                    // the first layer is opaque, the others are blended by their alpha map
+                    let alpha = match layer.alpha_map {
+                        _ if layer_index == 0 => 1.0,
+                        Some(ref alpha_map) => alpha_map.get_f32((y * 64 / chunk_size * 64 + x * 64 / chunk_size) as usize),
+                        None => continue,
+                    };
+
+                    let source_x = x * TEXTURE_REPEATS_PER_CHUNK * width / chunk_size % width;
+                    let source_y = y * TEXTURE_REPEATS_PER_CHUNK * height / chunk_size % height;
+                    let source = pixels[(source_y * width + source_x) as usize];
+                    let target = &mut canvas.pixels[((top + y) * canvas.width + left + x) as usize];
+
+                    let mix = |t: u8, s: u8| (t as f32 + (s as f32 - t as f32) * alpha).round() as u8;
+                    *target = RGBA8 { r: mix(target.r, source.r), g: mix(target.g, source.g), b: mix(target.b, source.b), a: 255 };
+                }
+            }
+        }
+    }
+
+    canvas
+}
+
+// where the mtl expects the png version of a texture, relative to itself
+pub fn texture_path(name: &str) -> String {
+    let (directory, stem, _) = split_resource_name(name);
+    let path = format!("{}{}.png", directory, stem);
+    path.replace('\\', "/")
+}
+
+impl Obj {
+    fn new(mtl_name: &str) -> Obj {
+        let mut output = Obj::default();
+        let _ = writeln!(output.obj, "mtllib {}", mtl_name);
+        output
+    }
+
+    // `indices` are zero based within the vertices starting at `base`
+    fn face(&mut self, indices: [usize; 3], base: usize, texcoords: bool, normals: bool) {
+        let _ = write!(self.obj, "f");
+        for &index in indices.iter() {
+            let index = base + index;
+            let _ = match (texcoords, normals) {
+                (true, true) => write!(self.obj, " {0}/{0}/{0}", index),
+                (true, false) => write!(self.obj, " {0}/{0}", index),
+                (false, true) => write!(self.obj, " {0}//{0}", index),
+                (false, false) => write!(self.obj, " {}", index),
+            };
+        }
+        let _ = writeln!(self.obj);
+    }
+
+    fn material(&mut self, name: &str, texture: Option<&str>) {
+        let _ = writeln!(self.mtl, "newmtl {}", name);
+        let _ = writeln!(self.mtl, "Kd 1 1 1");
+        if let Some(texture) = texture {
+            let _ = writeln!(self.mtl, "map_Kd {}", texture);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point3;
+    use adt::{MapChunk, Holes};
+
+    #[test]
+    fn test_terrain_obj() {
+        let chunk = MapChunk {
+            index_x: 1,
+            index_y: 0,
+            position: Point3::new(100.0, 200.0, 10.0),
+            heights: vec![0.5; 145],
+            normals: Vec::new(),
+            holes: Holes::HighRes(u64::MAX >> 1),
+            texture_layers: Vec::new(),
+            liquids: Vec::new(),
+        };
+        let tile = MapTile {
+            textures: Vec::new(),
+            m2: Vec::new(),
+            wmo: Vec::new(),
+            m2_placements: Vec::new(),
+            wmo_placements: Vec::new(),
+            chunks: vec![chunk],
+        };
+
+        let output = terrain(&tile, "tile.mtl", Some("tile.png"));
+        let lines: Vec<&str> = output.obj.lines().collect();
+        assert_eq!(&lines[..3], &["mtllib tile.mtl", "o terrain", "v 100 200 10.5"]);
+        assert_eq!(lines[2 + 145], "vt 0.0625 1");
+        // only the last square is left
+        assert_eq!(&lines[lines.len() - 4..], &["f 127/127 128/128 136/136", "f 128/128 145/145 136/136", "f 145/145 144/144 136/136", "f 144/144 127/127 136/136"][..]);
+        assert_eq!(output.mtl, "newmtl terrain\nKd 1 1 1\nmap_Kd tile.png\n");
+        assert_eq!(texture_path("Tileset\\Grass.blp"), "Tileset/Grass.png");
+    }
+}
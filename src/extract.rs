@@ -0,0 +1,417 @@
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io::{self, Read, Write, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use adt::{MapTile, ChunkRect, Holes};
+use asset::LoadOptions;
+use reader::{ResourceReader, split_resource_name};
+use wdt;
+use export::collision::{Collision, CollisionBuilder};
+
+const EXTRACT_FORMAT_VERSION: u32 = 1;
+const DBC_HEADER_SIZE: usize = 20;
+
+bitflags! {
+    pub struct Targets: u32 {
+        // terrain heights and holes per tile
+        const MAPS = 0x1;
+        // world-space collision triangles per tile
+        const VMAPS = 0x2;
+        // client database tables
+        const DBC = 0x4;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    // little-endian binary (maps, vmaps) or the original file (dbc)
+    Binary,
+    Csv,
+}
+
+#[derive(Clone, Debug)]
+pub struct EmulatorTargets {
+    pub targets: Targets,
+    // internal map names, e.g. "Azeroth"
+    pub maps: Vec<String>,
+    // e.g. "DBFilesClient\\Map.dbc"
+    pub dbc_files: Vec<String>,
+    pub map_format: OutputFormat,
+    pub dbc_format: OutputFormat,
+    pub threads: usize,
+    // keep outputs of a previous (possibly interrupted) run instead of rewriting them
+    pub resume: bool,
+    // limits, parse mode and diagnostics for every file loaded, on every worker;
+    // each output is loaded under its report label
+    pub load_options: LoadOptions,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ExtractReport {
+    pub written: Vec<PathBuf>,
+    pub skipped: usize,
+    pub failures: Vec<(String, String)>,
+}
+
+enum Job {
+    // heights, holes and liquids
+    Map { map: String, coords: (u32, u32), big_alpha: bool },
+    Vmap { map: String, coords: (u32, u32), big_alpha: bool },
+    Dbc { name: String },
+}
+
+impl EmulatorTargets {
+    pub fn new(maps: Vec<String>, dbc_files: Vec<String>) -> Self {
+        EmulatorTargets {
+            targets: Targets::all(),
+            maps,
+            dbc_files,
+            map_format: OutputFormat::Binary,
+            dbc_format: OutputFormat::Binary,
+            threads: 4,
+            resume: true,
+            load_options: LoadOptions::default(),
+        }
+    }
+}
+
+impl ExtractReport {
+    fn merge(&mut self, other: ExtractReport) {
+        self.written.extend(other.written);
+        self.skipped += other.skipped;
+        self.failures.extend(other.failures);
+    }
+}
+
+// extracts everything a server emulator needs in one run. every output is written
+// through a temporary file, so an interrupted run can be resumed without leaving
+// truncated files behind
+pub fn emulator<R, P>(reader: Arc<R>, out_dir: P, targets: &EmulatorTargets) -> io::Result<ExtractReport>
+where
+    R: ResourceReader + Send + Sync + 'static,
+    P: AsRef<Path>
+{
+    let out_dir = out_dir.as_ref().to_owned();
+    let mut report = ExtractReport::default();
+    let mut jobs = Vec::new();
+
+    for &(target, dir) in &[(Targets::MAPS, "maps"), (Targets::VMAPS, "vmaps"), (Targets::DBC, "dbc")] {
+        if targets.targets.contains(target) {
+            fs::create_dir_all(out_dir.join(dir))?;
+        }
+    }
+
+    if targets.targets.intersects(Targets::MAPS | Targets::VMAPS) {
+        for map in targets.maps.iter() {
+            let shared: Arc<dyn ResourceReader> = reader.clone();
+            let name = wdt::map_name(map);
+            let wdt = match targets.load_options.apply(&name, || wdt::load(shared, &name)) {
+                Ok(wdt) => wdt,
+                Err(error) => {
+                    warn!("Skipping map {}: {}", map, error);
+                    report.failures.push((map.clone(), error.to_string()));
+                    continue;
+                }
+            };
+
+            for coords in wdt.existing_tiles() {
+                let big_alpha = wdt.big_alpha();
+                if targets.targets.contains(Targets::MAPS) {
+                    jobs.push(Job::Map { map: map.clone(), coords, big_alpha });
+                }
+                if targets.targets.contains(Targets::VMAPS) {
+                    jobs.push(Job::Vmap { map: map.clone(), coords, big_alpha });
+                }
+            }
+        }
+    }
+
+    if targets.targets.contains(Targets::DBC) {
+        jobs.extend(targets.dbc_files.iter().map(|name| Job::Dbc { name: name.clone() }));
+    }
+
+    // workers pop from the end, so reverse to process jobs in submission order
+    jobs.reverse();
+    let jobs = Arc::new(Mutex::new(jobs));
+
+    let workers: Vec<_> = (0..targets.threads.max(1)).map(|_| {
+        let reader = reader.clone();
+        let jobs = jobs.clone();
+        let out_dir = out_dir.clone();
+        let targets = targets.clone();
+
+        thread::spawn(move || {
+            let reader: Arc<dyn ResourceReader> = reader;
+            let mut report = ExtractReport::default();
+            let mut models = CollisionBuilder::new(reader.clone(), None);
+
+            loop {
+                let job = match jobs.lock() {
+                    Ok(mut jobs) => jobs.pop(),
+                    Err(_) => None,
+                };
+                let job = match job {
+                    Some(job) => job,
+                    None => break,
+                };
+
+                let (label, path) = output_path(&job, &out_dir, &targets);
+
+                if targets.resume && path.exists() {
+                    trace!("{:?} already exists", &path);
+                    report.skipped += 1;
+                    continue;
+                }
+
+                let result = targets.load_options.apply(&label, || match job {
+                    Job::Map { ref map, coords, big_alpha } => {
+                        extract_map(&reader, map, coords, big_alpha, targets.map_format)
+                    }
+                    Job::Vmap { ref map, coords, big_alpha } => {
+                        extract_vmap(&reader, map, coords, big_alpha, &mut models)
+                    }
+                    Job::Dbc { ref name } => extract_dbc(&reader, name, targets.dbc_format),
+                });
+
+                match result.and_then(|data| write_output(&path, &data)) {
+                    Ok(()) => {
+                        info!("extracted {}", &label);
+                        report.written.push(path);
+                    }
+                    Err(error) => {
+                        warn!("Unable to extract {}: {}", &label, error);
+                        report.failures.push((label, error.to_string()));
+                    }
+                }
+            }
+
+            report
+        })
+    }).collect();
+
+    for worker in workers {
+        let worker_report = worker.join()
+            .map_err(|_| io_error!(Other, "extraction worker panicked"))?;
+        report.merge(worker_report);
+    }
+
+    Ok(report)
+}
+
+fn tile_name(map: &str, coords: (u32, u32)) -> String {
+    format!("World\\Maps\\{0}\\{0}_{1}_{2}.adt", map, coords.0, coords.1)
+}
+
+// (label for the report, output file)
+fn output_path(job: &Job, out_dir: &Path, targets: &EmulatorTargets) -> (String, PathBuf) {
+    match *job {
+        Job::Map { ref map, coords, .. } => {
+            let ext = if targets.map_format == OutputFormat::Csv { "csv" } else { "map" };
+            let file = format!("{}_{:02}_{:02}.{}", map, coords.0, coords.1, ext);
+            (tile_name(map, coords), out_dir.join("maps").join(file))
+        }
+        Job::Vmap { ref map, coords, .. } => {
+            let file = format!("{}_{:02}_{:02}.vmtile", map, coords.0, coords.1);
+            (format!("{} (collision)", tile_name(map, coords)), out_dir.join("vmaps").join(file))
+        }
+        Job::Dbc { ref name } => {
+            let (_, file, _) = split_resource_name(name);
+            let ext = if targets.dbc_format == OutputFormat::Csv { "csv" } else { "dbc" };
+            (name.clone(), out_dir.join("dbc").join(format!("{}.{}", file, ext)))
+        }
+    }
+}
+
+fn write_output(path: &Path, data: &[u8]) -> io::Result<()> {
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, data)?;
+    fs::rename(&temp_path, path)
+}
+
+fn extract_map(reader: &Arc<dyn ResourceReader>, map: &str, coords: (u32, u32), big_alpha: bool, format: OutputFormat) -> io::Result<Vec<u8>> {
+    let tile = MapTile::load(reader.clone(), &tile_name(map, coords), Some(big_alpha))?;
+
+    let holes = |holes: &Holes| match *holes {
+        Holes::LowRes(value) => (0u8, value as u64),
+        Holes::HighRes(value) => (1u8, value),
+    };
+
+    let mut output = Vec::new();
+
+    match format {
+        OutputFormat::Binary => {
+            output.write_all(b"WMAP")?;
+            output.write_u32::<LE>(EXTRACT_FORMAT_VERSION)?;
+            output.write_u32::<LE>(tile.chunks.len() as u32)?;
+
+            for chunk in tile.chunks.iter() {
+                let (holes_kind, holes_value) = holes(&chunk.holes);
+                output.write_u32::<LE>(chunk.index_x)?;
+                output.write_u32::<LE>(chunk.index_y)?;
+                for &value in &[chunk.position.x, chunk.position.y, chunk.position.z] {
+                    output.write_f32::<LE>(value)?;
+                }
+                output.write_u8(holes_kind)?;
+                output.write_u64::<LE>(holes_value)?;
+                output.write_u32::<LE>(chunk.heights.len() as u32)?;
+                for &height in chunk.heights.iter() {
+                    output.write_f32::<LE>(height)?;
+                }
+                output.write_u32::<LE>(chunk.liquids.len() as u32)?;
+                for liquid in chunk.liquids.iter() {
+                    output.write_u16::<LE>(liquid.liquid_type)?;
+                    output.write_f32::<LE>(liquid.min_height)?;
+                    output.write_f32::<LE>(liquid.max_height)?;
+                    output.write_u64::<LE>(liquid.cells)?;
+                    for &height in liquid.heights.iter() {
+                        output.write_f32::<LE>(height)?;
+                    }
+                }
+            }
+        }
+        OutputFormat::Csv => {
+            // only the first liquid layer, without its heights; liquid_type is 0 without one
+            let mut text = String::from("index_x,index_y,position_x,position_y,position_z,high_res_holes,holes,\
+                liquid_type,liquid_min_height,liquid_max_height,liquid_cells,heights\n");
+
+            for chunk in tile.chunks.iter() {
+                let (holes_kind, holes_value) = holes(&chunk.holes);
+                let _ = write!(text, "{},{},{},{},{},{},{}", chunk.index_x, chunk.index_y,
+                    chunk.position.x, chunk.position.y, chunk.position.z, holes_kind, holes_value);
+                match chunk.liquids.first() {
+                    Some(liquid) => {
+                        let _ = write!(text, ",{},{},{},{}", liquid.liquid_type, liquid.min_height, liquid.max_height, liquid.cells);
+                    }
+                    None => text.push_str(",0,0,0,0"),
+                }
+                for height in chunk.heights.iter() {
+                    let _ = write!(text, ",{}", height);
+                }
+                text.push('\n');
+            }
+
+            output = text.into_bytes();
+        }
+    }
+
+    Ok(output)
+}
+
+fn extract_vmap(reader: &Arc<dyn ResourceReader>, map: &str, coords: (u32, u32), big_alpha: bool, models: &mut CollisionBuilder) -> io::Result<Vec<u8>> {
+    let tile = MapTile::load_region(reader.clone(), &tile_name(map, coords), Some(big_alpha), ChunkRect::none())?;
+    let mut soup = Collision::default();
+    models.add_placements(&mut soup, &tile);
+
+    let mut output = Vec::new();
+    output.write_all(b"WVMT")?;
+    output.write_u32::<LE>(EXTRACT_FORMAT_VERSION)?;
+    output.write_u32::<LE>(soup.vertices.len() as u32)?;
+    for vertex in soup.vertices.iter() {
+        output.write_f32::<LE>(vertex.x)?;
+        output.write_f32::<LE>(vertex.y)?;
+        output.write_f32::<LE>(vertex.z)?;
+    }
+    output.write_u32::<LE>(soup.indices.len() as u32)?;
+    for &index in soup.indices.iter() {
+        output.write_u32::<LE>(index)?;
+    }
+
+    Ok(output)
+}
+
+fn extract_dbc(reader: &Arc<dyn ResourceReader>, name: &str, format: OutputFormat) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.open(name)?.read_to_end(&mut data)?;
+
+    if data.len() < DBC_HEADER_SIZE || &data[0..4] != b"WDBC" {
+        return Err(io_error!(InvalidData, "{} isn't a WDBC file", name));
+    }
+
+    let mut header = Cursor::new(&data[4..DBC_HEADER_SIZE]);
+    let record_count = header.read_u32::<LE>()? as usize;
+    let field_count = header.read_u32::<LE>()? as usize;
+    let record_size = header.read_u32::<LE>()? as usize;
+    let string_block_size = header.read_u32::<LE>()? as usize;
+
+    if record_size == 0 {
+        return Err(io_error!(InvalidData, "{} has empty records", name));
+    }
+
+    let records_end = record_count.checked_mul(record_size)
+        .and_then(|size| size.checked_add(DBC_HEADER_SIZE))
+        .ok_or_else(|| io_error!(InvalidData, "{} declares too many records", name))?;
+    match records_end.checked_add(string_block_size) {
+        Some(end) if end <= data.len() => {}
+        _ => return Err(io_error!(UnexpectedEof, "{} is truncated", name)),
+    }
+
+    match format {
+        OutputFormat::Binary => Ok(data),
+        OutputFormat::Csv => {
+            // fields are dumped as raw u32 values; string fields are offsets into the string block
+            let mut text = String::new();
+            let columns = field_count.min(record_size / 4);
+
+            for record in data[DBC_HEADER_SIZE..records_end].chunks(record_size) {
+                let mut cursor = Cursor::new(record);
+                for column in 0..columns {
+                    if column > 0 { text.push(',') }
+                    let _ = write!(text, "{}", cursor.read_u32::<LE>()?);
+                }
+                text.push('\n');
+            }
+
+            Ok(text.into_bytes())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m2;
+    use reader::memory::MemoryReader;
+
+    #[test]
+    fn test_output_paths() {
+        let mut targets = EmulatorTargets::new(vec!["Azeroth".to_owned()], Vec::new());
+        targets.map_format = OutputFormat::Csv;
+
+        let job = Job::Map { map: "Azeroth".to_owned(), coords: (32, 8), big_alpha: false };
+        let (label, path) = output_path(&job, Path::new("out"), &targets);
+        assert_eq!(label, "World\\Maps\\Azeroth\\Azeroth_32_8.adt");
+        assert_eq!(path, Path::new("out").join("maps").join("Azeroth_32_08.csv"));
+
+        let job = Job::Dbc { name: "DBFilesClient\\Map.dbc".to_owned() };
+        assert_eq!(output_path(&job, Path::new("out"), &targets).1, Path::new("out").join("dbc").join("Map.dbc"));
+
+        assert_eq!(m2::model_file_name("World\\Tree.MDX"), "World\\Tree.m2");
+    }
+
+    #[test]
+    fn test_extract_dbc() {
+        fn dbc(record_count: u32, record_size: u32, body: &[u8]) -> Vec<u8> {
+            let mut data = b"WDBC".to_vec();
+            for &value in &[record_count, record_size / 4, record_size, 0] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            data.extend_from_slice(body);
+            data
+        }
+
+        let mut reader = MemoryReader::new();
+        reader.insert("ok.dbc", dbc(2, 8, &[1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0]));
+        reader.insert("empty_records.dbc", dbc(2, 0, &[]));
+        reader.insert("overflow.dbc", dbc(u32::MAX, u32::MAX, &[]));
+        reader.insert("truncated.dbc", dbc(2, 8, &[0; 12]));
+        let reader: Arc<dyn ResourceReader> = Arc::new(reader);
+
+        assert_eq!(extract_dbc(&reader, "ok.dbc", OutputFormat::Csv).unwrap(), b"1,2\n3,4\n");
+        assert_eq!(extract_dbc(&reader, "empty_records.dbc", OutputFormat::Csv).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(extract_dbc(&reader, "overflow.dbc", OutputFormat::Csv).is_err());
+        assert_eq!(extract_dbc(&reader, "truncated.dbc", OutputFormat::Binary).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
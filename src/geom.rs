@@ -0,0 +1,211 @@
+use nalgebra::{Point3, Vector3, Matrix4, U1, U3};
+#[cfg(feature = "adt")]
+use adt::{MapChunk, MapTile, M2Placement, WmoPlacement, placement_to_world};
+use culling::LocalBounds;
+#[cfg(feature = "m2")]
+use m2::Model;
+#[cfg(feature = "wmo")]
+use wmo::{MapObject, MeshGroup};
+
+// the grid maps are laid out on, in world units
+pub const TILE_SIZE: f32 = 533.0 + 1.0 / 3.0;
+pub const CHUNK_SIZE: f32 = TILE_SIZE / 16.0;
+pub const UNIT_SIZE: f32 = CHUNK_SIZE / 8.0;
+pub const MAP_CENTER: f32 = TILE_SIZE * 32.0; // every map is 64x64 tiles
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Aabb {
+        Aabb { min, max }
+    }
+
+    // None if there are no points
+    pub fn from_points<I: IntoIterator<Item = Point3<f32>>>(points: I) -> Option<Aabb> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Aabb::new(first, first), |aabb, point| aabb.union(&Aabb::new(point, point))))
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        Point3::from_coordinates((self.min.coords + self.max.coords) / 2.0)
+    }
+
+    pub fn size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Point3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: Point3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+
+    pub fn contains(&self, point: &Point3<f32>) -> bool {
+        (0..3).all(|axis| point[axis] >= self.min[axis] && point[axis] <= self.max[axis])
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.max[axis] && other.min[axis] <= self.max[axis])
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Point3::new(a.x, a.y, a.z), Point3::new(b.x, a.y, a.z), Point3::new(a.x, b.y, a.z), Point3::new(b.x, b.y, a.z),
+            Point3::new(a.x, a.y, b.z), Point3::new(b.x, a.y, b.z), Point3::new(a.x, b.y, b.z), Point3::new(b.x, b.y, b.z),
+        ]
+    }
+
+    // box around the transformed corners, so rotations make it grow
+    pub fn transform(&self, transform: &Matrix4<f32>) -> Aabb {
+        let corners = self.corners();
+        Aabb::from_points(corners.iter().map(|corner| transform_point(transform, corner))).unwrap_or(*self)
+    }
+
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere { center: self.center(), radius: self.size().norm() / 2.0 }
+    }
+}
+
+impl BoundingSphere {
+    pub fn new(center: Point3<f32>, radius: f32) -> BoundingSphere {
+        BoundingSphere { center, radius }
+    }
+
+    pub fn extent_min(&self) -> Point3<f32> {
+        Point3::new(self.center.x - self.radius, self.center.y - self.radius, self.center.z - self.radius)
+    }
+
+    pub fn extent_max(&self) -> Point3<f32> {
+        Point3::new(self.center.x + self.radius, self.center.y + self.radius, self.center.z + self.radius)
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::new(self.extent_min(), self.extent_max())
+    }
+
+    pub fn contains(&self, point: &Point3<f32>) -> bool {
+        (point - self.center).norm() <= self.radius
+    }
+
+    pub fn intersects(&self, center: &Point3<f32>, radius: f32) -> bool {
+        (center - self.center).norm() <= self.radius + radius
+    }
+
+    // the radius grows with the largest scale along any axis
+    pub fn transform(&self, transform: &Matrix4<f32>) -> BoundingSphere {
+        let scale = (0..3)
+            .map(|axis| transform.fixed_slice::<U3, U1>(0, axis).norm())
+            .fold(0.0f32, f32::max);
+        BoundingSphere { center: transform_point(transform, &self.center), radius: self.radius * scale }
+    }
+}
+
+impl LocalBounds {
+    pub fn aabb(&self) -> Aabb {
+        Aabb::new(self.min, self.max)
+    }
+
+    // centered on the model origin, see `radius_from_origin`
+    pub fn sphere_around_origin(&self) -> BoundingSphere {
+        BoundingSphere::new(Point3::origin(), self.radius_from_origin())
+    }
+}
+
+#[cfg(feature = "adt")]
+impl MapChunk {
+    // world space; just the chunk corner if no heights are loaded
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points((0..self.heights.len()).map(|index| self.vertex_position(index)))
+            .unwrap_or_else(|| Aabb::new(self.position, self.position))
+    }
+}
+
+#[cfg(feature = "adt")]
+impl MapTile {
+    // world space box of the loaded chunks
+    pub fn aabb(&self) -> Option<Aabb> {
+        let mut chunks = self.chunks.iter().map(|chunk| chunk.aabb());
+        let first = chunks.next()?;
+        Some(chunks.fold(first, |aabb, chunk| aabb.union(&chunk)))
+    }
+}
+
+#[cfg(feature = "wmo")]
+impl MeshGroup {
+    // group space, from the MOGP header
+    pub fn aabb(&self) -> Aabb {
+        self.bounds.aabb()
+    }
+}
+
+#[cfg(feature = "wmo")]
+impl MapObject {
+    // object space, from MOHD
+    pub fn aabb(&self) -> Aabb {
+        self.header.bounds().aabb()
+    }
+}
+
+#[cfg(feature = "m2")]
+impl Model {
+    // model space, from the header
+    pub fn aabb(&self) -> Aabb {
+        self.bounds.aabb()
+    }
+}
+
+#[cfg(feature = "adt")]
+impl M2Placement {
+    // world space box of a model with the model space box `model`
+    pub fn world_aabb(&self, model: &Aabb) -> Aabb {
+        model.transform(&self.world_transform())
+    }
+}
+
+#[cfg(feature = "adt")]
+impl WmoPlacement {
+    // MODF already carries the transformed box, so no file access is needed
+    pub fn world_aabb(&self) -> Aabb {
+        let a = placement_to_world(&self.bounding_box_min);
+        let b = placement_to_world(&self.bounding_box_max);
+        Aabb::new(a, a).union(&Aabb::new(b, b))
+    }
+}
+
+fn transform_point(transform: &Matrix4<f32>, point: &Point3<f32>) -> Point3<f32> {
+    Point3::from_homogeneous(transform * point.to_homogeneous()).unwrap_or(*point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transform() {
+        let aabb = Aabb::new(Point3::new(-1.0, -1.0, 0.0), Point3::new(1.0, 1.0, 2.0));
+        let transform = Matrix4::new_translation(&Vector3::new(10.0, 0.0, 0.0)) * Matrix4::new_scaling(3.0);
+
+        assert_eq!(aabb.transform(&transform), Aabb::new(Point3::new(7.0, -3.0, 0.0), Point3::new(13.0, 3.0, 6.0)));
+        assert!(aabb.intersects(&Aabb::new(Point3::new(1.0, 1.0, 2.0), Point3::new(5.0, 5.0, 5.0))));
+        assert!(!aabb.contains(&Point3::new(0.0, 0.0, -0.5)));
+
+        let sphere = aabb.bounding_sphere().transform(&transform);
+        assert_eq!(sphere.center, Point3::new(10.0, 0.0, 3.0));
+        assert!((sphere.radius - 3.0 * 3.0f32.sqrt()).abs() < 1e-5);
+    }
+}
@@ -1,21 +1,98 @@
+// the helpers shared by format modules go partly unused when only some are enabled
+#![cfg_attr(not(all(feature = "blp", feature = "adt", feature = "m2", feature = "wmo")), allow(dead_code, unused_macros, unused_imports))]
+
 #[macro_use]
 extern crate log;
 extern crate byteorder;
+#[cfg(feature = "rgb")]
 extern crate rgb;
 #[macro_use]
 extern crate bitflags;
+#[cfg(feature = "nalgebra")]
 extern crate nalgebra;
+extern crate bytemuck;
+#[macro_use]
+extern crate wow_derive;
+#[cfg(feature = "jpeg")]
+extern crate jpeg_decoder;
+#[cfg(feature = "image")]
+extern crate image;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "cache")]
+extern crate bincode;
+#[cfg(any(feature = "cache", feature = "cdn"))]
+extern crate flate2;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "cdn")]
+extern crate ureq;
+#[cfg(feature = "zip")]
+extern crate zip;
+#[cfg(feature = "zip")]
+extern crate tar;
+#[cfg(feature = "gltf")]
+#[macro_use]
+extern crate serde_json;
 
 mod misc;
+mod records;
 #[macro_use]
 mod read_ext;
+#[macro_use]
+mod write_ext;
+pub mod bytes;
+pub mod error;
+pub mod limits;
+#[macro_use]
+pub mod observer;
+pub mod version;
+pub mod asset;
+pub mod manager;
+pub mod diagnostics;
 pub mod reader;
 #[macro_use]
 mod chunked_old;
 pub mod chunked;
+#[cfg(feature = "blp")]
 pub mod blp;
+#[cfg(feature = "m2")]
 pub mod m2;
+#[cfg(feature = "wmo")]
 pub mod wmo;
+#[cfg(feature = "adt")]
 pub mod alpha_map;
+#[cfg(feature = "adt")]
+pub mod liquid;
+#[cfg(feature = "adt")]
 pub mod adt;
+#[cfg(feature = "wdt")]
 pub mod wdt;
+pub mod analyze;
+// shared by the formats with geometry
+#[cfg(feature = "nalgebra")]
+pub mod geom;
+#[cfg(feature = "nalgebra")]
+pub mod culling;
+#[cfg(all(feature = "adt", feature = "threads"))]
+pub mod streaming;
+#[cfg(all(feature = "wdt", feature = "m2", feature = "wmo"))]
+pub mod world;
+#[cfg(feature = "wdt")]
+pub mod unique_ids;
+#[cfg(any(feature = "m2", feature = "wmo"))]
+pub mod light;
+#[cfg(all(feature = "fs", feature = "threads", feature = "wdt", feature = "m2", feature = "wmo"))]
+pub mod extract;
+#[cfg(all(feature = "adt", feature = "wmo"))]
+pub mod export;
+#[cfg(feature = "cache")]
+pub mod cache;
+
+#[cfg(test)]
+mod test_util;
+
+pub use error::{Error, Result};
@@ -0,0 +1,156 @@
+use nalgebra::{Point3, Vector3, Matrix4};
+#[cfg(feature = "wmo")]
+use nalgebra::UnitQuaternion;
+use rgb::RGB;
+#[cfg(feature = "adt")]
+use adt::{M2Placement, WmoPlacement};
+#[cfg(feature = "m2")]
+use m2;
+#[cfg(feature = "wmo")]
+use wmo;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LightKind {
+    Omni,
+    Spot,
+    Directional,
+    Ambient,
+}
+
+// light source in world space, regardless of the format it came from
+#[derive(Clone, Debug)]
+pub struct SceneLight {
+    pub kind: LightKind,
+    pub position: Point3<f32>,
+    pub direction: Option<Vector3<f32>>,
+    pub color: RGB<f32>,
+    pub intensity: f32,
+    pub attenuation: Option<(f32, f32)>,
+}
+
+impl LightKind {
+    pub fn from_wmo(value: u8) -> Self {
+        match value {
+            1 => LightKind::Spot,
+            2 => LightKind::Directional,
+            3 => LightKind::Ambient,
+            _ => LightKind::Omni,
+        }
+    }
+
+    pub fn from_m2(value: u16) -> Self {
+        match value {
+            0 => LightKind::Directional,
+            _ => LightKind::Omni,
+        }
+    }
+}
+
+impl SceneLight {
+    // `transform` maps object space into world space (see `WmoPlacement::world_transform`)
+    #[cfg(feature = "wmo")]
+    pub fn from_wmo_light(light: &wmo::Light, transform: &Matrix4<f32>) -> Self {
+        // lights are assumed to point down their local -Z axis
+        let direction = match light.kind {
+            LightKind::Spot | LightKind::Directional if light.rotation.norm() > 0.0 => {
+                let rotation = UnitQuaternion::from_quaternion(light.rotation);
+                Some(transform_vector(transform, &(rotation * -Vector3::z())).normalize())
+            }
+            _ => None,
+        };
+
+        SceneLight {
+            kind: light.kind,
+            position: transform_point(transform, &light.position),
+            direction,
+            color: RGB {
+                r: light.color.r as f32 / 255.0,
+                g: light.color.g as f32 / 255.0,
+                b: light.color.b as f32 / 255.0,
+            },
+            intensity: light.intensity,
+            attenuation: if light.use_attenuation {
+                Some((light.attenuation_start, light.attenuation_end))
+            } else {
+                None
+            },
+        }
+    }
+
+    // bone animation isn't applied; lights are taken at their rest position
+    #[cfg(feature = "m2")]
+    pub fn from_m2_light(light: &m2::Light, transform: &Matrix4<f32>) -> Self {
+        let direction = if light.kind == LightKind::Directional {
+            Some(transform_vector(transform, &-Vector3::z()).normalize())
+        } else {
+            None
+        };
+
+        SceneLight {
+            kind: light.kind,
+            position: transform_point(transform, &light.position),
+            direction,
+            color: RGB {
+                r: light.diffuse_color.x,
+                g: light.diffuse_color.y,
+                b: light.diffuse_color.z,
+            },
+            intensity: light.diffuse_intensity,
+            attenuation: Some((light.attenuation_start, light.attenuation_end)),
+        }
+    }
+}
+
+#[cfg(all(feature = "adt", feature = "wmo"))]
+pub fn wmo_scene_lights(map_object: &wmo::MapObject, placement: &WmoPlacement) -> Vec<SceneLight> {
+    let transform = placement.world_transform();
+    map_object.lights.iter().map(|light| SceneLight::from_wmo_light(light, &transform)).collect()
+}
+
+#[cfg(all(feature = "adt", feature = "m2"))]
+pub fn m2_scene_lights(lights: &[m2::Light], placement: &M2Placement) -> Vec<SceneLight> {
+    let transform = placement.world_transform();
+    lights.iter()
+        .filter(|light| light.visible)
+        .map(|light| SceneLight::from_m2_light(light, &transform))
+        .collect()
+}
+
+fn transform_point(transform: &Matrix4<f32>, point: &Point3<f32>) -> Point3<f32> {
+    let result = transform * point.to_homogeneous();
+    Point3::new(result.x / result.w, result.y / result.w, result.z / result.w)
+}
+
+fn transform_vector(transform: &Matrix4<f32>, vector: &Vector3<f32>) -> Vector3<f32> {
+    let result = transform * vector.to_homogeneous();
+    Vector3::new(result.x, result.y, result.z)
+}
+
+#[cfg(all(test, feature = "wmo"))]
+mod tests {
+    use super::*;
+    use nalgebra::Quaternion;
+    use rgb::RGBA8;
+
+    #[test]
+    fn test_wmo_light_transform() {
+        let light = wmo::Light {
+            kind: LightKind::Spot,
+            use_attenuation: true,
+            color: RGBA8 { r: 255, g: 0, b: 51, a: 255 },
+            position: Point3::new(1.0, 2.0, 3.0),
+            intensity: 2.0,
+            rotation: Quaternion::identity(),
+            attenuation_start: 1.0,
+            attenuation_end: 5.0,
+        };
+        let transform = Matrix4::new_translation(&Vector3::new(10.0, 20.0, 30.0));
+
+        let scene_light = SceneLight::from_wmo_light(&light, &transform);
+        assert_eq!(scene_light.position, Point3::new(11.0, 22.0, 33.0));
+        assert_eq!(scene_light.direction, Some(Vector3::new(0.0, 0.0, -1.0)));
+        assert_eq!(scene_light.color, RGB { r: 1.0, g: 0.0, b: 0.2 });
+        assert_eq!(scene_light.attenuation, Some((1.0, 5.0)));
+    }
+}
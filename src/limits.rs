@@ -0,0 +1,108 @@
+// caps on allocations sized by values read from a file, so a malformed one fails
+// to load instead of exhausting memory. they apply to the current thread while
+// running under `Limits::apply`; `LoadOptions` carries them into `Asset::load`
+// and onto the worker threads of `TileLoader` and `extract::emulator`
+use std::cell::Cell;
+use std::io;
+use error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Limits {
+    // bytes of a chunk read into memory
+    pub max_chunk_size: u64,
+    // bytes of one mipmap, as stored and once decoded to RGBA
+    pub max_mipmap_size: u64,
+    // bytes of a single null terminated string
+    pub max_string_length: u64,
+}
+
+thread_local! {
+    static CURRENT: Cell<Limits> = const { Cell::new(Limits::DEFAULT) };
+}
+
+impl Limits {
+    // well above anything the client ships
+    pub const DEFAULT: Limits = Limits {
+        max_chunk_size: 256 << 20,
+        max_mipmap_size: 64 << 20,
+        max_string_length: 64 << 10,
+    };
+
+    // for trusted input
+    pub const UNLIMITED: Limits = Limits {
+        max_chunk_size: u64::MAX,
+        max_mipmap_size: u64::MAX,
+        max_string_length: u64::MAX,
+    };
+
+    // the limits in effect on this thread
+    pub fn current() -> Limits {
+        CURRENT.with(Cell::get)
+    }
+
+    // runs `load` with these limits in effect on this thread
+    pub fn apply<T, F: FnOnce() -> T>(&self, load: F) -> T {
+        struct Restore(Limits);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| current.set(self.0));
+            }
+        }
+
+        let _restore = Restore(CURRENT.with(|current| current.replace(*self)));
+        load()
+    }
+
+    pub fn check_chunk_size(&self, size: u64) -> io::Result<()> {
+        check("chunk", size, self.max_chunk_size)
+    }
+
+    pub fn check_mipmap_size(&self, size: u64) -> io::Result<()> {
+        check("mipmap", size, self.max_mipmap_size)
+    }
+
+    pub fn check_string_length(&self, length: u64) -> io::Result<()> {
+        check("string", length, self.max_string_length)
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits::DEFAULT
+    }
+}
+
+fn check(what: &'static str, size: u64, limit: u64) -> io::Result<()> {
+    if size > limit {
+        Err(Error::LimitExceeded { what, size, limit }.into())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use chunked::Chunked;
+    use read_ext::ReadExt;
+
+    #[test]
+    fn test_apply() {
+        let limits = Limits { max_chunk_size: 16, max_string_length: 3, ..Limits::DEFAULT };
+        // declares 4 GiB but doesn't have them
+        let data = b"REVM\xff\xff\xff\xff\x12\0\0\0";
+
+        limits.apply(|| {
+            match Chunked::new(Cursor::new(&data[..])).next().map(|chunk| chunk.map_err(Error::from)) {
+                Some(Err(Error::LimitExceeded { what: "chunk", size: 0xffff_ffff, limit: 16 })) => {}
+                other => panic!("unexpected {:?}", other),
+            }
+            assert!(Cursor::new(b"abcd\0").read_cstring().is_err());
+            assert_eq!(Cursor::new(b"abc\0").read_cstring().unwrap(), "abc");
+        });
+        assert_eq!(Limits::current(), Limits::DEFAULT);
+    }
+}
@@ -0,0 +1,207 @@
+// terrain liquids: MCLQ inside the map chunks before wotlk, the MH2O chunk of
+// the tile from wotlk on. both end up as a 9x9 height grid and an 8x8 cell mask
+use byteorder::LE;
+use bytes::{Bytes, ParseError, Result};
+
+pub const LIQUID_VERTICES_PER_SIDE: usize = 9;
+const LIQUID_CELLS_PER_SIDE: usize = 8;
+
+// min and max height, 9x9 vertices, 8x8 tiles, flow count and two flows
+pub const MCLQ_SIZE: usize = 8 + 81 * 8 + 64 + 4 + 2 * 40;
+const MH2O_HEADER_SIZE: usize = 12;
+const MH2O_INSTANCE_SIZE: usize = 24;
+
+// LiquidType.dbc ids of the liquids MCLQ has flags for
+pub const LIQUID_WATER: u16 = 1;
+pub const LIQUID_OCEAN: u16 = 2;
+pub const LIQUID_MAGMA: u16 = 3;
+pub const LIQUID_SLIME: u16 = 4;
+
+// vertex format storing depths only, other formats start with the heights
+const LVF_DEPTH_ONLY: u16 = 2;
+// values from here on are LiquidObject ids, which this doesn't resolve
+const LVF_MAX: u16 = 42;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChunkLiquid {
+    // LiquidType.dbc id
+    pub liquid_type: u16,
+    pub min_height: f32,
+    pub max_height: f32,
+    // 9x9, row-major; vertices outside of the liquid are at `min_height`
+    pub heights: Vec<f32>,
+    // bit `row * 8 + column` is set for the cells covered by liquid
+    pub cells: u64,
+}
+
+impl ChunkLiquid {
+    fn flat(liquid_type: u16, min_height: f32, max_height: f32) -> ChunkLiquid {
+        ChunkLiquid {
+            liquid_type,
+            min_height,
+            max_height,
+            heights: vec![min_height; LIQUID_VERTICES_PER_SIDE * LIQUID_VERTICES_PER_SIDE],
+            cells: 0,
+        }
+    }
+
+    pub fn has_cell(&self, row: u32, column: u32) -> bool {
+        self.cells & (1 << (row * LIQUID_CELLS_PER_SIDE as u32 + column)) != 0
+    }
+}
+
+// one MCLQ body, without its chunk header
+pub fn read_mclq(data: &mut Bytes, liquid_type: u16) -> Result<ChunkLiquid> {
+    let min_height = data.f32::<LE>()?;
+    let max_height = data.f32::<LE>()?;
+    let mut liquid = ChunkLiquid::flat(liquid_type, min_height, max_height);
+
+    // water vertices have depth and flow, magma ones texture coordinates; the
+    // height comes after either
+    for height in liquid.heights.iter_mut() {
+        data.skip(4)?;
+        *height = data.f32::<LE>()?;
+    }
+
+    for cell in 0..(LIQUID_CELLS_PER_SIDE * LIQUID_CELLS_PER_SIDE) {
+        if data.u8()? & 0x0F != 0x0F {
+            liquid.cells |= 1 << cell;
+        }
+    }
+
+    // flows aren't used
+    data.skip(4 + 2 * 40)?;
+    Ok(liquid)
+}
+
+// the liquid layers of each of the 256 map chunks, in row-major order
+pub fn read_mh2o(data: &[u8]) -> Result<Vec<Vec<ChunkLiquid>>> {
+    let mut headers = Bytes::new(data);
+    let mut result = Vec::with_capacity(256);
+
+    for _ in 0..256 {
+        let offset_instances = headers.u32::<LE>()? as usize;
+        let layer_count = headers.u32::<LE>()? as usize;
+        headers.skip(MH2O_HEADER_SIZE - 8)?;
+
+        let mut layers = Vec::new();
+        for layer in 0..layer_count {
+            let mut instance = Bytes::new(data);
+            instance.seek(offset_instances + layer * MH2O_INSTANCE_SIZE)?;
+            layers.push(read_mh2o_instance(data, &mut instance)?);
+        }
+        result.push(layers);
+    }
+
+    Ok(result)
+}
+
+fn read_mh2o_instance(data: &[u8], instance: &mut Bytes) -> Result<ChunkLiquid> {
+    let liquid_type = instance.u16::<LE>()?;
+    let vertex_format = instance.u16::<LE>()?;
+    let min_height = instance.f32::<LE>()?;
+    let max_height = instance.f32::<LE>()?;
+    let x = instance.u8()? as usize;
+    let y = instance.u8()? as usize;
+    let width = instance.u8()? as usize;
+    let height = instance.u8()? as usize;
+    let offset_exists = instance.u32::<LE>()? as usize;
+    let offset_vertices = instance.u32::<LE>()? as usize;
+
+    if x + width > LIQUID_CELLS_PER_SIDE || y + height > LIQUID_CELLS_PER_SIDE {
+        return Err(ParseError::InvalidData { offset: instance.position() - 12, reason: "liquid exceeds its map chunk" });
+    }
+
+    let mut liquid = ChunkLiquid::flat(liquid_type, min_height, max_height);
+
+    // without a bitmap the whole rectangle is covered
+    let mut exists = Bytes::new(data);
+    let bitmap = if offset_exists != 0 {
+        exists.seek(offset_exists)?;
+        Some(exists.take((width * height).div_ceil(8))?)
+    } else {
+        None
+    };
+
+    for row in 0..height {
+        for column in 0..width {
+            let bit = row * width + column;
+            if bitmap.is_none_or(|bitmap| bitmap[bit / 8] & (1 << (bit % 8)) != 0) {
+                liquid.cells |= 1 << ((y + row) * LIQUID_CELLS_PER_SIDE + x + column);
+            }
+        }
+    }
+
+    // oceans are usually stored flat, without vertex data
+    if offset_vertices != 0 && vertex_format != LVF_DEPTH_ONLY && vertex_format < LVF_MAX {
+        let mut vertices = Bytes::new(data);
+        vertices.seek(offset_vertices)?;
+
+        for row in 0..(height + 1) {
+            for column in 0..(width + 1) {
+                liquid.heights[(y + row) * LIQUID_VERTICES_PER_SIDE + x + column] = vertices.f32::<LE>()?;
+            }
+        }
+    }
+
+    Ok(liquid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{put_u32, put_f32};
+
+    #[test]
+    fn test_read_mclq() {
+        let mut data = vec![0u8; MCLQ_SIZE];
+        put_f32(&mut data, 0, 1.0);
+        put_f32(&mut data, 4, 2.0);
+        put_f32(&mut data, 8 + 4, 1.5);
+        // every tile but the first is empty
+        for tile in data[8 + 81 * 8 + 1..8 + 81 * 8 + 64].iter_mut() {
+            *tile = 0x0F;
+        }
+
+        let liquid = read_mclq(&mut Bytes::new(&data), LIQUID_WATER).unwrap();
+        assert_eq!((liquid.min_height, liquid.max_height), (1.0, 2.0));
+        assert_eq!((liquid.heights[0], liquid.heights[1]), (1.5, 0.0));
+        assert_eq!(liquid.cells, 1);
+        assert!(read_mclq(&mut Bytes::new(&data[..MCLQ_SIZE - 1]), LIQUID_WATER).is_err());
+    }
+
+    #[test]
+    fn test_read_mh2o() {
+        let instance = 256 * MH2O_HEADER_SIZE;
+        let mut data = vec![0u8; instance + MH2O_INSTANCE_SIZE + 4 * 4 + 1];
+
+        // the second chunk has a 1x1 liquid at (2, 3) with its own heights
+        put_u32(&mut data, MH2O_HEADER_SIZE, instance as u32);
+        put_u32(&mut data, MH2O_HEADER_SIZE + 4, 1);
+        data[instance..instance + 2].copy_from_slice(&LIQUID_MAGMA.to_le_bytes());
+        put_f32(&mut data, instance + 4, 5.0);
+        put_f32(&mut data, instance + 8, 6.0);
+        data[instance + 12..instance + 16].copy_from_slice(&[2, 3, 1, 1]);
+        put_u32(&mut data, instance + 16, (instance + 40) as u32);
+        put_u32(&mut data, instance + 20, (instance + 24) as u32);
+        put_f32(&mut data, instance + 24, 5.0);
+        put_f32(&mut data, instance + 24 + 12, 5.5);
+        data[instance + 40] = 1;
+
+        let chunks = read_mh2o(&data).unwrap();
+        assert_eq!(chunks.len(), 256);
+        assert!(chunks[0].is_empty());
+        let liquid = &chunks[1][0];
+        assert_eq!(liquid.liquid_type, LIQUID_MAGMA);
+        assert!(liquid.has_cell(3, 2) && liquid.cells.count_ones() == 1);
+        assert_eq!((liquid.heights[0], liquid.heights[3 * 9 + 2], liquid.heights[4 * 9 + 3]), (5.0, 5.0, 5.5));
+
+        // a liquid reaching past the chunk, and a layer count running past the data
+        data[instance + 12] = 8;
+        assert!(read_mh2o(&data).is_err());
+        data[instance + 12] = 2;
+        put_u32(&mut data, MH2O_HEADER_SIZE + 4, 1000);
+        assert!(read_mh2o(&data).is_err());
+    }
+}
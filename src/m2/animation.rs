@@ -0,0 +1,405 @@
+use nalgebra::{Matrix3, Matrix4, Quaternion, Rotation3, UnitQuaternion, Vector3};
+use m2::Model;
+use m2::bone::{Bone, Billboard};
+use m2::track::{M2Track, Interpolation, SplineKey, CompQuat};
+
+// value type which can be blended between keyframes
+pub trait Interpolate: Clone {
+    fn interpolate(from: &Self, to: &Self, t: f32) -> Self;
+    // weighted sum of the four control values of a cubic curve
+    fn combine(values: [&Self; 4], weights: [f32; 4]) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(from: &f32, to: &f32, t: f32) -> f32 {
+        from + (to - from) * t
+    }
+
+    fn combine(values: [&f32; 4], weights: [f32; 4]) -> f32 {
+        values.iter().zip(weights.iter()).map(|(&&value, &weight)| value * weight).sum()
+    }
+}
+
+impl Interpolate for Vector3<f32> {
+    fn interpolate(from: &Vector3<f32>, to: &Vector3<f32>, t: f32) -> Vector3<f32> {
+        from + (to - from) * t
+    }
+
+    fn combine(values: [&Vector3<f32>; 4], weights: [f32; 4]) -> Vector3<f32> {
+        values.iter().zip(weights.iter()).fold(Vector3::zeros(), |sum, (&value, &weight)| sum + value * weight)
+    }
+}
+
+impl Interpolate for UnitQuaternion<f32> {
+    fn interpolate(from: &UnitQuaternion<f32>, to: &UnitQuaternion<f32>, t: f32) -> UnitQuaternion<f32> {
+        // take the shortest path
+        if from.coords.dot(&to.coords) < 0.0 {
+            let to = UnitQuaternion::new_unchecked(-to.unwrap());
+            from.nlerp(&to, t)
+        } else {
+            from.nlerp(to, t)
+        }
+    }
+
+    // componentwise on the same hemisphere as the first value, then normalized
+    fn combine(values: [&UnitQuaternion<f32>; 4], weights: [f32; 4]) -> UnitQuaternion<f32> {
+        let reference = values[0].coords;
+        let sum = values.iter().zip(weights.iter()).fold(Quaternion::new(0.0, 0.0, 0.0, 0.0), |sum, (value, &weight)| {
+            let sign = if reference.dot(&value.coords) < 0.0 { -1.0 } else { 1.0 };
+            sum + value.quaternion() * (weight * sign)
+        });
+
+        if sum.norm() < 1e-6 {
+            *values[0]
+        } else {
+            UnitQuaternion::from_quaternion(sum)
+        }
+    }
+}
+
+// point in time at which tracks are sampled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AnimationTime {
+    pub sequence: usize,
+    // ms since the start of `sequence`
+    pub time: u32,
+    // ms on a timer running independently of the played sequence; drives
+    // tracks bound to a global sequence (glows, idle effects)
+    pub global_time: u32,
+}
+
+impl AnimationTime {
+    pub fn new(sequence: usize, time: u32, global_time: u32) -> Self {
+        AnimationTime { sequence, time, global_time }
+    }
+}
+
+// position of a point in time within a list of keyframes
+enum Keyframe {
+    At(usize),
+    // between this keyframe and the next, with the blend factor towards the next
+    Between(usize, f32),
+}
+
+// `timestamps` must not be empty; times outside of the range clamp to the first or last keyframe
+fn locate(timestamps: &[u32], time: u32, interpolation: Interpolation) -> Keyframe {
+    let next = match timestamps.binary_search(&time) {
+        Ok(index) => return Keyframe::At(index),
+        Err(index) => index,
+    };
+
+    if next == 0 {
+        Keyframe::At(0)
+    } else if next >= timestamps.len() {
+        Keyframe::At(timestamps.len() - 1)
+    } else if interpolation == Interpolation::None {
+        Keyframe::At(next - 1)
+    } else {
+        // some files have unsorted timestamps, so the span may be empty or reversed
+        let (start, end) = (timestamps[next - 1], timestamps[next]);
+        let span = end.saturating_sub(start);
+        let t = if span == 0 { 0.0 } else { (time.saturating_sub(start) as f32 / span as f32).min(1.0) };
+        Keyframe::Between(next - 1, t)
+    }
+}
+
+// weights of (value, out tangent, next in tangent, next value) at `t`.
+// bezier tangents are control points, hermite ones are derivatives
+fn curve_weights(interpolation: Interpolation, t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    let u = 1.0 - t;
+
+    match interpolation {
+        Interpolation::Bezier => [u * u * u, 3.0 * u * u * t, 3.0 * u * t2, t3],
+        Interpolation::Hermite => [2.0 * t3 - 3.0 * t2 + 1.0, t3 - 2.0 * t2 + t, t3 - t2, 3.0 * t2 - 2.0 * t3],
+        Interpolation::None | Interpolation::Linear => [u, 0.0, 0.0, t],
+    }
+}
+
+impl<T> M2Track<T> {
+    // time within the keyframes; global sequences loop over their duration from `global_loops`
+    fn local_time(&self, at: AnimationTime, global_loops: &[u32]) -> u32 {
+        match self.global_sequence {
+            Some(index) => match global_loops.get(index as usize) {
+                Some(&length) if length > 0 => at.global_time % length,
+                _ => at.global_time,
+            },
+            None => at.time,
+        }
+    }
+
+    // value at `at`, converting keyframe values with `convert`.
+    // bezier and hermite tracks store (value, in tangent, out tangent) per keyframe
+    pub fn sample_by<U, F>(&self, at: AnimationTime, global_loops: &[u32], convert: F) -> Option<U>
+    where
+        U: Interpolate,
+        F: Fn(&T) -> U,
+    {
+        let (timestamps, values) = self.keyframes(at.sequence);
+
+        let cubic = match self.interpolation {
+            Interpolation::Bezier | Interpolation::Hermite => values.len() >= timestamps.len() * 3,
+            _ => false,
+        };
+        let stride = if cubic { 3 } else { 1 };
+
+        let count = timestamps.len().min(values.len() / stride);
+        if count == 0 { return None }
+
+        match locate(&timestamps[..count], self.local_time(at, global_loops), self.interpolation) {
+            Keyframe::At(index) => Some(convert(&values[index * stride])),
+            Keyframe::Between(index, t) => {
+                let from = convert(&values[index * stride]);
+                let to = convert(&values[(index + 1) * stride]);
+
+                if cubic {
+                    let out_tangent = convert(&values[index * 3 + 2]);
+                    let in_tangent = convert(&values[(index + 1) * 3 + 1]);
+                    Some(U::combine([&from, &out_tangent, &in_tangent, &to], curve_weights(self.interpolation, t)))
+                } else {
+                    Some(U::interpolate(&from, &to, t))
+                }
+            }
+        }
+    }
+}
+
+impl<T: Interpolate> M2Track<T> {
+    pub fn sample(&self, at: AnimationTime, global_loops: &[u32]) -> Option<T> {
+        self.sample_by(at, global_loops, T::clone)
+    }
+}
+
+impl M2Track<CompQuat> {
+    pub fn sample_rotation(&self, at: AnimationTime, global_loops: &[u32]) -> Option<UnitQuaternion<f32>> {
+        self.sample_by(at, global_loops, |value| UnitQuaternion::from_quaternion(value.to_quaternion()))
+    }
+}
+
+impl<T: Interpolate> M2Track<SplineKey<T>> {
+    // like `sample`, with the tangents stored next to every keyframe value
+    pub fn sample_spline(&self, at: AnimationTime, global_loops: &[u32]) -> Option<T> {
+        let (timestamps, values) = self.keyframes(at.sequence);
+        let count = timestamps.len().min(values.len());
+        if count == 0 { return None }
+
+        match locate(&timestamps[..count], self.local_time(at, global_loops), self.interpolation) {
+            Keyframe::At(index) => Some(values[index].value.clone()),
+            Keyframe::Between(index, t) => {
+                let (from, to) = (&values[index], &values[index + 1]);
+
+                Some(match self.interpolation {
+                    Interpolation::Bezier | Interpolation::Hermite => T::combine(
+                        [&from.value, &from.out_tangent, &to.in_tangent, &to.value],
+                        curve_weights(self.interpolation, t),
+                    ),
+                    _ => T::interpolate(&from.value, &to.value, t),
+                })
+            }
+        }
+    }
+}
+
+impl Model {
+    // bone matrices (model space) at `at`.
+    // billboarded bones face the model's +X axis; see `sample_pose_for_view`
+    pub fn sample_pose(&self, at: AnimationTime) -> Vec<Matrix4<f32>> {
+        self.sample_pose_facing(at, &UnitQuaternion::identity())
+    }
+
+    // like `sample_pose`, with billboarded bones turned towards the camera of
+    // `model_view` (model to view space, looking down -Z with +Y up)
+    pub fn sample_pose_for_view(&self, at: AnimationTime, model_view: &Matrix4<f32>) -> Vec<Matrix4<f32>> {
+        self.sample_pose_facing(at, &billboard_facing(model_view))
+    }
+
+    // `facing` rotates +X (the direction billboards face) towards the viewer in model space
+    pub fn sample_pose_facing(&self, at: AnimationTime, facing: &UnitQuaternion<f32>) -> Vec<Matrix4<f32>> {
+        let mut pose: Vec<Option<Matrix4<f32>>> = vec![None; self.bones.len()];
+
+        for index in 0..self.bones.len() {
+            self.resolve_bone(index, at, facing, &mut pose, 0);
+        }
+
+        pose.into_iter().map(|matrix| matrix.unwrap_or_else(Matrix4::identity)).collect()
+    }
+
+    fn resolve_bone(&self, index: usize, at: AnimationTime, facing: &UnitQuaternion<f32>, pose: &mut [Option<Matrix4<f32>>], depth: usize) -> Matrix4<f32> {
+        if let Some(matrix) = pose[index] {
+            return matrix;
+        }
+
+        let bone = &self.bones[index];
+
+        // a malformed hierarchy with cycles is treated as flat past the bone count
+        let parent = match bone.parent {
+            Some(parent) if (parent as usize) < self.bones.len() && depth < self.bones.len() => {
+                self.resolve_bone(parent as usize, at, facing, pose, depth + 1)
+            }
+            _ => Matrix4::identity(),
+        };
+
+        let translation = bone.translation.sample(at, &self.global_loops).unwrap_or_else(Vector3::zeros);
+        let rotation = bone.rotation.sample_rotation(at, &self.global_loops).unwrap_or_else(UnitQuaternion::identity);
+        let scale = bone.scale.sample(at, &self.global_loops).unwrap_or_else(|| Vector3::new(1.0, 1.0, 1.0));
+
+        let pivot = bone.pivot.coords;
+        let rotation_scale = rotation.to_homogeneous() * Matrix4::new_nonuniform_scaling(&scale);
+        let local = Matrix4::new_translation(&(pivot + translation)) * rotation_scale * Matrix4::new_translation(&-pivot);
+
+        let matrix = match bone.billboard() {
+            Some(kind) => billboard(bone, kind, &(parent * local), &parent, &rotation_scale, facing),
+            None => parent * local,
+        };
+
+        pose[index] = Some(matrix);
+        matrix
+    }
+}
+
+// rotation which turns the billboard axes (+X towards the viewer, +Z up) to face
+// the camera of `model_view`
+pub fn billboard_facing(model_view: &Matrix4<f32>) -> UnitQuaternion<f32> {
+    let rotation = Matrix3::new(
+        model_view[(0, 0)], model_view[(0, 1)], model_view[(0, 2)],
+        model_view[(1, 0)], model_view[(1, 1)], model_view[(1, 2)],
+        model_view[(2, 0)], model_view[(2, 1)], model_view[(2, 2)],
+    );
+
+    // view space directions in model space; the inverse is the transpose up to scale
+    let inverse = rotation.transpose();
+    let towards_viewer = (inverse * Vector3::z()).normalize();
+    let up = (inverse * Vector3::y()).normalize();
+    let side = up.cross(&towards_viewer);
+
+    let basis = Matrix3::from_columns(&[towards_viewer, side, up]);
+    UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(basis))
+}
+
+// replaces the rotation inherited from the parents with one facing the viewer,
+// keeping the accumulated scale and the position of the pivot
+fn billboard(bone: &Bone, kind: Billboard, combined: &Matrix4<f32>, parent: &Matrix4<f32>, rotation_scale: &Matrix4<f32>, facing: &UnitQuaternion<f32>) -> Matrix4<f32> {
+    let pivot = combined * bone.pivot.to_homogeneous();
+    let pivot = Vector3::new(pivot.x, pivot.y, pivot.z) / pivot.w;
+
+    let column = |index: usize| Vector3::new(parent[(0, index)], parent[(1, index)], parent[(2, index)]).norm();
+    let scale = Vector3::new(column(0), column(1), column(2));
+
+    // cylindrical billboards only turn around their locked axis
+    let rotation = match kind.locked_axis() {
+        Some(axis) => twist(facing, &axis),
+        None => *facing,
+    };
+
+    Matrix4::new_translation(&pivot)
+        * rotation.to_homogeneous()
+        * Matrix4::new_nonuniform_scaling(&scale)
+        * rotation_scale
+        * Matrix4::new_translation(&-bone.pivot.coords)
+}
+
+// rotation of `rotation` around `axis` (swing-twist decomposition)
+fn twist(rotation: &UnitQuaternion<f32>, axis: &Vector3<f32>) -> UnitQuaternion<f32> {
+    let q = rotation.quaternion();
+    let projected = axis * q.vector().dot(axis);
+    let twist = Quaternion::from_parts(q.scalar(), projected);
+
+    if twist.norm() < 1e-6 {
+        UnitQuaternion::identity()
+    } else {
+        UnitQuaternion::from_quaternion(twist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point3;
+    use m2::bone::BoneFlags;
+
+    fn track<T>(timestamps: Vec<u32>, values: Vec<T>) -> M2Track<T> {
+        M2Track {
+            interpolation: Interpolation::Linear,
+            global_sequence: None,
+            timestamps: vec![timestamps],
+            values: vec![values],
+        }
+    }
+
+    fn bone(parent: Option<u16>, translation: M2Track<Vector3<f32>>, pivot: Point3<f32>) -> Bone {
+        Bone {
+            key_bone_id: -1,
+            flags: BoneFlags::empty(),
+            parent,
+            submesh_id: 0,
+            translation,
+            rotation: track(vec![0], vec![CompQuat([32767, 32767, 32767, -1])]),
+            scale: track(vec![], vec![]),
+            pivot,
+        }
+    }
+
+    #[test]
+    fn test_sample_pose() {
+        let moving = track(vec![0, 100], vec![Vector3::zeros(), Vector3::new(10.0, 0.0, 0.0)]);
+        assert_eq!(moving.sample(AnimationTime::new(0, 50, 0), &[]), Some(Vector3::new(5.0, 0.0, 0.0)));
+        assert_eq!(moving.sample(AnimationTime::new(0, 500, 0), &[]), Some(Vector3::new(10.0, 0.0, 0.0)));
+        assert_eq!(moving.sample(AnimationTime::new(1, 50, 0), &[]), None);
+
+        // hermite with (value, in, out) triplets and flat tangents eases in and out
+        let eased = M2Track {
+            interpolation: Interpolation::Hermite,
+            global_sequence: None,
+            timestamps: vec![vec![0, 100]],
+            values: vec![vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0]],
+        };
+        assert_eq!(eased.sample(AnimationTime::new(0, 50, 0), &[]), Some(0.5));
+        assert!(eased.sample(AnimationTime::new(0, 25, 0), &[]).unwrap() < 0.25);
+
+        let half_turn = M2Track {
+            interpolation: Interpolation::Linear,
+            global_sequence: None,
+            timestamps: vec![vec![0, 100]],
+            values: vec![vec![CompQuat([32767, 32767, 32767, -1]), CompQuat([32767, 32767, -1, 32767])]],
+        };
+
+        // global sequence tracks follow the global timer, looping over their duration
+        let glow = M2Track { global_sequence: Some(0), ..moving.clone() };
+        assert_eq!(glow.sample(AnimationTime::new(3, 0, 1050), &[1000]), Some(Vector3::new(5.0, 0.0, 0.0)));
+
+        let rotation = half_turn.sample_rotation(AnimationTime::new(0, 50, 0), &[]).unwrap();
+        assert!((rotation.angle() - ::std::f32::consts::FRAC_PI_2).abs() < 1e-4);
+
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let mut data = vec![0u8; 0x140];
+        data[0..4].copy_from_slice(b"MD20");
+        data[4..8].copy_from_slice(&264u32.to_le_bytes());
+        let mut model = Model::parse(&data).unwrap();
+        model.bones = vec![
+            bone(None, moving, origin),
+            bone(Some(0), track(vec![0], vec![Vector3::new(0.0, 0.0, 1.0)]), Point3::new(0.0, 0.0, 2.0)),
+        ];
+
+        let pose = model.sample_pose(AnimationTime::new(0, 50, 0));
+        let point = pose[1] * Point3::new(0.0, 0.0, 2.0).to_homogeneous();
+        assert_eq!(Point3::from_homogeneous(point), Some(Point3::new(5.0, 0.0, 3.0)));
+
+        // camera on the +Y axis looking at the origin: a billboard's +X turns to +Y
+        model.bones[1].flags = BoneFlags::SPHERICAL_BILLBOARD;
+        let view = Matrix4::look_at_rh(&Point3::new(0.0, 10.0, 0.0), &Point3::origin(), &Vector3::z());
+        let pose = model.sample_pose_for_view(AnimationTime::new(0, 0, 0), &view);
+        let front = pose[1] * Point3::new(1.0, 0.0, 2.0).to_homogeneous();
+        let front = Point3::from_homogeneous(front).unwrap();
+        assert!((front - Point3::new(0.0, 1.0, 3.0)).norm() < 1e-5, "{:?}", front);
+    }
+
+    #[test]
+    fn test_sample_unsorted() {
+        let values = vec![Vector3::zeros(), Vector3::x(), Vector3::y(), Vector3::z(), Vector3::x()];
+        let unsorted = track(vec![0, 300, 50, 10, 200], values);
+        for time in (0..400).step_by(5) {
+            let value = unsorted.sample(AnimationTime::new(0, time, 0), &[]).unwrap();
+            assert!(value.iter().all(|component| component.is_finite()), "{:?} at {}", value, time);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::io::{self, Cursor};
+use byteorder::LE;
+use nalgebra::{Point3, Vector3};
+use m2::Model;
+use m2::track::{M2Track, CompQuat, TRACK_SIZE};
+
+pub const BONE_SIZE: usize = 88;
+
+bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct BoneFlags: u32 {
+        const IGNORE_PARENT_TRANSLATE = 0x1;
+        const IGNORE_PARENT_SCALE = 0x2;
+        const IGNORE_PARENT_ROTATION = 0x4;
+        const SPHERICAL_BILLBOARD = 0x8;
+        const CYLINDRICAL_BILLBOARD_LOCK_X = 0x10;
+        const CYLINDRICAL_BILLBOARD_LOCK_Y = 0x20;
+        const CYLINDRICAL_BILLBOARD_LOCK_Z = 0x40;
+        const TRANSFORMED = 0x200;
+        const KINEMATIC_BONE = 0x400;
+        const HELMET_ANIM_SCALED = 0x1000;
+    }
+}
+
+// how a bone turns towards the camera
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Billboard {
+    Spherical,
+    // only turns around the named axis
+    CylindricalX,
+    CylindricalY,
+    CylindricalZ,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Bone {
+    // index into the key bone lookup, -1 for regular bones
+    pub key_bone_id: i32,
+    pub flags: BoneFlags,
+    pub parent: Option<u16>,
+    pub submesh_id: u16,
+    pub translation: M2Track<Vector3<f32>>,
+    pub rotation: M2Track<CompQuat>,
+    pub scale: M2Track<Vector3<f32>>,
+    pub pivot: Point3<f32>,
+}
+
+// well-known bones, as indexed by the key bone lookup
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum KeyBone {
+    ArmLeft,
+    ArmRight,
+    ShoulderLeft,
+    ShoulderRight,
+    SpineLow,
+    Waist,
+    Head,
+    Jaw,
+    IndexFingerRight,
+    MiddleFingerRight,
+    PinkyFingerRight,
+    RingFingerRight,
+    ThumbRight,
+    IndexFingerLeft,
+    MiddleFingerLeft,
+    PinkyFingerLeft,
+    RingFingerLeft,
+    ThumbLeft,
+    Breath,
+    HandRight,
+    HandLeft,
+    BreathEffect,
+    Name,
+    NameMount,
+    CustomHead,
+    CustomChest,
+    Root,
+}
+
+const KEY_BONES: [KeyBone; 27] = [
+    KeyBone::ArmLeft, KeyBone::ArmRight, KeyBone::ShoulderLeft, KeyBone::ShoulderRight,
+    KeyBone::SpineLow, KeyBone::Waist, KeyBone::Head, KeyBone::Jaw,
+    KeyBone::IndexFingerRight, KeyBone::MiddleFingerRight, KeyBone::PinkyFingerRight,
+    KeyBone::RingFingerRight, KeyBone::ThumbRight, KeyBone::IndexFingerLeft,
+    KeyBone::MiddleFingerLeft, KeyBone::PinkyFingerLeft, KeyBone::RingFingerLeft,
+    KeyBone::ThumbLeft, KeyBone::Breath, KeyBone::HandRight, KeyBone::HandLeft,
+    KeyBone::BreathEffect, KeyBone::Name, KeyBone::NameMount, KeyBone::CustomHead,
+    KeyBone::CustomChest, KeyBone::Root,
+];
+
+impl KeyBone {
+    pub fn from_id(id: i32) -> Option<KeyBone> {
+        if id < 0 { None } else { KEY_BONES.get(id as usize).cloned() }
+    }
+
+    pub fn id(&self) -> usize {
+        KEY_BONES.iter().position(|bone| bone == self).unwrap()
+    }
+}
+
+impl Billboard {
+    pub fn locked_axis(&self) -> Option<Vector3<f32>> {
+        match *self {
+            Billboard::Spherical => None,
+            Billboard::CylindricalX => Some(Vector3::x()),
+            Billboard::CylindricalY => Some(Vector3::y()),
+            Billboard::CylindricalZ => Some(Vector3::z()),
+        }
+    }
+}
+
+impl Model {
+    // index into `bones` of a well-known bone, if the model has it
+    pub fn key_bone(&self, key_bone: KeyBone) -> Option<usize> {
+        match self.lookups.key_bone.get(key_bone.id()) {
+            Some(&index) if index >= 0 && (index as usize) < self.bones.len() => Some(index as usize),
+            _ => None,
+        }
+    }
+}
+
+impl Bone {
+    pub fn key_bone(&self) -> Option<KeyBone> {
+        KeyBone::from_id(self.key_bone_id)
+    }
+
+    // bone record at `offset` of the MD20 block; see `M2Track::read` for `inline`
+    pub fn read(data: &[u8], offset: usize, inline: &[bool]) -> io::Result<Bone> {
+        let record = data.get(offset..offset + BONE_SIZE)
+            .ok_or_else(|| io_error!(UnexpectedEof, "bone at {} exceeds the model size", offset))?;
+        let mut cursor = Cursor::new(record);
+
+        let_read! { LE | cursor =>
+            key_bone_id: i32;
+            flags: u32;
+            parent: i16;
+            submesh_id: u16;
+            _bone_name_crc: u32;
+        }
+
+        let tracks = offset + 16;
+        let translation = M2Track::read(data, tracks, inline)?;
+        let rotation = M2Track::read(data, tracks + TRACK_SIZE, inline)?;
+        let scale = M2Track::read(data, tracks + TRACK_SIZE * 2, inline)?;
+
+        cursor.set_position((16 + TRACK_SIZE * 3) as u64);
+        let_read! { LE | cursor =>
+            pivot: (f32, f32, f32);
+        }
+
+        Ok(Bone {
+            key_bone_id,
+            flags: BoneFlags::from_bits_truncate(flags),
+            parent: if parent >= 0 { Some(parent as u16) } else { None },
+            submesh_id,
+            translation,
+            rotation,
+            scale,
+            pivot: Point3::new(pivot.0, pivot.1, pivot.2),
+        })
+    }
+
+    pub fn billboard(&self) -> Option<Billboard> {
+        if self.flags.contains(BoneFlags::SPHERICAL_BILLBOARD) {
+            Some(Billboard::Spherical)
+        } else if self.flags.contains(BoneFlags::CYLINDRICAL_BILLBOARD_LOCK_X) {
+            Some(Billboard::CylindricalX)
+        } else if self.flags.contains(BoneFlags::CYLINDRICAL_BILLBOARD_LOCK_Y) {
+            Some(Billboard::CylindricalY)
+        } else if self.flags.contains(BoneFlags::CYLINDRICAL_BILLBOARD_LOCK_Z) {
+            Some(Billboard::CylindricalZ)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_billboard(&self) -> bool {
+        self.billboard().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_bone() {
+        let mut data = vec![0u8; BONE_SIZE];
+        data[0..4].copy_from_slice(&(-1i32).to_le_bytes());
+        data[4..8].copy_from_slice(&0x8u32.to_le_bytes());
+        data[8..10].copy_from_slice(&(-1i16).to_le_bytes());
+        data[10..12].copy_from_slice(&3u16.to_le_bytes());
+        for track in 0..3 {
+            data[16 + track * TRACK_SIZE + 2..16 + track * TRACK_SIZE + 4].copy_from_slice(&(-1i16).to_le_bytes());
+        }
+        data[76..80].copy_from_slice(&2.5f32.to_le_bytes());
+
+        let bone = Bone::read(&data, 0, &[]).unwrap();
+        assert_eq!(bone.key_bone(), None);
+        assert_eq!(KeyBone::from_id(6), Some(KeyBone::Head));
+        assert_eq!(KeyBone::Root.id(), 26);
+        assert_eq!(bone.parent, None);
+        assert_eq!(bone.submesh_id, 3);
+        assert_eq!(bone.billboard(), Some(Billboard::Spherical));
+        assert!(!bone.rotation.is_animated());
+        assert_eq!(bone.pivot, Point3::new(2.5, 0.0, 0.0));
+
+        assert!(Bone::read(&data, 8, &[]).is_err());
+    }
+}
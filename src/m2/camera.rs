@@ -0,0 +1,219 @@
+use std::io::{self, Cursor};
+use byteorder::LE;
+use nalgebra::{Matrix4, Point3, Vector3, UnitQuaternion, Unit};
+use m2::track::{M2Track, SplineKey, TRACK_SIZE};
+use m2::animation::AnimationTime;
+
+// cataclysm replaced the fixed field of view with a track
+const VERSION_FOV_TRACK: u32 = 272;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CameraKind {
+    Portrait,
+    CharacterInfo,
+    // login screen and cinematic flybys
+    Flyby,
+    Other(i32),
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Camera {
+    pub kind: CameraKind,
+    // diagonal, in radians
+    pub fov: f32,
+    pub far_clip: f32,
+    pub near_clip: f32,
+    // spline positions are relative to the base positions
+    pub position: M2Track<SplineKey<Vector3<f32>>>,
+    pub position_base: Vector3<f32>,
+    pub target: M2Track<SplineKey<Vector3<f32>>>,
+    pub target_base: Vector3<f32>,
+    pub roll: M2Track<SplineKey<f32>>,
+    // cataclysm and later; overrides `fov` while animated
+    pub fov_track: Option<M2Track<SplineKey<f32>>>,
+}
+
+// camera evaluated at a point in time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraState {
+    pub position: Point3<f32>,
+    pub target: Point3<f32>,
+    pub roll: f32,
+    pub fov: f32,
+}
+
+impl CameraKind {
+    pub fn from_raw(value: i32) -> Self {
+        match value {
+            0 => CameraKind::Portrait,
+            1 => CameraKind::CharacterInfo,
+            -1 => CameraKind::Flyby,
+            other => CameraKind::Other(other),
+        }
+    }
+}
+
+impl Camera {
+    pub fn record_size(version: u32) -> usize {
+        // the fov track replaces the fixed fov value
+        if version >= VERSION_FOV_TRACK { 12 + TRACK_SIZE * 4 + 24 } else { 16 + TRACK_SIZE * 3 + 24 }
+    }
+
+    // camera record at `offset` of the MD20 block; see `M2Track::read` for `inline`
+    pub fn read(data: &[u8], offset: usize, version: u32, inline: &[bool]) -> io::Result<Camera> {
+        let record = data.get(offset..offset + Camera::record_size(version))
+            .ok_or_else(|| io_error!(UnexpectedEof, "camera at {} exceeds the model size", offset))?;
+        let mut cursor = Cursor::new(record);
+        let has_fov_track = version >= VERSION_FOV_TRACK;
+
+        let_read! { LE | cursor =>
+            kind: i32;
+        }
+
+        let fov = if has_fov_track { 0.0 } else {
+            let_read! { LE | cursor => fov: f32; }
+            fov
+        };
+
+        let_read! { LE | cursor =>
+            far_clip: f32;
+            near_clip: f32;
+        }
+
+        let position = M2Track::read(data, offset + cursor.position() as usize, inline)?;
+        cursor.set_position(cursor.position() + TRACK_SIZE as u64);
+        let_read! { LE | cursor => position_base: (f32, f32, f32); }
+
+        let target = M2Track::read(data, offset + cursor.position() as usize, inline)?;
+        cursor.set_position(cursor.position() + TRACK_SIZE as u64);
+        let_read! { LE | cursor => target_base: (f32, f32, f32); }
+
+        let roll = M2Track::read(data, offset + cursor.position() as usize, inline)?;
+        cursor.set_position(cursor.position() + TRACK_SIZE as u64);
+
+        let fov_track = if has_fov_track {
+            Some(M2Track::read(data, offset + cursor.position() as usize, inline)?)
+        } else {
+            None
+        };
+
+        Ok(Camera {
+            kind: CameraKind::from_raw(kind),
+            fov,
+            far_clip,
+            near_clip,
+            position,
+            position_base: Vector3::new(position_base.0, position_base.1, position_base.2),
+            target,
+            target_base: Vector3::new(target_base.0, target_base.1, target_base.2),
+            roll,
+            fov_track,
+        })
+    }
+
+    pub fn evaluate(&self, at: AnimationTime, global_loops: &[u32]) -> CameraState {
+        let position = self.position.sample_spline(at, global_loops).unwrap_or_else(Vector3::zeros);
+        let target = self.target.sample_spline(at, global_loops).unwrap_or_else(Vector3::zeros);
+        let roll = self.roll.sample_spline(at, global_loops).unwrap_or(0.0);
+        let fov = self.fov_track.as_ref()
+            .and_then(|track| track.sample_spline(at, global_loops))
+            .unwrap_or(self.fov);
+
+        CameraState {
+            position: Point3::from_coordinates(self.position_base + position),
+            target: Point3::from_coordinates(self.target_base + target),
+            roll,
+            fov,
+        }
+    }
+}
+
+impl CameraState {
+    // right-handed view matrix with z up, rolled around the view direction
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        let direction = self.target - self.position;
+        let roll = match Unit::try_new(direction, 1e-6) {
+            Some(axis) => UnitQuaternion::from_axis_angle(&axis, self.roll),
+            None => UnitQuaternion::identity(),
+        };
+        let up = roll * Vector3::z();
+
+        Matrix4::look_at_rh(&self.position, &self.target, &up)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m2::track::Interpolation;
+    use test_util::{f32s, u32s};
+
+    // cataclysm record with empty, non-global tracks
+    fn record(kind: i32, far_clip: f32) -> Vec<u8> {
+        let empty_track = u32s(&[0xffff_0000, 0, 0, 0, 0]);
+        let mut data = kind.to_le_bytes().to_vec();
+        data.extend(f32s(&[far_clip, 0.5]));
+        for base in &[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]] {
+            data.extend(&empty_track);
+            data.extend(f32s(base));
+        }
+        data.extend(&empty_track);
+        data.extend(&empty_track);
+        data
+    }
+
+    fn key<T: Copy>(value: T, in_tangent: T, out_tangent: T) -> SplineKey<T> {
+        SplineKey { value, in_tangent, out_tangent }
+    }
+
+    fn track<T>(interpolation: Interpolation, timestamps: Vec<u32>, values: Vec<T>) -> M2Track<T> {
+        M2Track { interpolation, global_sequence: None, timestamps: vec![timestamps], values: vec![values] }
+    }
+
+    #[test]
+    fn test_evaluate_camera() {
+        let zero = Vector3::zeros();
+        let camera = Camera {
+            kind: CameraKind::from_raw(-1),
+            fov: 0.8,
+            far_clip: 100.0,
+            near_clip: 0.1,
+            position: track(Interpolation::Hermite, vec![0, 1000], vec![
+                key(zero, zero, zero),
+                key(Vector3::new(10.0, 0.0, 0.0), zero, zero),
+            ]),
+            position_base: Vector3::new(0.0, 0.0, 5.0),
+            target: track(Interpolation::Linear, vec![0], vec![key(Vector3::new(0.0, 10.0, 0.0), zero, zero)]),
+            target_base: zero,
+            roll: track(Interpolation::None, vec![], vec![]),
+            fov_track: None,
+        };
+
+        assert_eq!(camera.kind, CameraKind::Flyby);
+
+        // with flat tangents the hermite curve passes the midpoint at half time
+        let state = camera.evaluate(AnimationTime::new(0, 500, 0), &[]);
+        assert_eq!(state.position, Point3::new(5.0, 0.0, 5.0));
+        assert_eq!(state.target, Point3::new(0.0, 10.0, 0.0));
+        assert_eq!(state.fov, 0.8);
+
+        let eye = state.view_matrix() * state.position.to_homogeneous();
+        assert!(eye.x.abs() < 1e-5 && eye.y.abs() < 1e-5 && eye.z.abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_read_cameras_with_fov_track() {
+        let size = Camera::record_size(VERSION_FOV_TRACK);
+        let data = [record(0, 10.0), record(-1, 20.0)].concat();
+        assert_eq!(data.len(), size * 2);
+
+        let second = Camera::read(&data, size, VERSION_FOV_TRACK, &[]).unwrap();
+        assert_eq!(second.kind, CameraKind::Flyby);
+        assert_eq!(second.far_clip, 20.0);
+        assert_eq!(second.near_clip, 0.5);
+        assert_eq!(second.target_base, Vector3::new(4.0, 5.0, 6.0));
+        assert!(second.fov_track.is_some());
+    }
+}
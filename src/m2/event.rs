@@ -0,0 +1,95 @@
+use std::io::{self, Cursor};
+use byteorder::LE;
+use nalgebra::Point3;
+use m2::track::{TrackBase, TRACK_BASE_SIZE};
+
+pub const EVENT_SIZE: usize = 24 + TRACK_BASE_SIZE;
+
+// animation event, e.g. `$CSD` (play sound) or `$DSL` (left foot step)
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Event {
+    pub identifier: String,
+    // meaning depends on the identifier, usually a dbc id
+    pub data: u32,
+    pub bone: u32,
+    pub position: Point3<f32>,
+    // times at which the event fires, per sequence
+    pub enabled: TrackBase,
+}
+
+impl Event {
+    // event record at `offset` of the MD20 block; see `M2Track::read` for `inline`
+    pub fn read(data: &[u8], offset: usize, inline: &[bool]) -> io::Result<Event> {
+        let record = data.get(offset..offset + EVENT_SIZE)
+            .ok_or_else(|| io_error!(UnexpectedEof, "event at {} exceeds the model size", offset))?;
+        let mut cursor = Cursor::new(&record[4..]);
+
+        let_read! { LE | cursor =>
+            event_data: u32;
+            bone: u32;
+            position: (f32, f32, f32);
+        }
+
+        Ok(Event {
+            identifier: String::from_utf8_lossy(&record[0..4]).trim_end_matches('\0').to_string(),
+            data: event_data,
+            bone,
+            position: Point3::new(position.0, position.1, position.2),
+            enabled: TrackBase::read(data, offset + 24, inline)?,
+        })
+    }
+
+    // timestamps of `sequence` within `from..to` ms, for firing events between two frames
+    pub fn fired_between(&self, sequence: usize, from: u32, to: u32) -> Vec<u32> {
+        let sequence = if self.enabled.global_sequence.is_some() { 0 } else { sequence };
+
+        self.enabled.timestamps.get(sequence)
+            .map(|timestamps| timestamps.iter().cloned().filter(|&time| time >= from && time < to).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_event() {
+        let mut data = vec![0u8; 0x60];
+        data[0..4].copy_from_slice(b"$DSL");
+        data[4..8].copy_from_slice(&7u32.to_le_bytes());
+        data[8..12].copy_from_slice(&2u32.to_le_bytes());
+        data[0x1A..0x1C].copy_from_slice(&(-1i16).to_le_bytes());
+
+        // one sequence with timestamps [100, 600]
+        data[0x1C..0x20].copy_from_slice(&1u32.to_le_bytes());
+        data[0x20..0x24].copy_from_slice(&0x40u32.to_le_bytes());
+        data[0x40..0x44].copy_from_slice(&2u32.to_le_bytes());
+        data[0x44..0x48].copy_from_slice(&0x50u32.to_le_bytes());
+        data[0x50..0x54].copy_from_slice(&100u32.to_le_bytes());
+        data[0x54..0x58].copy_from_slice(&600u32.to_le_bytes());
+
+        let event = Event::read(&data, 0, &[true]).unwrap();
+        assert_eq!(event.identifier, "$DSL");
+        assert_eq!((event.data, event.bone), (7, 2));
+        assert_eq!(event.fired_between(0, 0, 500), vec![100]);
+        assert_eq!(event.fired_between(0, 600, 1000), vec![600]);
+        assert!(event.fired_between(1, 0, 1000).is_empty());
+    }
+
+    #[test]
+    fn test_read_event_truncated() {
+        let mut data = vec![0u8; EVENT_SIZE];
+        data[0..4].copy_from_slice(b"$CAH");
+        data[0x1A..0x1C].copy_from_slice(&(-1i16).to_le_bytes());
+        assert!(Event::read(&data, 0, &[]).unwrap().fired_between(0, 0, 1000).is_empty());
+        assert!(Event::read(&data[..EVENT_SIZE - 1], 0, &[]).is_err());
+        assert!(Event::read(&data, 4, &[]).is_err());
+
+        // timestamps of more sequences than the model holds
+        data[0x1C..0x20].copy_from_slice(&2u32.to_le_bytes());
+        data[0x20..0x24].copy_from_slice(&0x20u32.to_le_bytes());
+        assert!(Event::read(&data, 0, &[true, true]).is_err());
+    }
+}
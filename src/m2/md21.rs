@@ -0,0 +1,145 @@
+use std::io::{self, Cursor};
+use byteorder::{ReadBytesExt, LE};
+use chunked::{Chunked, ChunkOptions, Token};
+use m2::Model;
+use error::{Error, Result};
+
+// file data ids of the files a chunked (legion+) model refers to
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FileDataIds {
+    pub physics: Option<u32>,
+    pub skeleton: Option<u32>,
+    pub skins: Vec<u32>,
+    pub lod_skins: Vec<u32>,
+    pub animations: Vec<AnimationFileId>,
+    pub bones: Vec<u32>,
+    pub textures: Vec<u32>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AnimationFileId {
+    pub animation_id: u16,
+    pub sub_animation_id: u16,
+    pub file_data_id: u32,
+}
+
+pub fn is_chunked(data: &[u8]) -> bool {
+    data.len() >= 4 && &data[0..4] == b"MD21"
+}
+
+// the MD21 chunk wraps a plain MD20 block; sibling chunks reference external files
+pub fn parse(data: &[u8]) -> Result<Model> {
+    let mut model = None;
+    let mut ids = FileDataIds::default();
+    let mut skins = Vec::new();
+
+    // unlike other chunked files, tokens are stored in reading order. some
+    // exporters pad the file with zeros after the last chunk
+    let options = ChunkOptions { trailing_padding: true, ..ChunkOptions::default() };
+    for chunk in Chunked::new_legion_m2(Cursor::new(data)).options(options) {
+        let chunk = chunk?;
+
+        match chunk.token {
+            Token::MD21 => model = Some(Model::parse(&chunk.data)?),
+            Token::PFID => ids.physics = Some(read_ids(&chunk.data)?.remove(0)),
+            Token::SKID => ids.skeleton = Some(read_ids(&chunk.data)?.remove(0)),
+            Token::SFID => skins = read_ids(&chunk.data)?,
+            Token::BFID => ids.bones = read_ids(&chunk.data)?,
+            Token::TXID => ids.textures = read_ids(&chunk.data)?,
+            Token::AFID => {
+                let mut cursor = chunk.cursor();
+                for _ in 0..(chunk.data.len() / 8) {
+                    ids.animations.push(AnimationFileId {
+                        animation_id: cursor.read_u16::<LE>()?,
+                        sub_animation_id: cursor.read_u16::<LE>()?,
+                        file_data_id: cursor.read_u32::<LE>()?,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut model = model.ok_or(Error::MissingChunk { format: "M2", token: Token::MD21 })?;
+
+    // SFID lists the regular skin profiles first, then the lod ones
+    let regular = (model.skin_profile_count as usize).min(skins.len());
+    ids.lod_skins = skins.split_off(regular);
+    ids.skins = skins;
+
+    model.file_data_ids = Some(ids);
+    Ok(model)
+}
+
+fn read_ids(data: &[u8]) -> io::Result<Vec<u32>> {
+    if data.len() < 4 {
+        return Err(io_error!(UnexpectedEof, "file data id chunk is empty"));
+    }
+
+    let mut cursor = Cursor::new(data);
+    (0..(data.len() / 4)).map(|_| cursor.read_u32::<LE>()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{m2_chunk, u32s};
+
+    #[test]
+    fn test_parse_md21() {
+        let mut md20 = vec![0u8; 0x140];
+        md20[0..4].copy_from_slice(b"MD20");
+        md20[4..8].copy_from_slice(&274u32.to_le_bytes());
+        md20[0x44..0x48].copy_from_slice(&2u32.to_le_bytes());
+
+        let mut afid = vec![1, 0, 2, 0];
+        afid.extend(u32s(&[500]));
+
+        let mut file = m2_chunk(b"MD21", &md20);
+        file.extend(m2_chunk(b"SFID", &u32s(&[10, 11, 12])));
+        file.extend(m2_chunk(b"TXID", &u32s(&[20])));
+        file.extend(m2_chunk(b"AFID", &afid));
+        file.extend(m2_chunk(b"PFID", &u32s(&[30])));
+
+        assert!(is_chunked(&file));
+        let model = parse(&file).unwrap();
+        assert_eq!(model.version, 274);
+
+        let ids = model.file_data_ids.unwrap();
+        assert_eq!(ids.skins, vec![10, 11]);
+        assert_eq!(ids.lod_skins, vec![12]);
+        assert_eq!(ids.textures, vec![20]);
+        assert_eq!(ids.physics, Some(30));
+        assert_eq!((ids.animations[0].animation_id, ids.animations[0].sub_animation_id, ids.animations[0].file_data_id), (1, 2, 500));
+    }
+
+    #[test]
+    fn test_parse_md21_truncated() {
+        let mut md20 = vec![0u8; 0x140];
+        md20[0..4].copy_from_slice(b"MD20");
+        md20[4..8].copy_from_slice(&274u32.to_le_bytes());
+        let file = m2_chunk(b"MD21", &md20);
+
+        // a chunk running past the end of the file
+        assert!(parse(&file[..file.len() - 1]).is_err());
+        // an MD20 block cut short inside its chunk
+        assert!(parse(&m2_chunk(b"MD21", &md20[..0x20])).is_err());
+
+        let mut without_model = m2_chunk(b"SFID", &u32s(&[10]));
+        without_model.extend(m2_chunk(b"TXID", &u32s(&[20])));
+        match parse(&without_model) {
+            Err(Error::MissingChunk { token, .. }) => assert_eq!(token, Token::MD21),
+            other => panic!("unexpected result {:?}", other.map(|model| model.version)),
+        }
+
+        // an empty id chunk, and an id list with a partial trailing entry
+        let mut empty_ids = file.clone();
+        empty_ids.extend(m2_chunk(b"PFID", &[]));
+        assert!(parse(&empty_ids).is_err());
+        let mut partial = file.clone();
+        partial.extend(m2_chunk(b"TXID", &[20, 0, 0, 0, 21, 0]));
+        assert_eq!(parse(&partial).unwrap().file_data_ids.unwrap().textures, vec![20]);
+    }
+}
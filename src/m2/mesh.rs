@@ -0,0 +1,176 @@
+use m2::{Model, TextureKind, resolve_lookup};
+use m2::skin::SkinProfile;
+
+// geometry of a skin profile, ready to be uploaded as vertex and index buffers
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct M2Mesh {
+    pub vertices: Vec<MeshVertex>,
+    // triangle list indexing into `vertices`
+    pub indices: Vec<u16>,
+    pub batches: Vec<MeshBatch>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coords: [[f32; 2]; 2],
+    // indices into `Model::bones`
+    pub bone_indices: [u8; 4],
+    pub bone_weights: [u8; 4],
+}
+
+// a draw call: a range of `M2Mesh::indices` with the material and textures to use
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MeshBatch {
+    pub submesh_id: u16,
+    pub priority_plane: i8,
+    pub index_start: u32,
+    pub index_count: u32,
+    // index into `Model::materials`
+    pub material: Option<usize>,
+    // indices into `Model::textures`
+    pub textures: Vec<Option<usize>>,
+    // filenames of `textures`, with replaceable ones resolved
+    pub texture_files: Vec<Option<String>>,
+    // indices into `Model::texture_transforms`, one per texture
+    pub texture_transforms: Vec<Option<usize>>,
+}
+
+impl Model {
+    // replaceable textures are left unresolved; see `build_mesh_with`
+    pub fn build_mesh(&self, skin: &SkinProfile) -> M2Mesh {
+        self.build_mesh_with(skin, |_| None)
+    }
+
+    // `replace` supplies the filenames of replaceable textures (skin, hair, ...)
+    pub fn build_mesh_with<F: Fn(TextureKind) -> Option<String>>(&self, skin: &SkinProfile, replace: F) -> M2Mesh {
+        let vertices = skin.vertex_lookup.iter()
+            .map(|&index| match self.vertices.get(index as usize) {
+                Some(vertex) => MeshVertex {
+                    position: [vertex.position.x, vertex.position.y, vertex.position.z],
+                    normal: [vertex.normal.x, vertex.normal.y, vertex.normal.z],
+                    tex_coords: [
+                        [vertex.tex_coords[0].x, vertex.tex_coords[0].y],
+                        [vertex.tex_coords[1].x, vertex.tex_coords[1].y],
+                    ],
+                    bone_indices: vertex.bone_indices,
+                    bone_weights: vertex.bone_weights,
+                },
+                None => {
+                    report_warn!("Skin vertex refers to missing model vertex {}", index);
+                    MeshVertex {
+                        position: [0.0; 3],
+                        normal: [0.0, 0.0, 1.0],
+                        tex_coords: [[0.0; 2]; 2],
+                        bone_indices: [0; 4],
+                        bone_weights: [0; 4],
+                    }
+                }
+            })
+            .collect();
+
+        let batches = skin.batches.iter()
+            .filter_map(|batch| {
+                let submesh = skin.submeshes.get(batch.submesh_index as usize)?;
+
+                // `level` holds the high bits of index start for meshes over 65535 indices
+                let index_start = (submesh.level as u32) << 16 | submesh.index_start as u32;
+
+                let textures = (0..batch.texture_count as usize)
+                    .map(|layer| resolve_lookup(&self.lookups.texture, batch.texture_combo_index as usize + layer, self.textures.len()))
+                    .collect::<Vec<_>>();
+
+                let texture_transforms = (0..batch.texture_count as usize)
+                    .map(|layer| resolve_lookup(&self.lookups.texture_transform, batch.texture_transform_combo_index as usize + layer, self.texture_transforms.len()))
+                    .collect();
+
+                let texture_files = textures.iter()
+                    .map(|index| index.and_then(|index| self.textures[index].resolve(&replace)))
+                    .collect();
+
+                let material = batch.material_index as usize;
+
+                Some(MeshBatch {
+                    submesh_id: submesh.id,
+                    priority_plane: batch.priority_plane,
+                    index_start,
+                    index_count: submesh.index_count as u32,
+                    material: if material < self.materials.len() { Some(material) } else { None },
+                    textures,
+                    texture_files,
+                    texture_transforms,
+                })
+            })
+            .collect();
+
+        M2Mesh {
+            vertices,
+            indices: skin.index_lookup.clone(),
+            batches,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m2::{Material, MaterialFlags, BlendMode, Texture, TextureFlags};
+    use m2::skin::{Submesh, Batch};
+    use nalgebra::Point3;
+
+    #[test]
+    fn test_build_mesh() {
+        let mut data = vec![0u8; 0x140];
+        data[0..4].copy_from_slice(b"MD20");
+        data[4..8].copy_from_slice(&264u32.to_le_bytes());
+        let mut model = Model::parse(&data).unwrap();
+
+        model.materials.push(Material { flags: MaterialFlags::empty(), blend_mode: BlendMode::Opaque });
+        model.textures.push(Texture { kind: TextureKind::Monster1, flags: TextureFlags::empty(), filename: String::new() });
+        model.lookups.texture = vec![-1, 0];
+
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let skin = SkinProfile {
+            vertex_lookup: vec![0],
+            index_lookup: vec![0, 0, 0],
+            bone_lookup: Vec::new(),
+            submeshes: vec![Submesh {
+                id: 1, level: 1, vertex_start: 0, vertex_count: 1, index_start: 2, index_count: 3,
+                bone_count: 0, bone_combo_index: 0, bone_influences: 0, center_bone_index: 0,
+                center_position: origin, sort_center_position: origin, sort_radius: 0.0,
+            }],
+            batches: vec![Batch {
+                flags: 0, priority_plane: 0, shader_id: 0, submesh_index: 0, geoset_index: 0,
+                color_index: 0, material_index: 0, material_layer: 0, texture_count: 2,
+                texture_combo_index: 0, texture_coord_combo_index: 0, texture_weight_combo_index: 0,
+                texture_transform_combo_index: 0,
+            }],
+            bone_count_max: 0,
+        };
+
+        // the vertex is missing from the model, so a placeholder is used
+        let mesh = model.build_mesh(&skin);
+        assert_eq!(mesh.vertices.len(), 1);
+        assert_eq!(mesh.indices, vec![0, 0, 0]);
+        assert_eq!(mesh.batches[0].index_start, 0x10002);
+        assert_eq!(mesh.batches[0].material, Some(0));
+        assert_eq!(mesh.batches[0].textures, vec![None, Some(0)]);
+        assert_eq!(mesh.batches[0].texture_files, vec![None, None]);
+        assert_eq!(mesh.batches[0].texture_transforms, vec![None, None]);
+
+        let mesh = model.build_mesh_with(&skin, |kind| {
+            if kind == TextureKind::Monster1 { Some("Creature\\Foo\\FooSkin.blp".to_string()) } else { None }
+        });
+        assert_eq!(mesh.batches[0].texture_files[1], Some("Creature\\Foo\\FooSkin.blp".to_string()));
+
+        // combo indices near u16::MAX point past the end of the lookups
+        let mut skin = skin;
+        skin.batches[0].texture_combo_index = u16::MAX;
+        skin.batches[0].texture_transform_combo_index = u16::MAX;
+        assert_eq!(model.build_mesh(&skin).batches[0].textures, vec![None, None]);
+    }
+}
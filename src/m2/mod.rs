@@ -0,0 +1,808 @@
+use std::io::{self, Read, Seek, SeekFrom, Cursor};
+use std::sync::Arc;
+use byteorder::{ReadBytesExt, LE};
+use nalgebra::{Point3, Vector2, Vector3};
+use read_ext::ReadExt;
+use reader::{ResourceReader, ResourceHandle, split_resource_name};
+use records::{read_records, Record, M2VertexRecord};
+use culling::LocalBounds;
+use light::LightKind;
+use version::GameVersion;
+use asset::{Asset, LoadOptions};
+use limits::Limits;
+
+pub mod md21;
+pub mod skin;
+pub mod track;
+pub mod bone;
+pub mod sequence;
+pub mod animation;
+pub mod mesh;
+pub mod camera;
+pub mod event;
+pub mod texture_transform;
+pub mod phys;
+pub mod skel;
+pub mod render;
+
+use self::md21::FileDataIds;
+use self::bone::{Bone, BONE_SIZE};
+use self::sequence::{Sequence, SEQUENCE_SIZE};
+use self::camera::Camera;
+use self::event::{Event, EVENT_SIZE};
+use self::track::{M2Track, TRACK_SIZE};
+use self::texture_transform::{TextureTransform, TEXTURE_TRANSFORM_SIZE};
+use self::animation::AnimationTime;
+use error::{Error, Result};
+
+// first version with the wotlk header layout
+const VERSION_WOTLK: u32 = 264;
+
+// pre-wotlk headers have extra M2Arrays (playable animation lookup, texture
+// flipbooks) and a M2Array of skin profiles instead of their count
+const BOUNDS_OFFSET_WOTLK: u64 = 0xA0;
+const BOUNDS_OFFSET_CLASSIC: u64 = 0xB4;
+const LIGHTS_OFFSET_WOTLK: u64 = 0x108;
+
+const LIGHT_SIZE: usize = 16 + TRACK_SIZE * 7;
+const TEXTURE_SIZE: usize = 16;
+const MATERIAL_SIZE: usize = 4;
+
+bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct ModelFlags: u32 {
+        const TILT_X = 0x1;
+        const TILT_Y = 0x2;
+        const USE_TEXTURE_COMBINER_COMBOS = 0x8;
+        const LOAD_PHYS_DATA = 0x20;
+        const CAMERA_RELATED = 0x100;
+        const NEW_PARTICLE_RECORD = 0x200;
+        const TEXTURE_TRANSFORMS_USE_BONE_SEQUENCES = 0x800;
+    }
+}
+
+bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct TextureFlags: u32 {
+        const WRAP_X = 0x1;
+        const WRAP_Y = 0x2;
+    }
+}
+
+bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct MaterialFlags: u16 {
+        const UNLIT = 0x1;
+        const UNFOGGED = 0x2;
+        const TWO_SIDED = 0x4;
+        const DEPTH_TEST_DISABLED = 0x8;
+        const DEPTH_WRITE_DISABLED = 0x10;
+        const SHADOW_BATCH_1 = 0x40;
+        const SHADOW_BATCH_2 = 0x80;
+        const PREVENT_ALPHA = 0x400;
+    }
+}
+
+// MD20 model (wotlk layout, also used by later expansions)
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Model {
+    pub version: u32,
+    pub name: String,
+    pub flags: ModelFlags,
+    pub global_loops: Vec<u32>,
+    pub sequences: Vec<Sequence>,
+    pub bones: Vec<Bone>,
+    pub vertices: Vec<Vertex>,
+    pub skin_profile_count: u32,
+    pub textures: Vec<Texture>,
+    pub texture_transforms: Vec<TextureTransform>,
+    pub materials: Vec<Material>,
+    pub lookups: Lookups,
+    pub bounds: LocalBounds,
+    pub collision_bounds: LocalBounds,
+    pub collision_indices: Vec<u16>,
+    pub collision_vertices: Vec<Point3<f32>>,
+    pub collision_normals: Vec<Vector3<f32>>,
+    pub lights: Vec<Light>,
+    pub cameras: Vec<Camera>,
+    pub events: Vec<Event>,
+    // only present in chunked (MD21) models
+    pub file_data_ids: Option<FileDataIds>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Vertex {
+    pub position: Point3<f32>,
+    pub bone_weights: [u8; 4],
+    pub bone_indices: [u8; 4],
+    pub normal: Vector3<f32>,
+    pub tex_coords: [Vector2<f32>; 2],
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Texture {
+    pub kind: TextureKind,
+    pub flags: TextureFlags,
+    // empty for replaceable textures
+    pub filename: String,
+}
+
+// hardcoded textures use their filename, any other kind is supplied at runtime
+// from the creature/character/item being displayed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TextureKind {
+    Hardcoded,
+    Skin,
+    ObjectSkin,
+    WeaponBlade,
+    WeaponHandle,
+    Environment,
+    Hair,
+    FacialHair,
+    SkinExtra,
+    UiSkin,
+    TaurenMane,
+    Monster1,
+    Monster2,
+    Monster3,
+    ItemIcon,
+    Other(u32),
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Material {
+    pub flags: MaterialFlags,
+    pub blend_mode: BlendMode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BlendMode {
+    Opaque,
+    // alpha tested against 224/255
+    AlphaKey,
+    Alpha,
+    NoAlphaAdd,
+    Add,
+    Mod,
+    Mod2x,
+    BlendAdd,
+    Other(u16),
+}
+
+// index tables referenced by skin profiles and animation data
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Lookups {
+    pub sequence: Vec<i16>,
+    pub key_bone: Vec<i16>,
+    pub replaceable_texture: Vec<i16>,
+    pub bone: Vec<i16>,
+    pub texture: Vec<i16>,
+    pub texture_unit: Vec<i16>,
+    pub transparency: Vec<i16>,
+    pub texture_transform: Vec<i16>,
+    pub attachment: Vec<i16>,
+    pub camera: Vec<i16>,
+}
+
+// count and offset of an array stored elsewhere in the MD20 block
+#[derive(Clone, Copy, Debug)]
+struct M2Array {
+    count: u32,
+    offset: u32,
+}
+
+// model-attached light; the plain fields hold the first keyframes of the
+// first sequence, use `Light::at` for other points in time
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Light {
+    pub kind: LightKind,
+    pub bone: i16,
+    pub position: Point3<f32>,
+    pub ambient_color: Vector3<f32>,
+    pub ambient_intensity: f32,
+    pub diffuse_color: Vector3<f32>,
+    pub diffuse_intensity: f32,
+    pub attenuation_start: f32,
+    pub attenuation_end: f32,
+    pub visible: bool,
+    pub tracks: LightTracks,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LightTracks {
+    pub ambient_color: M2Track<Vector3<f32>>,
+    pub ambient_intensity: M2Track<f32>,
+    pub diffuse_color: M2Track<Vector3<f32>>,
+    pub diffuse_intensity: M2Track<f32>,
+    pub attenuation_start: M2Track<f32>,
+    pub attenuation_end: M2Track<f32>,
+    pub visibility: M2Track<u8>,
+}
+
+// placements reference the old .mdx/.mdl names of models
+pub fn model_file_name(name: &str) -> String {
+    let (dir, file, ext) = split_resource_name(name);
+    match ext.to_lowercase().as_str() {
+        ".mdx" | ".mdl" => format!("{}{}.m2", dir, file),
+        _ => name.to_owned(),
+    }
+}
+
+// the layout follows the version in the file; `version` is only checked against it
+pub fn load(reader: Arc<dyn ResourceReader>, name: &str, version: Option<GameVersion>) -> Result<Model> {
+    Model::load(&*reader, name, &LoadOptions { version, ..LoadOptions::default() })
+}
+
+impl Asset for Model {
+    const EXTENSIONS: &'static [&'static str] = &[".m2"];
+
+    fn load(reader: &dyn ResourceReader, name: &str, options: &LoadOptions) -> Result<Model> {
+        options.apply(name, || {
+            let mut input = reader.open(name)?;
+            let mut data = Vec::new();
+            input.read_to_end(&mut data)?;
+
+            let model = parse(&data).map_err(|e| e.in_file(name))?;
+
+            if let Some(version) = options.version {
+                let (first, last) = version.m2_versions();
+                if model.version < first || model.version > last {
+                    report_inconsistency!("{} has M2 version {}, which {:?} doesn't use", name, model.version, version);
+                }
+            }
+
+            Ok(model)
+        })
+    }
+}
+
+// either a chunked (MD21, legion+) or a plain MD20 model file
+pub fn parse(data: &[u8]) -> Result<Model> {
+    if md21::is_chunked(data) { md21::parse(data) } else { Model::parse(data) }
+}
+
+impl Model {
+    // bounds of the model in any of its sequences, for culling and camera framing.
+    // the header `bounds` usually only cover the default pose
+    pub fn animated_bounds(&self) -> LocalBounds {
+        self.sequences.iter().fold(self.bounds.clone(), |bounds, sequence| bounds.union(&sequence.bounds))
+    }
+
+    // `data` is the MD20 block; all offsets in it are relative to its start
+    pub fn parse(data: &[u8]) -> Result<Model> {
+        let mut input = Cursor::new(data);
+        let version = read_magic_and_version(&mut input)?;
+
+        if version < VERSION_WOTLK {
+            return Err(Error::UnsupportedVersion { format: "M2", found: version });
+        }
+
+        let name = read_array_header_at(&mut input, 0x08)?;
+        input.seek(SeekFrom::Start(0x10))?;
+        let flags = ModelFlags::from_bits_truncate(input.read_u32::<LE>()?);
+
+        let vertices = read_array::<M2VertexRecord>(data, read_array_header_at(&mut input, 0x3C)?)?
+            .into_iter()
+            .map(|record| Vertex {
+                position: Point3::new(record.position[0], record.position[1], record.position[2]),
+                bone_weights: record.bone_weights,
+                bone_indices: record.bone_indices,
+                normal: Vector3::new(record.normal[0], record.normal[1], record.normal[2]),
+                tex_coords: [
+                    Vector2::new(record.tex_coords[0][0], record.tex_coords[0][1]),
+                    Vector2::new(record.tex_coords[1][0], record.tex_coords[1][1]),
+                ],
+            })
+            .collect();
+
+        input.seek(SeekFrom::Start(0x44))?;
+        let skin_profile_count = input.read_u32::<LE>()?;
+
+        let textures = read_textures(data, read_array_header_at(&mut input, 0x50)?)?;
+        let materials = read_materials(data, read_array_header_at(&mut input, 0x70)?)?;
+
+        let mut lookup = |offset: u64| -> io::Result<Vec<i16>> {
+            let array = read_array_header_at(&mut input, offset)?;
+            Ok(read_array::<u16>(data, array)?.into_iter().map(|value| value as i16).collect())
+        };
+
+        let lookups = Lookups {
+            sequence: lookup(0x24)?,
+            key_bone: lookup(0x34)?,
+            replaceable_texture: lookup(0x68)?,
+            bone: lookup(0x78)?,
+            texture: lookup(0x80)?,
+            texture_unit: lookup(0x88)?,
+            transparency: lookup(0x90)?,
+            texture_transform: lookup(0x98)?,
+            attachment: lookup(0xF8)?,
+            camera: lookup(0x118)?,
+        };
+
+        input.seek(SeekFrom::Start(BOUNDS_OFFSET_WOTLK))?;
+        let bounds = read_bounds(&mut input)?;
+        let collision_bounds = read_bounds(&mut input)?;
+
+        let collision_indices = read_array::<u16>(data, read_array_header_at(&mut input, 0xD8)?)?;
+        let collision_vertices = read_array::<[f32; 3]>(data, read_array_header_at(&mut input, 0xE0)?)?
+            .into_iter()
+            .map(|v| Point3::new(v[0], v[1], v[2]))
+            .collect();
+        let collision_normals = read_array::<[f32; 3]>(data, read_array_header_at(&mut input, 0xE8)?)?
+            .into_iter()
+            .map(|v| Vector3::new(v[0], v[1], v[2]))
+            .collect();
+
+        let global_loops = read_array::<u32>(data, read_array_header_at(&mut input, 0x14)?)?;
+
+        let sequences_array = read_array_header_at(&mut input, 0x1C)?;
+        let sequences = sequence::read_sequences(array_data(data, sequences_array, SEQUENCE_SIZE)?)?;
+
+        // keyframes of sequences stored in .anim files aren't part of the block
+        let inline: Vec<bool> = sequences.iter().map(Sequence::is_inline).collect();
+        let bones_array = read_array_header_at(&mut input, 0x2C)?;
+        array_data(data, bones_array, BONE_SIZE)?;
+        let bones = (0..bones_array.count as usize)
+            .map(|index| Bone::read(data, bones_array.offset as usize + index * BONE_SIZE, &inline))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let transforms_array = read_array_header_at(&mut input, 0x60)?;
+        array_data(data, transforms_array, TEXTURE_TRANSFORM_SIZE)?;
+        let texture_transforms = (0..transforms_array.count as usize)
+            .map(|index| TextureTransform::read(data, transforms_array.offset as usize + index * TEXTURE_TRANSFORM_SIZE, &inline))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let lights = read_lights(data, read_array_header_at(&mut input, LIGHTS_OFFSET_WOTLK)?, &inline)?;
+
+        let cameras_array = read_array_header_at(&mut input, 0x110)?;
+        let camera_size = Camera::record_size(version);
+        array_data(data, cameras_array, camera_size)?;
+        let cameras = (0..cameras_array.count as usize)
+            .map(|index| Camera::read(data, cameras_array.offset as usize + index * camera_size, version, &inline))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let events_array = read_array_header_at(&mut input, 0x100)?;
+        array_data(data, events_array, EVENT_SIZE)?;
+        let events = (0..events_array.count as usize)
+            .map(|index| Event::read(data, events_array.offset as usize + index * EVENT_SIZE, &inline))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Model {
+            version,
+            name: read_string(data, name)?,
+            flags,
+            global_loops,
+            sequences,
+            bones,
+            vertices,
+            skin_profile_count,
+            textures,
+            texture_transforms,
+            materials,
+            lookups,
+            bounds,
+            collision_bounds,
+            collision_indices,
+            collision_vertices,
+            collision_normals,
+            lights,
+            cameras,
+            events,
+            file_data_ids: None,
+        })
+    }
+}
+
+impl TextureKind {
+    pub fn from_raw(value: u32) -> Self {
+        match value {
+            0 => TextureKind::Hardcoded,
+            1 => TextureKind::Skin,
+            2 => TextureKind::ObjectSkin,
+            3 => TextureKind::WeaponBlade,
+            4 => TextureKind::WeaponHandle,
+            5 => TextureKind::Environment,
+            6 => TextureKind::Hair,
+            7 => TextureKind::FacialHair,
+            8 => TextureKind::SkinExtra,
+            9 => TextureKind::UiSkin,
+            10 => TextureKind::TaurenMane,
+            11 => TextureKind::Monster1,
+            12 => TextureKind::Monster2,
+            13 => TextureKind::Monster3,
+            14 => TextureKind::ItemIcon,
+            other => TextureKind::Other(other),
+        }
+    }
+
+    pub fn is_replaceable(&self) -> bool {
+        *self != TextureKind::Hardcoded
+    }
+}
+
+impl BlendMode {
+    pub fn from_raw(value: u16) -> Self {
+        match value {
+            0 => BlendMode::Opaque,
+            1 => BlendMode::AlphaKey,
+            2 => BlendMode::Alpha,
+            3 => BlendMode::NoAlphaAdd,
+            4 => BlendMode::Add,
+            5 => BlendMode::Mod,
+            6 => BlendMode::Mod2x,
+            7 => BlendMode::BlendAdd,
+            other => BlendMode::Other(other),
+        }
+    }
+
+    // whether batches with this mode are drawn after opaque ones, sorted back to front
+    pub fn is_transparent(&self) -> bool {
+        !matches!(*self, BlendMode::Opaque | BlendMode::AlphaKey)
+    }
+}
+
+impl Material {
+    pub fn is_lit(&self) -> bool {
+        !self.flags.contains(MaterialFlags::UNLIT)
+    }
+
+    pub fn is_fogged(&self) -> bool {
+        !self.flags.contains(MaterialFlags::UNFOGGED)
+    }
+
+    pub fn is_two_sided(&self) -> bool {
+        self.flags.contains(MaterialFlags::TWO_SIDED)
+    }
+
+    pub fn depth_test(&self) -> bool {
+        !self.flags.contains(MaterialFlags::DEPTH_TEST_DISABLED)
+    }
+
+    pub fn depth_write(&self) -> bool {
+        !self.flags.contains(MaterialFlags::DEPTH_WRITE_DISABLED)
+    }
+}
+
+impl Texture {
+    // filename to load; replaceable textures are looked up with `replace`
+    pub fn resolve<F: Fn(TextureKind) -> Option<String>>(&self, replace: F) -> Option<String> {
+        if self.kind.is_replaceable() {
+            replace(self.kind)
+        } else if self.filename.is_empty() {
+            None
+        } else {
+            Some(self.filename.clone())
+        }
+    }
+}
+
+// reads only the model header, without touching any geometry
+pub fn load_bounds(reader: Arc<dyn ResourceReader>, name: &str) -> Result<LocalBounds> {
+    let mut input = open_md20(&*reader, name)?;
+    let version = read_magic_and_version(&mut input)?;
+
+    let offset = if version < VERSION_WOTLK { BOUNDS_OFFSET_CLASSIC } else { BOUNDS_OFFSET_WOTLK };
+    input.seek(SeekFrom::Start(offset))?;
+    Ok(read_bounds(&mut input)?)
+}
+
+pub fn load_lights(reader: Arc<dyn ResourceReader>, name: &str) -> Result<Vec<Light>> {
+    let mut input = open_md20(&*reader, name)?;
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+
+    let mut input = Cursor::new(&data[..]);
+    let version = read_magic_and_version(&mut input)?;
+
+    if version < VERSION_WOTLK {
+        report_warn!("Skipping lights of pre-wotlk model {} (version {})", name, version);
+        return Ok(Vec::new());
+    }
+
+    let sequences = sequence::read_sequences(array_data(&data, read_array_header_at(&mut input, 0x1C)?, SEQUENCE_SIZE)?)?;
+    let inline: Vec<bool> = sequences.iter().map(Sequence::is_inline).collect();
+    Ok(read_lights(&data, read_array_header_at(&mut input, LIGHTS_OFFSET_WOTLK)?, &inline)?)
+}
+
+fn read_lights(data: &[u8], array: M2Array, inline: &[bool]) -> io::Result<Vec<Light>> {
+    let mut cursor = Cursor::new(array_data(data, array, LIGHT_SIZE)?);
+    let mut lights = Vec::with_capacity(array.count as usize);
+
+    for index in 0..(array.count as usize) {
+        cursor.set_position((index * LIGHT_SIZE) as u64);
+
+        let_read! { LE | cursor =>
+            light_type: u16;
+            bone: i16;
+            position: (f32, f32, f32);
+        }
+
+        let base = array.offset as usize + index * LIGHT_SIZE + 16;
+        let tracks = LightTracks {
+            ambient_color: M2Track::read(data, base, inline)?,
+            ambient_intensity: M2Track::read(data, base + TRACK_SIZE, inline)?,
+            diffuse_color: M2Track::read(data, base + TRACK_SIZE * 2, inline)?,
+            diffuse_intensity: M2Track::read(data, base + TRACK_SIZE * 3, inline)?,
+            attenuation_start: M2Track::read(data, base + TRACK_SIZE * 4, inline)?,
+            attenuation_end: M2Track::read(data, base + TRACK_SIZE * 5, inline)?,
+            visibility: M2Track::read(data, base + TRACK_SIZE * 6, inline)?,
+        };
+
+        let first_f32 = |track: &M2Track<f32>| track.keyframes(0).1.first().cloned().unwrap_or(0.0);
+        let first_vector3 = |track: &M2Track<Vector3<f32>>| track.keyframes(0).1.first().cloned().unwrap_or_else(Vector3::zeros);
+
+        lights.push(Light {
+            kind: LightKind::from_m2(light_type),
+            bone,
+            position: Point3::new(position.0, position.1, position.2),
+            ambient_color: first_vector3(&tracks.ambient_color),
+            ambient_intensity: first_f32(&tracks.ambient_intensity),
+            diffuse_color: first_vector3(&tracks.diffuse_color),
+            diffuse_intensity: first_f32(&tracks.diffuse_intensity),
+            attenuation_start: first_f32(&tracks.attenuation_start),
+            attenuation_end: first_f32(&tracks.attenuation_end),
+            visible: tracks.visibility.keyframes(0).1.first().map(|&v| v != 0).unwrap_or(true),
+            tracks,
+        });
+    }
+
+    Ok(lights)
+}
+
+impl Light {
+    // copy of the light with its values sampled at `at`
+    pub fn at(&self, at: AnimationTime, global_loops: &[u32]) -> Light {
+        let tracks = &self.tracks;
+        let visibility = tracks.visibility.sample_by(at, global_loops, |&v| v as f32);
+
+        Light {
+            ambient_color: tracks.ambient_color.sample(at, global_loops).unwrap_or(self.ambient_color),
+            ambient_intensity: tracks.ambient_intensity.sample(at, global_loops).unwrap_or(self.ambient_intensity),
+            diffuse_color: tracks.diffuse_color.sample(at, global_loops).unwrap_or(self.diffuse_color),
+            diffuse_intensity: tracks.diffuse_intensity.sample(at, global_loops).unwrap_or(self.diffuse_intensity),
+            attenuation_start: tracks.attenuation_start.sample(at, global_loops).unwrap_or(self.attenuation_start),
+            attenuation_end: tracks.attenuation_end.sample(at, global_loops).unwrap_or(self.attenuation_end),
+            visible: visibility.map(|v| v >= 0.5).unwrap_or(self.visible),
+            ..self.clone()
+        }
+    }
+}
+
+// input positioned at the start of the MD20 block; for chunked models only
+// the MD21 chunk is read, since offsets are relative to it
+fn open_md20(reader: &dyn ResourceReader, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+    let mut input = reader.open(name)?;
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+
+    if &magic == b"MD21" {
+        let size = input.read_u32::<LE>()?;
+        Limits::current().check_chunk_size(size as u64)?;
+        Ok(Box::new(Cursor::new(input.read_vec(size as usize)?)))
+    } else {
+        input.seek(SeekFrom::Start(0))?;
+        Ok(input)
+    }
+}
+
+fn read_magic_and_version<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+
+    if &magic != b"MD20" {
+        return Err(Error::BadMagic { format: "M2", found: magic }.into());
+    }
+
+    input.read_u32::<LE>()
+}
+
+// (count, offset) pair
+fn read_array_header<R: Read>(input: &mut R) -> io::Result<(u32, u32)> {
+    let count = input.read_u32::<LE>()?;
+    let offset = input.read_u32::<LE>()?;
+    Ok((count, offset))
+}
+
+fn read_array_header_at<R: Read + Seek>(input: &mut R, header_offset: u64) -> io::Result<M2Array> {
+    input.seek(SeekFrom::Start(header_offset))?;
+    let (count, offset) = read_array_header(input)?;
+    Ok(M2Array { count, offset })
+}
+
+// bytes of `array` with elements of `element_size`, checked against the block size
+fn array_data(data: &[u8], array: M2Array, element_size: usize) -> io::Result<&[u8]> {
+    let start = array.offset as usize;
+    let end = start as u64 + array.count as u64 * element_size as u64;
+
+    if array.count == 0 {
+        Ok(&[])
+    } else if end > data.len() as u64 {
+        Err(io_error!(UnexpectedEof, "array of {} elements at {} exceeds the model size", array.count, array.offset))
+    } else {
+        Ok(&data[start..end as usize])
+    }
+}
+
+fn read_array<T: Record>(data: &[u8], array: M2Array) -> io::Result<Vec<T>> {
+    read_records(array_data(data, array, ::std::mem::size_of::<T>())?)
+}
+
+// resolves a lookup table entry, with -1 and out of range values as `None`
+fn resolve_lookup(table: &[i16], index: usize, len: usize) -> Option<usize> {
+    table.get(index)
+        .and_then(|&index| if index >= 0 { Some(index as usize) } else { None })
+        .filter(|&index| index < len)
+}
+
+fn read_string(data: &[u8], array: M2Array) -> io::Result<String> {
+    let bytes = array_data(data, array, 1)?;
+    let bytes = bytes.split(|&byte| byte == 0).next().unwrap_or(&[]);
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn read_textures(data: &[u8], array: M2Array) -> io::Result<Vec<Texture>> {
+    let mut cursor = Cursor::new(array_data(data, array, TEXTURE_SIZE)?);
+    let mut textures = Vec::with_capacity(array.count as usize);
+
+    for _ in 0..array.count {
+        let kind = cursor.read_u32::<LE>()?;
+        let flags = TextureFlags::from_bits_truncate(cursor.read_u32::<LE>()?);
+        let (count, offset) = read_array_header(&mut cursor)?;
+
+        textures.push(Texture {
+            kind: TextureKind::from_raw(kind),
+            flags,
+            filename: read_string(data, M2Array { count, offset })?,
+        });
+    }
+
+    Ok(textures)
+}
+
+fn read_materials(data: &[u8], array: M2Array) -> io::Result<Vec<Material>> {
+    let mut cursor = Cursor::new(array_data(data, array, MATERIAL_SIZE)?);
+    let mut materials = Vec::with_capacity(array.count as usize);
+
+    for _ in 0..array.count {
+        materials.push(Material {
+            flags: MaterialFlags::from_bits_truncate(cursor.read_u16::<LE>()?),
+            blend_mode: BlendMode::from_raw(cursor.read_u16::<LE>()?),
+        });
+    }
+
+    Ok(materials)
+}
+
+fn read_bounds<R: Read>(mut input: &mut R) -> io::Result<LocalBounds> {
+    let_read! { LE | input =>
+        bounding_box_min: (f32, f32, f32);
+        bounding_box_max: (f32, f32, f32);
+        bounding_radius: f32;
+    }
+
+    Ok(LocalBounds {
+        min: Point3::new(bounding_box_min.0, bounding_box_min.1, bounding_box_min.2),
+        max: Point3::new(bounding_box_max.0, bounding_box_max.1, bounding_box_max.2),
+        radius: bounding_radius,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{put_u32, put_f32};
+
+    #[test]
+    fn test_parse_model() {
+        let mut data = vec![0u8; 0x300];
+        data[0..4].copy_from_slice(b"MD20");
+        put_u32(&mut data, 0x04, 264);
+        put_u32(&mut data, 0x10, 0x8);
+
+        // name
+        data[0x140..0x145].copy_from_slice(b"Test\0");
+        put_u32(&mut data, 0x08, 5);
+        put_u32(&mut data, 0x0C, 0x140);
+
+        // one vertex
+        put_u32(&mut data, 0x3C, 1);
+        put_u32(&mut data, 0x40, 0x150);
+        put_f32(&mut data, 0x150, 1.0);
+        data[0x15C] = 255;
+        put_f32(&mut data, 0x168, 1.0);
+        put_f32(&mut data, 0x178, 0.5);
+
+        put_u32(&mut data, 0x44, 2);
+
+        // one texture with a filename
+        put_u32(&mut data, 0x50, 1);
+        put_u32(&mut data, 0x54, 0x180);
+        put_u32(&mut data, 0x184, 3);
+        put_u32(&mut data, 0x188, 6);
+        put_u32(&mut data, 0x18C, 0x190);
+        data[0x190..0x196].copy_from_slice(b"a.blp\0");
+
+        // two-sided additive material
+        put_u32(&mut data, 0x70, 1);
+        put_u32(&mut data, 0x74, 0x198);
+        put_u32(&mut data, 0x198, 0x4 | 4 << 16);
+
+        // texture lookup
+        put_u32(&mut data, 0x80, 2);
+        put_u32(&mut data, 0x84, 0x1A0);
+        data[0x1A2..0x1A4].copy_from_slice(&(-1i16).to_le_bytes());
+
+        put_f32(&mut data, 0xB8, 3.0);
+
+        // one light with a diffuse intensity going from 1 to 3 within 100ms
+        put_u32(&mut data, 0x108, 1);
+        put_u32(&mut data, 0x10C, 0x200);
+        put_u32(&mut data, 0x200, 1 | 0xFFFF << 16);
+        put_u32(&mut data, 0x24C, 1 | 0xFFFF << 16);
+        put_u32(&mut data, 0x250, 1);
+        put_u32(&mut data, 0x254, 0x2B0);
+        put_u32(&mut data, 0x258, 1);
+        put_u32(&mut data, 0x25C, 0x2C0);
+        put_u32(&mut data, 0x2B0, 2);
+        put_u32(&mut data, 0x2B4, 0x2D0);
+        put_u32(&mut data, 0x2C0, 2);
+        put_u32(&mut data, 0x2C4, 0x2E0);
+        put_u32(&mut data, 0x2D4, 100);
+        put_f32(&mut data, 0x2E0, 1.0);
+        put_f32(&mut data, 0x2E4, 3.0);
+
+        let model = Model::parse(&data).unwrap();
+        assert_eq!(model.name, "Test");
+        assert!(model.flags.contains(ModelFlags::USE_TEXTURE_COMBINER_COMBOS));
+        assert_eq!(model.vertices.len(), 1);
+        assert_eq!(model.vertices[0].position, Point3::new(1.0, 0.0, 0.0));
+        assert_eq!(model.vertices[0].bone_weights, [255, 0, 0, 0]);
+        assert_eq!(model.vertices[0].normal, Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(model.vertices[0].tex_coords[1], Vector2::new(0.5, 0.0));
+        assert_eq!(model.skin_profile_count, 2);
+        assert_eq!(model.textures[0].filename, "a.blp");
+        assert_eq!(model.textures[0].kind, TextureKind::Hardcoded);
+        assert_eq!(model.textures[0].resolve(|_| None), Some("a.blp".to_string()));
+        assert_eq!(model.textures[0].flags, TextureFlags::all());
+        assert_eq!(model.materials[0].blend_mode, BlendMode::Add);
+        assert!(model.materials[0].blend_mode.is_transparent());
+        assert!(model.materials[0].is_two_sided() && model.materials[0].depth_write());
+        assert_eq!(model.lookups.texture, vec![0, -1]);
+        assert_eq!(model.bounds.radius, 3.0);
+        assert_eq!(model.lights[0].bone, -1);
+        assert_eq!(model.lights[0].diffuse_intensity, 1.0);
+        assert_eq!(model.lights[0].at(AnimationTime::new(0, 50, 0), &[]).diffuse_intensity, 2.0);
+        assert!(model.lights[0].visible);
+
+        put_u32(&mut data, 0x40, 0x2F0);
+        assert!(Model::parse(&data).is_err());
+    }
+
+    #[test]
+    fn test_load_bounds() {
+        use reader::memory::MemoryReader;
+
+        let mut data = vec![0u8; 0x100];
+        data[0..4].copy_from_slice(b"MD20");
+        put_u32(&mut data, 0x04, 260);
+        for (index, value) in [-1.0, -2.0, -3.0, 1.0, 2.0, 3.0, 4.0].iter().enumerate() {
+            put_f32(&mut data, BOUNDS_OFFSET_CLASSIC as usize + index * 4, *value);
+        }
+
+        let mut reader = MemoryReader::new();
+        reader.insert("classic.m2", data);
+        let bounds = load_bounds(Arc::new(reader), "classic.m2").unwrap();
+        assert_eq!(bounds.min, Point3::new(-1.0, -2.0, -3.0));
+        assert_eq!(bounds.max, Point3::new(1.0, 2.0, 3.0));
+        assert_eq!(bounds.radius, 4.0);
+    }
+}
@@ -0,0 +1,333 @@
+use std::io::{self, Read, Cursor};
+use std::ops::Range;
+use std::sync::Arc;
+use byteorder::{ReadBytesExt, LE};
+use nalgebra::{Matrix4, Point3, Vector3};
+use chunked::{Chunked, Token};
+use reader::ResourceReader;
+use error::{Error, Result};
+
+const BODY_SIZE: usize = 24;
+const SHAPE_SIZE: usize = 20;
+const BOX_SIZE: usize = 60;
+const CAPSULE_SIZE: usize = 28;
+const SPHERE_SIZE: usize = 16;
+const JOINT_SIZE: usize = 16;
+
+// rigid body setup of a model, stored in the `.phys` file referenced by PFID.
+// only the original chunk versions are decoded (BODY, SHAP, JOIN); later
+// revisions (BDY2.., SHP2) and joint parameters are skipped
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Physics {
+    pub version: u16,
+    pub bodies: Vec<RigidBody>,
+    pub shapes: Vec<Shape>,
+    pub joints: Vec<Joint>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BodyKind {
+    Static,
+    Dynamic,
+    Kinematic,
+    Other(u16),
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RigidBody {
+    pub kind: BodyKind,
+    pub bone: u16,
+    pub position: Point3<f32>,
+    // range of `Physics::shapes`
+    pub shapes: Range<usize>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Shape {
+    pub geometry: ShapeGeometry,
+    pub friction: f32,
+    pub restitution: f32,
+    pub density: f32,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ShapeGeometry {
+    Box { transform: Matrix4<f32>, half_extents: Vector3<f32> },
+    Capsule { start: Point3<f32>, end: Point3<f32>, radius: f32 },
+    Sphere { center: Point3<f32>, radius: f32 },
+    // convex hulls (PLYT) aren't decoded
+    Polytope(u16),
+    Unknown(u16, u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum JointKind {
+    Spherical,
+    Shoulder,
+    Weld,
+    Revolute,
+    Prismatic,
+    Distance,
+    Other(u16),
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Joint {
+    // indices into `Physics::bodies`
+    pub body_a: u32,
+    pub body_b: u32,
+    pub kind: JointKind,
+    // index into the chunk holding parameters of this joint kind
+    pub index: u16,
+}
+
+pub fn load(reader: Arc<dyn ResourceReader>, name: &str) -> Result<Physics> {
+    let mut input = reader.open(name)?;
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+
+    Physics::parse(&data).map_err(|e| e.in_file(name))
+}
+
+impl Physics {
+    pub fn parse(data: &[u8]) -> Result<Physics> {
+        let mut physics = Physics::default();
+        let mut found_header = false;
+
+        // raw shape records, resolved once all geometry chunks are known
+        let mut shapes = Vec::new();
+        let mut boxes = Vec::new();
+        let mut capsules = Vec::new();
+        let mut spheres = Vec::new();
+
+        // like legion models, tokens are stored in reading order
+        for chunk in Chunked::new_legion_m2(Cursor::new(data)) {
+            let chunk = chunk?;
+            let mut cursor = chunk.cursor();
+
+            match chunk.token {
+                Token::PHYS => {
+                    physics.version = cursor.read_u16::<LE>()?;
+                    found_header = true;
+                }
+                Token::BODY => {
+                    for _ in 0..(chunk.data.len() / BODY_SIZE) {
+                        let_read! { LE | cursor =>
+                            kind: u16;
+                            bone: u16;
+                            position: (f32, f32, f32);
+                            shapes_index: u16;
+                            _padding: u16;
+                            shapes_count: i32;
+                        }
+
+                        let start = shapes_index as usize;
+                        physics.bodies.push(RigidBody {
+                            kind: BodyKind::from_raw(kind),
+                            bone,
+                            position: Point3::new(position.0, position.1, position.2),
+                            shapes: start..start + shapes_count.max(0) as usize,
+                        });
+                    }
+                }
+                Token::SHAP => {
+                    for _ in 0..(chunk.data.len() / SHAPE_SIZE) {
+                        let_read! { LE | cursor =>
+                            kind: u16;
+                            index: u16;
+                            _unknown: u32;
+                            friction: f32;
+                            restitution: f32;
+                            density: f32;
+                        }
+                        shapes.push((kind, index, friction, restitution, density));
+                    }
+                }
+                Token::BOXS => {
+                    for _ in 0..(chunk.data.len() / BOX_SIZE) {
+                        // 3x4 matrix, column major
+                        let mut columns = [[0f32; 3]; 4];
+                        for column in columns.iter_mut() {
+                            for value in column.iter_mut() {
+                                *value = cursor.read_f32::<LE>()?;
+                            }
+                        }
+                        let_read! { LE | cursor => half_extents: (f32, f32, f32); }
+
+                        let mut transform = Matrix4::identity();
+                        for (column, values) in columns.iter().enumerate() {
+                            for (row, &value) in values.iter().enumerate() {
+                                transform[(row, column)] = value;
+                            }
+                        }
+
+                        boxes.push(ShapeGeometry::Box {
+                            transform,
+                            half_extents: Vector3::new(half_extents.0, half_extents.1, half_extents.2),
+                        });
+                    }
+                }
+                Token::CAPS => {
+                    for _ in 0..(chunk.data.len() / CAPSULE_SIZE) {
+                        let_read! { LE | cursor =>
+                            start: (f32, f32, f32);
+                            end: (f32, f32, f32);
+                            radius: f32;
+                        }
+                        capsules.push(ShapeGeometry::Capsule {
+                            start: Point3::new(start.0, start.1, start.2),
+                            end: Point3::new(end.0, end.1, end.2),
+                            radius,
+                        });
+                    }
+                }
+                Token::SPHS => {
+                    for _ in 0..(chunk.data.len() / SPHERE_SIZE) {
+                        let_read! { LE | cursor =>
+                            center: (f32, f32, f32);
+                            radius: f32;
+                        }
+                        spheres.push(ShapeGeometry::Sphere {
+                            center: Point3::new(center.0, center.1, center.2),
+                            radius,
+                        });
+                    }
+                }
+                Token::JOIN => {
+                    for _ in 0..(chunk.data.len() / JOINT_SIZE) {
+                        let_read! { LE | cursor =>
+                            body_a: u32;
+                            body_b: u32;
+                            _unknown: u32;
+                            kind: u16;
+                            index: u16;
+                        }
+                        physics.joints.push(Joint { body_a, body_b, kind: JointKind::from_raw(kind), index });
+                    }
+                }
+                other => trace!("skipping phys chunk {}", other),
+            }
+        }
+
+        if !found_header {
+            return Err(Error::MissingChunk { format: "PHYS", token: Token::PHYS });
+        }
+
+        for (kind, index, friction, restitution, density) in shapes {
+            let table = match kind {
+                0 => Some(&boxes),
+                1 => Some(&capsules),
+                2 => Some(&spheres),
+                _ => None,
+            };
+
+            let geometry = match (kind, table) {
+                (3, _) => ShapeGeometry::Polytope(index),
+                (_, Some(table)) => table.get(index as usize).cloned()
+                    .ok_or_else(|| io_error!(InvalidData, "shape refers to missing geometry {} of type {}", index, kind))?,
+                (_, None) => ShapeGeometry::Unknown(kind, index),
+            };
+
+            physics.shapes.push(Shape { geometry, friction, restitution, density });
+        }
+
+        Ok(physics)
+    }
+
+    pub fn body_shapes(&self, body: &RigidBody) -> &[Shape] {
+        let end = body.shapes.end.min(self.shapes.len());
+        &self.shapes[body.shapes.start.min(end)..end]
+    }
+}
+
+impl BodyKind {
+    pub fn from_raw(value: u16) -> Self {
+        match value {
+            0 => BodyKind::Static,
+            1 => BodyKind::Dynamic,
+            2 => BodyKind::Kinematic,
+            other => BodyKind::Other(other),
+        }
+    }
+}
+
+impl JointKind {
+    pub fn from_raw(value: u16) -> Self {
+        match value {
+            0 => JointKind::Spherical,
+            1 => JointKind::Shoulder,
+            2 => JointKind::Weld,
+            3 => JointKind::Revolute,
+            4 => JointKind::Prismatic,
+            5 => JointKind::Distance,
+            other => JointKind::Other(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{m2_chunk, u32s};
+    use asset::ParseMode;
+
+    #[test]
+    fn test_parse_phys() {
+        let mut file = m2_chunk(b"PHYS", &[1, 0]);
+        // dynamic body on bone 3 with one shape
+        file.extend(m2_chunk(b"BODY", &u32s(&[1 | 3 << 16, 0, 0, 0, 0, 1])));
+        // sphere 0 with friction 0.5
+        file.extend(m2_chunk(b"SHAP", &u32s(&[2, 0, 0.5f32.to_bits(), 0, 0])));
+        file.extend(m2_chunk(b"SPHS", &u32s(&[0, 0, 1.0f32.to_bits(), 2.0f32.to_bits()])));
+        file.extend(m2_chunk(b"JOIN", &u32s(&[0, 1, 0, 3 | 7 << 16])));
+
+        let physics = Physics::parse(&file).unwrap();
+        assert_eq!(physics.version, 1);
+        assert_eq!(physics.bodies[0].kind, BodyKind::Dynamic);
+        assert_eq!(physics.bodies[0].bone, 3);
+
+        let shapes = physics.body_shapes(&physics.bodies[0]);
+        assert_eq!(shapes.len(), 1);
+        assert_eq!(shapes[0].friction, 0.5);
+        match shapes[0].geometry {
+            ShapeGeometry::Sphere { center, radius } => assert_eq!((center, radius), (Point3::new(0.0, 0.0, 1.0), 2.0)),
+            ref other => panic!("unexpected geometry {:?}", other),
+        }
+
+        assert_eq!((physics.joints[0].kind, physics.joints[0].index), (JointKind::Revolute, 7));
+        assert!(Physics::parse(&m2_chunk(b"BODY", &[])).is_err());
+    }
+
+    #[test]
+    fn test_parse_phys_bad_counts() {
+        let header = m2_chunk(b"PHYS", &[1, 0]);
+
+        // a shape referring to a sphere that isn't there
+        let mut file = header.clone();
+        file.extend(m2_chunk(b"SHAP", &u32s(&[2 | 1 << 16, 0, 0, 0, 0])));
+        file.extend(m2_chunk(b"SPHS", &u32s(&[0, 0, 0, 1.0f32.to_bits()])));
+        assert!(Physics::parse(&file).is_err());
+
+        // bodies claiming more shapes than the file has, or a negative count
+        let mut file = header.clone();
+        file.extend(m2_chunk(b"BODY", &u32s(&[1, 0, 0, 0, 0, 5, 1, 0, 0, 0, 0, -1i32 as u32])));
+        let physics = Physics::parse(&file).unwrap();
+        assert_eq!(physics.bodies.len(), 2);
+        assert!(physics.body_shapes(&physics.bodies[0]).is_empty());
+        assert!(physics.body_shapes(&physics.bodies[1]).is_empty());
+
+        // a chunk cut short is dropped, unless parsing strictly
+        let mut file = header;
+        file.extend(m2_chunk(b"JOIN", &u32s(&[0, 1, 0, 3])));
+        let file = &file[..file.len() - 2];
+        assert!(Physics::parse(file).unwrap().joints.is_empty());
+        assert!(ParseMode::Strict.apply(|| Physics::parse(file)).is_err());
+    }
+}
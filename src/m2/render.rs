@@ -0,0 +1,175 @@
+use std::ops::Range;
+use m2::{Model, TextureKind, BlendMode, MaterialFlags, resolve_lookup};
+use m2::skin::SkinProfile;
+
+// where a texture layer takes its coordinates from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TexCoordSource {
+    Uv(u16),
+    // sphere environment mapping
+    Environment,
+}
+
+// one texture layer of a draw call
+#[derive(Clone, Debug)]
+pub struct DrawTexture {
+    pub path: Option<String>,
+    pub coords: TexCoordSource,
+    // index into the texture weight (transparency) table
+    pub weight: Option<usize>,
+    // index into `Model::texture_transforms`
+    pub transform: Option<usize>,
+}
+
+// a skin batch with every lookup table resolved
+#[derive(Clone, Debug)]
+pub struct DrawCall {
+    // range of the skin profile (or `M2Mesh`) indices
+    pub mesh_range: Range<u32>,
+    pub submesh_id: u16,
+    pub textures: Vec<DrawTexture>,
+    pub blend_mode: BlendMode,
+    pub flags: MaterialFlags,
+    // index into the color table
+    pub color_index: Option<u16>,
+    pub priority_plane: i8,
+    pub material_layer: u16,
+}
+
+impl DrawCall {
+    pub fn texture_paths(&self) -> Vec<Option<&str>> {
+        self.textures.iter().map(|texture| texture.path.as_deref()).collect()
+    }
+}
+
+// draw calls of a skin profile in the order they should be issued: opaque ones
+// first, then by priority plane and material layer. `replace` supplies replaceable textures
+pub fn draw_calls<F: Fn(TextureKind) -> Option<String>>(model: &Model, skin: &SkinProfile, replace: F) -> Vec<DrawCall> {
+    let lookups = &model.lookups;
+
+    let mut calls: Vec<DrawCall> = skin.batches.iter()
+        .filter_map(|batch| {
+            let submesh = match skin.submeshes.get(batch.submesh_index as usize) {
+                Some(submesh) => submesh,
+                None => {
+                    report_warn!("Batch refers to missing submesh {}", batch.submesh_index);
+                    return None;
+                }
+            };
+
+            let start = (submesh.level as u32) << 16 | submesh.index_start as u32;
+
+            let textures = (0..batch.texture_count as usize)
+                .map(|layer| {
+                    let path = resolve_lookup(&lookups.texture, batch.texture_combo_index as usize + layer, model.textures.len())
+                        .and_then(|index| model.textures[index].resolve(&replace));
+
+                    let coords = match lookups.texture_unit.get(batch.texture_coord_combo_index as usize + layer) {
+                        Some(&-1) => TexCoordSource::Environment,
+                        Some(&unit) => TexCoordSource::Uv(unit as u16),
+                        None => TexCoordSource::Uv(0),
+                    };
+
+                    // weights themselves aren't parsed yet, so the index isn't range checked
+                    let weight = resolve_lookup(&lookups.transparency, batch.texture_weight_combo_index as usize + layer, usize::MAX);
+                    let transform = resolve_lookup(&lookups.texture_transform, batch.texture_transform_combo_index as usize + layer, model.texture_transforms.len());
+
+                    DrawTexture { path, coords, weight, transform }
+                })
+                .collect();
+
+            let material = model.materials.get(batch.material_index as usize);
+
+            Some(DrawCall {
+                mesh_range: start..start + submesh.index_count as u32,
+                submesh_id: submesh.id,
+                textures,
+                blend_mode: material.map(|m| m.blend_mode).unwrap_or(BlendMode::Opaque),
+                flags: material.map(|m| m.flags).unwrap_or_else(MaterialFlags::empty),
+                color_index: if batch.color_index == 0xFFFF { None } else { Some(batch.color_index) },
+                priority_plane: batch.priority_plane,
+                material_layer: batch.material_layer,
+            })
+        })
+        .collect();
+
+    // stable, so batches keep their file order otherwise
+    calls.sort_by_key(|call| (call.blend_mode.is_transparent(), call.priority_plane, call.material_layer));
+    calls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point3;
+    use m2::{Material, Texture, TextureFlags};
+    use m2::skin::{Submesh, Batch};
+
+    fn batch(material_index: u16, texture_combo_index: u16) -> Batch {
+        Batch {
+            flags: 0, priority_plane: 0, shader_id: 0, submesh_index: 0, geoset_index: 0,
+            color_index: 0xFFFF, material_index, material_layer: 0, texture_count: 1,
+            texture_combo_index, texture_coord_combo_index: 0, texture_weight_combo_index: 0,
+            texture_transform_combo_index: 0,
+        }
+    }
+
+    #[test]
+    fn test_draw_calls() {
+        let mut data = vec![0u8; 0x140];
+        data[0..4].copy_from_slice(b"MD20");
+        data[4..8].copy_from_slice(&264u32.to_le_bytes());
+        let mut model = Model::parse(&data).unwrap();
+
+        model.materials = vec![
+            Material { flags: MaterialFlags::empty(), blend_mode: BlendMode::Add },
+            Material { flags: MaterialFlags::TWO_SIDED, blend_mode: BlendMode::Opaque },
+        ];
+        model.textures = vec![
+            Texture { kind: TextureKind::Hardcoded, flags: TextureFlags::empty(), filename: "a.blp".to_string() },
+            Texture { kind: TextureKind::Skin, flags: TextureFlags::empty(), filename: String::new() },
+        ];
+        model.lookups.texture = vec![0, 1];
+        model.lookups.texture_unit = vec![-1];
+        model.lookups.transparency = vec![0];
+
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let skin = SkinProfile {
+            vertex_lookup: Vec::new(),
+            index_lookup: Vec::new(),
+            bone_lookup: Vec::new(),
+            submeshes: vec![Submesh {
+                id: 0, level: 0, vertex_start: 0, vertex_count: 0, index_start: 6, index_count: 3,
+                bone_count: 0, bone_combo_index: 0, bone_influences: 0, center_bone_index: 0,
+                center_position: origin, sort_center_position: origin, sort_radius: 0.0,
+            }],
+            batches: vec![batch(0, 0), batch(1, 1)],
+            bone_count_max: 0,
+        };
+
+        let calls = draw_calls(&model, &skin, |kind| if kind == TextureKind::Skin { Some("skin.blp".to_string()) } else { None });
+        assert_eq!(calls.len(), 2);
+
+        // the opaque batch is drawn first
+        assert_eq!(calls[0].blend_mode, BlendMode::Opaque);
+        assert_eq!(calls[0].flags, MaterialFlags::TWO_SIDED);
+        assert_eq!(calls[0].texture_paths(), vec![Some("skin.blp")]);
+        assert_eq!(calls[1].texture_paths(), vec![Some("a.blp")]);
+        assert_eq!(calls[1].mesh_range, 6..9);
+        assert_eq!(calls[1].textures[0].coords, TexCoordSource::Environment);
+        assert_eq!(calls[1].textures[0].weight, Some(0));
+        assert_eq!(calls[1].color_index, None);
+
+        // combo indices near u16::MAX point past the end of the lookups
+        let mut last = batch(0, u16::MAX);
+        last.texture_count = 2;
+        last.texture_coord_combo_index = u16::MAX;
+        last.texture_weight_combo_index = u16::MAX;
+        last.texture_transform_combo_index = u16::MAX;
+        let skin = SkinProfile { batches: vec![last], ..skin };
+        let calls = draw_calls(&model, &skin, |_| None);
+        assert_eq!(calls[0].texture_paths(), vec![None, None]);
+        assert_eq!(calls[0].textures[1].coords, TexCoordSource::Uv(0));
+        assert_eq!(calls[0].textures[1].weight, None);
+    }
+}
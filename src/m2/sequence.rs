@@ -0,0 +1,292 @@
+use std::io::{self, Cursor};
+use byteorder::LE;
+use nalgebra::Point3;
+use culling::LocalBounds;
+use m2::Model;
+
+pub const SEQUENCE_SIZE: usize = 64;
+
+// frequencies of all variations of an animation add up to this
+const FREQUENCY_TOTAL: f32 = 32767.0;
+
+bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct SequenceFlags: u32 {
+        const BLENDED_ANIMATION = 0x8;
+        // keyframes are stored in the model rather than an external .anim file
+        const INLINE = 0x20;
+        // the animation data is found by following `alias_next`
+        const IS_ALIAS = 0x40;
+    }
+}
+
+// AnimationData.dbc id
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AnimationId(pub u16);
+
+impl AnimationId {
+    pub const STAND: AnimationId = AnimationId(0);
+    pub const DEATH: AnimationId = AnimationId(1);
+    pub const SPELL: AnimationId = AnimationId(2);
+    pub const STOP: AnimationId = AnimationId(3);
+    pub const WALK: AnimationId = AnimationId(4);
+    pub const RUN: AnimationId = AnimationId(5);
+    pub const DEAD: AnimationId = AnimationId(6);
+    pub const RISE: AnimationId = AnimationId(7);
+    pub const STAND_WOUND: AnimationId = AnimationId(8);
+    pub const COMBAT_WOUND: AnimationId = AnimationId(9);
+    pub const COMBAT_CRITICAL: AnimationId = AnimationId(10);
+    pub const SHUFFLE_LEFT: AnimationId = AnimationId(11);
+    pub const SHUFFLE_RIGHT: AnimationId = AnimationId(12);
+    pub const WALK_BACKWARDS: AnimationId = AnimationId(13);
+    pub const STUN: AnimationId = AnimationId(14);
+    pub const HANDS_CLOSED: AnimationId = AnimationId(15);
+    pub const ATTACK_UNARMED: AnimationId = AnimationId(16);
+    pub const ATTACK_1H: AnimationId = AnimationId(17);
+    pub const ATTACK_2H: AnimationId = AnimationId(18);
+    pub const ATTACK_2HL: AnimationId = AnimationId(19);
+    pub const PARRY_UNARMED: AnimationId = AnimationId(20);
+    pub const FLY: AnimationId = AnimationId(135);
+}
+
+// all variations of an animation
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SequenceChain {
+    pub id: AnimationId,
+    // never empty; the first one is the primary variation
+    pub variations: Vec<SequenceVariation>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SequenceVariation {
+    // index into `Model::sequences` of the variation itself
+    pub index: usize,
+    // index of the sequence holding the keyframes, after following aliases
+    pub data_index: usize,
+    // chance of being picked, 0..1
+    pub probability: f32,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Sequence {
+    // AnimationData.dbc id
+    pub id: u16,
+    pub variation_index: u16,
+    pub duration: u32,
+    pub move_speed: f32,
+    pub flags: SequenceFlags,
+    pub frequency: i16,
+    pub replay: (u32, u32),
+    pub blend_time: u32,
+    pub bounds: LocalBounds,
+    pub variation_next: i16,
+    pub alias_next: u16,
+}
+
+impl Sequence {
+    pub fn is_inline(&self) -> bool {
+        self.flags.contains(SequenceFlags::INLINE)
+    }
+}
+
+impl SequenceChain {
+    pub fn primary(&self) -> &SequenceVariation {
+        &self.variations[0]
+    }
+
+    // variation for a uniformly distributed `roll` in 0..1
+    pub fn pick(&self, roll: f32) -> &SequenceVariation {
+        let total: f32 = self.variations.iter().map(|variation| variation.probability).sum();
+        if total <= 0.0 {
+            return self.primary();
+        }
+
+        let mut remaining = roll * total;
+        for variation in &self.variations {
+            if remaining < variation.probability {
+                return variation;
+            }
+            remaining -= variation.probability;
+        }
+
+        self.variations.last().unwrap()
+    }
+}
+
+impl Model {
+    // looks the animation up in the sequence lookup table (a hash table with
+    // quadratic probing), falling back to a scan of the sequences
+    pub fn find_sequence(&self, id: AnimationId) -> Option<SequenceChain> {
+        let primary = self.lookup_sequence(id)
+            .or_else(|| self.sequences.iter().position(|sequence| sequence.id == id.0 && sequence.variation_index == 0))?;
+
+        let mut variations = Vec::new();
+        let mut index = primary;
+
+        // variation_next links form a list; guard against malformed cycles
+        while variations.len() < self.sequences.len() {
+            let sequence = &self.sequences[index];
+            variations.push(SequenceVariation {
+                index,
+                data_index: self.resolve_alias(index),
+                probability: sequence.frequency.max(0) as f32 / FREQUENCY_TOTAL,
+            });
+
+            match sequence.variation_next {
+                next if next >= 0 && (next as usize) < self.sequences.len() => index = next as usize,
+                _ => break,
+            }
+        }
+
+        Some(SequenceChain { id, variations })
+    }
+
+    // bounds covering every variation of an animation, the primary one included
+    pub fn sequence_bounds(&self, id: AnimationId) -> Option<LocalBounds> {
+        let chain = self.find_sequence(id)?;
+        let mut bounds = chain.variations.iter().map(|variation| &self.sequences[variation.data_index].bounds);
+        let first = bounds.next()?.clone();
+
+        Some(bounds.fold(first, |union, bounds| union.union(bounds)))
+    }
+
+    fn lookup_sequence(&self, id: AnimationId) -> Option<usize> {
+        let table = &self.lookups.sequence;
+        if table.is_empty() { return None }
+
+        for step in 0..table.len() {
+            let slot = (id.0 as usize + step * step) % table.len();
+
+            match table[slot] {
+                -1 => return None,
+                index => match self.sequences.get(index as usize) {
+                    Some(sequence) if sequence.id == id.0 => return Some(index as usize),
+                    _ => continue,
+                },
+            }
+        }
+
+        None
+    }
+
+    fn resolve_alias(&self, mut index: usize) -> usize {
+        for _ in 0..self.sequences.len() {
+            let sequence = &self.sequences[index];
+            if !sequence.flags.contains(SequenceFlags::IS_ALIAS) || sequence.alias_next as usize >= self.sequences.len() {
+                break;
+            }
+            index = sequence.alias_next as usize;
+        }
+
+        index
+    }
+}
+
+// `data` holds consecutive 64-byte sequence records
+pub fn read_sequences(data: &[u8]) -> io::Result<Vec<Sequence>> {
+    let mut cursor = Cursor::new(data);
+    let mut sequences = Vec::with_capacity(data.len() / SEQUENCE_SIZE);
+
+    for _ in 0..(data.len() / SEQUENCE_SIZE) {
+        let_read! { LE | cursor =>
+            id: u16;
+            variation_index: u16;
+            duration: u32;
+            move_speed: f32;
+            flags: u32;
+            frequency: i16;
+            _padding: u16;
+            replay_min: u32;
+            replay_max: u32;
+            blend_time: u32;
+            bounds_min: (f32, f32, f32);
+            bounds_max: (f32, f32, f32);
+            bounds_radius: f32;
+            variation_next: i16;
+            alias_next: u16;
+        }
+
+        sequences.push(Sequence {
+            id,
+            variation_index,
+            duration,
+            move_speed,
+            flags: SequenceFlags::from_bits_truncate(flags),
+            frequency,
+            replay: (replay_min, replay_max),
+            blend_time,
+            bounds: LocalBounds {
+                min: Point3::new(bounds_min.0, bounds_min.1, bounds_min.2),
+                max: Point3::new(bounds_max.0, bounds_max.1, bounds_max.2),
+                radius: bounds_radius,
+            },
+            variation_next,
+            alias_next,
+        });
+    }
+
+    Ok(sequences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(id: u16, variation_index: u16, frequency: i16, variation_next: i16, alias_next: Option<u16>) -> Sequence {
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        Sequence {
+            id,
+            variation_index,
+            duration: 1000,
+            move_speed: 0.0,
+            flags: if alias_next.is_some() { SequenceFlags::IS_ALIAS } else { SequenceFlags::INLINE },
+            frequency,
+            replay: (0, 0),
+            blend_time: 150,
+            bounds: LocalBounds::from_box(origin, origin),
+            variation_next,
+            alias_next: alias_next.unwrap_or(0),
+        }
+    }
+
+    #[test]
+    fn test_find_sequence() {
+        let mut data = vec![0u8; 0x140];
+        data[0..4].copy_from_slice(b"MD20");
+        data[4..8].copy_from_slice(&264u32.to_le_bytes());
+        let mut model = Model::parse(&data).unwrap();
+
+        // stand with a second variation aliased to a walk, and a walk
+        model.sequences = vec![
+            sequence(0, 0, 24000, 2, None),
+            sequence(4, 0, 32767, -1, None),
+            sequence(0, 1, 8767, -1, Some(1)),
+        ];
+        // 4 collides with 0 in a table of 4 slots and moves to slot 1
+        model.lookups.sequence = vec![0, 1, -1, -1];
+
+        let stand = model.find_sequence(AnimationId::STAND).unwrap();
+        assert_eq!(stand.variations.iter().map(|v| (v.index, v.data_index)).collect::<Vec<_>>(), vec![(0, 0), (2, 1)]);
+        assert_eq!(stand.pick(0.1).index, 0);
+        assert_eq!(stand.pick(0.9).index, 2);
+
+        assert_eq!(model.find_sequence(AnimationId::WALK).unwrap().primary().index, 1);
+        assert!(model.find_sequence(AnimationId::RUN).is_none());
+
+        // the aliased variation contributes the walk bounds
+        model.sequences[1].bounds = LocalBounds::from_box(Point3::new(-1.0, 0.0, 0.0), Point3::new(0.0, 0.0, 2.0));
+        let bounds = model.sequence_bounds(AnimationId::STAND).unwrap();
+        assert_eq!((bounds.min, bounds.max), (Point3::new(-1.0, 0.0, 0.0), Point3::new(0.0, 0.0, 2.0)));
+        assert!(model.sequence_bounds(AnimationId::RUN).is_none());
+        assert_eq!(model.animated_bounds().max, Point3::new(0.0, 0.0, 2.0));
+        // the union is re-centered, rather than keeping the larger radius
+        assert_eq!(model.animated_bounds().radius, LocalBounds::from_box(bounds.min, bounds.max).radius);
+
+        model.lookups.sequence.clear();
+        assert_eq!(model.find_sequence(AnimationId::WALK).unwrap().primary().index, 1);
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Cursor, Seek, SeekFrom};
+use std::sync::Arc;
+use byteorder::{ReadBytesExt, LE};
+use nalgebra::Matrix4;
+use chunked::{Chunked, Token};
+use reader::{ResourceReader, ResourceHandle};
+use m2::{Model, read_array, read_array_header_at, read_string, array_data};
+use m2::bone::{Bone, BONE_SIZE};
+use m2::sequence::{self, Sequence, SEQUENCE_SIZE};
+use error::Result;
+
+// skeleton and animations moved out of the model (bfa+), referenced by SKID.
+// offsets inside every chunk are relative to the start of that chunk
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Skeleton {
+    pub name: String,
+    pub flags: u32,
+    pub bones: Vec<Bone>,
+    pub key_bone_lookup: Vec<i16>,
+    pub global_loops: Vec<u32>,
+    pub sequences: Vec<Sequence>,
+    pub sequence_lookup: Vec<i16>,
+    // file data id of a skeleton this one inherits from (SKPD)
+    pub parent: Option<u32>,
+}
+
+// alternative bone placement (e.g. for another body type), stored in `.bone` files
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BoneFile {
+    pub version: u32,
+    pub bone_ids: Vec<u16>,
+    // one matrix per entry of `bone_ids`
+    pub matrices: Vec<Matrix4<f32>>,
+}
+
+pub fn load(reader: Arc<dyn ResourceReader>, name: &str) -> Result<Skeleton> {
+    let data = read_file(reader.open(name)?)?;
+    Skeleton::parse(&data).map_err(|e| e.in_file(name))
+}
+
+// as referenced by SKID, or by `Skeleton::parent`
+pub fn load_by_id(reader: Arc<dyn ResourceReader>, file_data_id: u32) -> Result<Skeleton> {
+    let data = read_file(reader.open_by_id(file_data_id)?)?;
+    Skeleton::parse(&data).map_err(|e| e.in_file(format!("FileDataID {}", file_data_id)))
+}
+
+pub fn load_bone_file(reader: Arc<dyn ResourceReader>, name: &str) -> Result<BoneFile> {
+    let data = read_file(reader.open(name)?)?;
+    BoneFile::parse(&data).map_err(|e| e.in_file(name))
+}
+
+fn read_file(mut input: Box<dyn ResourceHandle>) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+impl Skeleton {
+    pub fn parse(data: &[u8]) -> Result<Skeleton> {
+        let mut chunks = BTreeMap::new();
+        for chunk in Chunked::new_legion_m2(Cursor::new(data)) {
+            let chunk = chunk?;
+            chunks.insert(chunk.token, chunk.data);
+        }
+
+        let mut skeleton = Skeleton::default();
+
+        if let Some(chunk) = chunks.get(&Token::SKL1) {
+            let mut input = Cursor::new(&chunk[..]);
+            skeleton.flags = input.read_u32::<LE>()?;
+            skeleton.name = read_string(chunk, read_array_header_at(&mut input, 4)?)?;
+        }
+
+        // sequences come first, as bone tracks depend on which of them are inline
+        if let Some(chunk) = chunks.get(&Token::SKS1) {
+            let mut input = Cursor::new(&chunk[..]);
+            skeleton.global_loops = read_array::<u32>(chunk, read_array_header_at(&mut input, 0x00)?)?;
+            skeleton.sequences = sequence::read_sequences(array_data(chunk, read_array_header_at(&mut input, 0x08)?, SEQUENCE_SIZE)?)?;
+            skeleton.sequence_lookup = lookup(chunk, &mut input, 0x10)?;
+        }
+
+        if let Some(chunk) = chunks.get(&Token::SKB1) {
+            let inline: Vec<bool> = skeleton.sequences.iter().map(Sequence::is_inline).collect();
+            let mut input = Cursor::new(&chunk[..]);
+
+            let bones = read_array_header_at(&mut input, 0x00)?;
+            array_data(chunk, bones, BONE_SIZE)?;
+            skeleton.bones = (0..bones.count as usize)
+                .map(|index| Bone::read(chunk, bones.offset as usize + index * BONE_SIZE, &inline))
+                .collect::<io::Result<Vec<_>>>()?;
+            skeleton.key_bone_lookup = lookup(chunk, &mut input, 0x08)?;
+        }
+
+        if let Some(chunk) = chunks.get(&Token::SKPD) {
+            // 8 unknown bytes, parent file data id, 4 unknown bytes
+            let mut input = Cursor::new(&chunk[..]);
+            input.seek(SeekFrom::Start(8))?;
+            skeleton.parent = Some(input.read_u32::<LE>()?);
+        }
+
+        Ok(skeleton)
+    }
+}
+
+impl BoneFile {
+    pub fn parse(data: &[u8]) -> Result<BoneFile> {
+        let mut input = Cursor::new(data);
+        let version = input.read_u32::<LE>()?;
+        let mut bone_file = BoneFile { version, ..BoneFile::default() };
+
+        for chunk in Chunked::new_legion_m2(input) {
+            let chunk = chunk?;
+            let mut cursor = chunk.cursor();
+
+            match chunk.token {
+                Token::BIDA => {
+                    for _ in 0..(chunk.data.len() / 2) {
+                        bone_file.bone_ids.push(cursor.read_u16::<LE>()?);
+                    }
+                }
+                Token::BOMT => {
+                    for _ in 0..(chunk.data.len() / 64) {
+                        let mut matrix = Matrix4::zeros();
+                        // column major
+                        for column in 0..4 {
+                            for row in 0..4 {
+                                matrix[(row, column)] = cursor.read_f32::<LE>()?;
+                            }
+                        }
+                        bone_file.matrices.push(matrix);
+                    }
+                }
+                other => trace!("skipping bone chunk {}", other),
+            }
+        }
+
+        Ok(bone_file)
+    }
+
+    pub fn matrix(&self, bone_id: u16) -> Option<&Matrix4<f32>> {
+        self.bone_ids.iter().position(|&id| id == bone_id).and_then(|index| self.matrices.get(index))
+    }
+}
+
+impl Model {
+    // takes bones and sequences from an external skeleton; a skeleton with a parent
+    // should be merged after its parent so that its own data wins
+    pub fn merge_skeleton(&mut self, skeleton: &Skeleton) {
+        if !skeleton.bones.is_empty() {
+            self.bones = skeleton.bones.clone();
+            self.lookups.key_bone = skeleton.key_bone_lookup.clone();
+        }
+
+        if !skeleton.sequences.is_empty() {
+            self.sequences = skeleton.sequences.clone();
+            self.lookups.sequence = skeleton.sequence_lookup.clone();
+            self.global_loops = skeleton.global_loops.clone();
+        }
+    }
+}
+
+fn lookup(chunk: &[u8], input: &mut Cursor<&[u8]>, offset: u64) -> io::Result<Vec<i16>> {
+    let array = read_array_header_at(input, offset)?;
+    Ok(read_array::<u16>(chunk, array)?.into_iter().map(|value| value as i16).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::m2_chunk;
+    use asset::ParseMode;
+
+    #[test]
+    fn test_parse_skeleton() {
+        // one inline sequence of 1000ms at 0x20
+        let mut sks1 = vec![0u8; 0x60];
+        sks1[0x08..0x0C].copy_from_slice(&1u32.to_le_bytes());
+        sks1[0x0C..0x10].copy_from_slice(&0x20u32.to_le_bytes());
+        sks1[0x24..0x28].copy_from_slice(&1000u32.to_le_bytes());
+        sks1[0x2C..0x30].copy_from_slice(&0x20u32.to_le_bytes());
+
+        // one root bone at 0x10
+        let mut skb1 = vec![0u8; 0x10 + BONE_SIZE];
+        skb1[0x00..0x04].copy_from_slice(&1u32.to_le_bytes());
+        skb1[0x04..0x08].copy_from_slice(&0x10u32.to_le_bytes());
+        skb1[0x18..0x1A].copy_from_slice(&(-1i16).to_le_bytes());
+
+        let mut file = m2_chunk(b"SKL1", &[0u8; 16]);
+        file.extend(m2_chunk(b"SKB1", &skb1));
+        file.extend(m2_chunk(b"SKS1", &sks1));
+
+        let skeleton = Skeleton::parse(&file).unwrap();
+        assert_eq!(skeleton.sequences.len(), 1);
+        assert_eq!(skeleton.sequences[0].duration, 1000);
+        assert!(skeleton.sequences[0].is_inline());
+        assert_eq!(skeleton.bones.len(), 1);
+        assert_eq!(skeleton.bones[0].parent, None);
+        assert_eq!(skeleton.parent, None);
+
+        let mut bone_file = 1u32.to_le_bytes().to_vec();
+        bone_file.extend(m2_chunk(b"BIDA", &[5, 0]));
+        bone_file.extend(m2_chunk(b"BOMT", &[0u8; 64]));
+        let bone_file = BoneFile::parse(&bone_file).unwrap();
+        assert_eq!(bone_file.matrix(5), Some(&Matrix4::zeros()));
+        assert_eq!(bone_file.matrix(6), None);
+    }
+
+    #[test]
+    fn test_parse_skeleton_bad_counts() {
+        // more bones than the chunk holds
+        let mut skb1 = vec![0u8; 0x10 + BONE_SIZE];
+        skb1[0x00..0x04].copy_from_slice(&2u32.to_le_bytes());
+        skb1[0x04..0x08].copy_from_slice(&0x10u32.to_le_bytes());
+        assert!(Skeleton::parse(&m2_chunk(b"SKB1", &skb1)).is_err());
+
+        // a sequence array starting past the end of the chunk
+        let mut sks1 = vec![0u8; 0x20];
+        sks1[0x08..0x0C].copy_from_slice(&1u32.to_le_bytes());
+        sks1[0x0C..0x10].copy_from_slice(&0x100u32.to_le_bytes());
+        assert!(Skeleton::parse(&m2_chunk(b"SKS1", &sks1)).is_err());
+
+        // a parent chunk too short for the file data id, and a truncated file
+        // under strict parsing
+        assert!(Skeleton::parse(&m2_chunk(b"SKPD", &[0u8; 10])).is_err());
+        let file = m2_chunk(b"SKPD", &[0u8; 12]);
+        assert_eq!(Skeleton::parse(&file[..file.len() - 1]).unwrap().parent, None);
+        assert!(ParseMode::Strict.apply(|| Skeleton::parse(&file[..file.len() - 1])).is_err());
+
+        // bone files need their version, partial matrices are ignored
+        assert!(BoneFile::parse(&[1, 0]).is_err());
+        let mut bone_file = 1u32.to_le_bytes().to_vec();
+        bone_file.extend(m2_chunk(b"BOMT", &[0u8; 60]));
+        assert!(BoneFile::parse(&bone_file).unwrap().matrices.is_empty());
+    }
+}
@@ -0,0 +1,350 @@
+use std::io::{self, Read, Cursor};
+use std::sync::Arc;
+use nalgebra::Point3;
+use reader::{ResourceReader, ResourceHandle, split_resource_name, open_preferring_id};
+use records::{M2SkinSectionRecord, M2BatchRecord};
+use culling::LocalBounds;
+use m2::{Model, read_array, read_array_header_at};
+use m2::mesh::M2Mesh;
+use error::{Error, Result};
+
+// distance, in model radii, from which each further lod is used
+const LOD_DISTANCES: [f32; 3] = [30.0, 60.0, 120.0];
+
+// a level of detail of the model geometry, as stored in a `.skin` file
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SkinProfile {
+    // model vertex index for every skin vertex
+    pub vertex_lookup: Vec<u16>,
+    // triangle list indexing into `vertex_lookup`
+    pub index_lookup: Vec<u16>,
+    pub bone_lookup: Vec<[u8; 4]>,
+    pub submeshes: Vec<Submesh>,
+    pub batches: Vec<Batch>,
+    pub bone_count_max: u32,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Submesh {
+    // geoset id; the part before the last two digits selects a geoset group
+    pub id: u16,
+    pub level: u16,
+    pub vertex_start: u16,
+    pub vertex_count: u16,
+    pub index_start: u16,
+    pub index_count: u16,
+    pub bone_count: u16,
+    pub bone_combo_index: u16,
+    pub bone_influences: u16,
+    pub center_bone_index: u16,
+    pub center_position: Point3<f32>,
+    pub sort_center_position: Point3<f32>,
+    pub sort_radius: f32,
+}
+
+// a texture unit; draws a submesh with a material and a set of textures
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Batch {
+    pub flags: u8,
+    pub priority_plane: i8,
+    pub shader_id: u16,
+    pub submesh_index: u16,
+    pub geoset_index: u16,
+    pub color_index: u16,
+    pub material_index: u16,
+    pub material_layer: u16,
+    pub texture_count: u16,
+    pub texture_combo_index: u16,
+    pub texture_coord_combo_index: u16,
+    pub texture_weight_combo_index: u16,
+    pub texture_transform_combo_index: u16,
+}
+
+// summary of a skin profile, for choosing a level of detail
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProfileInfo {
+    pub index: u32,
+    pub name: String,
+    pub triangle_count: usize,
+    // bounds of the model vertices used by the profile
+    pub bounds: Option<LocalBounds>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LodSelection {
+    // 0 is the most detailed profile; clamped to the available ones
+    Quality(u32),
+    // distance from the viewer to the model origin, in world units
+    Distance(f32),
+}
+
+// `Creature\Foo\Foo.m2` -> `Creature\Foo\Foo01.skin` for profile 1
+pub fn skin_name(model_name: &str, profile: u32) -> String {
+    let (dir, file, _) = split_resource_name(model_name);
+    format!("{}{}{:02}.skin", dir, file, profile)
+}
+
+pub fn load(reader: Arc<dyn ResourceReader>, name: &str) -> Result<SkinProfile> {
+    read_profile(reader.open(name)?, name)
+}
+
+// by the model's SFID entry (legion+) when there is one, by `skin_name` otherwise
+pub fn load_for_model(reader: Arc<dyn ResourceReader>, model_name: &str, model: &Model, profile: u32) -> Result<SkinProfile> {
+    let name = skin_name(model_name, profile);
+    read_profile(open_preferring_id(&*reader, skin_file_data_id(model, profile), &name)?, &name)
+}
+
+fn skin_file_data_id(model: &Model, profile: u32) -> Option<u32> {
+    model.file_data_ids.as_ref().and_then(|ids| ids.skins.get(profile as usize).cloned())
+}
+
+fn read_profile(mut input: Box<dyn ResourceHandle>, name: &str) -> Result<SkinProfile> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+
+    SkinProfile::parse(&data).map_err(|e| e.in_file(name))
+}
+
+// every skin profile the model declares, in order
+pub fn load_all(reader: Arc<dyn ResourceReader>, model_name: &str, skin_profile_count: u32) -> Result<Vec<SkinProfile>> {
+    (0..skin_profile_count)
+        .map(|profile| load(reader.clone(), &skin_name(model_name, profile)))
+        .collect()
+}
+
+// profiles the model declares which are present in `reader`
+pub fn enumerate(reader: Arc<dyn ResourceReader>, model_name: &str, model: &Model) -> Result<Vec<ProfileInfo>> {
+    let mut profiles = Vec::new();
+
+    for index in 0..model.skin_profile_count {
+        let name = skin_name(model_name, index);
+        let by_id = match skin_file_data_id(model, index) {
+            Some(id) => reader.exists_by_id(id)?,
+            None => false,
+        };
+        if !by_id && !reader.exists(&name)? {
+            continue;
+        }
+
+        let skin = load_for_model(reader.clone(), model_name, model, index)?;
+        profiles.push(ProfileInfo {
+            index,
+            name,
+            triangle_count: skin.index_lookup.len() / 3,
+            bounds: skin.bounds(model),
+        });
+    }
+
+    Ok(profiles)
+}
+
+// picks one of `profiles` (as returned by `enumerate`) for a model of `radius`
+pub fn select_profile(profiles: &[ProfileInfo], radius: f32, selection: LodSelection) -> Option<&ProfileInfo> {
+    let level = match selection {
+        LodSelection::Quality(level) => level as usize,
+        LodSelection::Distance(distance) => {
+            let distance = distance / radius.max(1.0);
+            LOD_DISTANCES.iter().take_while(|&&threshold| distance >= threshold).count()
+        }
+    };
+
+    profiles.get(level.min(profiles.len().saturating_sub(1)))
+}
+
+impl Model {
+    // loads the profile picked by `selection` and builds its mesh
+    pub fn build_lod_mesh(&self, reader: Arc<dyn ResourceReader>, model_name: &str, selection: LodSelection) -> Result<M2Mesh> {
+        let profiles = enumerate(reader.clone(), model_name, self)?;
+        let profile = select_profile(&profiles, self.bounds.radius, selection)
+            .ok_or_else(|| Error::Io(io_error!(NotFound, "no skin profiles found for {}", model_name)))?;
+
+        Ok(self.build_mesh(&load_for_model(reader, model_name, self, profile.index)?))
+    }
+}
+
+impl SkinProfile {
+    pub fn parse(data: &[u8]) -> Result<SkinProfile> {
+        let mut input = Cursor::new(data);
+
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != b"SKIN" {
+            return Err(Error::BadMagic { format: "SKIN", found: magic });
+        }
+
+        let vertex_lookup = read_array::<u16>(data, read_array_header_at(&mut input, 0x04)?)?;
+        let index_lookup = read_array::<u16>(data, read_array_header_at(&mut input, 0x0C)?)?;
+        let bone_lookup = read_array::<[u8; 4]>(data, read_array_header_at(&mut input, 0x14)?)?;
+
+        let submeshes = read_array::<M2SkinSectionRecord>(data, read_array_header_at(&mut input, 0x1C)?)?
+            .into_iter()
+            .map(|record| Submesh {
+                id: record.skin_section_id,
+                level: record.level,
+                vertex_start: record.vertex_start,
+                vertex_count: record.vertex_count,
+                index_start: record.index_start,
+                index_count: record.index_count,
+                bone_count: record.bone_count,
+                bone_combo_index: record.bone_combo_index,
+                bone_influences: record.bone_influences,
+                center_bone_index: record.center_bone_index,
+                center_position: point(record.center_position),
+                sort_center_position: point(record.sort_center_position),
+                sort_radius: record.sort_radius,
+            })
+            .collect();
+
+        let batches = read_array::<M2BatchRecord>(data, read_array_header_at(&mut input, 0x24)?)?
+            .into_iter()
+            .map(|record| Batch {
+                flags: record.flags,
+                priority_plane: record.priority_plane,
+                shader_id: record.shader_id,
+                submesh_index: record.skin_section_index,
+                geoset_index: record.geoset_index,
+                color_index: record.color_index,
+                material_index: record.material_index,
+                material_layer: record.material_layer,
+                texture_count: record.texture_count,
+                texture_combo_index: record.texture_combo_index,
+                texture_coord_combo_index: record.texture_coord_combo_index,
+                texture_weight_combo_index: record.texture_weight_combo_index,
+                texture_transform_combo_index: record.texture_transform_combo_index,
+            })
+            .collect();
+
+        let bone_count_max = if data.len() >= 0x30 {
+            u32::from_le_bytes([data[0x2C], data[0x2D], data[0x2E], data[0x2F]])
+        } else {
+            0
+        };
+
+        Ok(SkinProfile {
+            vertex_lookup,
+            index_lookup,
+            bone_lookup,
+            submeshes,
+            batches,
+            bone_count_max,
+        })
+    }
+
+    pub fn bounds(&self, model: &Model) -> Option<LocalBounds> {
+        let mut positions = self.vertex_lookup.iter()
+            .filter_map(|&index| model.vertices.get(index as usize))
+            .map(|vertex| vertex.position);
+
+        let first = positions.next()?;
+        let (min, max) = positions.fold((first, first), |(min, max), p| {
+            (
+                Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+            )
+        });
+
+        Some(LocalBounds::from_box(min, max))
+    }
+
+    // model vertex indices of the triangles of a submesh
+    pub fn submesh_triangles(&self, submesh: &Submesh) -> Vec<u16> {
+        let start = submesh.index_start as usize;
+        let end = (start + submesh.index_count as usize).min(self.index_lookup.len());
+
+        self.index_lookup[start.min(end)..end].iter()
+            .filter_map(|&index| self.vertex_lookup.get(index as usize).cloned())
+            .collect()
+    }
+}
+
+fn point(value: [f32; 3]) -> Point3<f32> {
+    Point3::new(value[0], value[1], value[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_skin() {
+        assert_eq!(skin_name("Creature\\Foo\\Foo.m2", 1), "Creature\\Foo\\Foo01.skin");
+
+        let mut data = vec![0u8; 0x80];
+        data[0..4].copy_from_slice(b"SKIN");
+        let mut put = |offset: usize, value: u32| data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+
+        // vertex lookup [5, 6, 7], indices [2, 1, 0]
+        put(0x04, 3);
+        put(0x08, 0x40);
+        put(0x0C, 3);
+        put(0x10, 0x48);
+        put(0x40, 5 | 6 << 16);
+        put(0x44, 7);
+        put(0x48, 2 | 1 << 16);
+        put(0x4C, 0);
+        put(0x2C, 21);
+
+        let skin = SkinProfile::parse(&data).unwrap();
+        assert_eq!(skin.vertex_lookup, vec![5, 6, 7]);
+        assert_eq!(skin.index_lookup, vec![2, 1, 0]);
+        assert_eq!(skin.bone_count_max, 21);
+        assert!(skin.submeshes.is_empty());
+
+        let mut submesh_data = data.clone();
+        submesh_data[0x1C..0x20].copy_from_slice(&1u32.to_le_bytes());
+        submesh_data[0x20..0x24].copy_from_slice(&0x50u32.to_le_bytes());
+        submesh_data[0x5A..0x5C].copy_from_slice(&3u16.to_le_bytes()); // index count
+        let skin = SkinProfile::parse(&submesh_data).unwrap();
+        assert_eq!(skin.submesh_triangles(&skin.submeshes[0]), vec![7, 6, 5]);
+
+        let profiles: Vec<_> = (0..3)
+            .map(|index| ProfileInfo { index, name: skin_name("Foo.m2", index), triangle_count: 0, bounds: None })
+            .collect();
+        assert_eq!(select_profile(&profiles, 2.0, LodSelection::Distance(10.0)).unwrap().index, 0);
+        assert_eq!(select_profile(&profiles, 2.0, LodSelection::Distance(130.0)).unwrap().index, 2);
+        assert_eq!(select_profile(&profiles, 2.0, LodSelection::Quality(7)).unwrap().index, 2);
+        assert!(select_profile(&[], 2.0, LodSelection::Quality(0)).is_none());
+    }
+
+    #[test]
+    fn test_parse_skin_bad_counts() {
+        let mut data = vec![0u8; 0x60];
+        data[0..4].copy_from_slice(b"SKIN");
+        assert!(SkinProfile::parse(&data).is_ok());
+        assert!(SkinProfile::parse(b"SKI").is_err());
+        match SkinProfile::parse(b"NIKS") {
+            Err(Error::BadMagic { format, found }) => assert_eq!((format, &found), ("SKIN", b"NIKS")),
+            other => panic!("unexpected result {:?}", other.map(|skin| skin.vertex_lookup)),
+        }
+        assert!(SkinProfile::parse(&data[..0x20]).is_err());
+
+        // a vertex lookup running past the end of the file
+        let mut overrun = data.clone();
+        overrun[0x04..0x08].copy_from_slice(&0x100u32.to_le_bytes());
+        overrun[0x08..0x0C].copy_from_slice(&0x40u32.to_le_bytes());
+        assert!(SkinProfile::parse(&overrun).is_err());
+
+        // submeshes referring past the index lookup get what's there
+        let mut submesh_data = vec![0u8; 0x100];
+        submesh_data[0..4].copy_from_slice(b"SKIN");
+        let mut put = |offset: usize, value: u32| submesh_data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        put(0x04, 1);
+        put(0x08, 0x40);
+        put(0x0C, 1);
+        put(0x10, 0x44);
+        put(0x1C, 2);
+        put(0x20, 0x50);
+        put(0x40, 4);
+        put(0x58, 9 << 16);
+        put(0x58 + 0x30, 5 | 9 << 16);
+
+        let skin = SkinProfile::parse(&submesh_data).unwrap();
+        assert_eq!(skin.submesh_triangles(&skin.submeshes[0]), vec![4]);
+        assert!(skin.submesh_triangles(&skin.submeshes[1]).is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+use std::io;
+use nalgebra::{Matrix4, Quaternion, UnitQuaternion, Vector3};
+use m2::track::{M2Track, TRACK_SIZE};
+use m2::animation::AnimationTime;
+
+pub const TEXTURE_TRANSFORM_SIZE: usize = TRACK_SIZE * 3;
+
+// animated texture coordinate transform (scrolling water, rotating portals)
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TextureTransform {
+    pub translation: M2Track<Vector3<f32>>,
+    pub rotation: M2Track<Quaternion<f32>>,
+    pub scale: M2Track<Vector3<f32>>,
+}
+
+impl TextureTransform {
+    // texture transform record at `offset` of the MD20 block; see `M2Track::read` for `inline`
+    pub fn read(data: &[u8], offset: usize, inline: &[bool]) -> io::Result<TextureTransform> {
+        Ok(TextureTransform {
+            translation: M2Track::read(data, offset, inline)?,
+            rotation: M2Track::read(data, offset + TRACK_SIZE, inline)?,
+            scale: M2Track::read(data, offset + TRACK_SIZE * 2, inline)?,
+        })
+    }
+
+    // matrix to apply to texture coordinates; rotation and scale are around the texture center
+    pub fn matrix(&self, at: AnimationTime, global_loops: &[u32]) -> Matrix4<f32> {
+        let translation = self.translation.sample(at, global_loops).unwrap_or_else(Vector3::zeros);
+        let rotation = self.rotation
+            .sample_by(at, global_loops, |&value| UnitQuaternion::from_quaternion(value))
+            .unwrap_or_else(UnitQuaternion::identity);
+        let scale = self.scale.sample(at, global_loops).unwrap_or_else(|| Vector3::new(1.0, 1.0, 1.0));
+
+        let center = Vector3::new(0.5, 0.5, 0.0);
+        Matrix4::new_translation(&(translation + center))
+            * rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&scale)
+            * Matrix4::new_translation(&-center)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point3;
+    use m2::track::Interpolation;
+
+    fn empty<T>() -> M2Track<T> {
+        M2Track { interpolation: Interpolation::None, global_sequence: None, timestamps: vec![], values: vec![] }
+    }
+
+    #[test]
+    fn test_scrolling_texture() {
+        let transform = TextureTransform {
+            translation: M2Track {
+                interpolation: Interpolation::Linear,
+                global_sequence: Some(0),
+                timestamps: vec![vec![0, 1000]],
+                values: vec![vec![Vector3::zeros(), Vector3::new(1.0, 0.0, 0.0)]],
+            },
+            rotation: empty(),
+            scale: empty(),
+        };
+
+        // global sequence of 1000ms, so 1250ms of global time wraps to a quarter
+        let uv = transform.matrix(AnimationTime::new(3, 0, 1250), &[1000]) * Point3::new(0.0, 0.0, 0.0).to_homogeneous();
+        assert_eq!(Point3::from_homogeneous(uv), Some(Point3::new(0.25, 0.0, 0.0)));
+    }
+}
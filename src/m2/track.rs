@@ -0,0 +1,302 @@
+use std::io::{self, Cursor};
+use byteorder::{ReadBytesExt, LE};
+use nalgebra::{Quaternion, Vector2, Vector3};
+use m2::{M2Array, array_data, read_array_header};
+
+// size of a wotlk M2Track header
+pub const TRACK_SIZE: usize = 20;
+// size of a track header without values
+pub const TRACK_BASE_SIZE: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Interpolation {
+    None,
+    Linear,
+    Bezier,
+    Hermite,
+}
+
+// animated value: per sequence, a list of keyframe timestamps (ms) and values.
+// sequences whose data lives in external .anim files have empty lists
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct M2Track<T> {
+    pub interpolation: Interpolation,
+    pub global_sequence: Option<u16>,
+    pub timestamps: Vec<Vec<u32>>,
+    pub values: Vec<Vec<T>>,
+}
+
+// timestamps only, as used by event tracks
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TrackBase {
+    pub interpolation: Interpolation,
+    pub global_sequence: Option<u16>,
+    pub timestamps: Vec<Vec<u32>>,
+}
+
+// fixed-size element of a track value array
+pub trait TrackValue: Sized {
+    const SIZE: usize;
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Self>;
+}
+
+// keyframe value with tangents, used by camera tracks
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SplineKey<T> {
+    pub value: T,
+    pub in_tangent: T,
+    pub out_tangent: T,
+}
+
+// rotation stored as four i16 components (x, y, z, w)
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CompQuat(pub [i16; 4]);
+
+impl Interpolation {
+    pub fn from_raw(value: u16) -> Self {
+        match value {
+            1 => Interpolation::Linear,
+            2 => Interpolation::Bezier,
+            3 => Interpolation::Hermite,
+            _ => Interpolation::None,
+        }
+    }
+}
+
+impl CompQuat {
+    pub fn to_quaternion(&self) -> Quaternion<f32> {
+        let component = |value: i16| {
+            let value = value as i32;
+            (if value < 0 { value + 32768 } else { value - 32767 }) as f32 / 32767.0
+        };
+
+        let q = &self.0;
+        Quaternion::new(component(q[3]), component(q[0]), component(q[1]), component(q[2]))
+    }
+}
+
+impl<T> M2Track<T> {
+    pub fn is_animated(&self) -> bool {
+        self.values.iter().any(|values| !values.is_empty())
+    }
+
+    // keyframes of a sequence, or of the global sequence for globally timed tracks
+    pub fn keyframes(&self, sequence: usize) -> (&[u32], &[T]) {
+        let sequence = if self.global_sequence.is_some() { 0 } else { sequence };
+
+        match (self.timestamps.get(sequence), self.values.get(sequence)) {
+            (Some(timestamps), Some(values)) => (timestamps, values),
+            _ => (&[], &[]),
+        }
+    }
+}
+
+impl<T: TrackValue> M2Track<T> {
+    // `inline` tells for every sequence whether its keyframes are stored in `data`
+    pub fn read(data: &[u8], offset: usize, inline: &[bool]) -> io::Result<Self> {
+        let base = TrackBase::read(data, offset, inline)?;
+
+        let header = data.get(offset + TRACK_BASE_SIZE..offset + TRACK_SIZE)
+            .ok_or_else(|| io_error!(UnexpectedEof, "track at {} exceeds the model size", offset))?;
+        let (count, values_offset) = read_array_header(&mut Cursor::new(header))?;
+        let values = read_sub_arrays(data, count, values_offset, base.global_sequence.is_some(), inline)?;
+
+        Ok(M2Track {
+            interpolation: base.interpolation,
+            global_sequence: base.global_sequence,
+            timestamps: base.timestamps,
+            values,
+        })
+    }
+}
+
+impl TrackBase {
+    pub fn read(data: &[u8], offset: usize, inline: &[bool]) -> io::Result<Self> {
+        let header = data.get(offset..offset + TRACK_BASE_SIZE)
+            .ok_or_else(|| io_error!(UnexpectedEof, "track at {} exceeds the model size", offset))?;
+        let mut cursor = Cursor::new(header);
+
+        let interpolation = Interpolation::from_raw(cursor.read_u16::<LE>()?);
+        let global_sequence = cursor.read_i16::<LE>()?;
+        let (count, timestamps_offset) = read_array_header(&mut cursor)?;
+
+        let global_sequence = if global_sequence >= 0 { Some(global_sequence as u16) } else { None };
+        let timestamps = read_sub_arrays(data, count, timestamps_offset, global_sequence.is_some(), inline)?;
+
+        Ok(TrackBase { interpolation, global_sequence, timestamps })
+    }
+}
+
+// one array per sequence; external sequences are left empty
+fn read_sub_arrays<T: TrackValue>(data: &[u8], count: u32, offset: u32, global: bool, inline: &[bool]) -> io::Result<Vec<Vec<T>>> {
+    let mut outer = Cursor::new(array_data(data, M2Array { count, offset }, 8)?);
+
+    (0..count as usize)
+        .map(|sequence| {
+            let (count, offset) = read_array_header(&mut outer)?;
+
+            // global sequence tracks have a single sub array not tied to any sequence
+            if global || inline.get(sequence).cloned().unwrap_or(true) {
+                read_values(data, count, offset)
+            } else {
+                Ok(Vec::new())
+            }
+        })
+        .collect()
+}
+
+fn read_values<T: TrackValue>(data: &[u8], count: u32, offset: u32) -> io::Result<Vec<T>> {
+    let mut cursor = Cursor::new(array_data(data, M2Array { count, offset }, T::SIZE)?);
+    (0..count).map(|_| T::read(&mut cursor)).collect()
+}
+
+impl TrackValue for u8 {
+    const SIZE: usize = 1;
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        cursor.read_u8()
+    }
+}
+
+impl TrackValue for u16 {
+    const SIZE: usize = 2;
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        cursor.read_u16::<LE>()
+    }
+}
+
+impl TrackValue for i16 {
+    const SIZE: usize = 2;
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        cursor.read_i16::<LE>()
+    }
+}
+
+impl TrackValue for u32 {
+    const SIZE: usize = 4;
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        cursor.read_u32::<LE>()
+    }
+}
+
+impl TrackValue for f32 {
+    const SIZE: usize = 4;
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        cursor.read_f32::<LE>()
+    }
+}
+
+impl TrackValue for Vector2<f32> {
+    const SIZE: usize = 8;
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        Ok(Vector2::new(cursor.read_f32::<LE>()?, cursor.read_f32::<LE>()?))
+    }
+}
+
+impl TrackValue for Vector3<f32> {
+    const SIZE: usize = 12;
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        Ok(Vector3::new(cursor.read_f32::<LE>()?, cursor.read_f32::<LE>()?, cursor.read_f32::<LE>()?))
+    }
+}
+
+// uncompressed (x, y, z, w), as used by texture transform tracks
+impl TrackValue for Quaternion<f32> {
+    const SIZE: usize = 16;
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        let (x, y, z, w) = (cursor.read_f32::<LE>()?, cursor.read_f32::<LE>()?, cursor.read_f32::<LE>()?, cursor.read_f32::<LE>()?);
+        Ok(Quaternion::new(w, x, y, z))
+    }
+}
+
+impl TrackValue for CompQuat {
+    const SIZE: usize = 8;
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        Ok(CompQuat([
+            cursor.read_i16::<LE>()?,
+            cursor.read_i16::<LE>()?,
+            cursor.read_i16::<LE>()?,
+            cursor.read_i16::<LE>()?,
+        ]))
+    }
+}
+
+impl<T: TrackValue> TrackValue for SplineKey<T> {
+    const SIZE: usize = T::SIZE * 3;
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        Ok(SplineKey {
+            value: T::read(cursor)?,
+            in_tangent: T::read(cursor)?,
+            out_tangent: T::read(cursor)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::put_u32;
+
+    #[test]
+    fn test_read_track() {
+        let mut data = vec![0u8; 0x80];
+        let mut put = |offset: usize, value: u32| data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+
+        // linear, no global sequence, two sequences
+        put(0x00, 1 | 0xFFFF << 16);
+        put(0x04, 2);
+        put(0x08, 0x20);
+        put(0x0C, 2);
+        put(0x10, 0x30);
+
+        // sequence 0: timestamps [0, 100], values [1.0, 2.0]; sequence 1 is external
+        put(0x20, 2);
+        put(0x24, 0x40);
+        put(0x28, 1);
+        put(0x2C, 0x7C);
+        put(0x30, 2);
+        put(0x34, 0x48);
+        put(0x38, 1);
+        put(0x3C, 0x7C);
+        put(0x40, 0);
+        put(0x44, 100);
+        put(0x48, 1.0f32.to_bits());
+        put(0x4C, 2.0f32.to_bits());
+
+        let track = M2Track::<f32>::read(&data, 0, &[true, false]).unwrap();
+        assert_eq!(track.interpolation, Interpolation::Linear);
+        assert_eq!(track.global_sequence, None);
+        assert_eq!(track.keyframes(0), (&[0, 100][..], &[1.0, 2.0][..]));
+        assert_eq!(track.keyframes(1), (&[][..], &[][..]));
+        assert!(track.is_animated());
+
+        let identity = CompQuat([32767, 32767, 32767, -1]).to_quaternion();
+        assert_eq!(identity, Quaternion::new(1.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_read_track_bad_counts() {
+        let mut data = vec![0u8; 0x40];
+        put_u32(&mut data, 0x00, 0xFFFF << 16);
+
+        // the header itself past the end
+        assert!(M2Track::<f32>::read(&data, 0x30, &[]).is_err());
+        assert!(TrackBase::read(&data, 0x38, &[]).is_err());
+
+        // more sequences than the data holds
+        put_u32(&mut data, 0x04, 0x1000);
+        put_u32(&mut data, 0x08, 0x20);
+        assert!(M2Track::<f32>::read(&data, 0, &[true]).is_err());
+
+        // one sequence with more timestamps than the data holds, unless external
+        put_u32(&mut data, 0x04, 1);
+        put_u32(&mut data, 0x20, 100);
+        put_u32(&mut data, 0x24, 0x30);
+        assert!(TrackBase::read(&data, 0, &[true]).is_err());
+        assert_eq!(TrackBase::read(&data, 0, &[false]).unwrap().timestamps, vec![Vec::<u32>::new()]);
+    }
+}
@@ -0,0 +1,204 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use reader::ResourceReader;
+use asset::{Asset, LoadOptions};
+use error::Result;
+
+type Shared = Arc<dyn Any + Send + Sync>;
+// filled in by whichever thread loads the asset first; others wait on the lock
+type Slot = Arc<Mutex<Option<Shared>>>;
+
+// loads assets through a reader once and hands out shared copies. names are
+// compared ignoring case and separator style. failed loads aren't cached
+pub struct ResourceManager {
+    reader: Arc<dyn ResourceReader>,
+    options: LoadOptions,
+    slots: Mutex<HashMap<(TypeId, String), Slot>>,
+}
+
+impl ResourceManager {
+    pub fn new(reader: Arc<dyn ResourceReader>, options: LoadOptions) -> ResourceManager {
+        ResourceManager { reader, options, slots: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn reader(&self) -> &Arc<dyn ResourceReader> {
+        &self.reader
+    }
+
+    pub fn options(&self) -> &LoadOptions {
+        &self.options
+    }
+
+    // the cached asset, loading it first if needed. concurrent calls for the same
+    // asset load it only once
+    pub fn get<A: Asset + Send + Sync + 'static>(&self, name: &str) -> Result<Arc<A>> {
+        let slot = self.slots.lock().unwrap_or_else(PoisonError::into_inner)
+            .entry(key::<A>(name))
+            .or_default()
+            .clone();
+
+        let mut value = slot.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(ref value) = *value {
+            return Ok(downcast(value.clone()));
+        }
+
+        let asset = Arc::new(A::load(&*self.reader, name, &self.options)?);
+        *value = Some(asset.clone());
+        Ok(asset)
+    }
+
+    // the asset if it's already loaded
+    pub fn cached<A: Asset + Send + Sync + 'static>(&self, name: &str) -> Option<Arc<A>> {
+        let slot = self.slots.lock().unwrap_or_else(PoisonError::into_inner).get(&key::<A>(name))?.clone();
+        let value = slot.try_lock().ok()?;
+        value.clone().map(downcast)
+    }
+
+    // drops the cached asset; copies handed out before stay valid.
+    // returns whether it was loaded
+    pub fn evict<A: Asset + 'static>(&self, name: &str) -> bool {
+        let slot = self.slots.lock().unwrap_or_else(PoisonError::into_inner).remove(&key::<A>(name));
+        slot.is_some_and(|slot| slot.lock().unwrap_or_else(PoisonError::into_inner).is_some())
+    }
+
+    pub fn evict_all(&self) {
+        self.slots.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    // number of loaded assets
+    pub fn len(&self) -> usize {
+        let slots = self.slots.lock().unwrap_or_else(PoisonError::into_inner);
+        slots.values().filter(|slot| slot.try_lock().map(|value| value.is_some()).unwrap_or(false)).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// loads all `names` on up to `parallelism` threads; results are in the order of `names`.
+// with a `parallelism` of 1 they're loaded on the calling thread
+pub fn load_many<A, S>(reader: Arc<dyn ResourceReader>, names: &[S], options: &LoadOptions, parallelism: usize) -> Vec<Result<Arc<A>>>
+    where A: Asset + Send + Sync + 'static, S: AsRef<str>
+{
+    let options = options.clone();
+    in_parallel(names, parallelism, move |name| Ok(Arc::new(A::load(&*reader, name, &options)?)))
+}
+
+// same through a manager, so assets already loaded (or listed twice) are reused
+pub fn load_many_cached<A, S>(manager: Arc<ResourceManager>, names: &[S], parallelism: usize) -> Vec<Result<Arc<A>>>
+    where A: Asset + Send + Sync + 'static, S: AsRef<str>
+{
+    in_parallel(names, parallelism, move |name| manager.get(name))
+}
+
+fn in_parallel<A, S, F>(names: &[S], parallelism: usize, load: F) -> Vec<Result<Arc<A>>>
+    where A: Send + Sync + 'static, S: AsRef<str>, F: Fn(&str) -> Result<Arc<A>> + Send + Sync + 'static
+{
+    // on the calling thread when there's nothing to gain, or no threads to use
+    if parallelism <= 1 || names.len() <= 1 || !cfg!(feature = "threads") {
+        return names.iter().map(|name| load(name.as_ref())).collect();
+    }
+
+    // workers pop from the end, so reverse to load in the given order
+    let mut jobs: Vec<(usize, String)> = names.iter().map(|name| name.as_ref().to_owned()).enumerate().collect();
+    jobs.reverse();
+    let jobs = Arc::new(Mutex::new(jobs));
+    let load = Arc::new(load);
+
+    let workers: Vec<_> = (0..parallelism.max(1).min(names.len())).map(|_| {
+        let jobs = jobs.clone();
+        let load = load.clone();
+
+        thread::spawn(move || {
+            let mut results = Vec::new();
+            loop {
+                let job = jobs.lock().unwrap_or_else(PoisonError::into_inner).pop();
+                match job {
+                    Some((index, name)) => results.push((index, load(&name))),
+                    None => break,
+                }
+            }
+            results
+        })
+    }).collect();
+
+    let mut results: Vec<Option<Result<Arc<A>>>> = names.iter().map(|_| None).collect();
+    for worker in workers {
+        match worker.join() {
+            Ok(worker_results) => {
+                for (index, result) in worker_results {
+                    results[index] = Some(result);
+                }
+            }
+            // whatever the worker had loaded is lost; reported as failed below
+            Err(_) => warn!("Asset loading worker panicked"),
+        }
+    }
+
+    results.into_iter().zip(names)
+        .map(|(result, name)| result.unwrap_or_else(|| {
+            Err(io_error!(Other, "loading {} panicked", name.as_ref()).into())
+        }))
+        .collect()
+}
+
+fn key<A: 'static>(name: &str) -> (TypeId, String) {
+    (TypeId::of::<A>(), name.to_lowercase().replace('/', "\\"))
+}
+
+fn downcast<A: Send + Sync + 'static>(value: Shared) -> Arc<A> {
+    value.downcast().unwrap_or_else(|_| unreachable!("slots are keyed by type"))
+}
+
+#[cfg(all(test, feature = "wdt", feature = "threads"))]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use reader::ResourceHandle;
+    use wdt::Wdt;
+
+    #[derive(Default)]
+    struct CountingReader(AtomicUsize);
+
+    impl ResourceReader for CountingReader {
+        fn open(&self, _name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(Box::new(Cursor::new(b"REVM\x04\0\0\0\x12\0\0\0".to_vec())))
+        }
+    }
+
+    #[test]
+    fn test_resource_manager() {
+        let reader = Arc::new(CountingReader::default());
+        let manager = Arc::new(ResourceManager::new(reader.clone(), LoadOptions::default()));
+
+        let threads: Vec<_> = (0..4).map(|_| {
+            let manager = manager.clone();
+            thread::spawn(move || manager.get::<Wdt>("World\\Maps\\Test\\Test.wdt").unwrap())
+        }).collect();
+        for thread in threads { thread.join().unwrap(); }
+        assert_eq!(reader.0.load(Ordering::SeqCst), 1);
+
+        let wdt = manager.get::<Wdt>("world/maps/test/TEST.wdt").unwrap();
+        assert!(Arc::ptr_eq(&wdt, &manager.cached::<Wdt>("World\\Maps\\Test\\Test.wdt").unwrap()));
+        assert_eq!(manager.len(), 1);
+
+        assert!(manager.evict::<Wdt>("World\\Maps\\Test\\Test.wdt"));
+        assert!(manager.cached::<Wdt>("World\\Maps\\Test\\Test.wdt").is_none());
+        manager.get::<Wdt>("World\\Maps\\Test\\Test.wdt").unwrap();
+        assert_eq!(reader.0.load(Ordering::SeqCst), 2);
+
+        let names = ["A.wdt", "B.wdt", "a.wdt", "C.blp"];
+        let results = load_many_cached::<Wdt, _>(manager.clone(), &names, 3);
+        assert_eq!(results.len(), 4);
+        assert!(Arc::ptr_eq(results[0].as_ref().unwrap(), results[2].as_ref().unwrap()));
+        assert_eq!(reader.0.load(Ordering::SeqCst), 2 + 3);
+        assert_eq!(load_many::<Wdt, _>(reader.clone(), &names, &LoadOptions::default(), 8).len(), 4);
+        assert_eq!(reader.0.load(Ordering::SeqCst), 2 + 3 + 4);
+    }
+}
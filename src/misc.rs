@@ -1,7 +1,12 @@
 use std::ops::{Deref, DerefMut};
 use std::fmt;
+#[cfg(feature = "rgb")]
 use rgb::{RGB8, RGBA8};
 
+// (de)serialized as a plain sequence
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Blob<T>(pub Vec<T>);
 
 impl<T: Sized> From<Vec<T>> for Blob<T> {
@@ -46,6 +51,10 @@ impl_debug! {
     (f32, f32);
     (f32, f32, f32);
     (f32, f32, f32, f32);
+}
+
+#[cfg(feature = "rgb")]
+impl_debug! {
     RGB8;
     RGBA8;
 }
@@ -0,0 +1,195 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use reader::{ResourceReader, ResourceHandle, ResourceMetadata};
+use chunked::Token;
+
+// receives progress and diagnostics from loaders running under `observe`.
+// all methods default to doing nothing, so implement only what you need
+pub trait LoadObserver : Send + Sync {
+    // `name` is "FileDataID <id>" for files opened by id
+    fn file_opened(&self, _name: &str) {}
+    fn chunk_parsed(&self, _token: Token, _size: u32) {}
+    fn bytes_read(&self, _count: u64) {}
+    // same messages as logged with `warn!`
+    fn warning(&self, _message: &str) {}
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<dyn LoadObserver>>> = RefCell::new(None);
+}
+
+// runs `load` with a reader reporting opened files and read bytes to `observer`.
+// chunk and warning events are reported from the calling thread only, work
+// handed off to other threads shows up as file and byte counts
+pub fn observe<T, F>(observer: Arc<dyn LoadObserver>, reader: Arc<dyn ResourceReader>, load: F) -> T
+    where F: FnOnce(Arc<dyn ResourceReader>) -> T
+{
+    struct Restore(Option<Arc<dyn LoadObserver>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    let previous = CURRENT.with(|current| current.borrow_mut().replace(observer.clone()));
+    let _restore = Restore(previous);
+    load(Arc::new(ObservedReader { inner: reader, observer }))
+}
+
+// calls `f` with the observer installed on this thread, if any
+pub fn notify<F: FnOnce(&dyn LoadObserver)>(f: F) {
+    let observer = CURRENT.with(|current| current.borrow().clone());
+    if let Some(observer) = observer {
+        f(&*observer);
+    }
+}
+
+pub fn warning(message: &str) {
+    notify(|observer| observer.warning(message));
+}
+
+// `warn!` which is also reported to the current `LoadObserver` and `Diagnostics`
+macro_rules! report_warn {
+    ($($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        warn!("{}", message);
+        ::observer::warning(&message);
+        ::diagnostics::report(None, &message);
+    }}
+}
+
+// `report_warn!` about a given chunk rather than the one last read
+macro_rules! report_chunk_warn {
+    ($token:expr, $offset:expr, $($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        warn!("{}", message);
+        ::observer::warning(&message);
+        ::diagnostics::report(Some(($token, $offset)), &message);
+    }}
+}
+
+// something inconsistent in the data being parsed. under `ParseMode::Strict` it's
+// returned from the enclosing function as `Error::InvalidData`, otherwise it's
+// reported as a warning and the caller carries on
+macro_rules! report_inconsistency {
+    ($($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        if ::asset::ParseMode::current() == ::asset::ParseMode::Strict {
+            return Err(::error::Error::InvalidData(message).into());
+        }
+        report_warn!("{}", message);
+    }}
+}
+
+pub struct ObservedReader {
+    inner: Arc<dyn ResourceReader>,
+    observer: Arc<dyn LoadObserver>,
+}
+
+impl ObservedReader {
+    pub fn new(inner: Arc<dyn ResourceReader>, observer: Arc<dyn LoadObserver>) -> ObservedReader {
+        ObservedReader { inner, observer }
+    }
+
+    fn wrap(&self, name: &str, handle: Box<dyn ResourceHandle>) -> Box<dyn ResourceHandle> {
+        self.observer.file_opened(name);
+        Box::new(ObservedHandle { inner: handle, observer: self.observer.clone() })
+    }
+}
+
+impl ResourceReader for ObservedReader {
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        self.inner.exists(name)
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+        let handle = self.inner.open(name)?;
+        Ok(self.wrap(name, handle))
+    }
+
+    fn metadata(&self, name: &str) -> io::Result<ResourceMetadata> {
+        self.inner.metadata(name)
+    }
+
+    fn exists_by_id(&self, file_data_id: u32) -> io::Result<bool> {
+        self.inner.exists_by_id(file_data_id)
+    }
+
+    fn open_by_id(&self, file_data_id: u32) -> io::Result<Box<dyn ResourceHandle>> {
+        let handle = self.inner.open_by_id(file_data_id)?;
+        Ok(self.wrap(&format!("FileDataID {}", file_data_id), handle))
+    }
+
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        self.inner.list(prefix)
+    }
+}
+
+struct ObservedHandle {
+    inner: Box<dyn ResourceHandle>,
+    observer: Arc<dyn LoadObserver>,
+}
+
+impl Read for ObservedHandle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        if count > 0 {
+            self.observer.bytes_read(count as u64);
+        }
+        Ok(count)
+    }
+}
+
+impl Seek for ObservedHandle {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::Mutex;
+    use chunked::Chunked;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl LoadObserver for Recorder {
+        fn file_opened(&self, name: &str) { self.0.lock().unwrap().push(format!("open {}", name)); }
+        fn chunk_parsed(&self, token: Token, size: u32) { self.0.lock().unwrap().push(format!("chunk {} {}", token, size)); }
+        fn bytes_read(&self, count: u64) { self.0.lock().unwrap().push(format!("read {}", count)); }
+        fn warning(&self, message: &str) { self.0.lock().unwrap().push(format!("warn {}", message)); }
+    }
+
+    struct OneFile;
+
+    impl ResourceReader for OneFile {
+        fn exists(&self, _name: &str) -> io::Result<bool> { Ok(true) }
+        fn open(&self, _name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+            Ok(Box::new(Cursor::new(b"REVM\x04\0\0\0\x12\0\0\0".to_vec())))
+        }
+    }
+
+    #[test]
+    fn test_observe() {
+        let recorder = Arc::new(Recorder::default());
+        observe(recorder.clone(), Arc::new(OneFile), |reader| {
+            let input = reader.open("test.wdt").unwrap();
+            assert_eq!(Chunked::new(input).count(), 1);
+            report_warn!("something odd in {}", "test.wdt");
+        });
+        // nothing is reported outside of `observe`
+        warning("ignored");
+
+        let events = recorder.0.lock().unwrap();
+        assert_eq!(events[0], "open test.wdt");
+        assert!(events.contains(&"chunk MVER 4".to_string()));
+        assert_eq!(events.last().unwrap(), "warn something odd in test.wdt");
+        assert!(events.iter().any(|event| event.starts_with("read ")));
+    }
+}
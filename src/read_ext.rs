@@ -1,6 +1,8 @@
 use std::collections::BTreeMap;
 use std::io::{self, Read};
 use byteorder::{ReadBytesExt, ByteOrder};
+use limits::Limits;
+use bytes::Bytes;
 
 macro_rules! io_error {
     ($id:ident, $err:expr) => (io::Error::new(io::ErrorKind::$id, $err));
@@ -26,53 +28,28 @@ pub trait ReadExt : Read + ReadBytesExt {
         Ok(result)
     }
 
+    // a stream can't be rewound past the NUL, so this goes byte by byte;
+    // prefer `cstring` for data already in memory
     fn read_cstring(&mut self) -> io::Result<String> {
+        let limits = Limits::current();
         let mut result = Vec::new();
         loop {
             let byte = self.read_u8()?;
             if byte == 0 { break }
+            limits.check_string_length(result.len() as u64 + 1)?;
             result.push(byte);
         }
         String::from_utf8(result).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
 
-    fn read_cstring_table_with<F>(&mut self, mut f: F) -> io::Result<()>
+    // the rest of the input is read at once, see `cstring_table_with`
+    fn read_cstring_table_with<F>(&mut self, f: F) -> io::Result<()>
     where
         F: FnMut(u32, String)
     {
-        let mut string_buffer = Vec::new();
-        let mut index = 0;
-        let mut string_id = -1;
-        let mut at_string = false;
-        let mut done: bool;
-
-        loop {
-            let mut read_buffer = [0u8; 1];
-            done = self.read(&mut read_buffer)? == 0;
-            let byte = if done { 0u8 } else { read_buffer[0] };
-
-            if byte != 0 {
-                if !at_string {
-                    at_string = true;
-                    string_id = index;
-                }
-                string_buffer.push(byte);
-            } else if !string_buffer.is_empty() {
-                at_string = false;
-
-                let string = String::from_utf8(string_buffer.clone())
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                string_buffer.clear();
-
-                trace!("found string: [{}] '{}'", string_id, &string);
-                f(string_id as u32, string);
-                string_id = -1;
-            }
-
-            if done { break }
-            index += 1;
-        }
-        Ok(())
+        let mut data = Vec::new();
+        self.read_to_end(&mut data)?;
+        cstring_table_with(&data, f)
     }
 
     fn read_cstring_table_into(&mut self, target: &mut BTreeMap<u32, String>) -> io::Result<()> {
@@ -134,10 +111,58 @@ pub trait ReadExt : Read + ReadBytesExt {
 }
 impl<R: Read + ReadBytesExt> ReadExt for R {}
 
+// the string at the start of `data`, up to its NUL
+pub fn cstring(data: &[u8]) -> io::Result<String> {
+    let string = Bytes::new(data).cstr()?;
+    Limits::current().check_string_length(string.len() as u64)?;
+    Ok(string.to_owned())
+}
+
+// calls `f` with the offset and contents of each string in a block of NUL
+// separated ones (MTEX, MOTX and the like), skipping empty ones. a string
+// running to the end without a NUL still counts
+pub fn cstring_table_with<F>(data: &[u8], mut f: F) -> io::Result<()>
+where
+    F: FnMut(u32, String)
+{
+    let limits = Limits::current();
+    let mut offset = 0;
+
+    for bytes in data.split(|&byte| byte == 0) {
+        if !bytes.is_empty() {
+            limits.check_string_length(bytes.len() as u64)?;
+            let string = String::from_utf8(bytes.to_vec())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            trace!("found string: [{}] '{}'", offset, &string);
+            f(offset as u32, string);
+        }
+        offset += bytes.len() + 1;
+    }
+    Ok(())
+}
+
+pub fn cstring_table_into(data: &[u8], target: &mut BTreeMap<u32, String>) -> io::Result<()> {
+    cstring_table_with(data, |index, string| {
+        target.insert(index, string);
+    })
+}
+
+pub fn cstring_array_into(data: &[u8], target: &mut Vec<String>) -> io::Result<()> {
+    cstring_table_with(data, |_, string| {
+        target.push(string);
+    })
+}
+
 pub trait FromRead: Sized {
     fn from_read<O: ByteOrder>(reader: &mut Read) -> io::Result<Self>;
 }
 
+// records whose layout changed between versions of their file, see `wow_derive`
+pub trait FromReadVersion: Sized {
+    fn from_read_version<O: ByteOrder>(reader: &mut dyn Read, version: u32) -> io::Result<Self>;
+}
+
 impl FromRead for i8 {
     fn from_read<O: ByteOrder>(reader: &mut Read) -> io::Result<Self> {
         reader.read_i8()
@@ -212,3 +237,49 @@ macro_rules! let_read {
         )*
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use byteorder::{BE, LE};
+
+    #[derive(Debug, PartialEq, FromRead)]
+    struct Record {
+        id: u16,
+        #[from_read(byteorder = "BE")]
+        magic: u32,
+        #[from_read(version = "version >= 17")]
+        flags: u16,
+        #[from_read(with = "read_twice")]
+        size: u32,
+    }
+
+    fn read_twice(reader: &mut dyn Read) -> io::Result<u32> {
+        Ok(reader.read_u8()? as u32 * 2)
+    }
+
+    #[test]
+    fn test_derive() {
+        let data = b"\x01\0MVER\x02\0\x03";
+        let record = Record::from_read_version::<LE>(&mut Cursor::new(&data[..]), 17).unwrap();
+        assert_eq!(record, Record { id: 1, magic: 0x4d564552, flags: 2, size: 6 });
+
+        let record = Record::from_read_version::<LE>(&mut Cursor::new(&data[..]), 14).unwrap();
+        assert_eq!(record, Record { id: 1, magic: 0x4d564552, flags: 0, size: 4 });
+        assert!(Record::from_read_version::<BE>(&mut Cursor::new(&data[..4]), 14).is_err());
+    }
+
+    #[test]
+    fn test_cstring_table() {
+        let data = b"a.blp\0\0bc.m2\0tail";
+
+        let mut strings = Vec::new();
+        cstring_table_with(data, |offset, string| strings.push((offset, string))).unwrap();
+        assert_eq!(strings, vec![(0, "a.blp".to_owned()), (7, "bc.m2".to_owned()), (13, "tail".to_owned())]);
+        assert_eq!(Cursor::new(&data[..]).read_cstring_table().unwrap().into_iter().collect::<Vec<_>>(), strings);
+
+        assert_eq!(cstring(&data[7..]).unwrap(), "bc.m2");
+        assert!(cstring(b"tail").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zip::ZipArchive;
+use tar;
+use reader::{ResourceReader, ResourceHandle, ResourceMetadata, has_prefix};
+
+// extracted data dumps packed as zip or tar; names are looked up ignoring case
+// and separator style. files are read into memory as a whole when opened
+pub struct ArchiveReader {
+    // normalized name -> index into `files`
+    entries: HashMap<String, usize>,
+    // name as stored and index of the entry within the backend
+    files: Vec<(String, usize)>,
+    // uncompressed, one per file
+    sizes: Vec<u64>,
+    backend: Backend,
+}
+
+enum Backend {
+    Zip(Mutex<ZipArchive<File>>),
+    // tar has no index, so data offsets and sizes are collected up front
+    Tar { path: PathBuf, spans: Vec<(u64, u64)> },
+}
+
+impl ArchiveReader {
+    // picks the format by extension: `.zip`, otherwise tar
+    pub fn open(path: &Path) -> io::Result<ArchiveReader> {
+        match path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase()) {
+            Some(ref ext) if ext == "zip" => ArchiveReader::open_zip(path),
+            _ => ArchiveReader::open_tar(path),
+        }
+    }
+
+    pub fn open_zip(path: &Path) -> io::Result<ArchiveReader> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let mut files = Vec::new();
+        let mut sizes = Vec::new();
+
+        for index in 0..archive.len() {
+            let file = archive.by_index(index)?;
+            if !file.is_dir() {
+                files.push((file.name().to_owned(), index));
+                sizes.push(file.size());
+            }
+        }
+
+        Ok(ArchiveReader::with_files(files, sizes, Backend::Zip(Mutex::new(archive))))
+    }
+
+    pub fn open_tar(path: &Path) -> io::Result<ArchiveReader> {
+        let mut archive = tar::Archive::new(File::open(path)?);
+        let mut files = Vec::new();
+        let mut spans = Vec::new();
+
+        for entry in archive.entries()? {
+            let entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let name = entry.path()?.to_string_lossy().into_owned();
+            files.push((name, spans.len()));
+            spans.push((entry.raw_file_position(), entry.size()));
+        }
+
+        let sizes = spans.iter().map(|&(_, size)| size).collect();
+        Ok(ArchiveReader::with_files(files, sizes, Backend::Tar { path: path.to_owned(), spans }))
+    }
+
+    fn with_files(files: Vec<(String, usize)>, sizes: Vec<u64>, backend: Backend) -> ArchiveReader {
+        let entries = files.iter().enumerate().map(|(index, (name, _))| (normalize(name), index)).collect();
+        ArchiveReader { entries, files, sizes, backend }
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let &(_, index) = self.entries.get(&normalize(name))
+            .map(|&position| &self.files[position])
+            .ok_or_else(|| io_error!(NotFound, "archive does not contain {:?}", name))?;
+
+        let mut data = Vec::new();
+        match self.backend {
+            Backend::Zip(ref archive) => {
+                let mut archive = archive.lock().map_err(|_| io_error!(Other, "zip archive lock is poisoned"))?;
+                archive.by_index(index)?.read_to_end(&mut data)?;
+            }
+            Backend::Tar { ref path, ref spans } => {
+                let (offset, size) = spans[index];
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                file.take(size).read_to_end(&mut data)?;
+            }
+        }
+        Ok(data)
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.to_lowercase().replace('\\', "/")
+}
+
+impl ResourceReader for ArchiveReader {
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        Ok(self.entries.contains_key(&normalize(name)))
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+        Ok(Box::new(Cursor::new(self.read(name)?)))
+    }
+
+    fn metadata(&self, name: &str) -> io::Result<ResourceMetadata> {
+        let &position = self.entries.get(&normalize(name))
+            .ok_or_else(|| io_error!(NotFound, "archive does not contain {:?}", name))?;
+        Ok(ResourceMetadata { size: self.sizes[position], modified: None })
+    }
+
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut names: Vec<String> = self.files.iter()
+            .filter(|(name, _)| has_prefix(name, prefix))
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use zip::ZipWriter;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn test_archive_reader() {
+        let dir = env::temp_dir().join(format!("wow-archive-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let zip_path = dir.join("dump.zip");
+        let mut writer = ZipWriter::new(File::create(&zip_path).unwrap());
+        writer.add_directory("World/", FileOptions::default()).unwrap();
+        writer.start_file("World/Maps/Azeroth.wdt", FileOptions::default()).unwrap();
+        writer.write_all(b"wdt").unwrap();
+        writer.finish().unwrap();
+
+        let tar_path = dir.join("dump.tar");
+        let mut builder = tar::Builder::new(File::create(&tar_path).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_cksum();
+        builder.append_data(&mut header, "World/Maps/Azeroth.wdt", &b"tar"[..]).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        for &(ref path, expected) in [(zip_path, "wdt"), (tar_path, "tar")].iter() {
+            let reader = ArchiveReader::open(path).unwrap();
+            let mut data = String::new();
+            reader.open("world\\maps\\AZEROTH.wdt").unwrap().read_to_string(&mut data).unwrap();
+            assert_eq!(data, expected);
+            assert_eq!(reader.len(), 1);
+            assert_eq!(reader.metadata("World/Maps/Azeroth.wdt").unwrap().size, 3);
+            assert!(!reader.exists("World\\Maps\\Kalimdor.wdt").unwrap());
+            assert_eq!(reader.list("world/").unwrap(), vec!["World/Maps/Azeroth.wdt".to_string()]);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+use std::io::{self, Cursor, Read};
+use byteorder::{ReadBytesExt, BE};
+use flate2::read::ZlibDecoder;
+use limits::Limits;
+
+// decodes a BLTE container: an optional chunk table followed by chunks, each
+// prefixed with its mode ('N' plain, 'Z' zlib, 'F' nested BLTE, 'E' encrypted)
+pub fn decode(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut input = Cursor::new(data);
+
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+    if &magic != b"BLTE" {
+        return Err(io_error!(InvalidData, "invalid BLTE magic {:?}", magic));
+    }
+
+    let header_size = input.read_u32::<BE>()? as usize;
+    let mut chunks = Vec::new();
+
+    if header_size == 0 {
+        chunks.push((data.len() - 8, None));
+    } else {
+        let _flags = input.read_u8()?;
+        let count = input.read_u24::<BE>()?;
+        for _ in 0..count {
+            let compressed_size = input.read_u32::<BE>()? as usize;
+            let decompressed_size = input.read_u32::<BE>()? as usize;
+            // sizes are preallocated below, so a corrupt table mustn't ask for gigabytes
+            Limits::current().check_chunk_size(decompressed_size as u64)?;
+            let mut _checksum = [0u8; 16];
+            input.read_exact(&mut _checksum)?;
+            chunks.push((compressed_size, Some(decompressed_size)));
+        }
+        input.set_position(header_size as u64);
+    }
+
+    let mut output = Vec::with_capacity(chunks.iter().map(|&(size, decoded)| decoded.unwrap_or(size)).sum());
+
+    for (index, (size, decoded)) in chunks.into_iter().enumerate() {
+        let start = input.position() as usize;
+        let chunk = data.get(start..start + size)
+            .ok_or_else(|| io_error!(UnexpectedEof, "BLTE chunk {} is out of bounds", index))?;
+        input.set_position((start + size) as u64);
+
+        let before = output.len();
+        decode_chunk(chunk, &mut output)?;
+
+        if let Some(decoded) = decoded {
+            if output.len() - before != decoded {
+                return Err(io_error!(InvalidData, "BLTE chunk {} decoded to {} bytes instead of {}", index, output.len() - before, decoded));
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+fn decode_chunk(chunk: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+    let (mode, payload) = chunk.split_first().ok_or_else(|| io_error!(InvalidData, "empty BLTE chunk"))?;
+
+    match *mode {
+        b'N' => output.extend_from_slice(payload),
+        b'Z' => { ZlibDecoder::new(payload).read_to_end(output)?; }
+        b'F' => output.extend(decode(payload)?),
+        b'E' => return Err(io_error!(Other, "encrypted BLTE chunks are not supported")),
+        other => return Err(io_error!(InvalidData, "unknown BLTE chunk mode {:?}", other as char)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+
+    #[test]
+    fn test_decode() {
+        let mut single = b"BLTE\0\0\0\0N".to_vec();
+        single.extend_from_slice(b"plain");
+        assert_eq!(decode(&single).unwrap(), b"plain");
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b" zlib").unwrap();
+        let mut zlib = vec![b'Z'];
+        zlib.extend(encoder.finish().unwrap());
+
+        // two chunks behind a table
+        let mut table = b"BLTE".to_vec();
+        table.extend_from_slice(&(8u32 + 4 + 2 * 24).to_be_bytes());
+        table.extend_from_slice(&[0x0F, 0, 0, 2]);
+        table.extend_from_slice(&6u32.to_be_bytes());
+        table.extend_from_slice(&5u32.to_be_bytes());
+        table.extend_from_slice(&[0u8; 16]);
+        table.extend_from_slice(&(zlib.len() as u32).to_be_bytes());
+        table.extend_from_slice(&5u32.to_be_bytes());
+        table.extend_from_slice(&[0u8; 16]);
+        table.extend_from_slice(b"Nplain");
+        table.extend(zlib);
+        assert_eq!(decode(&table).unwrap(), b"plain zlib");
+
+        assert!(decode(b"BLTE\0\0\0\0E").is_err());
+    }
+
+    #[test]
+    fn test_decode_corrupt() {
+        assert!(decode(b"BLT").is_err());
+        assert!(decode(b"BLTF\0\0\0\0Nplain").is_err());
+        assert!(decode(b"BLTE\0\0\0\0").is_err());
+        assert!(decode(b"BLTE\0\0\0\0X").is_err());
+
+        let table = |compressed: u32, decompressed: u32| {
+            let mut table = b"BLTE".to_vec();
+            table.extend_from_slice(&(8u32 + 4 + 24).to_be_bytes());
+            table.extend_from_slice(&[0x0F, 0, 0, 1]);
+            table.extend_from_slice(&compressed.to_be_bytes());
+            table.extend_from_slice(&decompressed.to_be_bytes());
+            table.extend_from_slice(&[0u8; 16]);
+            table.extend_from_slice(b"Nplain");
+            table
+        };
+        assert_eq!(decode(&table(6, 5)).unwrap(), b"plain");
+        // a chunk running past the data, decoding to another size, or claiming 4GB
+        assert!(decode(&table(7, 5)).is_err());
+        assert!(decode(&table(6, 4)).is_err());
+        assert!(decode(&table(6, u32::MAX)).is_err());
+
+        // more chunks in the table than it has room for
+        let mut truncated = table(6, 5);
+        truncated[11] = 2;
+        assert!(decode(&truncated).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use ureq;
+use reader::{ResourceReader, ResourceHandle};
+
+pub mod blte;
+pub mod tact;
+
+use self::tact::{Key, ArchiveEntry, RootEntry, parse_key, key_hex};
+
+const PATCH_SERVER_PORT: u16 = 1119;
+
+// what to fetch and from where; see `CdnSettings::latest` for the current build
+#[derive(Clone, Debug)]
+pub struct CdnSettings {
+    // tried in order until one answers
+    pub hosts: Vec<String>,
+    // e.g. "tpr/wow"
+    pub path: String,
+    pub build_config: Key,
+    pub cdn_config: Key,
+    // downloads are kept here, so a build is fetched only once
+    pub cache_dir: PathBuf,
+    // preferred variant of localized files, a `tact::LOCALE_*` flag
+    pub locale: u32,
+}
+
+// reads files of any build straight from the blizzard cdn. the root file only
+// knows FileDataIDs, so wrap this into a `ListfileReader` to open by name
+pub struct CdnReader {
+    settings: CdnSettings,
+    archives: Vec<Key>,
+    index: HashMap<Key, ArchiveEntry>,
+    encoding: HashMap<Key, Key>,
+    root: BTreeMap<u32, Vec<RootEntry>>,
+}
+
+impl CdnSettings {
+    // current build of `product` ("wow", "wow_classic", ...) in `region` ("us", "eu", ...)
+    pub fn latest<P: AsRef<Path>>(product: &str, region: &str, cache_dir: P) -> io::Result<CdnSettings> {
+        let server = format!("http://{}.patch.battle.net:{}/{}", region, PATCH_SERVER_PORT, product);
+        let select = |table: &str| -> io::Result<HashMap<String, String>> {
+            let text = String::from_utf8_lossy(&download(&format!("{}/{}", server, table), None)?).into_owned();
+            tact::parse_psv(&text).into_iter()
+                .find(|row| row.get("Name").or_else(|| row.get("Region")).map(|name| name == region).unwrap_or(false))
+                .ok_or_else(|| io_error!(NotFound, "no {} entry for region {} of {}", table, region, product))
+        };
+
+        let version = select("versions")?;
+        let cdn = select("cdns")?;
+        let field = |row: &HashMap<String, String>, name: &str| row.get(name).cloned()
+            .ok_or_else(|| io_error!(InvalidData, "{} is missing from the patch server response", name));
+
+        Ok(CdnSettings {
+            hosts: field(&cdn, "Hosts")?.split_whitespace().map(|host| host.to_owned()).collect(),
+            path: field(&cdn, "Path")?,
+            build_config: parse_key(&field(&version, "BuildConfig")?)?,
+            cdn_config: parse_key(&field(&version, "CDNConfig")?)?,
+            cache_dir: cache_dir.as_ref().to_owned(),
+            locale: tact::LOCALE_EN_US,
+        })
+    }
+}
+
+impl CdnReader {
+    // downloads (or takes from the cache) the configs, archive indices, encoding and root
+    pub fn new(settings: CdnSettings) -> io::Result<CdnReader> {
+        let mut reader = CdnReader {
+            settings,
+            archives: Vec::new(),
+            index: HashMap::new(),
+            encoding: HashMap::new(),
+            root: BTreeMap::new(),
+        };
+
+        let build_config = tact::parse_config(&String::from_utf8_lossy(&reader.fetch("config", &reader.settings.build_config, "")?));
+        let cdn_config = tact::parse_config(&String::from_utf8_lossy(&reader.fetch("config", &reader.settings.cdn_config, "")?));
+
+        for hash in cdn_config.get("archives").into_iter().flat_map(|archives| archives.split_whitespace()) {
+            let archive = parse_key(hash)?;
+            let data = reader.fetch("data", &archive, ".index")?;
+            tact::parse_archive_index(&data, reader.archives.len(), &mut reader.index)?;
+            reader.archives.push(archive);
+        }
+
+        // `encoding = <ckey> <ekey>`; the encoding file can't be looked up in itself
+        let encoding_key = build_config.get("encoding").and_then(|keys| keys.split_whitespace().nth(1))
+            .ok_or_else(|| io_error!(InvalidData, "build config has no encoding key"))?;
+        reader.encoding = tact::parse_encoding(&reader.read_encoded(&parse_key(encoding_key)?)?)?;
+
+        let root_key = build_config.get("root").ok_or_else(|| io_error!(InvalidData, "build config has no root key"))?;
+        reader.root = tact::parse_root(&reader.read_content(&parse_key(root_key)?)?)?;
+
+        Ok(reader)
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.root.len()
+    }
+
+    fn content_key(&self, file_data_id: u32) -> Option<Key> {
+        let entries = self.root.get(&file_data_id)?;
+        entries.iter()
+            .find(|entry| entry.locale & self.settings.locale != 0)
+            .or_else(|| entries.first())
+            .map(|entry| entry.content)
+    }
+
+    fn read_content(&self, content: &Key) -> io::Result<Vec<u8>> {
+        let encoded = self.encoding.get(content)
+            .ok_or_else(|| io_error!(NotFound, "content key {} is missing from the encoding file", key_hex(content)))?;
+        self.read_encoded(encoded)
+    }
+
+    // archived files are cut out of their archive with a range request
+    fn read_encoded(&self, encoded: &Key) -> io::Result<Vec<u8>> {
+        let data = match self.index.get(encoded) {
+            Some(entry) => self.cached(&self.cache_path("encoded", encoded, ""), || {
+                let archive = self.archives.get(entry.archive)
+                    .ok_or_else(|| io_error!(InvalidData, "archive {} is missing from the cdn config", entry.archive))?;
+                self.download_any(&self.cdn_path("data", archive, ""), Some(entry.byte_range()?))
+            })?,
+            None => self.fetch("data", encoded, "")?,
+        };
+
+        blte::decode(&data)
+    }
+
+    fn fetch(&self, kind: &str, key: &Key, suffix: &str) -> io::Result<Vec<u8>> {
+        self.cached(&self.cache_path(kind, key, suffix), || self.download_any(&self.cdn_path(kind, key, suffix), None))
+    }
+
+    fn cached<F: FnOnce() -> io::Result<Vec<u8>>>(&self, path: &Path, fetch: F) -> io::Result<Vec<u8>> {
+        if path.exists() {
+            return fs::read(path);
+        }
+
+        let data = fetch()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // written aside first, so an interrupted download doesn't leave a broken entry
+        let temp_path = path.with_extension("part");
+        fs::write(&temp_path, &data)?;
+        fs::rename(&temp_path, path)?;
+        Ok(data)
+    }
+
+    fn cache_path(&self, kind: &str, key: &Key, suffix: &str) -> PathBuf {
+        let hex = key_hex(key);
+        self.settings.cache_dir.join(kind).join(&hex[0..2]).join(&hex[2..4]).join(hex.clone() + suffix)
+    }
+
+    fn cdn_path(&self, kind: &str, key: &Key, suffix: &str) -> String {
+        let hex = key_hex(key);
+        format!("{}/{}/{}/{}/{}{}", self.settings.path, kind, &hex[0..2], &hex[2..4], hex, suffix)
+    }
+
+    fn download_any(&self, path: &str, range: Option<(u64, u64)>) -> io::Result<Vec<u8>> {
+        let mut last_error = io_error!(NotFound, "no cdn hosts configured");
+
+        for host in self.settings.hosts.iter() {
+            match download(&format!("http://{}/{}", host, path), range) {
+                Ok(data) => return Ok(data),
+                Err(error) => {
+                    warn!("Unable to fetch {} from {}: {}", path, host, error);
+                    last_error = error;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+}
+
+// `range` is inclusive on both ends
+fn download(url: &str, range: Option<(u64, u64)>) -> io::Result<Vec<u8>> {
+    let mut request = ureq::get(url);
+    if let Some((start, end)) = range {
+        request = request.set("Range", &format!("bytes={}-{}", start, end));
+    }
+
+    let response = request.call().map_err(|e| match e {
+        ureq::Error::Status(404, _) => io_error!(NotFound, "{} not found", url),
+        e => io_error!(Other, "{}: {}", url, e),
+    })?;
+
+    let mut data = Vec::new();
+    response.into_reader().read_to_end(&mut data)?;
+    Ok(data)
+}
+
+impl ResourceReader for CdnReader {
+    fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+        Err(io_error!(NotFound, "can't open {} by name: the cdn root only knows file data ids", name))
+    }
+
+    fn exists_by_id(&self, file_data_id: u32) -> io::Result<bool> {
+        Ok(self.root.contains_key(&file_data_id))
+    }
+
+    fn open_by_id(&self, file_data_id: u32) -> io::Result<Box<dyn ResourceHandle>> {
+        let content = self.content_key(file_data_id)
+            .ok_or_else(|| io_error!(NotFound, "file data id {} is not in the root file", file_data_id))?;
+        Ok(Box::new(Cursor::new(self.read_content(&content)?)))
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use byteorder::{ReadBytesExt, BE, LE};
+
+// content (ckey) and encoding (ekey) keys are md5 hashes
+pub type Key = [u8; 16];
+
+pub const LOCALE_ALL: u32 = 0xFFFF_FFFF;
+pub const LOCALE_EN_US: u32 = 0x2;
+
+const ROOT_NO_NAME_HASH: u32 = 0x1000_0000;
+const INDEX_BLOCK_SIZE: usize = 4096;
+const INDEX_ENTRY_SIZE: usize = 24;
+const INDEX_FOOTER_SIZE: usize = 28;
+
+pub fn parse_key(hex: &str) -> io::Result<Key> {
+    let hex = hex.trim();
+    if hex.len() != 32 || !hex.is_ascii() {
+        return Err(io_error!(InvalidData, "invalid key {:?}", hex));
+    }
+
+    let mut key = [0u8; 16];
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)
+            .map_err(|_| io_error!(InvalidData, "invalid key {:?}", hex))?;
+    }
+    Ok(key)
+}
+
+pub fn key_hex(key: &Key) -> String {
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// `versions` and `cdns` tables: a `Name!TYPE:size|...` header line, then rows.
+// rows are returned as column name -> value
+pub fn parse_psv(text: &str) -> Vec<HashMap<String, String>> {
+    let mut lines = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+    let columns: Vec<String> = match lines.next() {
+        Some(header) => header.split('|').map(|column| column.split('!').next().unwrap_or("").to_owned()).collect(),
+        None => return Vec::new(),
+    };
+
+    lines
+        .map(|line| columns.iter().cloned().zip(line.split('|').map(|value| value.to_owned())).collect())
+        .collect()
+}
+
+// build and cdn configs: `key = value` lines
+pub fn parse_config(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => Some((name.trim().to_owned(), value.trim().to_owned())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// location of an encoded file within a cdn archive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArchiveEntry {
+    pub archive: usize,
+    pub offset: u32,
+    pub size: u32,
+}
+
+impl ArchiveEntry {
+    // first and last byte, for an http range request
+    pub fn byte_range(&self) -> io::Result<(u64, u64)> {
+        if self.size == 0 {
+            return Err(io_error!(InvalidData, "empty archive entry at {}", self.offset));
+        }
+        Ok((self.offset as u64, self.offset as u64 + self.size as u64 - 1))
+    }
+}
+
+// `<archive>.index`: 4k blocks of (ekey, size, offset) entries and a footer
+pub fn parse_archive_index(data: &[u8], archive: usize, into: &mut HashMap<Key, ArchiveEntry>) -> io::Result<()> {
+    if data.len() < INDEX_FOOTER_SIZE {
+        return Err(io_error!(UnexpectedEof, "archive index is too short"));
+    }
+
+    let count = Cursor::new(&data[data.len() - 12..]).read_u32::<LE>()? as usize;
+    let per_block = INDEX_BLOCK_SIZE / INDEX_ENTRY_SIZE;
+
+    for index in 0..count {
+        let start = (index / per_block) * INDEX_BLOCK_SIZE + (index % per_block) * INDEX_ENTRY_SIZE;
+        let entry = data.get(start..start + INDEX_ENTRY_SIZE)
+            .ok_or_else(|| io_error!(UnexpectedEof, "archive index entry {} is out of bounds", index))?;
+
+        let mut key = [0u8; 16];
+        key.copy_from_slice(&entry[..16]);
+        let mut cursor = Cursor::new(&entry[16..]);
+        let size = cursor.read_u32::<BE>()?;
+        let offset = cursor.read_u32::<BE>()?;
+        into.insert(key, ArchiveEntry { archive, offset, size });
+    }
+
+    Ok(())
+}
+
+// ckey -> ekeys, from the encoding file (already BLTE-decoded)
+pub fn parse_encoding(data: &[u8]) -> io::Result<HashMap<Key, Key>> {
+    let mut input = Cursor::new(data);
+
+    let mut magic = [0u8; 2];
+    input.read_exact(&mut magic)?;
+    if &magic != b"EN" {
+        return Err(io_error!(InvalidData, "invalid encoding file magic {:?}", magic));
+    }
+
+    let _version = input.read_u8()?;
+    let ckey_size = input.read_u8()? as usize;
+    let ekey_size = input.read_u8()? as usize;
+    let page_size = input.read_u16::<BE>()? as usize * 1024;
+    let _espec_page_size = input.read_u16::<BE>()?;
+    let page_count = input.read_u32::<BE>()? as usize;
+    let _espec_page_count = input.read_u32::<BE>()?;
+    let _unknown = input.read_u8()?;
+    let espec_size = input.read_u32::<BE>()? as u64;
+
+    if ckey_size != 16 || ekey_size != 16 {
+        return Err(io_error!(InvalidData, "unsupported encoding key sizes {}/{}", ckey_size, ekey_size));
+    }
+
+    // skip the espec strings and the page table (first key and md5 per page)
+    let pages_start = input.position() + espec_size + page_count as u64 * 32;
+    let mut keys = HashMap::new();
+
+    for page in 0..page_count {
+        let start = pages_start as usize + page * page_size;
+        let page_data = data.get(start..start + page_size)
+            .ok_or_else(|| io_error!(UnexpectedEof, "encoding page {} is out of bounds", page))?;
+        let mut page_input = Cursor::new(page_data);
+
+        loop {
+            let key_count = match page_input.read_u8() {
+                Ok(0) | Err(_) => break,
+                Ok(count) => count as i64,
+            };
+
+            // 40 bit file size
+            page_input.seek(SeekFrom::Current(5))?;
+            let mut ckey = [0u8; 16];
+            page_input.read_exact(&mut ckey)?;
+            let mut ekey = [0u8; 16];
+            page_input.read_exact(&mut ekey)?;
+            page_input.seek(SeekFrom::Current((key_count - 1) * 16))?;
+
+            keys.entry(ckey).or_insert(ekey);
+        }
+    }
+
+    Ok(keys)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RootEntry {
+    pub locale: u32,
+    pub content: Key,
+}
+
+// file data id -> ckeys of all its locale variants, from the root file (already
+// BLTE-decoded); understands the pre-8.2, MFST and 10.1.7+ layouts
+pub fn parse_root(data: &[u8]) -> io::Result<BTreeMap<u32, Vec<RootEntry>>> {
+    let mut input = Cursor::new(data);
+    let mut entries: BTreeMap<u32, Vec<RootEntry>> = BTreeMap::new();
+
+    let (manifest, extended_blocks) = if data.len() >= 12 && &data[0..4] == b"MFST" {
+        input.seek(SeekFrom::Start(4))?;
+        let header_size = input.read_u32::<LE>()?;
+        let version = input.read_u32::<LE>()?;
+
+        // 10.1.7+ leads with a header size and version instead of the file counts
+        if header_size == 24 && version <= 2 {
+            input.seek(SeekFrom::Start(header_size as u64))?;
+            (true, version == 2)
+        } else {
+            input.seek(SeekFrom::Start(12))?;
+            (true, false)
+        }
+    } else {
+        (false, false)
+    };
+
+    while (input.position() as usize) < data.len() {
+        let count = input.read_u32::<LE>()? as usize;
+
+        let (content_flags, locale) = if extended_blocks {
+            let locale = input.read_u32::<LE>()?;
+            let flags = input.read_u32::<LE>()? | input.read_u32::<LE>()? | (input.read_u8()? as u32) << 17;
+            (flags, locale)
+        } else {
+            (input.read_u32::<LE>()?, input.read_u32::<LE>()?)
+        };
+
+        // don't trust the count for the allocation, every id takes 4 bytes
+        let mut ids = Vec::with_capacity(count.min(data.len() / 4));
+        let mut id = -1i64;
+        for _ in 0..count {
+            id += input.read_i32::<LE>()? as i64 + 1;
+            ids.push(id as u32);
+        }
+
+        for &id in ids.iter() {
+            let mut content = [0u8; 16];
+            input.read_exact(&mut content)?;
+            // legacy blocks interleave name hashes with the keys
+            if !manifest {
+                input.seek(SeekFrom::Current(8))?;
+            }
+            entries.entry(id).or_default().push(RootEntry { locale, content });
+        }
+
+        if manifest && content_flags & ROOT_NO_NAME_HASH == 0 {
+            input.seek(SeekFrom::Current(count as i64 * 8))?;
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tact_files() {
+        let versions = "Region!STRING:0|BuildConfig!HEX:16|BuildId!DEC:4\n## seqn = 1\nus|00112233445566778899aabbccddeeff|123\n";
+        let rows = parse_psv(versions);
+        assert_eq!(rows[0]["Region"], "us");
+        assert_eq!(parse_key(&rows[0]["BuildConfig"]).unwrap()[15], 0xFF);
+        assert!(parse_key("0011").is_err());
+        assert!(parse_key("aéééééééééééééééa").is_err());
+
+        let config = parse_config("# Build Configuration\n\nroot = aa\nencoding = bb cc\n");
+        assert_eq!(config["encoding"], "bb cc");
+
+        // two entries in the first block, footer count at len - 12
+        let mut index = vec![0u8; INDEX_BLOCK_SIZE + INDEX_FOOTER_SIZE];
+        index[0] = 1;
+        index[16..24].copy_from_slice(&[0, 0, 0, 10, 0, 0, 1, 0]);
+        index[24] = 2;
+        let footer = index.len() - 12;
+        index[footer..footer + 4].copy_from_slice(&2u32.to_le_bytes());
+        let mut entries = HashMap::new();
+        parse_archive_index(&index, 3, &mut entries).unwrap();
+        let mut key = [0u8; 16];
+        key[0] = 1;
+        assert_eq!(entries[&key], ArchiveEntry { archive: 3, offset: 256, size: 10 });
+        assert_eq!(entries.len(), 2);
+
+        // one MFST block of ids 5 and 7 without name hashes
+        let mut root = b"MFST".to_vec();
+        root.extend_from_slice(&[2, 0, 0, 0, 2, 0, 0, 0]);
+        root.extend_from_slice(&2u32.to_le_bytes());
+        root.extend_from_slice(&ROOT_NO_NAME_HASH.to_le_bytes());
+        root.extend_from_slice(&LOCALE_EN_US.to_le_bytes());
+        root.extend_from_slice(&5i32.to_le_bytes());
+        root.extend_from_slice(&1i32.to_le_bytes());
+        root.extend_from_slice(&[1u8; 16]);
+        root.extend_from_slice(&[2u8; 16]);
+        let root = parse_root(&root).unwrap();
+        assert_eq!(root[&7], vec![RootEntry { locale: LOCALE_EN_US, content: [2u8; 16] }]);
+        assert_eq!(root.keys().cloned().collect::<Vec<_>>(), vec![5, 7]);
+    }
+
+    #[test]
+    fn test_parse_tact_files_corrupt() {
+        // entries past the end of the index, and an index too short for its footer
+        let mut index = vec![0u8; INDEX_BLOCK_SIZE + INDEX_FOOTER_SIZE];
+        let footer = index.len() - 12;
+        index[footer..footer + 4].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(parse_archive_index(&index, 0, &mut HashMap::new()).is_err());
+        assert!(parse_archive_index(&index[..INDEX_FOOTER_SIZE - 1], 0, &mut HashMap::new()).is_err());
+
+        // a block claiming four billion ids, and one cut short in its keys
+        let mut root = b"MFST".to_vec();
+        root.extend_from_slice(&[2, 0, 0, 0, 2, 0, 0, 0]);
+        root.extend_from_slice(&u32::MAX.to_le_bytes());
+        root.extend_from_slice(&ROOT_NO_NAME_HASH.to_le_bytes());
+        root.extend_from_slice(&LOCALE_EN_US.to_le_bytes());
+        root.extend_from_slice(&5i32.to_le_bytes());
+        assert!(parse_root(&root).is_err());
+        root[12..16].copy_from_slice(&1u32.to_le_bytes());
+        root.extend_from_slice(&[1u8; 15]);
+        assert!(parse_root(&root).is_err());
+
+        assert!(parse_encoding(b"EN").is_err());
+        assert!(parse_encoding(b"XX\x01\x10\x10\0\x04\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn test_archive_entry_range() {
+        let entry = ArchiveEntry { archive: 0, offset: 0xFFFF_FFF0, size: 0x20 };
+        assert_eq!(entry.byte_range().unwrap(), (0xFFFF_FFF0, 0x1_0000_000F));
+        assert!(ArchiveEntry { size: 0, ..entry }.byte_range().is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::io;
+use reader::{ResourceReader, ResourceHandle, ResourceMetadata};
+
+// readers consulted in priority order, highest first: e.g. loose files, patch-3,
+// patch-2, patch, base. a reader failing with NotFound passes the request on to
+// the next one, other errors are returned as is
+pub struct ChainedReader(pub Vec<Box<dyn ResourceReader>>);
+
+impl ChainedReader {
+    fn first<T, F>(&self, what: &str, f: F) -> io::Result<T>
+    where
+        F: Fn(&dyn ResourceReader) -> io::Result<T>
+    {
+        for reader in self.0.iter() {
+            match f(&**reader) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                result => return result,
+            }
+        }
+
+        Err(io_error!(NotFound, "{} not found in any of {} readers", what, self.0.len()))
+    }
+}
+
+impl ResourceReader for ChainedReader {
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        for reader in self.0.iter() {
+            if reader.exists(name)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn exists_by_id(&self, file_data_id: u32) -> io::Result<bool> {
+        for reader in self.0.iter() {
+            if reader.exists_by_id(file_data_id)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+        self.first(name, |reader| reader.open(name))
+    }
+
+    fn metadata(&self, name: &str) -> io::Result<ResourceMetadata> {
+        self.first(name, |reader| reader.metadata(name))
+    }
+
+    fn open_by_id(&self, file_data_id: u32) -> io::Result<Box<dyn ResourceHandle>> {
+        self.first(&format!("file data id {}", file_data_id), |reader| reader.open_by_id(file_data_id))
+    }
+
+    // union over the readers which support listing, without duplicates
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut names = BTreeMap::new();
+        let mut supported = false;
+
+        for reader in self.0.iter() {
+            if let Ok(list) = reader.list(prefix) {
+                supported = true;
+                for name in list {
+                    names.entry(name.to_lowercase().replace('/', "\\")).or_insert(name);
+                }
+            }
+        }
+
+        if !supported {
+            return Err(io_error!(Other, "listing files is not supported by any of the readers"));
+        }
+        Ok(names.into_values().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    struct OneFile(&'static str, &'static [u8]);
+
+    impl ResourceReader for OneFile {
+        fn exists(&self, name: &str) -> io::Result<bool> {
+            Ok(name == self.0)
+        }
+
+        fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+            if name == self.0 {
+                Ok(Box::new(Cursor::new(self.1)))
+            } else {
+                Err(io_error!(NotFound, "{} not found", name))
+            }
+        }
+    }
+
+    #[test]
+    fn test_chained_reader() {
+        let reader = ChainedReader(vec![
+            Box::new(OneFile("a.blp", b"patch")),
+            Box::new(OneFile("b.blp", b"base")),
+            Box::new(OneFile("a.blp", b"base")),
+        ]);
+
+        let read = |name: &str| {
+            let mut data = String::new();
+            reader.open(name)?.read_to_string(&mut data)?;
+            Ok(data)
+        };
+
+        assert_eq!(read("a.blp").unwrap(), "patch");
+        assert_eq!(read("b.blp").unwrap(), "base");
+        assert_eq!(read("c.blp").map_err(|e: io::Error| e.kind()), Err(io::ErrorKind::NotFound));
+        assert!(reader.exists("b.blp").unwrap());
+        assert!(!reader.exists("c.blp").unwrap());
+        assert!(!reader.exists_by_id(1).unwrap());
+        assert_eq!(reader.metadata("a.blp").unwrap().size, 5);
+        assert_eq!(reader.open_by_id(1).err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+    }
+}
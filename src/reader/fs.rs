@@ -1,62 +1,188 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::io::{self, BufReader};
 use std::fs::{self, File};
-use reader::{ResourceReader, ResourceHandle};
+use std::sync::{Arc, RwLock};
+#[cfg(feature = "mmap")]
+use std::io::Cursor;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use reader::{ResourceReader, ResourceHandle, ResourceMetadata, has_prefix};
 
 #[allow(dead_code)]
 pub struct FsResourceReader {
-    path: PathBuf
+    path: PathBuf,
+    // open files as memory maps instead of buffered readers
+    mmap: bool,
+    // lowercase entry name -> path, per directory, filled as directories are visited
+    dirs: RwLock<HashMap<PathBuf, Arc<DirIndex>>>,
 }
 
+type DirIndex = HashMap<String, PathBuf>;
+
 impl FsResourceReader {
     #[allow(dead_code)]
     pub fn new(path: &Path) -> FsResourceReader {
-        FsResourceReader { path: path.to_owned() }
+        FsResourceReader { path: path.to_owned(), mmap: false, dirs: RwLock::new(HashMap::new()) }
+    }
+
+    // `open` hands out cursors over memory maps, saving a copy for large files
+    #[cfg(feature = "mmap")]
+    pub fn with_mmap(path: &Path) -> FsResourceReader {
+        FsResourceReader { path: path.to_owned(), mmap: true, dirs: RwLock::new(HashMap::new()) }
+    }
+
+    // the whole file as a byte slice which stays valid as long as the map lives
+    #[cfg(feature = "mmap")]
+    pub fn map(&self, name: &str) -> io::Result<Mmap> {
+        let file = File::open(self.find_resource_path(name)?)?;
+        // safe as long as nobody truncates game data while it's mapped
+        unsafe { Mmap::map(&file) }
+    }
+
+    // directories are indexed once; call this after files were added or removed
+    // on disk, otherwise they stay invisible (or dangling) to `open`
+    pub fn invalidate(&self) {
+        if let Ok(mut dirs) = self.dirs.write() {
+            dirs.clear();
+        }
+    }
+
+    fn find_resource_path(&self, target: &str) -> io::Result<PathBuf> {
+        let mut path = self.path.clone();
+
+        for component in target.split(['/', '\\']) {
+            path = self.dir_index(&path)?.get(&component.to_lowercase()).cloned()
+                .ok_or_else(|| io_error!(NotFound, "directory {:?} does not contain file {:?}", path, component))?;
+        }
+
+        Ok(path)
+    }
+
+    fn dir_index(&self, dir: &Path) -> io::Result<Arc<DirIndex>> {
+        if let Some(index) = self.dirs.read().ok().and_then(|dirs| dirs.get(dir).cloned()) {
+            return Ok(index);
+        }
+
+        let mut index = HashMap::new();
+        for entry_result in fs::read_dir(dir)? {
+            let entry = entry_result?;
+
+            let name_lowercase = entry.file_name()
+                .into_string()
+                .map(|s| s.to_lowercase())
+                .map_err(|_| io_error!(Other, "unable to decode OsString"))?;
+
+            index.insert(name_lowercase, entry.path());
+        }
+
+        let index = Arc::new(index);
+        if let Ok(mut dirs) = self.dirs.write() {
+            dirs.insert(dir.to_owned(), index.clone());
+        }
+        Ok(index)
     }
 }
 
 impl ResourceReader for FsResourceReader {
     fn exists(&self, name: &str) -> io::Result<bool> {
-        match find_resource_path(&self.path, name) {
+        match self.find_resource_path(name) {
             Ok(target) => Ok(target.exists()),
             Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
             Err(e) => Err(e),
         }
     }
 
-    fn open(&self, name: &str) -> io::Result<Box<ResourceHandle>> {
-        let target = find_resource_path(&self.path, name)?;
+    fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+        #[cfg(feature = "mmap")]
+        {
+            if self.mmap {
+                return Ok(Box::new(Cursor::new(self.map(name)?)));
+            }
+        }
+
+        let target = self.find_resource_path(name)?;
         let file = File::open(target)?;
         let reader = BufReader::new(file);
         Ok(Box::new(reader))
     }
-}
 
-fn find_resource_path(dir: &Path, target: &str) -> io::Result<PathBuf> {
-    let mut path = dir.to_owned();
-
-    for component in target.split(|c| c == '/' || c == '\\') {
-        path = find_dir_entry(&path, &component)?;
+    fn metadata(&self, name: &str) -> io::Result<ResourceMetadata> {
+        let metadata = fs::metadata(self.find_resource_path(name)?)?;
+        Ok(ResourceMetadata { size: metadata.len(), modified: metadata.modified().ok() })
     }
 
-    Ok(path)
+    // names use `\\` separators and the case found on disk
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        list_dir(&self.path, "", prefix, &mut names)?;
+        names.sort();
+        Ok(names)
+    }
 }
 
-fn find_dir_entry(dir: &Path, target: &str) -> io::Result<PathBuf> {
-    let target_lowercase = target.to_lowercase();
-
+// only descends into directories which can contain matches
+fn list_dir(dir: &Path, dir_name: &str, prefix: &str, names: &mut Vec<String>) -> io::Result<()> {
     for entry_result in fs::read_dir(dir)? {
         let entry = entry_result?;
 
-        let mut candidate_lowercase = entry.file_name()
+        let file_name = entry.file_name()
             .into_string()
-            .map(|s| s.to_lowercase())
             .map_err(|_| io_error!(Other, "unable to decode OsString"))?;
+        let name = format!("{}{}", dir_name, file_name);
 
-        if candidate_lowercase == target_lowercase {
-            return Ok(entry.path());
+        if entry.file_type()?.is_dir() {
+            let name = name + "\\";
+            if has_prefix(&name, prefix) || has_prefix(prefix, &name) {
+                list_dir(&entry.path(), &name, prefix, names)?;
+            }
+        } else if has_prefix(&name, prefix) {
+            names.push(name);
         }
     }
 
-    Err(io_error!(NotFound, "directory {:?} does not contain file {:?}", dir, target))
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Read;
+
+    #[test]
+    fn test_dir_index() {
+        let dir = env::temp_dir().join(format!("wow-fs-index-{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("DBFilesClient")).unwrap();
+        fs::write(dir.join("DBFilesClient").join("Map.dbc"), b"WDBC").unwrap();
+
+        let reader = FsResourceReader::new(&dir);
+        assert!(reader.exists("dbfilesclient\\MAP.DBC").unwrap());
+
+        // new files show up only once the cached index is dropped
+        fs::write(dir.join("DBFilesClient").join("AreaTable.dbc"), b"WDBC").unwrap();
+        assert!(!reader.exists("DBFilesClient\\AreaTable.dbc").unwrap());
+        reader.invalidate();
+        let mut data = Vec::new();
+        reader.open("DBFilesClient/areatable.dbc").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"WDBC");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap() {
+        let dir = env::temp_dir().join(format!("wow-fs-mmap-{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("World")).unwrap();
+        fs::write(dir.join("World").join("Test.wdt"), b"MVER").unwrap();
+
+        let reader = FsResourceReader::with_mmap(&dir);
+        let mut data = Vec::new();
+        reader.open("world\\test.wdt").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"MVER");
+        assert_eq!(&reader.map("World/Test.wdt").unwrap()[..], b"MVER");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "fs")]
+use std::fs::File;
+use std::io::{self, BufRead, SeekFrom};
+#[cfg(feature = "fs")]
+use std::io::BufReader;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use reader::{ResourceReader, ResourceHandle, ResourceMetadata, has_prefix};
+
+// the community listfile: one `id;path` line per file
+#[derive(Clone, Debug, Default)]
+pub struct Listfile {
+    paths: BTreeMap<u32, String>,
+    // normalized path -> id
+    ids: HashMap<String, u32>,
+}
+
+// makes name-based loaders work on a backend keyed by FileDataID (CASC), and
+// id-based ones on a backend keyed by name (MPQ, loose files)
+pub struct ListfileReader<R> {
+    pub inner: R,
+    pub listfile: Listfile,
+}
+
+impl Listfile {
+    #[cfg(feature = "fs")]
+    pub fn load(path: &Path) -> io::Result<Listfile> {
+        Listfile::parse(BufReader::new(File::open(path)?))
+    }
+
+    // empty lines are skipped, malformed ones are an error
+    pub fn parse<R: BufRead>(input: R) -> io::Result<Listfile> {
+        let mut listfile = Listfile::default();
+
+        for (number, line) in input.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() { continue }
+
+            let mut parts = line.splitn(2, ';');
+            let id = parts.next().and_then(|id| id.parse().ok());
+            match (id, parts.next()) {
+                (Some(id), Some(path)) if !path.is_empty() => listfile.insert(id, path),
+                _ => return Err(io_error!(InvalidData, "listfile line {}: expected `id;path`, got {:?}", number + 1, line)),
+            }
+        }
+
+        Ok(listfile)
+    }
+
+    pub fn insert(&mut self, file_data_id: u32, path: &str) {
+        self.ids.insert(normalize(path), file_data_id);
+        self.paths.insert(file_data_id, path.to_owned());
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    // case and separators don't matter
+    pub fn id(&self, path: &str) -> Option<u32> {
+        self.ids.get(&normalize(path)).cloned()
+    }
+
+    pub fn path(&self, file_data_id: u32) -> Option<&str> {
+        self.paths.get(&file_data_id).map(|path| path.as_str())
+    }
+}
+
+fn normalize(path: &str) -> String {
+    path.to_lowercase().replace('\\', "/")
+}
+
+impl<R: ResourceReader> ListfileReader<R> {
+    pub fn new(inner: R, listfile: Listfile) -> Self {
+        ListfileReader { inner, listfile }
+    }
+}
+
+impl<R: ResourceReader> ResourceReader for ListfileReader<R> {
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        if self.inner.exists(name)? {
+            return Ok(true);
+        }
+        match self.listfile.id(name) {
+            Some(id) => self.inner.exists_by_id(id),
+            None => Ok(false),
+        }
+    }
+
+    fn exists_by_id(&self, file_data_id: u32) -> io::Result<bool> {
+        if self.inner.exists_by_id(file_data_id)? {
+            return Ok(true);
+        }
+        match self.listfile.path(file_data_id) {
+            Some(path) => self.inner.exists(path),
+            None => Ok(false),
+        }
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+        match self.listfile.id(name) {
+            Some(id) => match self.inner.open_by_id(id) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => self.inner.open(name),
+                result => result,
+            },
+            None => self.inner.open(name),
+        }
+    }
+
+    // the inner reader knows the size by name, or the file is opened by id
+    fn metadata(&self, name: &str) -> io::Result<ResourceMetadata> {
+        match self.inner.metadata(name) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                let size = self.open(name)?.seek(SeekFrom::End(0))?;
+                Ok(ResourceMetadata { size, modified: None })
+            }
+            result => result,
+        }
+    }
+
+    fn open_by_id(&self, file_data_id: u32) -> io::Result<Box<dyn ResourceHandle>> {
+        match self.inner.open_by_id(file_data_id) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => match self.listfile.path(file_data_id) {
+                Some(path) => self.inner.open(path),
+                None => Err(io_error!(NotFound, "file data id {} is neither known to the reader nor listed", file_data_id)),
+            },
+            result => result,
+        }
+    }
+
+    // listed paths, plus whatever the inner reader lists itself
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut names: Vec<String> = self.listfile.paths.values()
+            .filter(|path| has_prefix(path, prefix))
+            .cloned()
+            .collect();
+
+        if let Ok(list) = self.inner.list(prefix) {
+            names.extend(list.into_iter().filter(|name| self.listfile.id(name).is_none()));
+        }
+
+        names.sort();
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    // knows files by name only
+    struct NameReader;
+
+    impl ResourceReader for NameReader {
+        fn exists(&self, name: &str) -> io::Result<bool> {
+            Ok(name == "world/wmo/a.wmo")
+        }
+
+        fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+            if self.exists(name)? {
+                Ok(Box::new(Cursor::new(name.as_bytes().to_vec())))
+            } else {
+                Err(io_error!(NotFound, "{} not found", name))
+            }
+        }
+    }
+
+    #[test]
+    fn test_listfile() {
+        let listfile = Listfile::parse(Cursor::new("107;world/wmo/a.wmo\n\n108;World/WMO/a_000.wmo\n")).unwrap();
+        assert_eq!(listfile.len(), 2);
+        assert_eq!(listfile.id("World\\WMO\\A_000.wmo"), Some(108));
+        assert_eq!(listfile.path(107), Some("world/wmo/a.wmo"));
+        assert_eq!(listfile.path(109), None);
+        assert!(Listfile::parse(Cursor::new("a.wmo\n")).is_err());
+
+        let reader = ListfileReader::new(NameReader, listfile);
+        let mut data = String::new();
+        reader.open_by_id(107).unwrap().read_to_string(&mut data).unwrap();
+        assert_eq!(data, "world/wmo/a.wmo");
+        assert!(reader.exists_by_id(107).unwrap());
+        assert!(!reader.exists_by_id(108).unwrap());
+        assert_eq!(reader.open_by_id(109).err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+        assert_eq!(reader.list("World\\WMO\\A_").unwrap(), vec!["World/WMO/a_000.wmo".to_string()]);
+    }
+}
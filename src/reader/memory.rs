@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+use std::sync::Arc;
+use reader::{ResourceReader, ResourceHandle, ResourceMetadata, has_prefix};
+
+// files held in memory, e.g. fetched by a browser or bundled with a tool. names
+// match ignoring case and separator style; data is shared by all open handles
+#[derive(Clone, Default)]
+pub struct MemoryReader {
+    // keyed by normalized name, with the name as inserted for `list`
+    files: HashMap<String, (String, Arc<[u8]>)>,
+    ids: HashMap<u32, Arc<[u8]>>,
+}
+
+impl MemoryReader {
+    pub fn new() -> MemoryReader {
+        MemoryReader::default()
+    }
+
+    // replaces any file of the same name
+    pub fn insert(&mut self, name: &str, data: Vec<u8>) {
+        self.files.insert(normalize(name), (name.to_owned(), data.into()));
+    }
+
+    pub fn insert_id(&mut self, file_data_id: u32, data: Vec<u8>) {
+        self.ids.insert(file_data_id, data.into());
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.files.remove(&normalize(name)).is_some()
+    }
+
+    // files inserted by name
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    fn get(&self, name: &str) -> io::Result<&Arc<[u8]>> {
+        self.files.get(&normalize(name))
+            .map(|(_, data)| data)
+            .ok_or_else(|| io_error!(NotFound, "{} not found in memory", name))
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.to_lowercase().replace('/', "\\")
+}
+
+impl ResourceReader for MemoryReader {
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        Ok(self.files.contains_key(&normalize(name)))
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+        Ok(Box::new(Cursor::new(self.get(name)?.clone())))
+    }
+
+    fn metadata(&self, name: &str) -> io::Result<ResourceMetadata> {
+        Ok(ResourceMetadata { size: self.get(name)?.len() as u64, modified: None })
+    }
+
+    fn exists_by_id(&self, file_data_id: u32) -> io::Result<bool> {
+        Ok(self.ids.contains_key(&file_data_id))
+    }
+
+    fn open_by_id(&self, file_data_id: u32) -> io::Result<Box<dyn ResourceHandle>> {
+        match self.ids.get(&file_data_id) {
+            Some(data) => Ok(Box::new(Cursor::new(data.clone()))),
+            None => Err(io_error!(NotFound, "file data id {} not found in memory", file_data_id)),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut names: Vec<String> = self.files.values()
+            .map(|(name, _)| name.clone())
+            .filter(|name| has_prefix(name, prefix))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_memory_reader() {
+        let mut reader = MemoryReader::new();
+        reader.insert("Textures\\A.blp", b"a".to_vec());
+        reader.insert("World/B.wmo", b"bb".to_vec());
+        reader.insert_id(10, b"c".to_vec());
+
+        let mut data = Vec::new();
+        reader.open("textures/a.BLP").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"a");
+        assert_eq!(reader.metadata("world\\b.wmo").unwrap().size, 2);
+        assert_eq!(reader.open("C.m2").err().unwrap().kind(), io::ErrorKind::NotFound);
+        assert!(reader.exists_by_id(10).unwrap());
+        assert_eq!(reader.list("world\\").unwrap(), vec!["World/B.wmo"]);
+    }
+}
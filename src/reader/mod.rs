@@ -1,11 +1,79 @@
-use std::io::{self, Read, Seek};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::SystemTime;
 
-pub trait ResourceHandle : Read + Seek {}
-impl<R: Read + Seek> ResourceHandle for R {}
+// handles and readers are shared across loader threads
+pub trait ResourceHandle : Read + Seek + Send {}
+impl<R: Read + Seek + Send> ResourceHandle for R {}
 
-pub trait ResourceReader {
-    fn exists(&self, name: &str) -> io::Result<bool>;
-    fn open(&self, name: &str) -> io::Result<Box<ResourceHandle>>;
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceMetadata {
+    pub size: u64,
+    // only where the backend tracks it
+    pub modified: Option<SystemTime>,
+}
+
+pub trait ResourceReader : Send + Sync {
+    fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>>;
+
+    // answered by opening the file; backends with a cheaper lookup override this
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        Ok(self.open_if_exists(name)?.is_some())
+    }
+
+    // None instead of a NotFound error, for probing optional files
+    fn open_if_exists(&self, name: &str) -> io::Result<Option<Box<dyn ResourceHandle>>> {
+        match self.open(name) {
+            Ok(handle) => Ok(Some(handle)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // falls back to opening the file; backends with a file table override this
+    fn metadata(&self, name: &str) -> io::Result<ResourceMetadata> {
+        let size = self.open(name)?.seek(SeekFrom::End(0))?;
+        Ok(ResourceMetadata { size, modified: None })
+    }
+
+    // readers which know FileDataIDs (legion+ data) override these
+    fn exists_by_id(&self, _file_data_id: u32) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    fn open_by_id(&self, file_data_id: u32) -> io::Result<Box<dyn ResourceHandle>> {
+        Err(io_error!(NotFound, "can't open file data id {}: not supported by this reader", file_data_id))
+    }
+
+    // names of all files starting with `prefix` (ignoring case and separator style),
+    // e.g. "World\\Minimaps\\"
+    fn list(&self, _prefix: &str) -> io::Result<Vec<String>> {
+        Err(io_error!(Other, "listing files is not supported by this reader"))
+    }
+}
+
+// case- and separator-insensitive `name.starts_with(prefix)`
+pub fn has_prefix(name: &str, prefix: &str) -> bool {
+    let mut name = name.chars();
+    prefix.chars().all(|p| match name.next() {
+        Some(c) => c == p || is_separator(c) && is_separator(p) || c.to_lowercase().eq(p.to_lowercase()),
+        None => false,
+    })
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+// opens by FileDataID if there is one (0 means none), falling back to `name`
+// when the reader doesn't know the id
+pub fn open_preferring_id(reader: &dyn ResourceReader, file_data_id: Option<u32>, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+    match file_data_id {
+        Some(id) if id != 0 => match reader.open_by_id(id) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => reader.open(name),
+            result => result,
+        },
+        _ => reader.open(name),
+    }
 }
 
 pub fn split_resource_name(input: &str) -> (&str, &str, &str) {
@@ -34,11 +102,114 @@ pub fn split_resource_name(input: &str) -> (&str, &str, &str) {
     (dir, file, ext)
 }
 
+#[cfg(feature = "fs")]
 pub mod fs;
+pub mod memory;
+pub mod chained;
+pub mod listfile;
+#[cfg(feature = "fs")]
+pub mod overlay;
+#[cfg(feature = "zip")]
+pub mod archive;
+#[cfg(feature = "cdn")]
+pub mod cdn;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Cursor;
+
+    struct IdReader;
+
+    impl ResourceReader for IdReader {
+        fn exists(&self, _name: &str) -> io::Result<bool> {
+            Ok(true)
+        }
+
+        fn open(&self, _name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+            Ok(Box::new(Cursor::new(b"by name".to_vec())))
+        }
+
+        fn open_by_id(&self, file_data_id: u32) -> io::Result<Box<dyn ResourceHandle>> {
+            match file_data_id {
+                1 => Ok(Box::new(Cursor::new(b"by id".to_vec()))),
+                2 => Err(io_error!(PermissionDenied, "locked")),
+                _ => Err(io_error!(NotFound, "unknown id")),
+            }
+        }
+    }
+
+    // only knows how to open, existence comes from the defaults
+    struct NameReader;
+
+    impl ResourceReader for NameReader {
+        fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+            match name {
+                "a.skin" => Ok(Box::new(Cursor::new(Vec::new()))),
+                "locked.skin" => Err(io_error!(PermissionDenied, "locked")),
+                _ => Err(io_error!(NotFound, "{} not found", name)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_open_if_exists() {
+        assert!(NameReader.exists("a.skin").unwrap());
+        assert!(!NameReader.exists("b.skin").unwrap());
+        assert!(NameReader.open_if_exists("a.skin").unwrap().is_some());
+        assert!(NameReader.open_if_exists("b.skin").unwrap().is_none());
+        assert!(NameReader.open_if_exists("locked.skin").is_err());
+    }
+
+    #[test]
+    fn test_has_prefix() {
+        assert!(has_prefix("World\\Minimaps\\Azeroth\\map32_48.blp", "world/minimaps/"));
+        assert!(has_prefix("World\\Minimaps", ""));
+        assert!(!has_prefix("World\\Minimaps", "World\\Minimaps\\"));
+        assert!(!has_prefix("World\\Maps\\a.wdt", "World\\Minimaps"));
+    }
+
+    #[test]
+    fn test_metadata() {
+        assert_eq!(IdReader.metadata("a.skin").unwrap(), ResourceMetadata { size: 7, modified: None });
+    }
+
+    #[test]
+    fn test_open_preferring_id() {
+        let read = |id| -> io::Result<String> {
+            let mut data = String::new();
+            open_preferring_id(&IdReader, id, "a.skin")?.read_to_string(&mut data)?;
+            Ok(data)
+        };
+
+        assert_eq!(read(Some(1)).unwrap(), "by id");
+        assert_eq!(read(Some(3)).unwrap(), "by name");
+        assert_eq!(read(Some(0)).unwrap(), "by name");
+        assert_eq!(read(None).unwrap(), "by name");
+        assert_eq!(read(Some(2)).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    #[cfg(feature = "threads")]
+    fn test_shared_reader() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let reader: Arc<dyn ResourceReader> = Arc::new(IdReader);
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let reader = reader.clone();
+                thread::spawn(move || reader.open_by_id(1))
+            })
+            .collect();
+
+        for handle in handles {
+            let mut input = handle.join().unwrap().unwrap();
+            let mut data = String::new();
+            input.read_to_string(&mut data).unwrap();
+            assert_eq!(data, "by id");
+        }
+    }
 
     #[test]
     fn test_split_resource_name() {
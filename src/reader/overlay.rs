@@ -0,0 +1,99 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use reader::{ResourceReader, ResourceHandle, ResourceMetadata};
+use reader::chained::ChainedReader;
+use reader::fs::FsResourceReader;
+
+// loose files from local folders (first one wins) served before the base
+// reader, the usual way to test modified assets without repacking archives
+pub struct OverlayReader {
+    patches: Vec<PathBuf>,
+    // one fs reader per patch folder, then the base reader
+    chain: ChainedReader,
+}
+
+impl OverlayReader {
+    pub fn new(patches: Vec<PathBuf>, base: Box<dyn ResourceReader>) -> OverlayReader {
+        let mut readers: Vec<Box<dyn ResourceReader>> = patches.iter()
+            .map(|path| Box::new(FsResourceReader::new(path)) as Box<dyn ResourceReader>)
+            .collect();
+        readers.push(base);
+
+        OverlayReader { patches, chain: ChainedReader(readers) }
+    }
+
+    pub fn patches(&self) -> &[PathBuf] {
+        &self.patches
+    }
+
+    // the folder a file is taken from, or None if it comes from the base reader
+    pub fn patch_of(&self, name: &str) -> io::Result<Option<&Path>> {
+        for (path, reader) in self.patches.iter().zip(self.chain.0.iter()) {
+            if reader.exists(name)? {
+                return Ok(Some(path));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl ResourceReader for OverlayReader {
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        self.chain.exists(name)
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn ResourceHandle>> {
+        self.chain.open(name)
+    }
+
+    fn metadata(&self, name: &str) -> io::Result<ResourceMetadata> {
+        self.chain.metadata(name)
+    }
+
+    // loose files have no ids, so these always go to the base reader
+    fn exists_by_id(&self, file_data_id: u32) -> io::Result<bool> {
+        self.chain.exists_by_id(file_data_id)
+    }
+
+    fn open_by_id(&self, file_data_id: u32) -> io::Result<Box<dyn ResourceHandle>> {
+        self.chain.open_by_id(file_data_id)
+    }
+
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        self.chain.list(prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Read;
+
+    #[test]
+    fn test_overlay_reader() {
+        let dir = env::temp_dir().join(format!("wow-overlay-{}", ::std::process::id()));
+        let (patch, base) = (dir.join("patch"), dir.join("base"));
+        fs::create_dir_all(patch.join("Textures")).unwrap();
+        fs::create_dir_all(base.join("Textures")).unwrap();
+        fs::write(patch.join("Textures").join("A.blp"), b"mod").unwrap();
+        fs::write(base.join("Textures").join("A.blp"), b"original").unwrap();
+        fs::write(base.join("Textures").join("B.blp"), b"original").unwrap();
+
+        let reader = OverlayReader::new(vec![patch.clone()], Box::new(FsResourceReader::new(&base)));
+        let read = |name: &str| {
+            let mut data = String::new();
+            reader.open(name).unwrap().read_to_string(&mut data).unwrap();
+            data
+        };
+
+        assert_eq!(read("Textures\\A.blp"), "mod");
+        assert_eq!(read("Textures\\B.blp"), "original");
+        assert_eq!(reader.patch_of("textures\\a.blp").unwrap(), Some(patch.as_path()));
+        assert_eq!(reader.patch_of("Textures\\B.blp").unwrap(), None);
+        assert_eq!(reader.list("Textures\\").unwrap().len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
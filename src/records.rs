@@ -0,0 +1,208 @@
+use std::io;
+use std::mem::size_of;
+use byteorder::LE;
+use bytemuck::{self, Pod, Zeroable};
+use bytes::{self, Bytes};
+
+// fixed-size little-endian record as stored in chunk data
+pub trait Record: Pod {
+    // fallback for big-endian targets, where the bytes can't be reinterpreted in place
+    fn read_le(reader: &mut Bytes) -> bytes::Result<Self>;
+}
+
+// converts the whole chunk into records at once; trailing bytes which
+// don't form a complete record are ignored, like the per-element loops do
+pub fn read_records<T: Record>(data: &[u8]) -> io::Result<Vec<T>> {
+    let count = data.len() / size_of::<T>();
+    let data = &data[..count * size_of::<T>()];
+
+    if cfg!(target_endian = "little") {
+        // chunk buffers have no alignment guarantees, so copy instead of casting in place
+        let mut result = vec![T::zeroed(); count];
+        bytemuck::cast_slice_mut::<T, u8>(&mut result).copy_from_slice(data);
+        Ok(result)
+    } else {
+        let mut reader = Bytes::new(data);
+        (0..count).map(|_| T::read_le(&mut reader).map_err(io::Error::from)).collect()
+    }
+}
+
+impl Record for u16 {
+    fn read_le(reader: &mut Bytes) -> bytes::Result<Self> {
+        reader.u16::<LE>()
+    }
+}
+
+impl Record for [u8; 4] {
+    fn read_le(reader: &mut Bytes) -> bytes::Result<Self> {
+        reader.u8x4()
+    }
+}
+
+impl Record for u32 {
+    fn read_le(reader: &mut Bytes) -> bytes::Result<Self> {
+        reader.u32::<LE>()
+    }
+}
+
+impl Record for [f32; 2] {
+    fn read_le(reader: &mut Bytes) -> bytes::Result<Self> {
+        reader.f32x2::<LE>()
+    }
+}
+
+impl Record for [f32; 3] {
+    fn read_le(reader: &mut Bytes) -> bytes::Result<Self> {
+        reader.f32x3::<LE>()
+    }
+}
+
+// MDDF entry
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct M2PlacementRecord {
+    pub name_id: u32,
+    pub unique_id: u32,
+    pub position: [f32; 3],
+    pub rotation: [f32; 3],
+    pub scale: u16,
+    pub flags: u16,
+}
+
+impl Record for M2PlacementRecord {
+    fn read_le(reader: &mut Bytes) -> bytes::Result<Self> {
+        Ok(M2PlacementRecord {
+            name_id: reader.u32::<LE>()?,
+            unique_id: reader.u32::<LE>()?,
+            position: <[f32; 3]>::read_le(reader)?,
+            rotation: <[f32; 3]>::read_le(reader)?,
+            scale: reader.u16::<LE>()?,
+            flags: reader.u16::<LE>()?,
+        })
+    }
+}
+
+// M2 vertex
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct M2VertexRecord {
+    pub position: [f32; 3],
+    pub bone_weights: [u8; 4],
+    pub bone_indices: [u8; 4],
+    pub normal: [f32; 3],
+    pub tex_coords: [[f32; 2]; 2],
+}
+
+impl Record for M2VertexRecord {
+    fn read_le(reader: &mut Bytes) -> bytes::Result<Self> {
+        Ok(M2VertexRecord {
+            position: <[f32; 3]>::read_le(reader)?,
+            bone_weights: reader.u8x4()?,
+            bone_indices: reader.u8x4()?,
+            normal: <[f32; 3]>::read_le(reader)?,
+            tex_coords: [<[f32; 2]>::read_le(reader)?, <[f32; 2]>::read_le(reader)?],
+        })
+    }
+}
+
+// M2 skin section (submesh), wotlk layout
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct M2SkinSectionRecord {
+    pub skin_section_id: u16,
+    pub level: u16,
+    pub vertex_start: u16,
+    pub vertex_count: u16,
+    pub index_start: u16,
+    pub index_count: u16,
+    pub bone_count: u16,
+    pub bone_combo_index: u16,
+    pub bone_influences: u16,
+    pub center_bone_index: u16,
+    pub center_position: [f32; 3],
+    pub sort_center_position: [f32; 3],
+    pub sort_radius: f32,
+}
+
+impl Record for M2SkinSectionRecord {
+    fn read_le(reader: &mut Bytes) -> bytes::Result<Self> {
+        Ok(M2SkinSectionRecord {
+            skin_section_id: reader.u16::<LE>()?,
+            level: reader.u16::<LE>()?,
+            vertex_start: reader.u16::<LE>()?,
+            vertex_count: reader.u16::<LE>()?,
+            index_start: reader.u16::<LE>()?,
+            index_count: reader.u16::<LE>()?,
+            bone_count: reader.u16::<LE>()?,
+            bone_combo_index: reader.u16::<LE>()?,
+            bone_influences: reader.u16::<LE>()?,
+            center_bone_index: reader.u16::<LE>()?,
+            center_position: <[f32; 3]>::read_le(reader)?,
+            sort_center_position: <[f32; 3]>::read_le(reader)?,
+            sort_radius: reader.f32::<LE>()?,
+        })
+    }
+}
+
+// M2 batch (texture unit)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct M2BatchRecord {
+    pub flags: u8,
+    pub priority_plane: i8,
+    pub shader_id: u16,
+    pub skin_section_index: u16,
+    pub geoset_index: u16,
+    pub color_index: u16,
+    pub material_index: u16,
+    pub material_layer: u16,
+    pub texture_count: u16,
+    pub texture_combo_index: u16,
+    pub texture_coord_combo_index: u16,
+    pub texture_weight_combo_index: u16,
+    pub texture_transform_combo_index: u16,
+}
+
+impl Record for M2BatchRecord {
+    fn read_le(reader: &mut Bytes) -> bytes::Result<Self> {
+        Ok(M2BatchRecord {
+            flags: reader.u8()?,
+            priority_plane: reader.i8()?,
+            shader_id: reader.u16::<LE>()?,
+            skin_section_index: reader.u16::<LE>()?,
+            geoset_index: reader.u16::<LE>()?,
+            color_index: reader.u16::<LE>()?,
+            material_index: reader.u16::<LE>()?,
+            material_layer: reader.u16::<LE>()?,
+            texture_count: reader.u16::<LE>()?,
+            texture_combo_index: reader.u16::<LE>()?,
+            texture_coord_combo_index: reader.u16::<LE>()?,
+            texture_weight_combo_index: reader.u16::<LE>()?,
+            texture_transform_combo_index: reader.u16::<LE>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_records() {
+        let data = [1, 0, 2, 0, 0xFF, 0xFF, 9];
+        assert_eq!(read_records::<u16>(&data).unwrap(), vec![1, 2, 0xFFFF]);
+
+        let mut data = [0u8; 37];
+        data[0] = 7;
+        data[8..12].copy_from_slice(&1.5f32.to_le_bytes());
+        data[32..34].copy_from_slice(&1024u16.to_le_bytes());
+
+        let records = read_records::<M2PlacementRecord>(&data[1..]).unwrap();
+        assert_eq!(records.len(), 1);
+
+        let records = read_records::<M2PlacementRecord>(&data[..36]).unwrap();
+        assert_eq!(records[0].name_id, 7);
+        assert_eq!(records[0].position, [1.5, 0.0, 0.0]);
+        assert_eq!(records[0].scale, 1024);
+    }
+}
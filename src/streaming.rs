@@ -0,0 +1,286 @@
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender, Sender, TrySendError, TryRecvError};
+use std::thread::{self, JoinHandle};
+use adt::MapTile;
+use asset::{Asset, LoadOptions};
+use reader::ResourceReader;
+
+pub type TileCoords = (u32, u32);
+
+// anything able to produce a parsed tile; implement this to plug in readers
+// which aren't a plain ResourceReader (e.g. ones driven by an async runtime)
+pub trait TileSource: Send + Sync {
+    fn load_tile(&self, coords: TileCoords) -> io::Result<MapTile>;
+}
+
+pub struct ReaderTileSource<R> {
+    reader: Arc<R>,
+    map_name: String,
+    options: LoadOptions,
+}
+
+impl<R: ResourceReader + Send + Sync + 'static> ReaderTileSource<R> {
+    // `options` apply to every tile, on whichever worker thread loads it
+    pub fn new(reader: Arc<R>, map_name: &str, options: LoadOptions) -> Self {
+        ReaderTileSource { reader, map_name: map_name.to_owned(), options }
+    }
+
+    pub fn tile_name(&self, coords: TileCoords) -> String {
+        format!("World\\Maps\\{0}\\{0}_{1}_{2}.adt", &self.map_name, coords.0, coords.1)
+    }
+}
+
+impl<R: ResourceReader + Send + Sync + 'static> TileSource for ReaderTileSource<R> {
+    fn load_tile(&self, coords: TileCoords) -> io::Result<MapTile> {
+        Ok(<MapTile as Asset>::load(&*self.reader, &self.tile_name(coords), &self.options)?)
+    }
+}
+
+pub struct LoadedTile {
+    pub coords: TileCoords,
+    pub result: io::Result<MapTile>,
+}
+
+pub struct TileLoader {
+    jobs: Option<SyncSender<TileCoords>>,
+    results: Receiver<LoadedTile>,
+    backlog: VecDeque<TileCoords>,
+    pending: HashSet<TileCoords>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl TileLoader {
+    pub fn new<S: TileSource + 'static>(source: S, worker_count: usize, queue_size: usize) -> Self {
+        let source = Arc::new(source);
+        let (jobs_sender, jobs_receiver) = mpsc::sync_channel(queue_size.max(1));
+        let (results_sender, results_receiver) = mpsc::channel();
+        let jobs_receiver = Arc::new(Mutex::new(jobs_receiver));
+
+        let workers = (0..worker_count.max(1)).map(|_| {
+            let source = source.clone();
+            let jobs = jobs_receiver.clone();
+            let results: Sender<LoadedTile> = results_sender.clone();
+
+            thread::spawn(move || loop {
+                let next = match jobs.lock() {
+                    Ok(jobs) => jobs.recv(),
+                    Err(_) => break,
+                };
+
+                let coords = match next {
+                    Ok(coords) => coords,
+                    Err(_) => break, // loader was dropped
+                };
+
+                trace!("loading tile {:?}", coords);
+                let result = source.load_tile(coords);
+
+                if results.send(LoadedTile { coords, result }).is_err() {
+                    break;
+                }
+            })
+        }).collect();
+
+        TileLoader {
+            jobs: Some(jobs_sender),
+            results: results_receiver,
+            backlog: VecDeque::new(),
+            pending: HashSet::new(),
+            workers,
+        }
+    }
+
+    pub fn from_reader<R: ResourceReader + Send + Sync + 'static>(reader: Arc<R>, map_name: &str, options: LoadOptions, worker_count: usize) -> Self {
+        let source = ReaderTileSource::new(reader, map_name, options);
+        TileLoader::new(source, worker_count, worker_count * 2)
+    }
+
+    // returns false if the tile is already being loaded
+    pub fn request(&mut self, coords: TileCoords) -> bool {
+        if !self.pending.insert(coords) {
+            return false;
+        }
+        self.backlog.push_back(coords);
+        self.flush_backlog();
+        true
+    }
+
+    // requests every tile within `radius` tiles of `center`, nearest rings first
+    pub fn prefetch_around(&mut self, center: TileCoords, radius: u32) {
+        for distance in 0..(radius + 1) {
+            for coords in ring(center, distance) {
+                self.request(coords);
+            }
+        }
+    }
+
+    pub fn is_pending(&self, coords: TileCoords) -> bool {
+        self.pending.contains(&coords)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    // non-blocking; meant to be called once per frame
+    pub fn try_recv(&mut self) -> Option<LoadedTile> {
+        self.flush_backlog();
+
+        match self.results.try_recv() {
+            Ok(tile) => Some(self.complete(tile)),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+
+    // blocks until the next tile is loaded; returns None when nothing is pending
+    pub fn recv(&mut self) -> Option<LoadedTile> {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        // whatever stays in the backlog is waiting for a full job queue, so at
+        // least one job is in flight and blocking on its result is safe
+        self.flush_backlog();
+        self.results.recv().ok().map(|tile| self.complete(tile))
+    }
+
+    fn complete(&mut self, tile: LoadedTile) -> LoadedTile {
+        self.pending.remove(&tile.coords);
+        tile
+    }
+
+    fn flush_backlog(&mut self) {
+        let jobs = match self.jobs {
+            Some(ref jobs) => jobs,
+            None => return,
+        };
+
+        while let Some(coords) = self.backlog.pop_front() {
+            match jobs.try_send(coords) {
+                Ok(()) => {}
+                Err(TrySendError::Full(coords)) => {
+                    self.backlog.push_front(coords);
+                    break;
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    error!("tile loader workers are gone");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for TileLoader {
+    fn drop(&mut self) {
+        // closing the job channel stops the workers once they finish current jobs
+        self.jobs = None;
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+// tiles at exactly `distance` (chebyshev) from `center`, clipped to the 64x64 map grid
+pub fn ring(center: TileCoords, distance: u32) -> Vec<TileCoords> {
+    let distance = distance as i64;
+    let (cx, cy) = (center.0 as i64, center.1 as i64);
+    let mut result = Vec::new();
+
+    for y in (cy - distance)..(cy + distance + 1) {
+        for x in (cx - distance)..(cx + distance + 1) {
+            let on_edge = (x - cx).abs() == distance || (y - cy).abs() == distance;
+            let in_map = x >= 0 && y >= 0 && x < 64 && y < 64;
+
+            if on_edge && in_map {
+                result.push((x as u32, y as u32));
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunked::Token;
+    use diagnostics::Diagnostics;
+    use error::Error;
+    use limits::Limits;
+    use test_util::{chunk, memory_reader};
+
+    struct MissingTiles;
+
+    impl TileSource for MissingTiles {
+        fn load_tile(&self, coords: TileCoords) -> io::Result<MapTile> {
+            Err(io_error!(NotFound, "tile {:?} is missing", coords))
+        }
+    }
+
+    #[test]
+    fn test_ring() {
+        assert_eq!(ring((10, 10), 0), vec![(10, 10)]);
+        assert_eq!(ring((10, 10), 1).len(), 8);
+        assert_eq!(ring((10, 10), 2).len(), 16);
+        assert_eq!(ring((0, 0), 1), vec![(1, 0), (0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn test_tile_loader() {
+        let mut loader = TileLoader::new(MissingTiles, 2, 1);
+        loader.prefetch_around((5, 5), 1);
+        assert!(!loader.request((5, 5)));
+        assert_eq!(loader.pending_count(), 9);
+
+        let mut loaded = HashSet::new();
+        while let Some(tile) = loader.recv() {
+            assert!(tile.result.is_err());
+            loaded.insert(tile.coords);
+        }
+
+        assert_eq!(loaded.len(), 9);
+        assert_eq!(loader.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_options_reach_workers() {
+        let mut tile = chunk(b"MVER", &18u32.to_le_bytes());
+        tile.extend(chunk(b"MTEX", b"Tileset\\A.blp\0"));
+        let reader = memory_reader(vec![("World\\Maps\\Test\\Test_1_2.adt", tile)]);
+
+        let mut loader = TileLoader::from_reader(reader.clone(), "Test", LoadOptions::default(), 1);
+        loader.request((1, 2));
+        assert!(loader.recv().unwrap().result.is_ok());
+
+        let limits = Limits { max_string_length: 8, ..Limits::DEFAULT };
+        let mut loader = TileLoader::from_reader(reader, "Test", LoadOptions { limits, ..LoadOptions::default() }, 1);
+        loader.request((1, 2));
+        match loader.recv().unwrap().result.map_err(Error::from) {
+            Err(Error::LimitExceeded { what: "string", size: 13, limit: 8 }) => {}
+            other => panic!("unexpected {:?}", other.map(|tile| tile.chunks.len())),
+        }
+    }
+
+    #[test]
+    fn test_diagnostics_from_workers() {
+        // the second model id doesn't point at a name
+        let mut tile = chunk(b"MVER", &18u32.to_le_bytes());
+        tile.extend(chunk(b"MMDX", b"A.m2\0"));
+        tile.extend(chunk(b"MMID", &[0, 0, 0, 0, 9, 0, 0, 0]));
+        let name = "World\\Maps\\Test\\Test_1_2.adt";
+
+        let diagnostics = Diagnostics::new();
+        let options = LoadOptions { diagnostics: Some(diagnostics.clone()), ..LoadOptions::default() };
+        let mut loader = TileLoader::from_reader(memory_reader(vec![(name, tile)]), "Test", options, 2);
+        loader.request((1, 2));
+        assert!(loader.recv().unwrap().result.is_ok());
+
+        let diagnostics = diagnostics.take();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].file.as_deref(), diagnostics[0].token), (Some(name), Some(Token::MMID)));
+    }
+}
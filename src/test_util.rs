@@ -0,0 +1,44 @@
+// fixtures shared by the unit tests
+use std::sync::Arc;
+use reader::memory::MemoryReader;
+
+// a chunk as stored in adt, wdt and wmo files, with the token reversed
+pub fn chunk(token: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut result: Vec<u8> = token.iter().rev().cloned().collect();
+    result.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    result.extend_from_slice(data);
+    result
+}
+
+// a chunk of the legion+ model files (MD21, skel, phys), with the token as read
+pub fn m2_chunk(token: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut result = token.to_vec();
+    result.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    result.extend_from_slice(data);
+    result
+}
+
+pub fn u32s(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect()
+}
+
+pub fn f32s(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect()
+}
+
+pub fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+pub fn put_f32(data: &mut [u8], offset: usize, value: f32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+// a reader holding `files`
+pub fn memory_reader(files: Vec<(&str, Vec<u8>)>) -> Arc<MemoryReader> {
+    let mut reader = MemoryReader::new();
+    for (name, data) in files {
+        reader.insert(name, data);
+    }
+    Arc::new(reader)
+}
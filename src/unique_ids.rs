@@ -0,0 +1,191 @@
+use std::collections::BTreeSet;
+use std::io::{self, Cursor};
+use std::ops::Range;
+use std::sync::Arc;
+use byteorder::{ReadBytesExt, LE};
+use adt::MapTile;
+use chunked::{Chunked, Token};
+use reader::ResourceReader;
+use wdt;
+
+const MDDF_RECORD_SIZE: usize = 36;
+const MODF_RECORD_SIZE: usize = 64;
+
+// hands out placement unique ids which don't collide with any id already used
+// by the scanned maps; the client despawns objects sharing an id, so custom
+// content must never reuse one. allocation is deterministic: the same set of
+// scanned maps and the same sequence of requests yields the same ids
+#[derive(Clone, Debug)]
+pub struct UniqueIdAllocator {
+    used: BTreeSet<u32>,
+    floor: u32,
+}
+
+impl Default for UniqueIdAllocator {
+    fn default() -> Self {
+        UniqueIdAllocator::new()
+    }
+}
+
+impl UniqueIdAllocator {
+    pub fn new() -> Self {
+        UniqueIdAllocator::with_floor(1)
+    }
+
+    // ids below `floor` are never handed out (e.g. to leave room for blizzard content)
+    pub fn with_floor(floor: u32) -> Self {
+        UniqueIdAllocator { used: BTreeSet::new(), floor: floor.max(1) }
+    }
+
+    pub fn used_count(&self) -> usize {
+        self.used.len()
+    }
+
+    pub fn is_used(&self, id: u32) -> bool {
+        self.used.contains(&id)
+    }
+
+    pub fn mark_used(&mut self, id: u32) {
+        self.used.insert(id);
+    }
+
+    pub fn scan_tile(&mut self, tile: &MapTile) {
+        self.used.extend(tile.m2_placements.iter().map(|placement| placement.unique_id));
+        self.used.extend(tile.wmo_placements.iter().map(|placement| placement.unique_id));
+    }
+
+    // reads only the placement chunks of an adt, skipping terrain entirely
+    pub fn scan_adt(&mut self, reader: &dyn ResourceReader, name: &str) -> io::Result<()> {
+        // placements are small, map chunks make up nearly all of the file and are skipped
+        let mut chunks = Chunked::stream(reader.open(name)?)?.filter_tokens(&[Token::MDDF, Token::MODF]);
+
+        while let Some(chunk) = chunks.next_chunk()? {
+            let record_size = match chunk.token {
+                Token::MDDF => MDDF_RECORD_SIZE,
+                Token::MODF => MODF_RECORD_SIZE,
+                _ => continue,
+            };
+
+            for record in chunks.read_body(&chunk)?.chunks(record_size) {
+                if record.len() == record_size {
+                    // unique id follows the name id in both record kinds
+                    let id = Cursor::new(&record[4..8]).read_u32::<LE>()?;
+                    self.used.insert(id);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // scans every tile the map's wdt lists, plus its global wmo. placements moved
+    // to the _obj0 and _obj1 split files in cataclysm, so those are scanned too
+    pub fn scan_map(&mut self, reader: Arc<dyn ResourceReader>, map_name: &str) -> io::Result<()> {
+        let wdt = wdt::load(reader.clone(), &wdt::map_name(map_name))?;
+
+        if let Some(ref placement) = wdt.global_wmo_placement {
+            self.used.insert(placement.unique_id);
+        }
+
+        for (x, y) in wdt.existing_tiles() {
+            let name = format!("World\\Maps\\{0}\\{0}_{1}_{2}", map_name, x, y);
+            self.scan_adt(&*reader, &format!("{}.adt", name))?;
+
+            for split in &["_obj0", "_obj1"] {
+                let split_name = format!("{}{}.adt", name, split);
+                if reader.exists(&split_name)? {
+                    self.scan_adt(&*reader, &split_name)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn allocate(&mut self) -> io::Result<u32> {
+        self.allocate_range(1).map(|range| range.start)
+    }
+
+    // lowest run of `count` consecutive free ids at or above the floor
+    pub fn allocate_range(&mut self, count: u32) -> io::Result<Range<u32>> {
+        if count == 0 {
+            return Ok(self.floor..self.floor);
+        }
+
+        let mut start = self.floor as u64;
+
+        for &id in self.used.range(self.floor..) {
+            if (id as u64) >= start + count as u64 {
+                break;
+            }
+            start = id as u64 + 1;
+        }
+
+        // the end is exclusive, so u32::MAX itself can't be handed out
+        let end = if start <= u32::MAX as u64 { (start as u32).checked_add(count) } else { None };
+        let end = end.ok_or_else(|| io_error!(Other, "no room left for {} unique ids", count))?;
+
+        let range = (start as u32)..end;
+        self.used.extend(range.clone());
+        Ok(range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{chunk, memory_reader};
+
+    // an MDDF or MODF record with only the unique id set
+    fn placement(unique_id: u32, record_size: usize) -> Vec<u8> {
+        let mut record = vec![0u8; record_size];
+        record[4..8].copy_from_slice(&unique_id.to_le_bytes());
+        record
+    }
+
+    #[test]
+    fn test_allocate_range() {
+        let mut allocator = UniqueIdAllocator::with_floor(100);
+        for &id in &[5, 100, 101, 104, 110] {
+            allocator.mark_used(id);
+        }
+
+        assert_eq!(allocator.allocate_range(2).unwrap(), 102..104);
+        assert_eq!(allocator.allocate_range(3).unwrap(), 105..108);
+        assert_eq!(allocator.allocate().unwrap(), 108);
+        assert_eq!(allocator.allocate_range(2).unwrap(), 111..113);
+        assert!(!allocator.is_used(109));
+        assert_eq!(allocator.allocate().unwrap(), 109);
+    }
+
+    #[test]
+    fn test_allocate_range_at_end() {
+        let mut allocator = UniqueIdAllocator::with_floor(u32::MAX - 3);
+        assert_eq!(allocator.allocate_range(3).unwrap(), u32::MAX - 3..u32::MAX);
+        assert!(allocator.allocate().is_err());
+
+        let mut allocator = UniqueIdAllocator::with_floor(u32::MAX - 1);
+        assert!(allocator.allocate_range(2).is_err());
+        assert_eq!(allocator.used_count(), 0);
+    }
+
+    #[test]
+    fn test_scan_map_split_tiles() {
+        let mut main = vec![0u8; 64 * 64 * 8];
+        main[(2 * 64 + 1) * 8] = 1;
+        let wdt = [chunk(b"MVER", &18u32.to_le_bytes()), chunk(b"MPHD", &[0; 32]), chunk(b"MAIN", &main)].concat();
+
+        let reader = memory_reader(vec![
+            ("World\\Maps\\Test\\Test.wdt", wdt),
+            ("World\\Maps\\Test\\Test_1_2.adt", chunk(b"MVER", &18u32.to_le_bytes())),
+            ("World\\Maps\\Test\\Test_1_2_obj0.adt", chunk(b"MDDF", &placement(7, MDDF_RECORD_SIZE))),
+            ("World\\Maps\\Test\\Test_1_2_obj1.adt", chunk(b"MODF", &placement(9, MODF_RECORD_SIZE))),
+        ]);
+
+        let mut allocator = UniqueIdAllocator::new();
+        allocator.scan_map(reader, "Test").unwrap();
+        assert!(allocator.is_used(7));
+        assert!(allocator.is_used(9));
+        assert_eq!(allocator.used_count(), 2);
+    }
+}
@@ -0,0 +1,79 @@
+// client release the files come from. most formats carry their own version,
+// but some layouts changed without one (alpha map depth, split tiles, MOMT
+// texture references), so loaders take this as a hint where they can't tell
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum GameVersion {
+    Classic,
+    TBC,
+    WotLK,
+    Cata,
+    MoP,
+    WoD,
+    Legion,
+    // 8.1 and later
+    BfA,
+}
+
+impl GameVersion {
+    // the oldest release writing models with this M2 version. versions were only bumped
+    // now and then, so e.g. MoP models report a cata version
+    pub fn from_m2_version(version: u32) -> Option<GameVersion> {
+        match version {
+            256..=257 => Some(GameVersion::Classic),
+            258..=263 => Some(GameVersion::TBC),
+            264 => Some(GameVersion::WotLK),
+            265..=271 => Some(GameVersion::Cata),
+            272 => Some(GameVersion::Legion),
+            273..=274 => Some(GameVersion::BfA),
+            _ => None,
+        }
+    }
+
+    // range of M2 versions shipped by this release
+    pub fn m2_versions(&self) -> (u32, u32) {
+        match *self {
+            GameVersion::Classic => (256, 257),
+            GameVersion::TBC => (258, 263),
+            GameVersion::WotLK => (264, 264),
+            GameVersion::Cata | GameVersion::MoP => (265, 272),
+            GameVersion::WoD => (272, 272),
+            GameVersion::Legion => (272, 274),
+            GameVersion::BfA => (272, 274),
+        }
+    }
+
+    // terrain textures moved to _tex0 and placements to _obj0 files
+    pub fn has_split_tiles(&self) -> bool {
+        *self >= GameVersion::Cata
+    }
+
+    // depth of uncompressed alpha maps when the WDT flags aren't at hand; before
+    // wotlk they're always 4 bit, later it depends on the map
+    pub fn big_alpha(&self) -> Option<bool> {
+        if *self < GameVersion::WotLK { Some(false) } else { None }
+    }
+
+    // MOMT references textures by FileDataID instead of MOTX offsets
+    pub fn has_material_file_ids(&self) -> bool {
+        *self >= GameVersion::BfA
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_m2_versions() {
+        for &version in [GameVersion::Classic, GameVersion::TBC, GameVersion::WotLK, GameVersion::Legion].iter() {
+            let (first, last) = version.m2_versions();
+            assert!(GameVersion::from_m2_version(first).unwrap() <= version);
+            assert!(GameVersion::from_m2_version(last).unwrap() >= GameVersion::from_m2_version(first).unwrap());
+        }
+        assert_eq!(GameVersion::from_m2_version(264), Some(GameVersion::WotLK));
+        assert_eq!(GameVersion::from_m2_version(20), None);
+        assert_eq!(GameVersion::TBC.big_alpha(), Some(false));
+        assert!(!GameVersion::WotLK.has_split_tiles());
+    }
+}
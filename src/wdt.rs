@@ -0,0 +1,184 @@
+use std::io::Read;
+use std::sync::Arc;
+use std::collections::BTreeMap;
+use byteorder::{ReadBytesExt, LE};
+use nalgebra::{Vector3, Point3};
+use chunked::{Chunked, Token};
+use reader::ResourceReader;
+use read_ext;
+use adt::WmoPlacement;
+use error::Result;
+use asset::{Asset, LoadOptions};
+
+const MAP_TILES: usize = 64 * 64;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Wdt {
+    pub flags: WdtFlags,
+    pub tiles: Vec<bool>,
+    pub global_wmo: Option<String>,
+    pub global_wmo_placement: Option<WmoPlacement>,
+    // MAID (bfa+), one entry per tile in the order of `tiles`; empty for older maps
+    pub tile_file_data_ids: Vec<TileFileDataIds>,
+}
+
+// 0 where a tile lacks the file
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TileFileDataIds {
+    pub root: u32,
+    pub obj0: u32,
+    pub obj1: u32,
+    pub tex0: u32,
+    pub lod: u32,
+    pub map_texture: u32,
+    pub map_texture_normal: u32,
+    pub minimap_texture: u32,
+}
+
+bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct WdtFlags: u32 {
+        const GLOBAL_MAP_OBJECT = 1;
+        const HAS_MCCV = 1 << 1;
+        const BIG_ALPHA = 1 << 2;
+        const DOODAD_REFS_SORTED = 1 << 3;
+        const HAS_MCLV = 1 << 4;
+        const UPSIDE_DOWN_GROUND = 1 << 5;
+        const UNKNOWN_FIRELANDS = 1 << 6;
+        const HEIGHT_TEXTURING = 1 << 7;
+        const UNKNOWN_LOAD_LOD = 1 << 8;
+        const HAS_MAID = 1 << 9;
+    }
+}
+impl_bitflags_from_read!(read_u32, WdtFlags);
+impl_bitflags_to_write!(write_u32, WdtFlags);
+
+impl Wdt {
+    pub fn has_tile(&self, x: u32, y: u32) -> bool {
+        x < 64 && y < 64 && self.tiles[(y * 64 + x) as usize]
+    }
+
+    pub fn existing_tiles(&self) -> Vec<(u32, u32)> {
+        (0..MAP_TILES)
+            .filter(|index| self.tiles[*index])
+            .map(|index| ((index % 64) as u32, (index / 64) as u32))
+            .collect()
+    }
+
+    pub fn tile_file_data_ids(&self, x: u32, y: u32) -> Option<&TileFileDataIds> {
+        if x >= 64 || y >= 64 { return None }
+        self.tile_file_data_ids.get((y * 64 + x) as usize).filter(|ids| ids.root != 0)
+    }
+
+    // value for the `big_alpha` option of `MapTile::load`
+    pub fn big_alpha(&self) -> bool {
+        self.flags.contains(WdtFlags::BIG_ALPHA)
+    }
+}
+
+pub fn map_name(map: &str) -> String {
+    format!("World\\Maps\\{0}\\{0}.wdt", map)
+}
+
+pub fn load(reader: Arc<dyn ResourceReader>, name: &str) -> Result<Wdt> {
+    read(reader.open(name)?)
+}
+
+impl Asset for Wdt {
+    const EXTENSIONS: &'static [&'static str] = &[".wdt"];
+
+    fn load(reader: &dyn ResourceReader, name: &str, options: &LoadOptions) -> Result<Wdt> {
+        options.apply(name, || read(reader.open(name)?))
+    }
+}
+
+pub fn read<R: Read>(input: R) -> Result<Wdt> {
+    let mut wdt = Wdt {
+        flags: WdtFlags::empty(),
+        tiles: vec![false; MAP_TILES],
+        global_wmo: None,
+        global_wmo_placement: None,
+        tile_file_data_ids: Vec::new(),
+    };
+
+    for chunk in Chunked::new(input) {
+        let chunk = chunk?;
+        let mut cursor = chunk.cursor();
+
+        match chunk.token {
+            // header
+            Token::MPHD => {
+                let_read! { LE | cursor =>
+                    flags: WdtFlags;
+                }
+                wdt.flags = flags;
+            }
+            // tile table
+            Token::MAIN => {
+                for index in 0..MAP_TILES.min(chunk.data.len() / 8) {
+                    let_read! { LE | cursor =>
+                        tile_flags: u32;
+                        async_id: u32;
+                    }
+                    wdt.tiles[index] = tile_flags & 1 != 0;
+                }
+            }
+            // tile file data ids
+            Token::MAID => {
+                for _ in 0..MAP_TILES.min(chunk.data.len() / 32) {
+                    let_read! { LE | cursor =>
+                        root: u32;
+                        obj0: u32;
+                        obj1: u32;
+                        tex0: u32;
+                        lod: u32;
+                        map_texture: u32;
+                        map_texture_normal: u32;
+                        minimap_texture: u32;
+                    }
+                    wdt.tile_file_data_ids.push(TileFileDataIds {
+                        root, obj0, obj1, tex0, lod, map_texture, map_texture_normal, minimap_texture,
+                    });
+                }
+            }
+            // global wmo path
+            Token::MWMO => {
+                let mut names = BTreeMap::new();
+                read_ext::cstring_table_into(&chunk.data, &mut names)?;
+                wdt.global_wmo = names.into_iter().next().map(|(_, name)| name);
+            }
+            // global wmo placement
+            Token::MODF if chunk.data.len() >= 64 => {
+                let_read! { LE | cursor =>
+                    name_id: u32;
+                    unique_id: u32;
+                    position: (f32, f32, f32);
+                    rotation: (f32, f32, f32);
+                    aabox_min: (f32, f32, f32);
+                    aabox_max: (f32, f32, f32);
+                    flags: u16;
+                    doodad_set: u16;
+                    name_set: u16;
+                    unknown1: u16;
+                }
+
+                wdt.global_wmo_placement = Some(WmoPlacement {
+                    name_id,
+                    unique_id,
+                    position: Point3::new(position.0, position.1, position.2),
+                    rotation: Vector3::new(rotation.0, rotation.1, rotation.2),
+                    bounding_box_min: Point3::new(aabox_min.0, aabox_min.1, aabox_min.2),
+                    bounding_box_max: Point3::new(aabox_max.0, aabox_max.1, aabox_max.2),
+                    flags,
+                    doodad_set,
+                    name_set,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(wdt)
+}
@@ -0,0 +1,170 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::sync::Arc;
+use nalgebra::{Matrix4, Point3, Vector3};
+use reader::ResourceReader;
+use m2::{self, skin};
+use m2::mesh::M2Mesh;
+use wmo::{MapObject, MeshGroup};
+use error::Result;
+
+// all geometry of a map object in object space, with one submesh per material
+#[derive(Debug, Default)]
+pub struct MergedMesh {
+    pub positions: Vec<Point3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    // first uv set
+    pub texcoords: Vec<(f32, f32)>,
+    pub indices: Vec<u32>,
+    pub submeshes: Vec<MergedSubmesh>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MergedSubmesh {
+    pub material: MergedMaterial,
+    // range of `MergedMesh::indices`
+    pub indices: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MergedMaterial {
+    // MOMT index
+    Wmo(u16),
+    // first texture of a doodad batch
+    Doodad(Option<String>),
+}
+
+// vertices are appended as they come, triangles are collected per material
+#[derive(Default)]
+struct Builder {
+    mesh: MergedMesh,
+    triangles: BTreeMap<MergedMaterial, Vec<u32>>,
+}
+
+impl MapObject {
+    // concatenates the geometry of all groups and, if a doodad set is given, of its
+    // doodads (see `doodads_for_set`; skin profile 0). doodads which fail to load
+    // are skipped with a warning
+    pub fn merge(&self, reader: Arc<dyn ResourceReader>, doodad_set: Option<usize>) -> Result<MergedMesh> {
+        let mut builder = Builder::default();
+
+        for info in self.groups.iter() {
+            builder.add_group(&info.load(reader.clone())?);
+        }
+
+        let mut models: HashMap<String, Option<M2Mesh>> = HashMap::new();
+
+        for doodad in doodad_set.into_iter().flat_map(|set| self.doodads_for_set(set)) {
+            let name = match doodad.model.and_then(|index| self.m2.get(index)) {
+                Some(name) => m2::model_file_name(name),
+                None => continue,
+            };
+
+            let mesh = models.entry(name.to_lowercase()).or_insert_with(|| {
+                load_doodad(reader.clone(), &name)
+                    .map_err(|error| report_warn!("Unable to load doodad {}: {}", name, error))
+                    .ok()
+            });
+
+            if let Some(ref mesh) = *mesh {
+                builder.add_model(mesh, &doodad.transform());
+            }
+        }
+
+        Ok(builder.finish())
+    }
+}
+
+fn load_doodad(reader: Arc<dyn ResourceReader>, name: &str) -> Result<M2Mesh> {
+    let model = m2::load(reader.clone(), name, None)?;
+    let skin = skin::load_for_model(reader, name, &model, 0)?;
+    Ok(model.build_mesh(&skin))
+}
+
+impl Builder {
+    fn add_group(&mut self, group: &MeshGroup) {
+        let base = self.mesh.positions.len() as u32;
+        let texcoords = group.texcoords.first();
+
+        for (index, vertex) in group.vertices.iter().enumerate() {
+            let normal = group.normals.get(index).map_or(Vector3::z(), |n| Vector3::new(n.0, n.1, n.2));
+            self.mesh.positions.push(Point3::new(vertex.0, vertex.1, vertex.2));
+            self.mesh.normals.push(normal);
+            self.mesh.texcoords.push(texcoords.and_then(|set| set.get(index).cloned()).unwrap_or((0.0, 0.0)));
+        }
+
+        for batch in group.batches.iter() {
+            let start = (batch.index_start as usize).min(group.indexes.len());
+            let end = (start + batch.index_count as usize).min(group.indexes.len());
+
+            self.triangles.entry(MergedMaterial::Wmo(batch.material_id)).or_default()
+                .extend(group.indexes[start..end].iter().map(|&index| base + index as u32));
+        }
+    }
+
+    fn add_model(&mut self, mesh: &M2Mesh, transform: &Matrix4<f32>) {
+        let base = self.mesh.positions.len() as u32;
+
+        for vertex in mesh.vertices.iter() {
+            let position = Point3::new(vertex.position[0], vertex.position[1], vertex.position[2]);
+            let normal = transform * Vector3::new(vertex.normal[0], vertex.normal[1], vertex.normal[2]).to_homogeneous();
+
+            self.mesh.positions.push(Point3::from_homogeneous(transform * position.to_homogeneous()).unwrap_or(position));
+            self.mesh.normals.push(Vector3::new(normal.x, normal.y, normal.z).normalize());
+            self.mesh.texcoords.push((vertex.tex_coords[0][0], vertex.tex_coords[0][1]));
+        }
+
+        for batch in mesh.batches.iter() {
+            let start = (batch.index_start as usize).min(mesh.indices.len());
+            let end = (start + batch.index_count as usize).min(mesh.indices.len());
+            let texture = batch.texture_files.first().cloned().and_then(|file| file);
+
+            self.triangles.entry(MergedMaterial::Doodad(texture)).or_default()
+                .extend(mesh.indices[start..end].iter().map(|&index| base + index as u32));
+        }
+    }
+
+    fn finish(self) -> MergedMesh {
+        let mut mesh = self.mesh;
+
+        for (material, triangles) in self.triangles {
+            let start = mesh.indices.len();
+            mesh.indices.extend(triangles);
+            mesh.submeshes.push(MergedSubmesh { material, indices: start..mesh.indices.len() });
+        }
+
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wmo::{read_group_file, RenderBatch};
+
+    #[test]
+    fn test_merge_groups() {
+        let mut group = read_group_file(&[], 17).unwrap();
+        group.vertices = vec![(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (1.0, 1.0, 0.0)].into();
+        group.indexes = vec![0, 1, 2, 1, 3, 2].into();
+        group.batches = vec![
+            RenderBatch { material_id: 1, index_start: 0, index_count: 3, vertex_start: 0, vertex_end: 2 },
+            RenderBatch { material_id: 0, index_start: 3, index_count: 3, vertex_start: 1, vertex_end: 3 },
+        ];
+
+        let mut builder = Builder::default();
+        builder.add_group(&group);
+        builder.add_group(&group);
+        let mesh = builder.finish();
+
+        assert_eq!(mesh.positions.len(), 8);
+        assert_eq!(mesh.normals[0], Vector3::z());
+        assert_eq!(mesh.texcoords[0], (0.0, 0.0));
+
+        // both groups' triangles end up in one submesh per material
+        assert_eq!(mesh.submeshes.len(), 2);
+        assert_eq!(mesh.submeshes[0].material, MergedMaterial::Wmo(0));
+        assert_eq!(&mesh.indices[mesh.submeshes[0].indices.clone()], &[1, 3, 2, 5, 7, 6]);
+        assert_eq!(&mesh.indices[mesh.submeshes[1].indices.clone()], &[0, 1, 2, 4, 5, 6]);
+    }
+}
@@ -0,0 +1,1688 @@
+use std::iter::Iterator;
+use std::collections::BTreeMap;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::Arc;
+use byteorder::{ReadBytesExt, LittleEndian as LE};
+use read_ext::{self, ReadExt, FromRead, FromReadVersion};
+use chunked::{Chunked, Token};
+use reader::{ResourceReader, ResourceHandle, split_resource_name, open_preferring_id};
+use nalgebra::{Matrix4, Point3, Quaternion, UnitQuaternion, Vector3};
+use rgb::RGBA8;
+use misc::Blob;
+use records::read_records;
+use culling::LocalBounds;
+use geom::UNIT_SIZE;
+use light::LightKind;
+use version::GameVersion;
+use asset::{Asset, LoadOptions};
+use self::visibility::Plane;
+use error::{Error, Result};
+
+#[cfg(feature = "m2")]
+pub mod merge;
+pub mod visibility;
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MapObject {
+    // 17, or 14 for alpha files
+    pub version: u32,
+    pub header: MapObjectHeader,
+    pub textures: Vec<String>,
+    pub m2: Vec<String>,
+    pub materials: Vec<Material>,
+    pub groups: Vec<MeshGroupInfo>,
+    pub lights: Vec<Light>,
+    pub doodad_sets: Vec<DoodadSet>,
+    pub doodads: Vec<DoodadPlacement>,
+    // m2 path of a sky shown while inside the object (MOSB)
+    pub skybox: Option<String>,
+    pub portals: Vec<Portal>,
+    // groups list their portals as ranges of this; see `group_portals`
+    pub portal_refs: Vec<PortalRef>,
+    // MCVP, bounding volume of transports; stored as found, not normalized
+    pub convex_volume_planes: Vec<Plane>,
+}
+
+// MOHD; counts are as declared, the parsed lists are authoritative
+#[derive(Debug, Clone, Default, FromRead)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MapObjectHeader {
+    pub material_count: u32,
+    pub group_count: u32,
+    pub portal_count: u32,
+    pub light_count: u32,
+    pub doodad_name_count: u32,
+    pub doodad_def_count: u32,
+    pub doodad_set_count: u32,
+    // base light of interior groups
+    #[from_read(with = "read_bgra")]
+    pub ambient_color: RGBA8,
+    // WMOAreaTable id
+    pub wmo_id: u32,
+    pub bounding_box_min: (f32, f32, f32),
+    pub bounding_box_max: (f32, f32, f32),
+    // always empty for v14, which has padding in their place
+    #[from_read(version = "version != 14")]
+    pub flags: WmoFlags,
+    #[from_read(version = "version != 14")]
+    pub lod_count: u16,
+}
+
+bitflags! {
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct WmoFlags: u16 {
+        const NO_PORTAL_ATTENUATION = 0x1;
+        const UNIFIED_RENDER_PATH = 0x2;
+        // MLIQ liquid types are LiquidType ids instead of legacy ones
+        const LIQUID_TYPE_DBC_ID = 0x4;
+        const NO_VERTEX_COLOR_ALPHA_FIX = 0x8;
+        const LOD = 0x10;
+        const DEFAULT_MAX_LOD = 0x20;
+    }
+}
+impl_bitflags_from_read!(read_u16, WmoFlags);
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DoodadSet {
+    pub name: String,
+    // range of `MapObject::doodads`
+    pub doodads: Range<usize>,
+}
+
+// m2 placed in object space (MODD)
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DoodadPlacement {
+    // index into `MapObject::m2`
+    pub model: Option<usize>,
+    pub flags: u8,
+    pub position: Point3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: f32,
+    pub color: RGBA8,
+}
+
+// convex polygon connecting two groups
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Portal {
+    pub vertices: Vec<Point3<f32>>,
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PortalRef {
+    // index into `MapObject::portals`
+    pub portal: u16,
+    // index of the group on the other side
+    pub group: u16,
+    // which side of the portal plane the owning group is on (1 or -1)
+    pub side: i16,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Light {
+    pub kind: LightKind,
+    pub use_attenuation: bool,
+    pub color: RGBA8,
+    pub position: Point3<f32>,
+    pub intensity: f32,
+    pub rotation: Quaternion<f32>,
+    pub attenuation_start: f32,
+    pub attenuation_end: f32,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Material {
+    pub texture_id: Option<usize>,
+    // diffuse texture of bfa+ files, which have no MOTX
+    pub texture_file_data_id: Option<u32>,
+    // uv scroll speed of both texture layers in units per second (MOUV, legion+)
+    pub uv_speed: [(f32, f32); 2],
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MeshGroupInfo {
+    pub resource_key: String,
+    pub flags: GroupFlags,
+    pub bounding_box_min: (f32, f32, f32),
+    pub bounding_box_max: (f32, f32, f32),
+    pub name: Option<String>,
+    // byte range of the MOGP chunk within `resource_key` for groups embedded
+    // in the root file (v14)
+    pub embedded: Option<Range<u64>>,
+    // from GFID (legion+), one per LOD level starting with the full detail one;
+    // 0 where a level has no file of its own
+    pub file_data_ids: Vec<u32>,
+}
+
+bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct GroupFlags: u32 {
+        const HAS_BSP_TREE = 0x1;
+        const HAS_LIGHT_MAP = 0x2;
+        const HAS_VERTEX_COLORS = 0x4;
+        const OUTDOOR = 0x8;
+        const EXTERIOR_LIT = 0x40;
+        const UNREACHABLE = 0x80;
+        const HAS_LIGHTS = 0x200;
+        const HAS_DOODADS = 0x800;
+        const HAS_WATER = 0x1000;
+        const INDOOR = 0x2000;
+        const ALWAYS_DRAW = 0x10000;
+        const HAS_TRIANGLE_STRIPS = 0x20000;
+        const SHOW_SKYBOX = 0x40000;
+        const IS_OCEAN = 0x80000;
+        const MOUNT_ALLOWED = 0x200000;
+        const HAS_TWO_VERTEX_COLOR_SETS = 0x1000000;
+        const HAS_TWO_TEXCOORD_SETS = 0x2000000;
+        const ANTIPORTAL = 0x4000000;
+        const HAS_THREE_TEXCOORD_SETS = 0x40000000;
+    }
+}
+impl_bitflags_from_read!(read_u32, GroupFlags);
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MeshGroup {
+    // MOGP header
+    pub flags: GroupFlags,
+    // liquid type of the whole group, see also `liquid`
+    pub group_liquid: u32,
+    pub transparent_batch_count: u16,
+    pub interior_batch_count: u16,
+    pub exterior_batch_count: u16,
+    // indices into the MFOG entries of the root file
+    pub fog_ids: [u8; 4],
+    pub indexes: Blob<u16>,
+    pub vertices: Blob<(f32, f32, f32)>,
+    pub normals: Blob<(f32, f32, f32)>,
+    // one set per MOTV chunk; later sets are used by env-mapped and lightmap-style shaders
+    pub texcoords: Vec<Blob<(f32, f32)>>,
+    // one set per MOCV chunk (bgra in the file)
+    pub colors: Vec<Blob<RGBA8>>,
+    pub batches: Vec<RenderBatch>,
+    pub shadow_batches: Vec<RenderBatch>,
+    pub bounds: LocalBounds,
+    // MOPY, one per triangle
+    pub faces: Vec<FaceInfo>,
+    // range of `MapObject::portal_refs`
+    pub portals: Range<usize>,
+    pub liquid: Option<Liquid>,
+    pub bsp: BspTree,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FaceInfo {
+    pub flags: FaceFlags,
+    // 0xFF for collision-only faces
+    pub material_id: u8,
+}
+
+bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct FaceFlags: u8 {
+        const TRANSITION = 0x01;
+        const NO_CAMERA_COLLIDE = 0x02;
+        const DETAIL = 0x04;
+        const COLLISION = 0x08;
+        const HINT = 0x10;
+        const RENDER = 0x20;
+        const CULL_OBJECTS = 0x40;
+        const COLLIDE_HIT = 0x80;
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RayHit {
+    pub position: Point3<f32>,
+    // in units of the ray direction's length
+    pub distance: f32,
+    // triangle index into `MeshGroup::indexes` / 3
+    pub face: usize,
+    pub flags: FaceFlags,
+}
+
+// MOBN/MOBR: axis aligned bsp tree over the group's collision triangles
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BspTree {
+    pub nodes: Vec<BspNode>,
+    // triangle indices (into `MeshGroup::indexes` / 3), referenced by leaves
+    pub face_refs: Vec<u16>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BspNode {
+    Leaf {
+        // range of `BspTree::face_refs`
+        faces: Range<usize>,
+    },
+    Branch {
+        axis: BspAxis,
+        // position of the splitting plane along `axis`
+        distance: f32,
+        // indices into `BspTree::nodes` of the halves below and above the plane
+        negative: Option<usize>,
+        positive: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BspAxis {
+    X,
+    Y,
+    Z,
+}
+
+// MLIQ: a grid of liquid heights over `tiles_x` by `tiles_y` tiles of `UNIT_SIZE`,
+// starting at `position` (group space)
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Liquid {
+    pub vertices_x: u32,
+    pub vertices_y: u32,
+    pub tiles_x: u32,
+    pub tiles_y: u32,
+    pub position: Point3<f32>,
+    pub material_id: u16,
+    // row major, `vertices_x` per row
+    pub vertices: Vec<LiquidVertex>,
+    // row major, `tiles_x` per row
+    pub tiles: Vec<LiquidTile>,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LiquidVertex {
+    // flow data for water, texture coordinates (2 x i16) for magma and slime
+    pub data: [u8; 4],
+    pub height: f32,
+}
+
+// low nibble is the liquid type, the rest are flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LiquidTile(pub u8);
+
+// triangle list of the visible liquid tiles
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LiquidMesh {
+    pub vertices: Vec<(f32, f32, f32)>,
+    pub indices: Vec<u16>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RenderBatch {
+    pub material_id: u16,
+    pub index_start: u32,
+    pub index_count: u16,
+    pub vertex_start: u16,
+    pub vertex_end: u16,
+}
+
+impl MapObject {
+    // names of the root file and its group files, up to the first missing group
+    pub fn source_files(reader: &dyn ResourceReader, name: &str) -> io::Result<Vec<String>> {
+        let mut names = vec![name.to_owned()];
+        for index in 0.. {
+            let group_name = group_file_name(name, index);
+            if !reader.exists(&group_name)? { break }
+            names.push(group_name);
+        }
+        Ok(names)
+    }
+
+    pub fn load_all_groups(&self, reader: Arc<dyn ResourceReader>) -> Vec<MeshGroup> {
+        self.groups.iter().flat_map(|group| {
+            match group.load_if_exists(reader.clone()) {
+                Ok(Some(result)) => Some(result),
+                Ok(None) => {
+                    report_warn!("Skipping missing WMO group file {}", group.resource_key);
+                    None
+                }
+                Err(error) => {
+                    error!("Unable to load WMO mesh group: {}", error);
+                    None
+                }
+            }
+        }).collect()
+    }
+
+    // loads every group only when the iterator reaches it
+    pub fn groups_lazy<'a>(&'a self, reader: Arc<dyn ResourceReader>) -> impl Iterator<Item = Result<MeshGroup>> + 'a {
+        self.groups_lazy_where(reader, |_| true)
+    }
+
+    // like `groups_lazy`, without loading groups rejected by `filter`
+    // (e.g. those whose bounding box is outside the view frustum)
+    pub fn groups_lazy_where<'a, F>(&'a self, reader: Arc<dyn ResourceReader>, filter: F) -> impl Iterator<Item = Result<MeshGroup>> + 'a
+        where F: Fn(&MeshGroupInfo) -> bool + 'a
+    {
+        self.groups.iter()
+            .filter(move |group| filter(group))
+            .map(move |group| group.load(reader.clone()))
+    }
+
+    // doodads of a set (e.g. `WmoPlacement::doodad_set`). set 0 holds the doodads
+    // shared by all sets and is always included
+    pub fn doodads_for_set<'a>(&'a self, set_index: usize) -> impl Iterator<Item = &'a DoodadPlacement> + 'a {
+        let window = |index: usize| {
+            let range = self.doodad_sets.get(index).map_or(0..0, |set| set.doodads.clone());
+            let end = range.end.min(self.doodads.len());
+            &self.doodads[range.start.min(end)..end]
+        };
+
+        let selected = if set_index != 0 { window(set_index) } else { &[] };
+        window(0).iter().chain(selected.iter())
+    }
+
+    pub fn group_portals(&self, group: &MeshGroup) -> &[PortalRef] {
+        let end = group.portals.end.min(self.portal_refs.len());
+        &self.portal_refs[group.portals.start.min(end)..end]
+    }
+
+    // closest hit among `groups` (see `load_all_groups`) of a world space ray, with
+    // `transform` placing the object in the world (see `WmoPlacement::world_transform`).
+    // returns the group index and the hit in world space
+    pub fn raycast(&self, groups: &[MeshGroup], transform: &Matrix4<f32>, origin: &Point3<f32>, direction: &Vector3<f32>) -> Option<(usize, RayHit)> {
+        let inverse = transform.try_inverse()?;
+        let local_origin = Point3::from_homogeneous(inverse * origin.to_homogeneous())?;
+        let local_direction = inverse * direction.to_homogeneous();
+        let local_direction = Vector3::new(local_direction.x, local_direction.y, local_direction.z);
+
+        let (index, mut hit) = groups.iter().enumerate()
+            .filter_map(|(index, group)| group.raycast(&local_origin, &local_direction).map(|hit| (index, hit)))
+            .filter(|(_, hit)| !hit.distance.is_nan())
+            .min_by(|a, b| a.1.distance.total_cmp(&b.1.distance))?;
+
+        // distances along the ray are unaffected by the transform
+        hit.position = Point3::from_homogeneous(transform * hit.position.to_homogeneous())?;
+        Some((index, hit))
+    }
+}
+
+impl FaceInfo {
+    // present for collision only and never drawn
+    pub fn is_collision_only(&self) -> bool {
+        self.material_id == 0xFF
+    }
+
+    pub fn is_render(&self) -> bool {
+        self.flags.contains(FaceFlags::RENDER) && !self.flags.contains(FaceFlags::DETAIL)
+    }
+
+    pub fn is_collidable(&self) -> bool {
+        self.flags.contains(FaceFlags::COLLISION) || self.is_render()
+    }
+
+    pub fn is_camera_collidable(&self) -> bool {
+        self.is_collidable() && !self.flags.contains(FaceFlags::NO_CAMERA_COLLIDE)
+    }
+}
+
+impl MeshGroup {
+    // triangle list of the faces which block movement, for collision export
+    pub fn collision_indexes(&self) -> Vec<u16> {
+        self.indexes.chunks(3)
+            .zip(self.faces.iter())
+            .filter(|&(triangle, face)| triangle.len() == 3 && face.is_collidable())
+            .flat_map(|(triangle, _)| triangle.iter().cloned())
+            .collect()
+    }
+
+    // closest triangle hit by a ray in group space
+    pub fn raycast(&self, origin: &Point3<f32>, direction: &Vector3<f32>) -> Option<RayHit> {
+        self.raycast_with(origin, direction, |_| true)
+    }
+
+    // like `raycast`, skipping faces for which `filter` returns false
+    // (e.g. to ignore faces without `FaceFlags::COLLISION`)
+    pub fn raycast_with<F: Fn(FaceFlags) -> bool>(&self, origin: &Point3<f32>, direction: &Vector3<f32>, filter: F) -> Option<RayHit> {
+        let (near, far) = ray_box(&self.bounds, origin, direction)?;
+        let mut closest: Option<RayHit> = None;
+
+        {
+            let mut test = |face: usize, min: f32, max: f32| {
+                let flags = self.faces.get(face).map_or(FaceFlags::empty(), |info| info.flags);
+                if !filter(flags) { return }
+
+                if let Some(distance) = self.ray_triangle(face, origin, direction) {
+                    if (min..=max).contains(&distance) && closest.as_ref().is_none_or(|hit| distance < hit.distance) {
+                        closest = Some(RayHit { position: origin + direction * distance, distance, face, flags });
+                    }
+                }
+            };
+
+            if self.bsp.is_empty() {
+                // no tree: test every triangle
+                for face in 0..(self.indexes.len() / 3) {
+                    test(face, near, far);
+                }
+            } else {
+                self.bsp.traverse(0, origin, direction, near, far, &mut |faces, min, max| {
+                    for &face in faces {
+                        test(face as usize, min, max);
+                    }
+                });
+            }
+        }
+
+        closest
+    }
+
+    // two-sided moller-trumbore intersection, as a distance along the ray
+    fn ray_triangle(&self, face: usize, origin: &Point3<f32>, direction: &Vector3<f32>) -> Option<f32> {
+        let vertex = |corner: usize| {
+            let index = *self.indexes.get(face * 3 + corner)? as usize;
+            self.vertices.get(index).map(|v| Point3::new(v.0, v.1, v.2))
+        };
+        let (a, b, c) = (vertex(0)?, vertex(1)?, vertex(2)?);
+
+        let ab = b - a;
+        let ac = c - a;
+        let p = direction.cross(&ac);
+        let determinant = ab.dot(&p);
+        if determinant.abs() < 1e-8 { return None }
+
+        let to_origin = origin - a;
+        let u = to_origin.dot(&p) / determinant;
+        if !(0.0..=1.0).contains(&u) { return None }
+
+        let q = to_origin.cross(&ab);
+        let v = direction.dot(&q) / determinant;
+        if v < 0.0 || u + v > 1.0 { return None }
+
+        let distance = ac.dot(&q) / determinant;
+        if distance >= 0.0 { Some(distance) } else { None }
+    }
+}
+
+impl DoodadPlacement {
+    // model space to object space
+    pub fn transform(&self) -> Matrix4<f32> {
+        Matrix4::new_translation(&self.position.coords)
+            * UnitQuaternion::from_quaternion(self.rotation).to_homogeneous()
+            * Matrix4::new_scaling(self.scale)
+    }
+}
+
+impl MapObjectHeader {
+    pub fn bounds(&self) -> LocalBounds {
+        let (min, max) = (self.bounding_box_min, self.bounding_box_max);
+        LocalBounds::from_box(Point3::new(min.0, min.1, min.2), Point3::new(max.0, max.1, max.2))
+    }
+}
+
+impl Material {
+    // texture coordinate offset of a layer after `time` seconds, wrapped to 0..1
+    pub fn uv_offset(&self, layer: usize, time: f32) -> (f32, f32) {
+        let (u, v) = self.uv_speed.get(layer).cloned().unwrap_or((0.0, 0.0));
+        ((u * time).rem_euclid(1.0), (v * time).rem_euclid(1.0))
+    }
+}
+
+impl MeshGroupInfo {
+    // opens the group by FileDataID when the reader supports it, by name otherwise
+    pub fn load(&self, reader: Arc<dyn ResourceReader>) -> Result<MeshGroup> {
+        let input = open_preferring_id(&*reader, self.file_data_ids.first().cloned(), &self.resource_key)?;
+        Ok(self.read(input)?)
+    }
+
+    // None if the group file is missing, as with some stripped-down client data
+    pub fn load_if_exists(&self, reader: Arc<dyn ResourceReader>) -> Result<Option<MeshGroup>> {
+        let input = match self.file_data_ids.first().cloned() {
+            Some(id) if id != 0 && reader.exists_by_id(id)? => reader.open_by_id(id)?,
+            _ => match reader.open_if_exists(&self.resource_key)? {
+                Some(input) => input,
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(self.read(input)?))
+    }
+
+    fn read(&self, mut input: Box<dyn ResourceHandle>) -> io::Result<MeshGroup> {
+        match self.embedded {
+            Some(ref range) => {
+                input.seek(SeekFrom::Start(range.start))?;
+                let mut data = Vec::new();
+                Read::take(&mut input, range.end - range.start).read_to_end(&mut data)?;
+                read_group_file(&data, 14)
+            }
+            None => {
+                let mut data = Vec::new();
+                input.read_to_end(&mut data)?;
+                read_group_file(&data, 17)
+            }
+        }
+    }
+}
+
+impl Liquid {
+    pub fn tile(&self, x: u32, y: u32) -> Option<LiquidTile> {
+        if x >= self.tiles_x { return None }
+        self.tiles.get((y * self.tiles_x + x) as usize).cloned()
+    }
+
+    pub fn vertex_position(&self, x: u32, y: u32) -> Option<Point3<f32>> {
+        if x >= self.vertices_x { return None }
+        self.vertices.get((y * self.vertices_x + x) as usize).map(|vertex| Point3::new(
+            self.position.x + x as f32 * UNIT_SIZE,
+            self.position.y + y as f32 * UNIT_SIZE,
+            vertex.height,
+        ))
+    }
+
+    // two triangles per visible tile, as indices into `vertices`
+    pub fn each_triangle<F>(&self, mut f: F) where F: FnMut((u16, u16, u16)) {
+        for y in 0..self.tiles_y.min(self.vertices_y.saturating_sub(1)) {
+            for x in 0..self.tiles_x.min(self.vertices_x.saturating_sub(1)) {
+                if !self.tile(x, y).is_some_and(|tile| tile.is_visible()) {
+                    continue;
+                }
+
+                let topleft = (y * self.vertices_x + x) as u16;
+                let topright = topleft + 1;
+                let botleft = topleft + self.vertices_x as u16;
+                let botright = botleft + 1;
+
+                f((topleft, topright, botleft));
+                f((topright, botright, botleft));
+            }
+        }
+    }
+
+    pub fn build_mesh(&self) -> LiquidMesh {
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        for y in 0..self.vertices_y {
+            for x in 0..self.vertices_x {
+                if let Some(position) = self.vertex_position(x, y) {
+                    vertices.push((position.x, position.y, position.z));
+                }
+            }
+        }
+
+        let mut indices = Vec::new();
+        self.each_triangle(|(a, b, c)| indices.extend_from_slice(&[a, b, c]));
+
+        LiquidMesh { vertices, indices }
+    }
+}
+
+impl BspTree {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // leaf containing `point`; a point on a plane goes to the positive side
+    pub fn leaf_at(&self, point: &Point3<f32>) -> Option<usize> {
+        let mut index = 0;
+
+        // depth is bounded by the node count in case of malformed cycles
+        for _ in 0..self.nodes.len() {
+            match *self.nodes.get(index)? {
+                BspNode::Leaf { .. } => return Some(index),
+                BspNode::Branch { axis, distance, negative, positive } => {
+                    index = if axis.coordinate(point) < distance { negative? } else { positive? };
+                }
+            }
+        }
+
+        None
+    }
+
+    // calls `f` with the faces of every leaf the ray passes through between `min`
+    // and `max`, nearest first, along with the part of the ray inside that leaf
+    fn traverse<F: FnMut(&[u16], f32, f32)>(&self, node: usize, origin: &Point3<f32>, direction: &Vector3<f32>, min: f32, max: f32, f: &mut F) {
+        match self.nodes.get(node) {
+            Some(&BspNode::Leaf { .. }) => f(self.faces(node), min, max),
+            Some(&BspNode::Branch { axis, distance, negative, positive }) => {
+                let start = axis.coordinate(origin);
+                let step = axis.coordinate(&Point3::from_coordinates(*direction));
+
+                let starts_negative = start < distance || (start == distance && step < 0.0);
+                let (near, far) = if starts_negative { (negative, positive) } else { (positive, negative) };
+
+                // distance to the plane; never crossed when parallel or behind
+                let split = if step != 0.0 { (distance - start) / step } else { -1.0 };
+
+                // parts of [min, max] on the near and far side
+                let (near_range, far_range) = if split < 0.0 || split > max {
+                    (Some((min, max)), None)
+                } else if split < min {
+                    (None, Some((min, max)))
+                } else {
+                    (Some((min, split)), Some((split, max)))
+                };
+
+                if let (Some(near), Some((min, max))) = (near, near_range) {
+                    self.traverse(near, origin, direction, min, max, f);
+                }
+                if let (Some(far), Some((min, max))) = (far, far_range) {
+                    self.traverse(far, origin, direction, min, max, f);
+                }
+            }
+            None => {}
+        }
+    }
+
+    // triangle indices of a leaf; empty for branches
+    pub fn faces(&self, node: usize) -> &[u16] {
+        match self.nodes.get(node) {
+            Some(BspNode::Leaf { faces }) => {
+                let end = faces.end.min(self.face_refs.len());
+                &self.face_refs[faces.start.min(end)..end]
+            }
+            _ => &[],
+        }
+    }
+}
+
+impl BspAxis {
+    pub fn coordinate(&self, point: &Point3<f32>) -> f32 {
+        match *self {
+            BspAxis::X => point.x,
+            BspAxis::Y => point.y,
+            BspAxis::Z => point.z,
+        }
+    }
+}
+
+impl LiquidTile {
+    pub fn liquid_type(&self) -> u8 {
+        self.0 & 0x0F
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.0 & 0x08 == 0
+    }
+
+    pub fn is_fishable(&self) -> bool {
+        self.0 & 0x40 != 0
+    }
+
+    pub fn is_fatigue(&self) -> bool {
+        self.0 & 0x80 != 0
+    }
+}
+
+// part of a ray inside a box, as distances along it; slab method
+fn ray_box(bounds: &LocalBounds, origin: &Point3<f32>, direction: &Vector3<f32>) -> Option<(f32, f32)> {
+    let mut near = 0f32;
+    let mut far = f32::INFINITY;
+
+    for axis in 0..3 {
+        if direction[axis] == 0.0 {
+            if origin[axis] < bounds.min[axis] || origin[axis] > bounds.max[axis] { return None }
+            continue;
+        }
+
+        let a = (bounds.min[axis] - origin[axis]) / direction[axis];
+        let b = (bounds.max[axis] - origin[axis]) / direction[axis];
+        near = near.max(a.min(b));
+        far = far.min(a.max(b));
+    }
+
+    if near <= far { Some((near, far)) } else { None }
+}
+
+// reads only the root file header, without touching any group files
+pub fn load_bounds(reader: Arc<dyn ResourceReader>, name: &str) -> Result<LocalBounds> {
+    let mut chunks = Chunked::stream(reader.open(name)?)?;
+
+    while let Some(chunk) = chunks.next_chunk()? {
+        if chunk.token == Token::MOHD {
+            let mut cursor = Cursor::new(chunks.read_body(&chunk)?);
+            cursor.seek(SeekFrom::Start(0x24))?;
+            let_read! { LE | cursor =>
+                bounding_box_min: (f32, f32, f32);
+                bounding_box_max: (f32, f32, f32);
+            }
+
+            return Ok(LocalBounds::from_box(
+                Point3::new(bounding_box_min.0, bounding_box_min.1, bounding_box_min.2),
+                Point3::new(bounding_box_max.0, bounding_box_max.1, bounding_box_max.2),
+            ));
+        }
+    }
+
+    Err(Error::MissingChunk { format: "WMO", token: Token::MOHD }.in_file(name))
+}
+
+// the first 48 of the 64 bytes of a MOMT entry
+#[derive(Debug, FromRead)]
+#[allow(dead_code)]
+struct MaterialRecord {
+    flags: u32,
+    shader_id: u32,
+    blend_mod: u32,
+    // offset into MOTX, or a file data id
+    diffuse_name_index: u32,
+    emissive_color: (u8, u8, u8, u8),
+    sidn_emissive_color: (u8, u8, u8, u8), // self-illuminated d? n?
+    env_name_index: u32,
+    diff_color: u32,
+    ground_type: u32,
+    texture_2: u32,
+    color_2: u32,
+    flags_2: u32,
+}
+
+// MOGP up to the fields that differ between v14 and v17
+#[derive(Debug, FromRead)]
+#[allow(dead_code)]
+struct GroupHeader {
+    group_id: u32,
+    group_name: u32,
+    flags: GroupFlags,
+    aabox_min: (f32, f32, f32),
+    aabox_max: (f32, f32, f32),
+    // v14 has 32 bit portal fields
+    #[from_read(version = "version == 14")]
+    portal_start_v14: u32,
+    #[from_read(version = "version == 14")]
+    portal_count_v14: u32,
+    #[from_read(version = "version != 14")]
+    portal_start: u16,
+    #[from_read(version = "version != 14")]
+    portal_count: u16,
+    trans_batch_count: u16,
+    int_batch_count: u16,
+    ext_batch_count: u16,
+    padding_or_batch_type: u16,
+    fog_ids: (u8, u8, u8, u8),
+    group_liquid: u32,
+}
+
+impl GroupHeader {
+    // `version` as the header was read with
+    fn portals(&self, version: u32) -> Range<usize> {
+        let (start, count) = if version == 14 {
+            (self.portal_start_v14 as usize, self.portal_count_v14 as usize)
+        } else {
+            (self.portal_start as usize, self.portal_count as usize)
+        };
+        start..start + count
+    }
+}
+
+// stored as bgra
+fn read_bgra(mut reader: &mut dyn Read) -> io::Result<RGBA8> {
+    let (b, g, r, a) = reader.read_u8tuple4()?;
+    Ok(RGBA8 { r, g, b, a })
+}
+
+// `version` decides how MOMT references textures; without it that's guessed
+// from whether the file has MOTX
+pub fn load(reader: Arc<dyn ResourceReader>, name: &str, version: Option<GameVersion>) -> Result<MapObject> {
+    MapObject::load(&*reader, name, &LoadOptions { version, ..LoadOptions::default() })
+}
+
+fn group_file_name(name: &str, index: usize) -> String {
+    let (dir, file, _) = split_resource_name(name);
+    format!("{}{}_{:03}.wmo", dir, file, index)
+}
+
+// group files share the extension, but only root files load as `MapObject`
+impl Asset for MapObject {
+    const EXTENSIONS: &'static [&'static str] = &[".wmo"];
+
+    fn load(reader: &dyn ResourceReader, name: &str, options: &LoadOptions) -> Result<MapObject> {
+        options.apply(name, || {
+            let mut data = Vec::new();
+            reader.open(name)?.read_to_end(&mut data)?;
+            parse(&data, name, options.version)
+        })
+    }
+}
+
+// parses a root file from memory. `name` is only used to derive the group file
+// names, which are opened later by `MeshGroupInfo::load` or passed to `parse_group`
+pub fn parse(data: &[u8], name: &str, version: Option<GameVersion>) -> Result<MapObject> {
+    let mut map_object = MapObject::default();
+
+    let mut textures_index = BTreeMap::new();
+    let mut portal_vertices = Vec::new();
+    let mut portal_infos = Vec::new();
+    let mut group_names_table = BTreeMap::new();
+    let mut m2_table = BTreeMap::new();
+    let mut doodad_names = Vec::new();
+    let mut group_file_data_ids = Vec::new();
+    let mut uv_speeds = Vec::new();
+
+    // alpha (v14) files wrap the root chunks in MOMO and embed the groups after it
+    let mut chunks = Vec::new();
+    let mut embedded_groups = Vec::new();
+    let mut position = 0u64;
+
+    for chunk in Chunked::over(data) {
+        let chunk = chunk?;
+        let size = 8 + chunk.data.len() as u64;
+
+        match chunk.token {
+            Token::MOMO => {
+                for inner in Chunked::over(chunk.data) {
+                    chunks.push(inner?);
+                }
+            }
+            Token::MOGP => embedded_groups.push(position..position + size),
+            _ => chunks.push(chunk),
+        }
+
+        position += size;
+    }
+
+    for chunk in chunks {
+        let mut cursor = chunk.cursor();
+
+        match chunk.token {
+            // version
+            Token::MVER => {
+                let version = cursor.read_u32::<LE>()?;
+                if version != 17 && version != 14 {
+                    return Err(Error::UnsupportedVersion { format: "WMO", found: version }.in_file(name))
+                }
+                map_object.version = version;
+            }
+            // header
+            Token::MOHD => {
+                map_object.header = MapObjectHeader::from_read_version::<LE>(&mut cursor, map_object.version)?;
+                trace!("{:?}", map_object.header);
+            }
+            // texture paths
+            Token::MOTX => {
+                read_ext::cstring_table_with(chunk.data, |offset, path| {
+                    let index = map_object.textures.len();
+                    map_object.textures.push(path);
+                    textures_index.insert(offset, index);
+                })?;
+            }
+            // materials
+            Token::MOMT if map_object.version == 14 => trace!("skipping v14 materials"),
+            Token::MOMT => {
+                let file_ids = version.map(|version| version.has_material_file_ids()).unwrap_or(textures_index.is_empty());
+
+                for index in 0..(chunk.data.len() / 64) {
+                    trace!("--- MATERIAL #{} ---", index);
+
+                    // WoWModelViewer struct
+                    // let_read! { LE | cursor =>
+                    //     flags: i32;
+                    //     specular_mode: i32;
+                    //     transparent: i32; // Blending: 0 for opaque, 1 for transparent
+                    //     name_start: i32; // Start position for the first texture filename in the MOTX data block
+                    //     color1: (u8, u8, u8, u8);
+                    //     flag1: u32;
+                    //     name_end: i32; // Start position for the second texture filename in the MOTX data block
+                    //     color2: (u8, u8, u8, u8);
+                    //     flag2: u32;
+                    //     f1: f32;
+                    //     f2: f32;
+                    //     dx1: i32;
+                    //     dx2: i32;
+                    //     dx3: i32;
+                    //     dx4: i32;
+                    //     dx5: i32;
+                    // }
+
+                    cursor.seek(SeekFrom::Start((index * 64) as u64))?;
+                    let record = MaterialRecord::from_read::<LE>(&mut cursor)?;
+                    trace!("{:?}", record);
+
+                    let diffuse_name_index = record.diffuse_name_index;
+                    map_object.materials.push(Material {
+                        texture_id: if file_ids { None } else { textures_index.get(&diffuse_name_index).cloned() },
+                        texture_file_data_id: if file_ids && diffuse_name_index != 0 { Some(diffuse_name_index) } else { None },
+                        uv_speed: [(0.0, 0.0); 2],
+                    });
+                }
+            }
+            // wmo group names
+            Token::MOGN => {
+                read_ext::cstring_table_into(chunk.data, &mut group_names_table)?;
+            }
+            // wmo group metadata
+            Token::MOGI => {
+                // v14 entries start with the offset and size of the embedded group
+                let entry_size = if map_object.version == 14 { 40 } else { 32 };
+
+                for index in 0..(chunk.data.len() / entry_size) {
+                    trace!("--- MESH GROUP INFO #{} ---", index);
+                    cursor.seek(SeekFrom::Start((index * entry_size + entry_size - 32) as u64))?;
+                    let_read! { LE | cursor =>
+                        flags: u32;
+                        bounding_box_min: (f32, f32, f32);
+                        bounding_box_max: (f32, f32, f32);
+                        name_offset: i32;
+                    }
+
+                    let group_name = if name_offset >= 0 {
+                        group_names_table.get(&(name_offset as u32)).map(|s| s.to_owned())
+                    } else {
+                        None
+                    };
+
+                    let embedded = if map_object.version == 14 { embedded_groups.get(index).cloned() } else { None };
+
+                    let group_path = if embedded.is_some() { name.to_owned() } else { group_file_name(name, index) };
+
+                    let group_info = MeshGroupInfo {
+                        resource_key: group_path,
+                        flags: GroupFlags::from_bits_truncate(flags),
+                        bounding_box_min,
+                        bounding_box_max,
+                        name: group_name,
+                        embedded,
+                        file_data_ids: Vec::new(),
+                    };
+
+                    map_object.groups.push(group_info);
+                }
+            }
+            // lights
+            // the v14 light layout isn't decoded
+            Token::MOLT if map_object.version == 14 => trace!("skipping v14 lights"),
+            Token::MOLT => {
+                for index in 0..(chunk.data.len() / 48) {
+                    trace!("--- LIGHT #{} ---", index);
+                    let_read! { LE | cursor =>
+                        light_type: u8;
+                        use_attenuation: u8;
+                        padding: u16;
+                        color: (u8, u8, u8, u8);
+                        position: (f32, f32, f32);
+                        intensity: f32;
+                        rotation: (f32, f32, f32, f32);
+                        attenuation_start: f32;
+                        attenuation_end: f32;
+                    }
+
+                    map_object.lights.push(Light {
+                        kind: LightKind::from_wmo(light_type),
+                        use_attenuation: use_attenuation != 0,
+                        color: RGBA8 { b: color.0, g: color.1, r: color.2, a: color.3 },
+                        position: Point3::new(position.0, position.1, position.2),
+                        intensity,
+                        rotation: Quaternion::new(rotation.3, rotation.0, rotation.1, rotation.2),
+                        attenuation_start,
+                        attenuation_end,
+                    });
+                }
+            }
+            // skybox, an empty string when there is none
+            Token::MOSB if !chunk.data.is_empty() => {
+                let name = read_ext::cstring(chunk.data)?;
+                if !name.is_empty() {
+                    map_object.skybox = Some(name);
+                }
+            }
+            // uv animation, two translation speeds per material
+            Token::MOUV => {
+                uv_speeds = read_records::<[f32; 2]>(chunk.data)?;
+            }
+            // convex volume planes
+            Token::MCVP => {
+                for _ in 0..(chunk.data.len() / 16) {
+                    let_read! { LE | cursor =>
+                        normal: (f32, f32, f32);
+                        distance: f32;
+                    }
+                    map_object.convex_volume_planes.push(Plane { normal: Vector3::new(normal.0, normal.1, normal.2), distance });
+                }
+            }
+            // group file data ids, all groups of every LOD level in turn
+            Token::GFID => {
+                group_file_data_ids = read_records::<u32>(chunk.data)?;
+            }
+            // portal vertices
+            Token::MOPV => {
+                let vertices = read_records::<[f32; 3]>(chunk.data)?;
+                portal_vertices.extend(vertices.into_iter().map(|v| Point3::new(v[0], v[1], v[2])));
+            }
+            // portals
+            Token::MOPT => {
+                for _ in 0..(chunk.data.len() / 20) {
+                    let_read! { LE | cursor =>
+                        start_vertex: u16;
+                        vertex_count: u16;
+                        normal: (f32, f32, f32);
+                        distance: f32;
+                    }
+                    portal_infos.push((start_vertex as usize, vertex_count as usize, Vector3::new(normal.0, normal.1, normal.2), distance));
+                }
+            }
+            // portal references
+            Token::MOPR => {
+                for _ in 0..(chunk.data.len() / 8) {
+                    let_read! { LE | cursor =>
+                        portal: u16;
+                        group: u16;
+                        side: i16;
+                        _filler: u16;
+                    }
+                    map_object.portal_refs.push(PortalRef { portal, group, side });
+                }
+            }
+            // doodad sets
+            Token::MODS => {
+                for _ in 0..(chunk.data.len() / 32) {
+                    let mut name = [0u8; 20];
+                    cursor.read_exact(&mut name)?;
+                    let_read! { LE | cursor =>
+                        start: u32;
+                        count: u32;
+                        _padding: u32;
+                    }
+
+                    let name = name.split(|&byte| byte == 0).next().unwrap_or(&[]);
+                    map_object.doodad_sets.push(DoodadSet {
+                        name: String::from_utf8_lossy(name).into_owned(),
+                        doodads: start as usize..(start as usize + count as usize),
+                    });
+                }
+            }
+            // m2 paths
+            Token::MODN => {
+                read_ext::cstring_table_into(chunk.data, &mut m2_table)?;
+            }
+            // m2 placement definitions
+            Token::MODD => {
+                for _ in 0..(chunk.data.len() / 40) {
+                    let_read! { LE | cursor =>
+                        name: u32;
+                        position: (f32, f32, f32);
+                        rotation: (f32, f32, f32, f32);
+                        scale: f32;
+                        color: (u8, u8, u8, u8);
+                    }
+
+                    // offset into MODN, resolved once all chunks are read
+                    doodad_names.push(name & 0xFFFFFF);
+                    map_object.doodads.push(DoodadPlacement {
+                        model: None,
+                        flags: (name >> 24) as u8,
+                        position: Point3::new(position.0, position.1, position.2),
+                        rotation: Quaternion::new(rotation.3, rotation.0, rotation.1, rotation.2),
+                        scale,
+                        color: RGBA8 { b: color.0, g: color.1, r: color.2, a: color.3 },
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (material, speeds) in map_object.materials.iter_mut().zip(uv_speeds.chunks(2)) {
+        for (layer, speed) in speeds.iter().enumerate() {
+            material.uv_speed[layer] = (speed[0], speed[1]);
+        }
+    }
+
+    let group_count = map_object.groups.len();
+    for (index, group) in map_object.groups.iter_mut().enumerate() {
+        group.file_data_ids = group_file_data_ids.iter().skip(index).step_by(group_count.max(1)).cloned().collect();
+    }
+
+    // MODN offsets become indices into `m2`
+    let m2_index: BTreeMap<u32, usize> = m2_table.keys().enumerate().map(|(index, &offset)| (offset, index)).collect();
+    map_object.m2 = m2_table.into_values().collect();
+    for (doodad, offset) in map_object.doodads.iter_mut().zip(doodad_names) {
+        doodad.model = m2_index.get(&offset).cloned();
+    }
+
+    // vertices are resolved last, as MOPT may precede MOPV
+    for (start, count, normal, distance) in portal_infos {
+        let vertices = portal_vertices.get(start..start + count)
+            .ok_or_else(|| io_error!(InvalidData, "portal refers to missing vertices {}..{}", start, start + count))?;
+        map_object.portals.push(Portal { vertices: vertices.to_vec(), normal, distance });
+    }
+
+    Ok(map_object)
+}
+
+// `version` selects the MOGP layout; v14 groups come without MVER
+// a group file already in memory; `version` is `MapObject::version` of its root
+pub fn parse_group(data: &[u8], version: u32) -> Result<MeshGroup> {
+    Ok(read_group_file(data, version)?)
+}
+
+fn read_group_file(data: &[u8], version: u32) -> io::Result<MeshGroup> {
+    let mut mesh_group = MeshGroup {
+        flags: GroupFlags::empty(),
+        group_liquid: 0,
+        transparent_batch_count: 0,
+        interior_batch_count: 0,
+        exterior_batch_count: 0,
+        fog_ids: [0; 4],
+        indexes: Vec::new().into(),
+        vertices: Vec::new().into(),
+        normals: Vec::new().into(),
+        texcoords: Vec::new(),
+        colors: Vec::new(),
+        batches: Vec::new(),
+        shadow_batches: Vec::new(),
+        bounds: LocalBounds::from_box(Point3::origin(), Point3::origin()),
+        faces: Vec::new(),
+        portals: 0..0,
+        liquid: None,
+        bsp: BspTree::default(),
+    };
+
+    for chunk in Chunked::over(data) {
+        let root_chunk = chunk?;
+        let mut root_cursor = root_chunk.cursor();
+
+        match root_chunk.token {
+            // version
+            Token::MVER => {
+                let version = root_cursor.read_u32::<LE>()?;
+                if version != 17 {
+                    return Err(Error::UnsupportedVersion { format: "WMO", found: version }.into())
+                }
+            }
+            Token::MOGP => {
+                let header = GroupHeader::from_read_version::<LE>(&mut root_cursor, version)?;
+                trace!("{:?}", header);
+
+                // v17: unique id, second flags and an unknown field;
+                // v14: eight lightmap batch ranges and an unknown field
+                root_cursor.seek(SeekFrom::Current(if version == 14 { 0x44 } else { 12 }))?;
+
+                let (min, max, fog_ids) = (header.aabox_min, header.aabox_max, header.fog_ids);
+                mesh_group.flags = header.flags;
+                mesh_group.group_liquid = header.group_liquid;
+                mesh_group.transparent_batch_count = header.trans_batch_count;
+                mesh_group.interior_batch_count = header.int_batch_count;
+                mesh_group.exterior_batch_count = header.ext_batch_count;
+                mesh_group.fog_ids = [fog_ids.0, fog_ids.1, fog_ids.2, fog_ids.3];
+                mesh_group.bounds = LocalBounds::from_box(Point3::new(min.0, min.1, min.2), Point3::new(max.0, max.1, max.2));
+                mesh_group.portals = header.portals(version);
+
+                for chunk in root_chunk.subchunks(root_cursor.position() as usize)? {
+                    let chunk = chunk?;
+                    let mut cursor = chunk.cursor();
+
+                    match chunk.token {
+                        Token::MOPY => {
+                            // v14 pads every entry to 4 bytes
+                            let entry_size = if version == 14 { 4 } else { 2 };
+
+                            for index in 0..(chunk.data.len() / entry_size) {
+                                cursor.seek(SeekFrom::Start((index * entry_size) as u64))?;
+                                let_read! { LE | cursor =>
+                                    flags: u8;
+                                    material_id: u8;
+                                }
+                                mesh_group.faces.push(FaceInfo { flags: FaceFlags::from_bits_truncate(flags), material_id });
+                            }
+                        }
+                        // MOIN is the v14 name
+                        Token::MOVI | Token::MOIN => {
+                            mesh_group.indexes.extend(read_records::<u16>(chunk.data)?);
+                        }
+                        Token::MOVT => {
+                            let vertices = read_records::<[f32; 3]>(chunk.data)?;
+                            mesh_group.vertices.extend(vertices.into_iter().map(|v| (v[0], v[1], v[2])));
+                        }
+                        Token::MONR => {
+                            let normals = read_records::<[f32; 3]>(chunk.data)?;
+                            mesh_group.normals.extend(normals.into_iter().map(|n| (n[0], n[1], n[2])));
+                        }
+                        Token::MOTV => {
+                            let texcoords = read_records::<[f32; 2]>(chunk.data)?;
+                            mesh_group.texcoords.push(texcoords.into_iter().map(|t| (t[0], t[1])).collect::<Vec<_>>().into());
+                        }
+                        Token::MOCV => {
+                            let colors = read_records::<[u8; 4]>(chunk.data)?;
+                            mesh_group.colors.push(colors.into_iter().map(|c| RGBA8 { b: c[0], g: c[1], r: c[2], a: c[3] }).collect::<Vec<_>>().into());
+                        }
+                        Token::MOBA if version == 14 => {
+                            for index in 0..(chunk.data.len() / 24) {
+                                trace!("--- RENDER BATCH #{} ---", index);
+                                cursor.seek(SeekFrom::Start((index * 24) as u64))?;
+                                let_read! { LE | cursor =>
+                                    _light_map: u8;
+                                    material_id: u8;
+                                }
+
+                                // skip the bounding box (6 x i16)
+                                cursor.seek(SeekFrom::Current(12))?;
+                                let_read! { LE | cursor =>
+                                    index_start: u16;
+                                    index_count: u16;
+                                    vertex_start: u16;
+                                    vertex_end: u16;
+                                    _flags: u8;
+                                    _padding: u8;
+                                }
+
+                                mesh_group.batches.push(RenderBatch {
+                                    material_id: material_id as u16,
+                                    index_start: index_start as u32,
+                                    index_count,
+                                    vertex_start,
+                                    vertex_end,
+                                });
+                            }
+                        }
+                        Token::MOBA => {
+                            mesh_group.batches = read_batches(&mut cursor, chunk.data.len() / 24)?;
+                        }
+                        // shadow batches (legion+), laid out like MOBA
+                        Token::MOBS => {
+                            mesh_group.shadow_batches = read_batches(&mut cursor, chunk.data.len() / 24)?;
+                        }
+                        Token::MLIQ => {
+                            mesh_group.liquid = Some(read_liquid(&mut cursor, chunk.data.len())?);
+                        }
+                        Token::MOBN => {
+                            for _ in 0..(chunk.data.len() / 16) {
+                                let_read! { LE | cursor =>
+                                    flags: u16;
+                                    negative: i16;
+                                    positive: i16;
+                                    face_count: u16;
+                                    face_start: u32;
+                                    distance: f32;
+                                }
+
+                                let child = |index: i16| if index >= 0 { Some(index as usize) } else { None };
+                                let axis = match flags & 0x3 {
+                                    0 => BspAxis::X,
+                                    1 => BspAxis::Y,
+                                    _ => BspAxis::Z,
+                                };
+
+                                mesh_group.bsp.nodes.push(if flags & 0x4 != 0 {
+                                    BspNode::Leaf { faces: face_start as usize..(face_start as usize + face_count as usize) }
+                                } else {
+                                    BspNode::Branch { axis, distance, negative: child(negative), positive: child(positive) }
+                                });
+                            }
+                        }
+                        Token::MOBR => {
+                            mesh_group.bsp.face_refs.extend(read_records::<u16>(chunk.data)?);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(mesh_group)
+}
+
+fn read_batches<R: ReadExt>(mut input: &mut R, count: usize) -> io::Result<Vec<RenderBatch>> {
+    let mut batches = Vec::with_capacity(count);
+
+    for index in 0..count {
+        trace!("--- RENDER BATCH #{} ---", index);
+        let_read! { LE | input =>
+            unknown1: u16;
+            unknown2: u16;
+            unknown3: u16;
+            unknown4: u16;
+            unknown5: u16;
+            material_id_large: u16;
+            index_start: u32;
+            index_count: u16;
+            vertex_start: u16;
+            vertex_end: u16;
+            flags: u8;
+            material_id_little: u8;
+        }
+
+        batches.push(RenderBatch {
+            material_id: material_id_little as u16,
+            index_start,
+            index_count,
+            vertex_start,
+            vertex_end
+        });
+    }
+
+    Ok(batches)
+}
+
+// `size` is the whole MLIQ chunk, which the declared counts have to fit into
+fn read_liquid<R: ReadExt>(mut input: &mut R, size: usize) -> io::Result<Liquid> {
+    let_read! { LE | input =>
+        vertices_x: u32;
+        vertices_y: u32;
+        tiles_x: u32;
+        tiles_y: u32;
+        position: (f32, f32, f32);
+        material_id: u16;
+    }
+
+    let vertex_count = vertices_x as u64 * vertices_y as u64;
+    let tile_count = tiles_x as u64 * tiles_y as u64;
+    if vertex_count * 8 + tile_count > size as u64 {
+        return Err(io_error!(InvalidData, "liquid of {}x{} vertices and {}x{} tiles doesn't fit its {} byte chunk", vertices_x, vertices_y, tiles_x, tiles_y, size));
+    }
+
+    let mut vertices = Vec::with_capacity(vertex_count as usize);
+    for _ in 0..vertex_count {
+        let mut data = [0u8; 4];
+        input.read_exact(&mut data)?;
+        vertices.push(LiquidVertex { data, height: input.read_f32::<LE>()? });
+    }
+
+    let mut tiles = Vec::with_capacity(tile_count as usize);
+    for _ in 0..tile_count {
+        tiles.push(LiquidTile(input.read_u8()?));
+    }
+
+    Ok(Liquid {
+        vertices_x,
+        vertices_y,
+        tiles_x,
+        tiles_y,
+        position: Point3::new(position.0, position.1, position.2),
+        material_id,
+        vertices,
+        tiles,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{chunk, f32s, memory_reader};
+
+    #[test]
+    fn test_load_portals() {
+        let mut portal = vec![1, 0, 3, 0];
+        portal.extend(f32s(&[1.0, 0.0, 0.0, -2.0]));
+
+        let mut file = chunk(b"MVER", &17u32.to_le_bytes());
+        file.extend(chunk(b"MOPT", &portal));
+        file.extend(chunk(b"MOPV", &f32s(&[9.0, 9.0, 9.0, 2.0, 0.0, 0.0, 2.0, 1.0, 0.0, 2.0, 1.0, 1.0])));
+        file.extend(chunk(b"MOPR", &[0, 0, 1, 0, 0xFF, 0xFF, 0, 0]));
+        file.extend(chunk(b"MOSB", b"Environments\\Stars\\Sky.m2\0\0\0"));
+
+        let mut set = b"Set_Chairs\0\0\0\0\0\0\0\0\0\0".to_vec();
+        set.extend_from_slice(&[1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+        file.extend(chunk(b"MODS", &set));
+        file.extend(chunk(b"MODN", b"\0\0A.mdx\0B.mdx\0\0"));
+        let mut doodad = (8u32 | 0x01 << 24).to_le_bytes().to_vec();
+        doodad.extend(f32s(&[1.0, 2.0, 3.0, 0.0, 0.0, 0.0, 1.0, 2.0]));
+        doodad.extend_from_slice(&[0, 0, 255, 255]);
+        file.extend(chunk(b"MODD", &doodad));
+
+        let mut map_object = load(memory_reader(vec![("a.wmo", file)]), "a.wmo", None).unwrap();
+        assert_eq!(map_object.portals[0].vertices, vec![Point3::new(2.0, 0.0, 0.0), Point3::new(2.0, 1.0, 0.0), Point3::new(2.0, 1.0, 1.0)]);
+        assert_eq!((map_object.portals[0].normal, map_object.portals[0].distance), (Vector3::x(), -2.0));
+        assert_eq!((map_object.portal_refs[0].group, map_object.portal_refs[0].side), (1, -1));
+        assert_eq!(map_object.skybox, Some("Environments\\Stars\\Sky.m2".to_string()));
+
+        assert_eq!(map_object.m2, vec!["A.mdx".to_string(), "B.mdx".to_string()]);
+        assert_eq!((map_object.doodad_sets[0].name.as_str(), map_object.doodad_sets[0].doodads.clone()), ("Set_Chairs", 1..3));
+
+        // set 0 is added to every set
+        let mut other = map_object.doodads[0].clone();
+        other.model = Some(0);
+        map_object.doodads.push(other);
+        map_object.doodad_sets.insert(0, DoodadSet { name: "Set_$DefaultGlobal".to_string(), doodads: 0..1 });
+        let models = |set: usize| map_object.doodads_for_set(set).map(|doodad| doodad.model).collect::<Vec<_>>();
+        assert_eq!(models(0), vec![Some(1)]);
+        assert_eq!(models(1), vec![Some(1), Some(0)]);
+        assert_eq!(models(5), vec![Some(1)]);
+        let doodad = &map_object.doodads[0];
+        assert_eq!((doodad.model, doodad.flags, doodad.color), (Some(1), 1, RGBA8 { r: 255, g: 0, b: 0, a: 255 }));
+        assert_eq!(Point3::from_homogeneous(doodad.transform() * Point3::new(1.0, 0.0, 0.0).to_homogeneous()), Some(Point3::new(3.0, 2.0, 3.0)));
+
+        let mut header = vec![0u8; 0x44];
+        header[0x08..0x0C].copy_from_slice(&0x2001u32.to_le_bytes());
+        header[0x24..0x2C].copy_from_slice(&[0, 0, 1, 0, 2, 0, 0, 0]);
+        header[0x30..0x38].copy_from_slice(&[1, 2, 3, 4, 14, 0, 0, 0]);
+        let mut group = chunk(b"MVER", &17u32.to_le_bytes());
+        group.extend(chunk(b"MOGP", &header));
+
+        let group = read_group_file(&group, 17).unwrap();
+        assert_eq!(group.portals, 0..1);
+        assert!(group.texcoords.is_empty() && group.colors.is_empty());
+        assert_eq!(group.flags, GroupFlags::INDOOR | GroupFlags::HAS_BSP_TREE);
+        assert_eq!((group.transparent_batch_count, group.fog_ids, group.group_liquid), (2, [1, 2, 3, 4], 14));
+        assert_eq!(map_object.group_portals(&group).len(), 1);
+    }
+
+    #[test]
+    fn test_load_group_file_data_ids() {
+        let mut group_info = vec![0u8; 32];
+        group_info[28..32].copy_from_slice(&(-1i32).to_le_bytes());
+
+        let mut file = chunk(b"MVER", &17u32.to_le_bytes());
+        file.extend(chunk(b"MOGI", &group_info));
+        file.extend(chunk(b"GFID", &[100, 0, 0, 0, 200, 0, 0, 0]));
+
+        // one group with two LOD levels
+        let map_object = load(memory_reader(vec![("a.wmo", file)]), "a.wmo", None).unwrap();
+        assert_eq!(map_object.groups[0].file_data_ids, vec![100, 200]);
+        assert_eq!(map_object.groups[0].resource_key, "a_000.wmo");
+        // falls back to the name, as the reader doesn't know file data ids
+        assert!(map_object.groups[0].load(memory_reader(vec![("a_000.wmo", Vec::new())])).is_ok());
+    }
+
+    #[test]
+    fn test_load_convex_volume_planes() {
+        let mut file = chunk(b"MVER", &17u32.to_le_bytes());
+        file.extend(chunk(b"MCVP", &f32s(&[0.0, 0.0, 1.0, -5.0])));
+
+        let map_object = load(memory_reader(vec![("a.wmo", file)]), "a.wmo", None).unwrap();
+        assert_eq!(map_object.convex_volume_planes, vec![Plane { normal: Vector3::z(), distance: -5.0 }]);
+    }
+
+    #[test]
+    fn test_load_shadow_batches() {
+        let mut batch = vec![0u8; 24];
+        batch[16..18].copy_from_slice(&6u16.to_le_bytes());
+        batch[23] = 2;
+
+        let mut header = vec![0u8; 0x44];
+        header.extend(chunk(b"MOBS", &batch));
+        let mut group = chunk(b"MVER", &17u32.to_le_bytes());
+        group.extend(chunk(b"MOGP", &header));
+
+        let group = read_group_file(&group, 17).unwrap();
+        assert_eq!((group.shadow_batches[0].material_id, group.shadow_batches[0].index_count), (2, 6));
+        assert!(group.batches.is_empty());
+    }
+
+    #[test]
+    fn test_load_materials() {
+        let mut material = vec![0u8; 64];
+        material[12..16].copy_from_slice(&8u32.to_le_bytes());
+
+        let mut file = chunk(b"MVER", &17u32.to_le_bytes());
+        file.extend(chunk(b"MOTX", b"A.blp\0\0\0Lava.blp\0\0\0\0"));
+        file.extend(chunk(b"MOMT", &[material.clone(), material].concat()));
+        file.extend(chunk(b"MOUV", &f32s(&[0.0, 0.5, 0.0, 0.0, 0.0, 0.0, -0.25, 0.0])));
+
+        let map_object = load(memory_reader(vec![("a.wmo", file.clone())]), "a.wmo", None).unwrap();
+        assert_eq!(map_object.materials.len(), 2);
+        assert_eq!(map_object.materials[0].texture_id, Some(1));
+        assert_eq!(map_object.materials[0].texture_file_data_id, None);
+        assert_eq!(map_object.materials[0].uv_speed, [(0.0, 0.5), (0.0, 0.0)]);
+        assert_eq!(map_object.materials[0].uv_offset(0, 3.0), (0.0, 0.5));
+        assert_eq!(map_object.materials[1].uv_offset(1, 1.0), (0.75, 0.0));
+        assert_eq!(map_object.materials[1].uv_offset(2, 1.0), (0.0, 0.0));
+
+        // same offset read as a FileDataID
+        let map_object = load(memory_reader(vec![("a.wmo", file)]), "a.wmo", Some(GameVersion::BfA)).unwrap();
+        assert_eq!((map_object.materials[0].texture_id, map_object.materials[0].texture_file_data_id), (None, Some(8)));
+    }
+
+    #[test]
+    fn test_load_header() {
+        let mut header = vec![0u8; 0x40];
+        header[0x04..0x08].copy_from_slice(&3u32.to_le_bytes());
+        header[0x1C..0x20].copy_from_slice(&[16, 32, 64, 255]);
+        header[0x24..0x3C].copy_from_slice(&f32s(&[-1.0, -2.0, -3.0, 1.0, 2.0, 3.0]));
+        header[0x3C..0x40].copy_from_slice(&[0x05, 0, 2, 0]);
+
+        let mut file = chunk(b"MVER", &17u32.to_le_bytes());
+        file.extend(chunk(b"MOHD", &header));
+
+        let map_object = load(memory_reader(vec![("a.wmo", file)]), "a.wmo", None).unwrap();
+        let header = &map_object.header;
+        assert_eq!(header.group_count, 3);
+        assert_eq!(header.ambient_color, RGBA8 { r: 64, g: 32, b: 16, a: 255 });
+        assert_eq!(header.flags, WmoFlags::NO_PORTAL_ATTENUATION | WmoFlags::LIQUID_TYPE_DBC_ID);
+        assert_eq!(header.lod_count, 2);
+        assert_eq!(header.bounds().max, Point3::new(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn test_load_v14() {
+        let mut group_info = vec![0u8; 40];
+        group_info[8..12].copy_from_slice(&0x2000u32.to_le_bytes());
+        group_info[36..40].copy_from_slice(&(-1i32).to_le_bytes());
+
+        let mut header = vec![0u8; 0x80];
+        header[0x24..0x28].copy_from_slice(&3u32.to_le_bytes());
+        header.extend(chunk(b"MOPY", &[0x20, 1, 0, 0]));
+        header.extend(chunk(b"MOIN", &[0, 0, 1, 0, 2, 0]));
+
+        let mut file = chunk(b"MVER", &14u32.to_le_bytes());
+        file.extend(chunk(b"MOMO", &chunk(b"MOGI", &group_info)));
+        file.extend(chunk(b"MOGP", &header));
+
+        let reader = memory_reader(vec![("a.wmo", file)]);
+        let map_object = load(reader.clone(), "a.wmo", None).unwrap();
+        assert_eq!(map_object.version, 14);
+        assert_eq!(map_object.groups[0].flags, GroupFlags::INDOOR);
+        assert_eq!(map_object.groups[0].embedded, Some(68..68 + 8 + 0x80 + 12 + 14));
+
+        assert_eq!(map_object.groups_lazy_where(reader.clone(), |info| !info.flags.contains(GroupFlags::INDOOR)).count(), 0);
+        let group = map_object.groups_lazy(reader).next().unwrap().unwrap();
+        assert_eq!(group.portals, 3..3);
+        assert_eq!(group.faces[0].material_id, 1);
+        assert_eq!(&group.indexes[..], &[0, 1, 2]);
+    }
+
+    #[test]
+    fn test_group_liquid() {
+        // 2x1 tiles, the second one hidden
+        let mut liquid = Vec::new();
+        for value in &[3u32, 2, 2, 1] {
+            liquid.extend_from_slice(&value.to_le_bytes());
+        }
+        liquid.extend(f32s(&[10.0, 20.0, 0.0]));
+        liquid.extend_from_slice(&[5, 0]);
+        for height in 0..6 {
+            liquid.extend_from_slice(&[0, 0, 0, 0]);
+            liquid.extend(f32s(&[height as f32]));
+        }
+        liquid.extend_from_slice(&[0x41, 0x0F]);
+
+        let mut header = vec![0u8; 0x44];
+        header.extend(chunk(b"MLIQ", &liquid));
+        header.extend(chunk(b"MOTV", &f32s(&[0.0, 1.0])));
+        header.extend(chunk(b"MOTV", &f32s(&[0.5, 0.5])));
+        header.extend(chunk(b"MOCV", &[1, 2, 3, 4]));
+        let mut group = chunk(b"MVER", &17u32.to_le_bytes());
+        group.extend(chunk(b"MOGP", &header));
+
+        let group = read_group_file(&group, 17).unwrap();
+        assert_eq!(group.texcoords.iter().map(|set| set[0]).collect::<Vec<_>>(), vec![(0.0, 1.0), (0.5, 0.5)]);
+        assert_eq!(group.colors[0][0], RGBA8 { r: 3, g: 2, b: 1, a: 4 });
+
+        let liquid = group.liquid.unwrap();
+        assert_eq!(liquid.material_id, 5);
+        assert_eq!(liquid.tile(0, 0).map(|tile| (tile.liquid_type(), tile.is_fishable())), Some((1, true)));
+        assert_eq!(liquid.vertex_position(1, 1), Some(Point3::new(10.0 + UNIT_SIZE, 20.0 + UNIT_SIZE, 4.0)));
+
+        let mesh = liquid.build_mesh();
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(mesh.indices, vec![0, 1, 3, 1, 4, 3]);
+    }
+
+    #[test]
+    fn test_group_bsp() {
+        // root splits at z = 1; the negative leaf holds faces 1 and 2
+        let mut nodes = Vec::new();
+        nodes.extend_from_slice(&[2, 0, 1, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+        nodes.extend(f32s(&[1.0]));
+        nodes.extend_from_slice(&[4, 0, 0xFF, 0xFF, 0xFF, 0xFF, 2, 0, 1, 0, 0, 0]);
+        nodes.extend(f32s(&[0.0]));
+        nodes.extend_from_slice(&[4, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0]);
+        nodes.extend(f32s(&[0.0]));
+
+        let mut header = vec![0u8; 0x44];
+        header.extend(chunk(b"MOBN", &nodes));
+        header.extend(chunk(b"MOBR", &[7, 0, 8, 0, 9, 0]));
+        let mut group = chunk(b"MVER", &17u32.to_le_bytes());
+        group.extend(chunk(b"MOGP", &header));
+
+        let bsp = read_group_file(&group, 17).unwrap().bsp;
+        assert_eq!(bsp.leaf_at(&Point3::new(5.0, 5.0, 0.0)), Some(1));
+        assert_eq!(bsp.leaf_at(&Point3::new(5.0, 5.0, 1.0)), Some(2));
+        assert_eq!(bsp.faces(1), &[8, 9]);
+        assert_eq!(bsp.faces(2), &[] as &[u16]);
+        assert_eq!(bsp.faces(0), &[] as &[u16]);
+    }
+
+    #[test]
+    fn test_raycast() {
+        // two stacked floors at z = 0 and z = 2, split by the bsp at z = 1
+        let mut group = read_group_file(&[], 17).unwrap();
+        group.vertices = vec![
+            (0.0, 0.0, 0.0), (4.0, 0.0, 0.0), (0.0, 4.0, 0.0),
+            (0.0, 0.0, 2.0), (4.0, 0.0, 2.0), (0.0, 4.0, 2.0),
+        ].into();
+        group.indexes = vec![0, 1, 2, 3, 4, 5].into();
+        group.faces = vec![
+            FaceInfo { flags: FaceFlags::COLLISION, material_id: 0xFF },
+            FaceInfo { flags: FaceFlags::RENDER, material_id: 0 },
+        ];
+        group.bounds = LocalBounds::from_box(Point3::new(0.0, 0.0, 0.0), Point3::new(4.0, 4.0, 2.0));
+
+        let down = Vector3::new(0.0, 0.0, -1.0);
+        let start = Point3::new(1.0, 1.0, 5.0);
+        let brute = group.raycast(&start, &down).unwrap();
+        assert_eq!((brute.face, brute.distance, brute.flags), (1, 3.0, FaceFlags::RENDER));
+
+        group.bsp.nodes = vec![
+            BspNode::Branch { axis: BspAxis::Z, distance: 1.0, negative: Some(1), positive: Some(2) },
+            BspNode::Leaf { faces: 0..1 },
+            BspNode::Leaf { faces: 1..2 },
+        ];
+        group.bsp.face_refs = vec![0, 1];
+        assert_eq!(group.raycast(&start, &down).unwrap().face, 1);
+        assert_eq!(group.raycast_with(&start, &down, |flags| flags.contains(FaceFlags::COLLISION)).unwrap().position, Point3::new(1.0, 1.0, 0.0));
+        assert!(group.raycast(&start, &-down).is_none());
+        assert!(group.faces[0].is_collision_only() && !group.faces[1].is_collision_only());
+        assert!(group.faces[0].is_collidable() && group.faces[1].is_camera_collidable());
+        assert_eq!(group.collision_indexes(), vec![0, 1, 2, 3, 4, 5]);
+        assert!(group.raycast(&Point3::new(5.0, 5.0, 5.0), &down).is_none());
+
+        // detail faces are drawn but don't collide
+        group.faces[1].flags = FaceFlags::RENDER | FaceFlags::DETAIL;
+        assert_eq!(group.collision_indexes(), vec![0, 1, 2]);
+        group.faces[1].flags = FaceFlags::RENDER;
+
+        let map_object = load(memory_reader(vec![("a.wmo", chunk(b"MVER", &17u32.to_le_bytes()))]), "a.wmo", None).unwrap();
+        assert_eq!(map_object.skybox, None);
+        let transform = Matrix4::new_translation(&Vector3::new(10.0, 0.0, 0.0));
+        let (index, hit) = map_object.raycast(&[group], &transform, &Point3::new(11.0, 1.0, 5.0), &down).unwrap();
+        assert_eq!((index, hit.position, hit.distance), (0, Point3::new(11.0, 1.0, 2.0), 3.0));
+    }
+}
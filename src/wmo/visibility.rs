@@ -0,0 +1,209 @@
+use std::collections::BTreeSet;
+use nalgebra::{Matrix4, Point3, Vector3};
+use culling::LocalBounds;
+use wmo::{MapObject, MeshGroup, GroupFlags};
+
+// a point is inside when `normal . point + distance >= 0`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+// convex volume bounded by planes facing inwards
+#[derive(Clone, Debug)]
+pub struct Frustum {
+    pub planes: Vec<Plane>,
+}
+
+impl Plane {
+    pub fn new(normal: Vector3<f32>, distance: f32) -> Self {
+        let length = normal.norm();
+        Plane { normal: normal / length, distance: distance / length }
+    }
+
+    pub fn signed_distance(&self, point: &Point3<f32>) -> f32 {
+        self.normal.dot(&point.coords) + self.distance
+    }
+}
+
+impl Frustum {
+    // planes of a (model-)view-projection matrix with a -1..1 depth range;
+    // the frustum is in the space the matrix transforms from
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Self {
+        let row = |index: usize| (Vector3::new(matrix[(index, 0)], matrix[(index, 1)], matrix[(index, 2)]), matrix[(index, 3)]);
+        let (w, w_distance) = row(3);
+
+        let planes = (0..3)
+            .flat_map(|index| {
+                let (axis, axis_distance) = row(index);
+                vec![
+                    Plane::new(w + axis, w_distance + axis_distance),
+                    Plane::new(w - axis, w_distance - axis_distance),
+                ]
+            })
+            .collect();
+
+        Frustum { planes }
+    }
+
+    // the volume seen from `eye` through a convex polygon
+    pub fn from_polygon(eye: &Point3<f32>, polygon: &[Point3<f32>]) -> Self {
+        let center = polygon.iter().fold(Vector3::zeros(), |sum, point| sum + point.coords) / polygon.len() as f32;
+        let center = Point3::from_coordinates(center);
+
+        let planes = (0..polygon.len())
+            .filter_map(|index| {
+                let a = polygon[index] - eye;
+                let b = polygon[(index + 1) % polygon.len()] - eye;
+                let normal = a.cross(&b);
+                if normal.norm() < 1e-6 { return None }
+
+                let plane = Plane::new(normal, -normal.dot(&eye.coords));
+                Some(if plane.signed_distance(&center) < 0.0 { Plane { normal: -plane.normal, distance: -plane.distance } } else { plane })
+            })
+            .collect();
+
+        Frustum { planes }
+    }
+
+    pub fn intersects_box(&self, bounds: &LocalBounds) -> bool {
+        self.planes.iter().all(|plane| {
+            // corner farthest along the plane normal
+            let corner = Point3::new(
+                if plane.normal.x >= 0.0 { bounds.max.x } else { bounds.min.x },
+                if plane.normal.y >= 0.0 { bounds.max.y } else { bounds.min.y },
+                if plane.normal.z >= 0.0 { bounds.max.z } else { bounds.min.z },
+            );
+            plane.signed_distance(&corner) >= 0.0
+        })
+    }
+
+    // part of a convex polygon inside the frustum (sutherland-hodgman)
+    pub fn clip(&self, polygon: &[Point3<f32>]) -> Vec<Point3<f32>> {
+        let mut result = polygon.to_vec();
+
+        for plane in self.planes.iter() {
+            let input = result;
+            result = Vec::with_capacity(input.len() + 1);
+
+            for index in 0..input.len() {
+                let current = input[index];
+                let next = input[(index + 1) % input.len()];
+                let (a, b) = (plane.signed_distance(&current), plane.signed_distance(&next));
+
+                if a >= 0.0 { result.push(current) }
+                if (a >= 0.0) != (b >= 0.0) {
+                    result.push(current + (next - current) * (a / (a - b)));
+                }
+            }
+
+            if result.len() < 3 { return Vec::new() }
+        }
+
+        result
+    }
+}
+
+// groups visible from `camera` (object space) through `frustum`. `groups` must be
+// all groups of `map_object`, in order. the camera starts in the smallest indoor
+// group containing it; outside of those, every outdoor group in view is a start
+pub fn visible_groups(map_object: &MapObject, groups: &[MeshGroup], camera: &Point3<f32>, frustum: &Frustum) -> BTreeSet<usize> {
+    let mut visible = BTreeSet::new();
+    let contains = |bounds: &LocalBounds| (0..3).all(|axis| camera[axis] >= bounds.min[axis] && camera[axis] <= bounds.max[axis]);
+
+    let indoor = groups.iter().enumerate()
+        .filter(|&(_, group)| group.flags.contains(GroupFlags::INDOOR) && contains(&group.bounds))
+        .min_by(|a, b| a.1.bounds.radius.total_cmp(&b.1.bounds.radius))
+        .map(|(index, _)| index);
+
+    let starts: Vec<usize> = match indoor {
+        Some(index) => vec![index],
+        None => groups.iter().enumerate()
+            .filter(|&(_, group)| group.flags.contains(GroupFlags::OUTDOOR) && frustum.intersects_box(&group.bounds))
+            .map(|(index, _)| index)
+            .collect(),
+    };
+
+    for start in starts {
+        let mut path = Vec::new();
+        traverse(map_object, groups, camera, frustum, start, &mut path, &mut visible);
+    }
+
+    visible
+}
+
+// `path` holds the portals already passed through, so none is crossed twice
+fn traverse(map_object: &MapObject, groups: &[MeshGroup], camera: &Point3<f32>, frustum: &Frustum, index: usize, path: &mut Vec<u16>, visible: &mut BTreeSet<usize>) {
+    visible.insert(index);
+
+    for portal_ref in map_object.group_portals(&groups[index]) {
+        let portal = match map_object.portals.get(portal_ref.portal as usize) {
+            Some(portal) => portal,
+            None => continue,
+        };
+        if path.contains(&portal_ref.portal) || portal_ref.group as usize >= groups.len() {
+            continue;
+        }
+
+        // the portal is only seen from the side of the group it belongs to
+        let plane = Plane { normal: portal.normal, distance: portal.distance };
+        if plane.signed_distance(camera) * portal_ref.side as f32 <= 0.0 {
+            continue;
+        }
+
+        let clipped = frustum.clip(&portal.vertices);
+        if clipped.is_empty() {
+            continue;
+        }
+
+        path.push(portal_ref.portal);
+        traverse(map_object, groups, camera, &Frustum::from_polygon(camera, &clipped), portal_ref.group as usize, path, visible);
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wmo::{read_group_file, Portal, PortalRef};
+
+    fn group(min_x: f32, portals: ::std::ops::Range<usize>) -> MeshGroup {
+        let mut group = read_group_file(&[], 17).unwrap();
+        group.flags = GroupFlags::INDOOR;
+        group.bounds = LocalBounds::from_box(Point3::new(min_x, 0.0, 0.0), Point3::new(min_x + 10.0, 10.0, 10.0));
+        group.portals = portals;
+        group
+    }
+
+    #[test]
+    fn test_visible_groups() {
+        // rooms 0 and 1 joined by a doorway at x = 10
+        let mut map_object = MapObject::default();
+        map_object.portals.push(Portal {
+            vertices: vec![Point3::new(10.0, 4.0, 0.0), Point3::new(10.0, 6.0, 0.0), Point3::new(10.0, 6.0, 3.0), Point3::new(10.0, 4.0, 3.0)],
+            normal: Vector3::x(),
+            distance: -10.0,
+        });
+        map_object.portal_refs = vec![
+            PortalRef { portal: 0, group: 1, side: -1 },
+            PortalRef { portal: 0, group: 0, side: 1 },
+        ];
+        let groups = vec![group(0.0, 0..1), group(10.0, 1..2)];
+        let camera = Point3::new(5.0, 5.0, 1.0);
+
+        let facing_door = Frustum { planes: vec![Plane::new(Vector3::x(), -4.0)] };
+        assert_eq!(visible_groups(&map_object, &groups, &camera, &facing_door).into_iter().collect::<Vec<_>>(), vec![0, 1]);
+
+        let facing_away = Frustum { planes: vec![Plane::new(-Vector3::x(), 6.0)] };
+        assert_eq!(visible_groups(&map_object, &groups, &camera, &facing_away).into_iter().collect::<Vec<_>>(), vec![0]);
+
+        // a view matrix looking down +x from the camera sees the doorway as well
+        let view = Matrix4::look_at_rh(&camera, &Point3::new(10.0, 5.0, 1.0), &Vector3::z());
+        let projection = Matrix4::new_perspective(1.0, 1.0, 0.1, 100.0);
+        let frustum = Frustum::from_matrix(&(projection * view));
+        assert!(frustum.intersects_box(&groups[1].bounds));
+        assert!(visible_groups(&map_object, &groups, &camera, &frustum).contains(&1));
+    }
+}
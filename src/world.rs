@@ -0,0 +1,388 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::Arc;
+use nalgebra::{Point3, Matrix4};
+use adt::{self, MapTile, M2Placement, WmoPlacement, TileOptions};
+use culling::CullingBounds;
+use m2::{self, Model};
+use m2::skin::{self, SkinProfile};
+use manager::{self, ResourceManager};
+use reader::ResourceReader;
+use wdt::{self, Wdt};
+use wmo::{MapObject, MeshGroup};
+use error::Result;
+
+// a map (continent) with lazily loaded tiles
+pub struct World {
+    reader: Arc<dyn ResourceReader>,
+    map_name: String,
+    wdt: Wdt,
+    tiles: HashMap<(u32, u32), Arc<MapTile>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum PlacementKind {
+    M2(M2Placement),
+    Wmo(WmoPlacement),
+}
+
+#[derive(Clone, Debug)]
+pub struct WorldPlacement {
+    pub name: String,
+    pub tile: (u32, u32),
+    pub kind: PlacementKind,
+    pub transform: Matrix4<f32>,
+}
+
+impl World {
+    pub fn load(reader: Arc<dyn ResourceReader>, map_name: &str) -> Result<World> {
+        let wdt = wdt::load(reader.clone(), &wdt::map_name(map_name))?;
+
+        Ok(World {
+            reader,
+            map_name: map_name.to_owned(),
+            wdt,
+            tiles: HashMap::new(),
+        })
+    }
+
+    pub fn map_name(&self) -> &str {
+        &self.map_name
+    }
+
+    pub fn wdt(&self) -> &Wdt {
+        &self.wdt
+    }
+
+    pub fn tile_name(&self, x: u32, y: u32) -> String {
+        tile_name(&self.map_name, x, y)
+    }
+
+    // loads the tile on first access; returns None for tiles the map doesn't have
+    pub fn tile(&mut self, x: u32, y: u32) -> Result<Option<Arc<MapTile>>> {
+        if !self.wdt.has_tile(x, y) {
+            return Ok(None);
+        }
+
+        if let Some(tile) = self.tiles.get(&(x, y)) {
+            return Ok(Some(tile.clone()));
+        }
+
+        let name = self.tile_name(x, y);
+        let tile = Arc::new(MapTile::load(self.reader.clone(), &name, Some(self.wdt.big_alpha()))?);
+        self.tiles.insert((x, y), tile.clone());
+        Ok(Some(tile))
+    }
+
+    pub fn unload_tile(&mut self, x: u32, y: u32) {
+        self.tiles.remove(&(x, y));
+    }
+
+    // placements whose origin (M2) or bounds (WMO) are within `radius` of a world position;
+    // objects spanning several tiles are reported once
+    pub fn placements_within(&mut self, center: &Point3<f32>, radius: f32) -> Result<Vec<WorldPlacement>> {
+        let (min_x, min_y) = adt::world_to_tile(center.x + radius, center.y + radius);
+        let (max_x, max_y) = adt::world_to_tile(center.x - radius, center.y - radius);
+
+        let mut seen_m2 = HashSet::new();
+        let mut seen_wmo = HashSet::new();
+        let mut result = Vec::new();
+
+        for y in min_y..(max_y + 1) {
+            for x in min_x..(max_x + 1) {
+                let tile = match self.tile(x, y)? {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+
+                for placement in &tile.m2_placements {
+                    if (placement.world_position() - center).norm() > radius { continue }
+                    if !seen_m2.insert(placement.unique_id) { continue }
+
+                    result.push(WorldPlacement {
+                        name: placement_name(&tile.m2, placement.name_id),
+                        tile: (x, y),
+                        kind: PlacementKind::M2(placement.clone()),
+                        transform: placement.world_transform(),
+                    });
+                }
+
+                for placement in &tile.wmo_placements {
+                    if !placement.culling_bounds().intersects(center, radius) { continue }
+                    if !seen_wmo.insert(placement.unique_id) { continue }
+
+                    result.push(WorldPlacement {
+                        name: placement_name(&tile.wmo, placement.name_id),
+                        tile: (x, y),
+                        kind: PlacementKind::Wmo(placement.clone()),
+                        transform: placement.world_transform(),
+                    });
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+// which doodads of a placed map object a scene includes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoodadSelection {
+    // the set chosen by the MODF entry
+    Placement,
+    // this set (plus set 0, which is always shown) for every object
+    Set(usize),
+    None,
+}
+
+// builds a `Scene` of a rectangle of tiles; assets are loaded through a manager,
+// so models shared with other scenes are only loaded once
+pub struct SceneBuilder {
+    manager: Arc<ResourceManager>,
+    map_name: String,
+    tiles: Option<(Range<u32>, Range<u32>)>,
+    doodads: DoodadSelection,
+    skins: bool,
+    parallelism: usize,
+}
+
+// everything placed on some tiles of a map, flattened into instances
+pub struct Scene {
+    pub map_name: String,
+    pub wdt: Arc<Wdt>,
+    pub instances: Vec<SceneInstance>,
+}
+
+#[derive(Clone)]
+pub struct SceneInstance {
+    pub name: String,
+    // the tile the placement (or terrain) comes from
+    pub tile: (u32, u32),
+    pub unique_id: Option<u32>,
+    pub kind: InstanceKind,
+    // model space to Z-up world space
+    pub transform: Matrix4<f32>,
+    pub bounds: CullingBounds,
+    // index into `Scene::instances` of the map object a doodad belongs to
+    pub parent: Option<usize>,
+}
+
+#[derive(Clone)]
+pub enum InstanceKind {
+    Terrain(Arc<MapTile>),
+    // `skin` is the first skin profile, if skins are loaded
+    Model { model: Arc<Model>, skin: Option<Arc<SkinProfile>> },
+    MapObject { root: Arc<MapObject>, groups: Arc<Vec<MeshGroup>>, doodad_set: usize },
+}
+
+impl SceneBuilder {
+    pub fn new(manager: Arc<ResourceManager>, map_name: &str) -> SceneBuilder {
+        SceneBuilder {
+            manager,
+            map_name: map_name.to_owned(),
+            tiles: None,
+            doodads: DoodadSelection::Placement,
+            skins: true,
+            parallelism: 1,
+        }
+    }
+
+    // limits the scene to these tile indices; by default every tile of the map is loaded
+    pub fn tiles(mut self, x: Range<u32>, y: Range<u32>) -> Self {
+        self.tiles = Some((x, y));
+        self
+    }
+
+    pub fn doodads(mut self, doodads: DoodadSelection) -> Self {
+        self.doodads = doodads;
+        self
+    }
+
+    // whether to load the first skin profile of every model
+    pub fn skins(mut self, skins: bool) -> Self {
+        self.skins = skins;
+        self
+    }
+
+    // number of threads loading models and map objects
+    pub fn parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism;
+        self
+    }
+
+    // fails if the WDT or a tile can't be loaded; models and objects which fail
+    // are reported and left out
+    pub fn build(&self) -> Result<Scene> {
+        let reader = self.manager.reader().clone();
+        let wdt = self.manager.get::<Wdt>(&wdt::map_name(&self.map_name))?;
+        let options = TileOptions { version: self.manager.options().version, ..TileOptions::new(Some(wdt.big_alpha())) };
+
+        let mut tiles = Vec::new();
+        for (x, y) in wdt.existing_tiles() {
+            if let Some((ref range_x, ref range_y)) = self.tiles {
+                if !range_x.contains(&x) || !range_y.contains(&y) { continue }
+            }
+            let tile = MapTile::load_with(reader.clone(), &tile_name(&self.map_name, x, y), &options)?;
+            tiles.push(((x, y), Arc::new(tile)));
+        }
+
+        let mut scene = Scene { map_name: self.map_name.clone(), wdt: wdt.clone(), instances: Vec::new() };
+        for &(coords, ref tile) in tiles.iter() {
+            if let Some(aabb) = tile.aabb() {
+                scene.instances.push(SceneInstance {
+                    name: tile_name(&self.map_name, coords.0, coords.1),
+                    tile: coords,
+                    unique_id: None,
+                    kind: InstanceKind::Terrain(tile.clone()),
+                    transform: Matrix4::identity(),
+                    bounds: aabb.bounding_sphere(),
+                    parent: None,
+                });
+            }
+        }
+
+        // objects spanning several tiles are listed by each of them
+        let mut seen_m2 = HashSet::new();
+        let mut seen_wmo = HashSet::new();
+        let mut m2_placements = Vec::new();
+        let mut wmo_placements = Vec::new();
+        for &(coords, ref tile) in tiles.iter() {
+            for placement in tile.m2_placements.iter().filter(|placement| seen_m2.insert(placement.unique_id)) {
+                m2_placements.push((coords, m2::model_file_name(&placement_name(&tile.m2, placement.name_id)), placement));
+            }
+            for placement in tile.wmo_placements.iter().filter(|placement| seen_wmo.insert(placement.unique_id)) {
+                wmo_placements.push((coords, placement_name(&tile.wmo, placement.name_id), placement));
+            }
+        }
+
+        let objects = self.load_objects(wmo_placements.iter().map(|(_, name, _)| name.clone()).collect());
+        let mut doodads = Vec::new();
+
+        for &(coords, ref name, placement) in wmo_placements.iter() {
+            let (root, groups) = match objects.get(name) {
+                Some((root, groups)) => (root.clone(), groups.clone()),
+                None => continue,
+            };
+            let doodad_set = match self.doodads {
+                DoodadSelection::Set(set) => set,
+                _ => placement.doodad_set as usize,
+            };
+            let transform = placement.world_transform();
+
+            scene.instances.push(SceneInstance {
+                name: name.clone(),
+                tile: coords,
+                unique_id: Some(placement.unique_id),
+                kind: InstanceKind::MapObject { root: root.clone(), groups, doodad_set },
+                transform,
+                bounds: placement.culling_bounds(),
+                parent: None,
+            });
+
+            if self.doodads != DoodadSelection::None {
+                let parent = scene.instances.len() - 1;
+                for doodad in root.doodads_for_set(doodad_set) {
+                    if let Some(doodad_name) = doodad.model.and_then(|index| root.m2.get(index)) {
+                        doodads.push((coords, m2::model_file_name(doodad_name), transform * doodad.transform(), parent));
+                    }
+                }
+            }
+        }
+
+        let model_names = m2_placements.iter().map(|(_, name, _)| name.clone())
+            .chain(doodads.iter().map(|(_, name, _, _)| name.clone()))
+            .collect();
+        let models = self.load_models(model_names);
+
+        for &(coords, ref name, placement) in m2_placements.iter() {
+            if let Some((model, skin)) = models.get(name) {
+                scene.instances.push(SceneInstance {
+                    name: name.clone(),
+                    tile: coords,
+                    unique_id: Some(placement.unique_id),
+                    kind: InstanceKind::Model { model: model.clone(), skin: skin.clone() },
+                    transform: placement.world_transform(),
+                    bounds: placement.culling_bounds(&model.bounds),
+                    parent: None,
+                });
+            }
+        }
+
+        for (coords, name, transform, parent) in doodads {
+            if let Some((model, skin)) = models.get(&name) {
+                scene.instances.push(SceneInstance {
+                    bounds: model.bounds.sphere_around_origin().transform(&transform),
+                    name,
+                    tile: coords,
+                    unique_id: None,
+                    kind: InstanceKind::Model { model: model.clone(), skin: skin.clone() },
+                    transform,
+                    parent: Some(parent),
+                });
+            }
+        }
+
+        Ok(scene)
+    }
+
+    fn load_objects(&self, mut names: Vec<String>) -> HashMap<String, (Arc<MapObject>, Arc<Vec<MeshGroup>>)> {
+        names.sort();
+        names.dedup();
+
+        let roots = manager::load_many_cached::<MapObject, _>(self.manager.clone(), &names, self.parallelism);
+        names.into_iter().zip(roots).filter_map(|(name, root)| match root {
+            Ok(root) => {
+                let groups = Arc::new(root.load_all_groups(self.manager.reader().clone()));
+                Some((name, (root, groups)))
+            }
+            Err(error) => {
+                report_warn!("Leaving out {}: {}", name, error);
+                None
+            }
+        }).collect()
+    }
+
+    fn load_models(&self, mut names: Vec<String>) -> HashMap<String, (Arc<Model>, Option<Arc<SkinProfile>>)> {
+        names.sort();
+        names.dedup();
+
+        let models = manager::load_many_cached::<Model, _>(self.manager.clone(), &names, self.parallelism);
+        names.into_iter().zip(models).filter_map(|(name, model)| match model {
+            Ok(model) => {
+                let skin = if self.skins {
+                    skin::load_for_model(self.manager.reader().clone(), &name, &model, 0)
+                        .map_err(|error| report_warn!("No skin for {}: {}", name, error))
+                        .ok()
+                        .map(Arc::new)
+                } else {
+                    None
+                };
+                Some((name, (model, skin)))
+            }
+            Err(error) => {
+                report_warn!("Leaving out {}: {}", name, error);
+                None
+            }
+        }).collect()
+    }
+}
+
+impl Scene {
+    pub fn builder(manager: Arc<ResourceManager>, map_name: &str) -> SceneBuilder {
+        SceneBuilder::new(manager, map_name)
+    }
+}
+
+fn tile_name(map_name: &str, x: u32, y: u32) -> String {
+    format!("World\\Maps\\{0}\\{0}_{1}_{2}.adt", map_name, x, y)
+}
+
+fn placement_name(names: &[String], name_id: u32) -> String {
+    match names.get(name_id as usize) {
+        Some(name) => name.clone(),
+        None => {
+            report_warn!("placement refers to missing name {}", name_id);
+            String::new()
+        }
+    }
+}
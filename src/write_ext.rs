@@ -0,0 +1,193 @@
+// the writing counterparts of `read_ext`, so files written back mirror how
+// they're read
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use byteorder::{WriteBytesExt, ByteOrder};
+
+#[allow(dead_code)]
+pub trait WriteExt : Write + WriteBytesExt {
+    fn write_cstring(&mut self, string: &str) -> io::Result<()> {
+        if string.as_bytes().contains(&0) {
+            return Err(io_error!(InvalidInput, "string '{}' contains a NUL", string.escape_default()));
+        }
+        self.write_all(string.as_bytes())?;
+        self.write_u8(0)
+    }
+
+    // strings back to back, as read by `read_cstring_array`; returns the offset
+    // of each, for chunks referring to them (MMID, MOMT and the like)
+    fn write_cstring_array<S: AsRef<str>>(&mut self, strings: &[S]) -> io::Result<Vec<u32>> {
+        let mut offsets = Vec::with_capacity(strings.len());
+        let mut offset = 0;
+        for string in strings {
+            let string = string.as_ref();
+            self.write_cstring(string)?;
+            offsets.push(offset);
+            offset += string.len() as u32 + 1;
+        }
+        Ok(offsets)
+    }
+
+    // strings at their offsets, as read by `read_cstring_table`, with NULs
+    // filling any gaps
+    fn write_cstring_table(&mut self, table: &BTreeMap<u32, String>) -> io::Result<()> {
+        let mut offset = 0;
+        for (&index, string) in table {
+            if index < offset {
+                return Err(io_error!(InvalidInput, "string at {} overlaps the one before", index));
+            }
+            for _ in offset..index {
+                self.write_u8(0)?;
+            }
+            self.write_cstring(string)?;
+            offset = index + string.len() as u32 + 1;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn write_u8tuple4(&mut self, value: (u8, u8, u8, u8)) -> io::Result<()> {
+        self.write_all(&[value.0, value.1, value.2, value.3])
+    }
+
+    #[inline]
+    fn write_f32tuple2<T: ByteOrder>(&mut self, value: (f32, f32)) -> io::Result<()> {
+        self.write_f32::<T>(value.0)?;
+        self.write_f32::<T>(value.1)
+    }
+
+    #[inline]
+    fn write_f32tuple3<T: ByteOrder>(&mut self, value: (f32, f32, f32)) -> io::Result<()> {
+        self.write_f32::<T>(value.0)?;
+        self.write_f32::<T>(value.1)?;
+        self.write_f32::<T>(value.2)
+    }
+
+    #[inline]
+    fn write_f32tuple4<T: ByteOrder>(&mut self, value: (f32, f32, f32, f32)) -> io::Result<()> {
+        self.write_f32::<T>(value.0)?;
+        self.write_f32::<T>(value.1)?;
+        self.write_f32::<T>(value.2)?;
+        self.write_f32::<T>(value.3)
+    }
+}
+impl<W: Write + WriteBytesExt> WriteExt for W {}
+
+pub trait ToWrite {
+    fn to_write<O: ByteOrder>(&self, writer: &mut dyn Write) -> io::Result<()>;
+}
+
+impl ToWrite for i8 {
+    fn to_write<O: ByteOrder>(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_i8(*self)
+    }
+}
+
+impl ToWrite for u8 {
+    fn to_write<O: ByteOrder>(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u8(*self)
+    }
+}
+
+impl ToWrite for (u8, u8, u8, u8) {
+    fn to_write<O: ByteOrder>(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_u8tuple4(*self)
+    }
+}
+
+impl ToWrite for (f32, f32) {
+    fn to_write<O: ByteOrder>(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_f32tuple2::<O>(*self)
+    }
+}
+
+impl ToWrite for (f32, f32, f32) {
+    fn to_write<O: ByteOrder>(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_f32tuple3::<O>(*self)
+    }
+}
+
+impl ToWrite for (f32, f32, f32, f32) {
+    fn to_write<O: ByteOrder>(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_f32tuple4::<O>(*self)
+    }
+}
+
+macro_rules! impl_to_write {
+    ($method:ident, $fortype:ty) => {
+        impl ::write_ext::ToWrite for $fortype {
+            fn to_write<O: ::byteorder::ByteOrder>(&self, writer: &mut dyn (::std::io::Write)) -> ::std::io::Result<()> {
+                ::byteorder::WriteBytesExt::$method::<O>(writer, *self)
+            }
+        }
+    };
+}
+impl_to_write!(write_i16, i16);
+impl_to_write!(write_i32, i32);
+impl_to_write!(write_i64, i64);
+impl_to_write!(write_u16, u16);
+impl_to_write!(write_u32, u32);
+impl_to_write!(write_u64, u64);
+impl_to_write!(write_f32, f32);
+impl_to_write!(write_f64, f64);
+
+macro_rules! impl_bitflags_to_write {
+    ($method:ident, $fortype:ident) => {
+        impl ::write_ext::ToWrite for $fortype {
+            fn to_write<O: ::byteorder::ByteOrder>(&self, writer: &mut dyn (::std::io::Write)) -> ::std::io::Result<()> {
+                ::byteorder::WriteBytesExt::$method::<O>(writer, self.bits())
+            }
+        }
+    }
+}
+
+// writes the named bindings in order, so the field list of a `let_read!` can
+// be reused as is
+macro_rules! let_write {
+    ($byteorder:ty | $writer:ident => $( $fieldname:ident : $fieldtype:ty ; )+ ) => {
+        $(
+            trace!("{} = {:?}", stringify!($fieldname), &$fieldname);
+            <$fieldtype as ::write_ext::ToWrite>::to_write::<$byteorder>(&$fieldname, &mut $writer)?;
+        )*
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use byteorder::LE;
+    use read_ext::ReadExt;
+
+    fn roundtrip() -> io::Result<()> {
+        let mut output = Vec::new();
+        let (flags, position, name_offsets) = (3u32, (1.0f32, 2.0f32, 3.0f32), vec![0u32, 5]);
+        let_write! { LE | output =>
+            flags: u32;
+            position: (f32, f32, f32);
+        }
+        assert_eq!(output.write_cstring_array(&["a.m2", "bc.m2"])?, name_offsets);
+
+        let mut table = BTreeMap::new();
+        table.insert(2, "x.blp".to_owned());
+        table.insert(9, "y.blp".to_owned());
+        output.write_cstring_table(&table)?;
+
+        let mut input = Cursor::new(&output[..]);
+        let_read! { LE | input =>
+            flags: u32;
+            position: (f32, f32, f32);
+        }
+        assert_eq!((flags, position), (3, (1.0, 2.0, 3.0)));
+        assert_eq!(input.read_cstring()?, "a.m2");
+        assert_eq!(input.read_cstring()?, "bc.m2");
+        assert_eq!(input.read_cstring_table()?, table);
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        roundtrip().unwrap();
+        assert!(Vec::new().write_cstring("a\0b").is_err());
+    }
+}
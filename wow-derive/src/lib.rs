@@ -0,0 +1,121 @@
+// `#[derive(FromRead)]` for the fixed layout records of wow's parsers. the
+// generated impls name `::read_ext` and so only build inside wow itself.
+//
+// fields are read in declaration order, each with its type's `FromRead`. a
+// field can carry `#[from_read(...)]` with
+//   byteorder = "BE"          read it with this byte order instead of the caller's
+//   with = "path::to::read"   read it with `read(reader)` instead, for fields
+//                             which don't depend on the byte order
+//   version = "version >= 17" read it only where this holds, else it's `Default`
+// structs with version gated fields implement `FromReadVersion` instead of
+// `FromRead`, with the version as `version`; the fields read so far are in
+// scope as well
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+#[macro_use]
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, TokenStream as TokenStream2};
+use syn::{Data, DeriveInput, Expr, Fields, LitStr, Path, Type};
+
+#[proc_macro_derive(FromRead, attributes(from_read))]
+pub fn derive_from_read(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+struct Field {
+    ident: Ident,
+    ty: Type,
+    byteorder: Option<Type>,
+    with: Option<Path>,
+    version: Option<Expr>,
+}
+
+impl Field {
+    fn parse(field: &syn::Field) -> syn::Result<Field> {
+        let mut result = Field {
+            ident: field.ident.clone().expect("named field"),
+            ty: field.ty.clone(),
+            byteorder: None,
+            with: None,
+            version: None,
+        };
+
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("from_read")) {
+            attr.parse_nested_meta(|meta| {
+                let value: LitStr = meta.value()?.parse()?;
+                if meta.path.is_ident("byteorder") {
+                    result.byteorder = Some(value.parse()?);
+                } else if meta.path.is_ident("with") {
+                    result.with = Some(value.parse()?);
+                } else if meta.path.is_ident("version") {
+                    result.version = Some(value.parse()?);
+                } else {
+                    return Err(meta.error("expected `byteorder`, `with` or `version`"));
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(result)
+    }
+
+    fn read(&self) -> TokenStream2 {
+        let byteorder = match self.byteorder {
+            Some(ref byteorder) => quote!(#byteorder),
+            None => quote!(O),
+        };
+        let ty = &self.ty;
+        let read = match self.with {
+            Some(ref with) => quote!(#with(__reader)?),
+            None => quote!(<#ty as ::read_ext::FromRead>::from_read::<#byteorder>(__reader)?),
+        };
+
+        match self.version {
+            Some(ref version) => quote!(if #version { #read } else { ::std::default::Default::default() }),
+            None => read,
+        }
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => fields.named.iter().map(Field::parse).collect::<syn::Result<Vec<_>>>()?,
+            _ => return Err(syn::Error::new_spanned(input, "FromRead needs named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(input, "FromRead can only be derived for structs")),
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let idents: Vec<_> = fields.iter().map(|field| &field.ident).collect();
+    let reads: Vec<_> = fields.iter().map(Field::read).collect();
+    let body = quote! {
+        #( let #idents = #reads; )*
+        Ok(#name { #( #idents ),* })
+    };
+
+    Ok(if fields.iter().any(|field| field.version.is_some()) {
+        quote! {
+            impl #impl_generics ::read_ext::FromReadVersion for #name #ty_generics #where_clause {
+                #[allow(unused_variables)]
+                fn from_read_version<O: ::byteorder::ByteOrder>(__reader: &mut dyn (::std::io::Read), version: u32) -> ::std::io::Result<Self> {
+                    #body
+                }
+            }
+        }
+    } else {
+        quote! {
+            impl #impl_generics ::read_ext::FromRead for #name #ty_generics #where_clause {
+                fn from_read<O: ::byteorder::ByteOrder>(__reader: &mut dyn (::std::io::Read)) -> ::std::io::Result<Self> {
+                    #body
+                }
+            }
+        }
+    })
+}